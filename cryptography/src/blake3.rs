@@ -0,0 +1,96 @@
+//! BLAKE3 implementation of the `Hasher` trait.
+//!
+//! Digests are BLAKE3's default output length of 32 bytes.
+
+use crate::{Digest, Hasher};
+
+const DIGEST_LENGTH: usize = 32;
+
+/// BLAKE3 hasher.
+pub struct Blake3 {
+    hasher: ::blake3::Hasher,
+}
+
+impl Default for Blake3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for Blake3 {
+    fn clone(&self) -> Self {
+        // We manually implement `Clone` to avoid cloning the hasher state.
+        Self::default()
+    }
+}
+
+impl Hasher for Blake3 {
+    fn new() -> Self {
+        Self {
+            hasher: ::blake3::Hasher::new(),
+        }
+    }
+
+    fn update(&mut self, message: &[u8]) {
+        self.hasher.update(message);
+    }
+
+    fn finalize(&mut self) -> Digest {
+        let digest = self.hasher.finalize();
+        self.hasher.reset();
+        digest.as_bytes().to_vec().into()
+    }
+
+    fn reset(&mut self) {
+        self.hasher.reset();
+    }
+
+    fn validate(digest: &Digest) -> bool {
+        digest.len() == DIGEST_LENGTH
+    }
+
+    fn len() -> usize {
+        DIGEST_LENGTH
+    }
+
+    fn random<R: rand::Rng + rand::CryptoRng>(rng: &mut R) -> Digest {
+        let mut digest = [0u8; DIGEST_LENGTH];
+        rng.fill_bytes(&mut digest);
+        digest.to_vec().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_utils::hex;
+
+    #[test]
+    fn test_blake3() {
+        let digest = b"hello world";
+
+        // Generate initial hash
+        let mut hasher = Blake3::new();
+        hasher.update(digest);
+        let hash = hasher.finalize();
+        assert!(Blake3::validate(&hash));
+        assert_eq!(
+            hex(&hash),
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+
+        // Reuse hasher
+        hasher.update(digest);
+        let hash = hasher.finalize();
+        assert!(Blake3::validate(&hash));
+        assert_eq!(
+            hex(&hash),
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+    }
+
+    #[test]
+    fn test_blake3_len() {
+        assert_eq!(Blake3::len(), DIGEST_LENGTH);
+    }
+}
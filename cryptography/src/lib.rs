@@ -8,6 +8,8 @@
 use bytes::Bytes;
 use rand::{CryptoRng, Rng, RngCore, SeedableRng};
 
+pub mod blake3;
+pub use blake3::Blake3;
 pub mod bls12381;
 pub use bls12381::Bls12381;
 pub mod ed25519;
@@ -490,4 +492,24 @@ mod tests {
     fn test_sha256_hasher_large_input() {
         test_hasher_large_input::<Sha256>();
     }
+
+    #[test]
+    fn test_blake3_hasher_multiple_runs() {
+        test_hasher_multiple_runs::<Blake3>();
+    }
+
+    #[test]
+    fn test_blake3_hasher_multiple_updates() {
+        test_hasher_multiple_updates::<Blake3>();
+    }
+
+    #[test]
+    fn test_blake3_hasher_empty_input() {
+        test_hasher_empty_input::<Blake3>();
+    }
+
+    #[test]
+    fn test_blake3_hasher_large_input() {
+        test_hasher_large_input::<Blake3>();
+    }
 }
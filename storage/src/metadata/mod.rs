@@ -342,6 +342,53 @@ mod tests {
         });
     }
 
+    #[test_traced]
+    fn test_read() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Reading a missing partition returns no data without creating it
+            let data = Metadata::read(&context, "test").await.unwrap();
+            assert!(data.is_empty());
+            assert!(context.scan("test").await.is_err());
+
+            // Create a metadata store
+            let cfg = Config {
+                registry: Arc::new(Mutex::new(Registry::default())),
+                partition: "test".to_string(),
+            };
+            let mut metadata = Metadata::init(context.clone(), cfg).await.unwrap();
+
+            // Sync a key, then overwrite it
+            let key = 42;
+            let hello = Bytes::from("hello");
+            metadata.put(key, hello.clone());
+            metadata.sync().await.unwrap();
+            let world = Bytes::from("world");
+            metadata.put(key, world.clone());
+            metadata.close().await.unwrap();
+
+            // Read the latest data
+            let data = Metadata::read(&context, "test").await.unwrap();
+            assert_eq!(data.get(&key), Some(&world));
+
+            // Corrupt the latest blob
+            let blob = context.open("test", b"left").await.unwrap();
+            blob.write_at(b"corrupted", 0).await.unwrap();
+            let len = blob.len().await.unwrap();
+            blob.close().await.unwrap();
+
+            // Reading falls back to the other blob without repairing the corrupt one
+            let data = Metadata::read(&context, "test").await.unwrap();
+            assert_eq!(data.get(&key), Some(&hello));
+            let blob = context.open("test", b"left").await.unwrap();
+            assert_eq!(blob.len().await.unwrap(), len);
+            let mut buf = [0u8; 9];
+            blob.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf, b"corrupted");
+        });
+    }
+
     #[test_traced]
     fn test_recover_corrupted_both() {
         // Initialize the deterministic runtime
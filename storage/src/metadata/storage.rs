@@ -1,6 +1,6 @@
 use super::{Config, Error};
 use bytes::{BufMut, Bytes};
-use commonware_runtime::{Blob, Clock, Storage};
+use commonware_runtime::{Blob, Clock, Error as RError, Storage};
 use commonware_utils::SystemTimeExt as _;
 use prometheus_client::metrics::{counter::Counter, gauge::Gauge};
 use std::{
@@ -8,7 +8,7 @@ use std::{
     marker::PhantomData,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tracing::{trace, warn};
+use tracing::{debug, trace, warn};
 
 const BLOB_NAMES: [&[u8]; 2] = [b"left", b"right"];
 const SECONDS_IN_NANOSECONDS: u128 = 1_000_000_000;
@@ -36,8 +36,8 @@ impl<B: Blob, E: Clock + Storage<B>> Metadata<B, E> {
         let right = runtime.open(&cfg.partition, BLOB_NAMES[1]).await?;
 
         // Find latest blob (check which includes a hash of the other)
-        let left_result = Self::load(0, &left).await?;
-        let right_result = Self::load(1, &right).await?;
+        let left_result = Self::load(0, &left, true).await?;
+        let right_result = Self::load(1, &right, true).await?;
 
         // Set checksums
         let mut left_timestamp = 0;
@@ -86,7 +86,40 @@ impl<B: Blob, E: Clock + Storage<B>> Metadata<B, E> {
         })
     }
 
-    async fn load(index: usize, blob: &B) -> Result<Option<(u128, BTreeMap<u32, Bytes>)>, Error> {
+    /// Read the data last committed to the `Metadata` stored in `partition` without creating,
+    /// truncating, or syncing any blob (unlike `init`), so that it can be read while another
+    /// instance is writing to it. A blob that is missing, too short, or fails its checksum (e.g.
+    /// because it is being synced) is ignored.
+    pub async fn read(runtime: &E, partition: &str) -> Result<BTreeMap<u32, Bytes>, Error> {
+        let stored = match runtime.scan(partition).await {
+            Ok(stored) => stored,
+            Err(RError::PartitionMissing(_)) => return Ok(BTreeMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut latest = (0, BTreeMap::new());
+        for (index, name) in BLOB_NAMES.iter().enumerate() {
+            if !stored.iter().any(|stored| stored == name) {
+                continue;
+            }
+            let blob = runtime.open(partition, name).await?;
+            let result = Self::load(index, &blob, false).await;
+            blob.close().await?;
+            if let Some((timestamp, data)) = result? {
+                if timestamp > latest.0 {
+                    latest = (timestamp, data);
+                }
+            }
+        }
+        Ok(latest.1)
+    }
+
+    /// Load the data stored in `blob`, returning `None` if it is empty, too short, or fails its
+    /// checksum (in which case it is truncated if `repair` is set).
+    async fn load(
+        index: usize,
+        blob: &B,
+        repair: bool,
+    ) -> Result<Option<(u128, BTreeMap<u32, Bytes>)>, Error> {
         // Get blob length
         let len = blob.len().await?;
         if len == 0 {
@@ -101,7 +134,11 @@ impl<B: Blob, E: Clock + Storage<B>> Metadata<B, E> {
 
         // Verify integrity
         if buf.len() < 20 {
-            // Truncate and return none
+            // Truncate (if repairing) and return none
+            if !repair {
+                debug!(blob = index, len = buf.len(), "blob is too short: ignoring");
+                return Ok(None);
+            }
             warn!(
                 blob = index,
                 len = buf.len(),
@@ -117,7 +154,16 @@ impl<B: Blob, E: Clock + Storage<B>> Metadata<B, E> {
         let stored_checksum = u32::from_be_bytes(buf[checksum_index..].try_into().unwrap());
         let computed_checksum = crc32fast::hash(&buf[..checksum_index]);
         if stored_checksum != computed_checksum {
-            // Truncate and return none
+            // Truncate (if repairing) and return none
+            if !repair {
+                debug!(
+                    blob = index,
+                    stored = stored_checksum,
+                    computed = computed_checksum,
+                    "checksum mismatch: ignoring"
+                );
+                return Ok(None);
+            }
             warn!(
                 blob = index,
                 stored = stored_checksum,
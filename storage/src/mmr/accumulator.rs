@@ -0,0 +1,165 @@
+//! An MMR accumulator that retains only the peaks of the MMR.
+//!
+//! An `Accumulator` can continue to add elements and compute the root hash of the MMR it represents,
+//! but it cannot generate proofs since all non-peak nodes have been discarded.
+
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{nodes_needing_parents, PeakIterator};
+use commonware_cryptography::{Digest, Hasher as CHasher};
+
+/// An `Accumulator` viewed as an MMR that supports root hash queries but not proof generation (see
+/// `Mmr::clone_peaks_only`).
+pub type PeakOnlyMmr<H> = Accumulator<H>;
+
+/// Implementation of `Accumulator`.
+pub struct Accumulator<H: CHasher> {
+    hasher: H,
+    // The scheme used to hash leaves, internal nodes, and the root.
+    scheme: Scheme,
+    // The size of the MMR represented by the accumulator.
+    size: u64,
+    // The hashes of the peaks of the MMR, in decreasing order of height.
+    peaks: Vec<Digest>,
+}
+
+impl<H: CHasher> Default for Accumulator<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: CHasher> Accumulator<H> {
+    /// Return a new (empty) `Accumulator`.
+    pub fn new() -> Self {
+        Self {
+            hasher: H::new(),
+            scheme: Scheme::default(),
+            size: 0,
+            peaks: Vec::new(),
+        }
+    }
+
+    /// Return an `Accumulator` for an MMR of the given `size` with the given peak hashes, which must
+    /// be provided in decreasing order of height, hashing with the given `scheme`.
+    pub(crate) fn from_peaks(hasher: H, scheme: Scheme, size: u64, peaks: Vec<Digest>) -> Self {
+        assert_eq!(PeakIterator::new(size).count(), peaks.len());
+        Self {
+            hasher,
+            scheme,
+            size,
+            peaks,
+        }
+    }
+
+    /// Return the size of the MMR represented by the accumulator.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Return the hashes of the peaks of the MMR, in decreasing order of height.
+    pub fn peaks(&self) -> &[Digest] {
+        &self.peaks
+    }
+
+    /// Add an element to the accumulator and return its position in the MMR.
+    pub fn add(&mut self, element: &Digest) -> u64 {
+        let hasher = &mut Hasher::with_scheme(&mut self.hasher, self.scheme.clone());
+        add_to_peaks(hasher, &mut self.size, &mut self.peaks, element)
+    }
+
+    /// Computes the root hash of the MMR.
+    pub fn root_hash(&mut self) -> Digest {
+        let hasher = &mut Hasher::with_scheme(&mut self.hasher, self.scheme.clone());
+        hasher.root_hash(self.size, self.peaks.iter())
+    }
+}
+
+/// Add an element to the MMR of the given `size` represented only by its `peaks` (in decreasing
+/// order of height), updating both and returning the position of the element.
+pub(crate) fn add_to_peaks<H: CHasher>(
+    hasher: &mut Hasher<H>,
+    size: &mut u64,
+    peaks: &mut Vec<Digest>,
+    element: &Digest,
+) -> u64 {
+    let merges = nodes_needing_parents(PeakIterator::new(*size)).len();
+    let element_pos = *size;
+
+    // Compute the leaf hash, then merge it with each peak that needs a new parent.
+    let mut hash = hasher.leaf_hash(element_pos, element);
+    *size += 1;
+    for _ in 0..merges {
+        let sibling = peaks.pop().unwrap();
+        hash = hasher.node_hash(*size, &sibling, &hash);
+        *size += 1;
+    }
+    peaks.push(hash);
+    element_pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Accumulator, PeakOnlyMmr};
+    use crate::mmr::mem::Mmr;
+    use commonware_cryptography::{Digest, Sha256};
+
+    #[test]
+    fn test_accumulator_matches_mmr() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut accumulator = Accumulator::<Sha256>::new();
+        assert_eq!(accumulator.root_hash(), mmr.root_hash());
+        for i in 0u8..100 {
+            let element = Digest::from(vec![i; 32]);
+            assert_eq!(accumulator.add(&element), mmr.add(&element));
+            assert_eq!(accumulator.size(), mmr.size());
+            assert_eq!(accumulator.root_hash(), mmr.root_hash());
+        }
+    }
+
+    #[test]
+    fn test_prune_to_accumulator() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut reference = Mmr::<Sha256>::new();
+        for i in 0u8..11 {
+            let element = Digest::from(vec![i; 32]);
+            mmr.add(&element);
+            reference.add(&element);
+        }
+
+        let mut accumulator = mmr.prune_to_accumulator();
+        assert_eq!(accumulator.size(), 19);
+        assert_eq!(accumulator.peaks().len(), 3);
+        assert_eq!(accumulator.root_hash(), reference.root_hash());
+
+        // the accumulator should keep tracking the root as elements are added
+        for i in 11u8..50 {
+            let element = Digest::from(vec![i; 32]);
+            assert_eq!(accumulator.add(&element), reference.add(&element));
+            assert_eq!(accumulator.root_hash(), reference.root_hash());
+        }
+    }
+
+    #[test]
+    fn test_clone_peaks_only() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut peaks_only: PeakOnlyMmr<Sha256> = mmr.clone_peaks_only();
+        assert_eq!(peaks_only.root_hash(), mmr.root_hash());
+        for i in 0u8..100 {
+            let element = Digest::from(vec![i; 32]);
+            assert_eq!(peaks_only.add(&element), mmr.add(&element));
+            assert_eq!(peaks_only.size(), mmr.size());
+            assert_eq!(peaks_only.root_hash(), mmr.root_hash());
+
+            // A copy taken at any size tracks the MMR from then on
+            let mut copy = mmr.clone_peaks_only();
+            assert_eq!(copy.size(), mmr.size());
+            assert_eq!(copy.root_hash(), mmr.root_hash());
+            let mut reference = mmr.clone_pruned();
+            for j in 0u8..3 {
+                let element = Digest::from(vec![j; 32]);
+                assert_eq!(copy.add(&element), reference.add(&element));
+                assert_eq!(copy.root_hash(), reference.root_hash());
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! `Arbitrary` implementations for property-based testing of MMRs.
+//!
+//! Enabled with the `arbitrary` feature. `ArbitraryMmr` generates a valid MMR of random size
+//! populated with random elements, allowing properties like "every generated proof verifies" to be
+//! expressed without hand-rolling generators.
+
+use crate::mmr::hasher::Scheme;
+use crate::mmr::iterator::PeakIterator;
+use crate::mmr::mem::Mmr;
+use crate::mmr::util::node_count;
+use crate::mmr::verification::Proof;
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+use commonware_cryptography::{Digest, Hasher as CHasher};
+
+/// The maximum number of elements added to an `ArbitraryMmr`.
+pub const MAX_ELEMENTS: usize = 1024;
+
+/// The length of each hash in an arbitrary `Proof`.
+const PROOF_DIGEST_LEN: usize = 32;
+
+impl<'a> Arbitrary<'a> for Proof {
+    /// Generate a structurally valid proof: the size corresponds to a valid MMR and the number of
+    /// hashes (each `PROOF_DIGEST_LEN` bytes) is within the bounds enforced by `sanity_check`. The
+    /// proof is not expected to verify against any particular root.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let leaves = u.int_in_range(1..=u32::MAX as u64)?;
+        let size = node_count(leaves);
+
+        let mut peak_iterator = PeakIterator::new(size);
+        let (_, max_height) = peak_iterator.next().unwrap();
+        let max_hashes = peak_iterator.count() + 2 * max_height as usize;
+        let count = u.int_in_range(0..=max_hashes)?;
+        let mut hashes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut hash = vec![0u8; PROOF_DIGEST_LEN];
+            u.fill_buffer(&mut hash)?;
+            hashes.push(Digest::from(hash));
+        }
+        Ok(Proof {
+            size,
+            hashes,
+            scheme: Scheme::default(),
+        })
+    }
+}
+
+/// An MMR populated with up to `MAX_ELEMENTS` random elements, along with the elements and the
+/// positions at which they were added.
+pub struct ArbitraryMmr<H: CHasher> {
+    pub mmr: Mmr<H>,
+    pub elements: Vec<Digest>,
+    pub positions: Vec<u64>,
+}
+
+impl<'a, H: CHasher> Arbitrary<'a> for ArbitraryMmr<H> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let count = u.int_in_range(0..=MAX_ELEMENTS)?;
+        let mut mmr = Mmr::new();
+        let mut elements = Vec::with_capacity(count);
+        let mut positions = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut element = vec![0u8; H::len()];
+            u.fill_buffer(&mut element)?;
+            let element = Digest::from(element);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        Ok(Self {
+            mmr,
+            elements,
+            positions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArbitraryMmr, PROOF_DIGEST_LEN};
+    use crate::mmr::verification::Proof;
+    use ::arbitrary::{Arbitrary, Unstructured};
+    use commonware_cryptography::Sha256;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    #[test]
+    fn test_arbitrary_proof_is_sane() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut data = vec![0u8; 4096];
+            rng.fill_bytes(&mut data);
+            let mut u = Unstructured::new(&data);
+            let proof = Proof::arbitrary(&mut u).unwrap();
+            assert_eq!(proof.sanity_check(PROOF_DIGEST_LEN), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_mmr_proofs_verify() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut hasher = Sha256::default();
+        for _ in 0..20 {
+            let mut data = vec![0u8; 8192];
+            rng.fill_bytes(&mut data);
+            let mut u = Unstructured::new(&data);
+            let mut generated = ArbitraryMmr::<Sha256>::arbitrary(&mut u).unwrap();
+            assert_eq!(generated.elements.len(), generated.positions.len());
+            let root_hash = generated.mmr.root_hash();
+            for (element, pos) in generated.elements.iter().zip(generated.positions.iter()) {
+                let proof = generated.mmr.proof(*pos).unwrap();
+                assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+            }
+        }
+    }
+}
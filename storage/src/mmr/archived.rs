@@ -0,0 +1,295 @@
+//! Zero-copy access to the nodes of an in-memory `Mmr` archived with `rkyv`.
+//!
+//! `Mmr::into_archived` converts an MMR into a `NodeStore`, whose archived bytes (returned by
+//! `NodeStore::to_bytes`) can be written to a file or shared memory region. `NodeStore::access`
+//! validates those bytes and returns an `ArchivedNodeStore` that serves proofs directly from them,
+//! copying only the hashes included in each proof rather than deserializing every node. If
+//! mutable access is needed, `Mmr::from_archived` deserializes the nodes into a new `Mmr`.
+
+use crate::mmr::hasher::Scheme;
+use crate::mmr::iterator::PeakIterator;
+use crate::mmr::verification::{nodes_required_for_range_proof, Proof};
+use crate::mmr::Error;
+use commonware_cryptography::Digest;
+use rkyv::{rancor, util::AlignedVec, Archive, Deserialize, Serialize};
+
+/// The retained nodes of an `Mmr`, laid out contiguously so that their archived form can be
+/// accessed without deserialization.
+#[derive(Archive, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct NodeStore {
+    // The length of each node.
+    digest_len: u32,
+    // The fingerprint of the scheme the nodes were hashed with (see `Scheme::fingerprint`).
+    scheme: Vec<u8>,
+    // The position of the first node in `nodes`.
+    oldest_retained_pos: u64,
+    // The retained nodes, concatenated in position order.
+    nodes: Vec<u8>,
+    // The positions of pruned nodes that are still required to compute the root hash and add new
+    // elements, and their hashes (concatenated in the same order).
+    pinned_positions: Vec<u64>,
+    pinned_nodes: Vec<u8>,
+}
+
+impl NodeStore {
+    /// Return a store of the given `nodes` (each `digest_len` bytes long, hashed with the scheme
+    /// with fingerprint `scheme`) starting at `oldest_retained_pos`, along with the given pinned
+    /// nodes (keyed by position).
+    pub(crate) fn new<'a>(
+        digest_len: usize,
+        scheme: Vec<u8>,
+        oldest_retained_pos: u64,
+        nodes: impl Iterator<Item = &'a Digest>,
+        pinned: impl Iterator<Item = (&'a u64, &'a Digest)>,
+    ) -> Self {
+        let mut store = Self {
+            digest_len: digest_len as u32,
+            scheme,
+            oldest_retained_pos,
+            nodes: nodes.flat_map(|node| node.iter().copied()).collect(),
+            pinned_positions: Vec::new(),
+            pinned_nodes: Vec::new(),
+        };
+        let mut pinned: Vec<_> = pinned.collect();
+        pinned.sort_unstable_by_key(|(pos, _)| **pos);
+        for (pos, node) in pinned {
+            store.pinned_positions.push(*pos);
+            store.pinned_nodes.extend_from_slice(node);
+        }
+        store
+    }
+
+    /// Return the archived bytes of the store.
+    pub fn to_bytes(&self) -> AlignedVec {
+        rkyv::to_bytes::<rancor::Error>(self).expect("failed to archive node store")
+    }
+
+    /// Validate `bytes` (as returned by `to_bytes`) and return the store they archive, without
+    /// copying any nodes.
+    ///
+    /// Returns `Error::InvalidArchive` if the bytes are not a valid archive of a store, or if the
+    /// nodes it holds do not form a valid MMR.
+    pub fn access(bytes: &[u8]) -> Result<&ArchivedNodeStore, Error> {
+        let archived = rkyv::access::<ArchivedNodeStore, rancor::Error>(bytes)
+            .map_err(|_| Error::InvalidArchive)?;
+        let digest_len = archived.digest_len.to_native() as usize;
+        if digest_len == 0
+            || archived.nodes.len() % digest_len != 0
+            || archived.pinned_nodes.len() != archived.pinned_positions.len() * digest_len
+        {
+            return Err(Error::InvalidArchive);
+        }
+        let oldest_retained_pos = archived.oldest_retained_pos.to_native();
+        let size = oldest_retained_pos.checked_add((archived.nodes.len() / digest_len) as u64);
+        match size {
+            Some(size) if PeakIterator::check_validity(size) => Ok(archived),
+            _ => Err(Error::InvalidArchive),
+        }
+    }
+
+    /// Deserialize an archived store.
+    pub fn from_archived(archived: &ArchivedNodeStore) -> Self {
+        rkyv::deserialize::<Self, rancor::Error>(archived)
+            .expect("failed to deserialize node store")
+    }
+}
+
+impl ArchivedNodeStore {
+    /// Return the length of each node.
+    pub fn digest_len(&self) -> usize {
+        self.digest_len.to_native() as usize
+    }
+
+    /// Return the fingerprint of the scheme the nodes were hashed with.
+    pub(crate) fn scheme(&self) -> &[u8] {
+        self.scheme.as_slice()
+    }
+
+    /// Return the total number of nodes in the MMR.
+    pub fn size(&self) -> u64 {
+        self.oldest_retained_pos() + (self.nodes.len() / self.digest_len()) as u64
+    }
+
+    /// Return the position of the oldest retained node.
+    pub fn oldest_retained_pos(&self) -> u64 {
+        self.oldest_retained_pos.to_native()
+    }
+
+    /// Return the hash of the node at `pos`, or `None` if it has been pruned (or doesn't exist).
+    pub fn get_node(&self, pos: u64) -> Option<&[u8]> {
+        let digest_len = self.digest_len();
+        if pos < self.oldest_retained_pos() {
+            let index = self
+                .pinned_positions
+                .iter()
+                .position(|pinned| pinned.to_native() == pos)?;
+            return Some(&self.pinned_nodes[index * digest_len..(index + 1) * digest_len]);
+        }
+        let start = (pos - self.oldest_retained_pos()) as usize * digest_len;
+        self.nodes.get(start..start + digest_len)
+    }
+
+    /// Return an inclusion proof for the specified element of the archived MMR, which was built with
+    /// `scheme`.
+    ///
+    /// Returns `Error::ElementPruned` if any node required by the proof has been pruned.
+    pub fn proof(&self, element_pos: u64, scheme: Scheme) -> Result<Proof, Error> {
+        self.range_proof(element_pos, element_pos, scheme)
+    }
+
+    /// Return an inclusion proof for the specified range of elements of the archived MMR, which was
+    /// built with `scheme`. The range is inclusive of both endpoints. Only the hashes included in
+    /// the proof are copied.
+    ///
+    /// Returns `Error::ElementPruned` if any node required by the proof has been pruned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range extends beyond the size of the MMR.
+    pub fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+        scheme: Scheme,
+    ) -> Result<Proof, Error> {
+        let size = self.size();
+        assert!(end_element_pos < size, "range exceeds size");
+        let hashes = nodes_required_for_range_proof(size, start_element_pos, end_element_pos)
+            .into_iter()
+            .map(|pos| {
+                self.get_node(pos)
+                    .map(Digest::copy_from_slice)
+                    .ok_or(Error::ElementPruned(pos))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Proof {
+            size,
+            hashes,
+            scheme,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmr::mem::Mmr;
+    use commonware_cryptography::{Hasher as CHasher, Sha256};
+
+    #[test]
+    fn test_archived() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..100 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let root = mmr.root_hash();
+        let proofs: Vec<_> = positions
+            .iter()
+            .map(|pos| mmr.proof(*pos).unwrap())
+            .collect();
+        let range_proof = mmr.range_proof(positions[10], positions[60]).unwrap();
+
+        // Proofs served from the archived bytes match those of the original MMR
+        let store = mmr.into_archived();
+        let bytes = store.to_bytes();
+        let archived = NodeStore::access(&bytes).unwrap();
+        assert_eq!(archived.digest_len(), Sha256::len());
+        assert_eq!(archived.oldest_retained_pos(), 0);
+        for (pos, proof) in positions.iter().zip(proofs.iter()) {
+            assert_eq!(&archived.proof(*pos, Scheme::default()).unwrap(), proof);
+        }
+        assert_eq!(
+            archived
+                .range_proof(positions[10], positions[60], Scheme::default())
+                .unwrap(),
+            range_proof
+        );
+        assert_eq!(NodeStore::from_archived(archived), store);
+
+        // Deserializing the archive restores the MMR
+        let mut restored = Mmr::<Sha256>::from_archived(archived, Scheme::default()).unwrap();
+        assert_eq!(restored.root_hash(), root);
+        restored.add(&elements[0]);
+        let mut mmr = Mmr::<Sha256>::from(elements.clone());
+        mmr.add(&elements[0]);
+        assert_eq!(restored.root_hash(), mmr.root_hash());
+
+        // Pinned nodes are archived alongside retained ones
+        let mut pruned = mmr.clone_pruned();
+        let pos = pruned.add(&elements[1]);
+        assert_eq!(mmr.add(&elements[1]), pos);
+        let proof = pruned.proof(pos).unwrap();
+        let bytes = pruned.into_archived().to_bytes();
+        let archived = NodeStore::access(&bytes).unwrap();
+        assert_eq!(archived.proof(pos, Scheme::default()).unwrap(), proof);
+        assert!(matches!(
+            archived.proof(positions[0], Scheme::default()),
+            Err(Error::ElementPruned(_))
+        ));
+        let mut restored = Mmr::<Sha256>::from_archived(archived, Scheme::default()).unwrap();
+        assert_eq!(restored.proof(pos).unwrap(), proof);
+        assert_eq!(restored.add(&elements[2]), mmr.add(&elements[2]));
+        assert_eq!(restored.root_hash(), mmr.root_hash());
+
+        // Archives that are malformed, don't form a valid MMR, or have mismatched digest lengths are
+        // rejected
+        assert!(matches!(
+            NodeStore::access(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidArchive)
+        ));
+        let invalid = NodeStore {
+            digest_len: 32,
+            scheme: Scheme::default().fingerprint::<Sha256>(),
+            oldest_retained_pos: 0,
+            nodes: vec![0u8; 2 * 32],
+            pinned_positions: Vec::new(),
+            pinned_nodes: Vec::new(),
+        };
+        assert!(matches!(
+            NodeStore::access(&invalid.to_bytes()),
+            Err(Error::InvalidArchive)
+        ));
+        let short = NodeStore {
+            digest_len: 16,
+            nodes: vec![0u8; 16],
+            ..invalid
+        };
+        let bytes = short.to_bytes();
+        let archived = NodeStore::access(&bytes).unwrap();
+        assert!(matches!(
+            Mmr::<Sha256>::from_archived(archived, Scheme::default()),
+            Err(Error::InvalidDigestLength(32, 16))
+        ));
+    }
+
+    #[test]
+    fn test_archived_scheme() {
+        let mut mmr = Mmr::<Sha256>::new_keyed(b"commonware");
+        for i in 0u8..20 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        let root = mmr.root_hash();
+        let bytes = mmr.into_archived().to_bytes();
+        let archived = NodeStore::access(&bytes).unwrap();
+
+        // The archive is restored only under the scheme (and key) it was built with
+        let scheme = Scheme::keyed(b"commonware");
+        let mut restored = Mmr::<Sha256>::from_archived(archived, scheme.clone()).unwrap();
+        assert_eq!(restored.scheme(), &scheme);
+        assert_eq!(restored.root_hash(), root);
+        assert_eq!(
+            archived.proof(5, scheme.clone()).unwrap(),
+            restored.proof(5).unwrap()
+        );
+        for scheme in [Scheme::default(), Scheme::keyed(b"other")] {
+            assert!(matches!(
+                Mmr::<Sha256>::from_archived(archived, scheme),
+                Err(Error::SchemeMismatch)
+            ));
+        }
+    }
+}
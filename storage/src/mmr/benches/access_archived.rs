@@ -0,0 +1,76 @@
+use criterion::{criterion_group, Criterion};
+
+#[cfg(feature = "rkyv")]
+fn bench_access_archived(c: &mut Criterion) {
+    use commonware_cryptography::{Digest, Hasher, Sha256};
+    use commonware_storage::mmr::{archived::NodeStore, mem::Mmr, Scheme};
+    use rand::{rngs::StdRng, seq::SliceRandom, RngCore, SeedableRng};
+
+    const PROOFS: usize = 100;
+
+    for n in [10_000, 100_000, 1_000_000] {
+        // Populate MMR
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::with_capacity(n);
+        let mut sampler = StdRng::seed_from_u64(0);
+        for _ in 0..n {
+            let mut digest = vec![0u8; Sha256::len()];
+            sampler.fill_bytes(&mut digest);
+            let element = Digest::from(digest);
+            positions.push(mmr.add(&element));
+        }
+        let bytes = mmr.into_archived().to_bytes();
+
+        // Select elements to prove
+        let selected: Vec<u64> = positions
+            .choose_multiple(&mut sampler, PROOFS)
+            .copied()
+            .collect();
+
+        // Generate proofs directly from the archived bytes
+        c.bench_function(
+            &format!(
+                "{}/n={} proofs={} method=zero_copy",
+                module_path!(),
+                n,
+                PROOFS
+            ),
+            |b| {
+                b.iter(|| {
+                    let archived = NodeStore::access(&bytes).unwrap();
+                    for pos in selected.iter() {
+                        archived.proof(*pos, Scheme::default()).unwrap();
+                    }
+                })
+            },
+        );
+
+        // Generate proofs after deserializing the archived bytes
+        c.bench_function(
+            &format!(
+                "{}/n={} proofs={} method=deserialized",
+                module_path!(),
+                n,
+                PROOFS
+            ),
+            |b| {
+                b.iter(|| {
+                    let archived = NodeStore::access(&bytes).unwrap();
+                    let mmr = Mmr::<Sha256>::from_archived(archived, Scheme::default()).unwrap();
+                    for pos in selected.iter() {
+                        mmr.proof(*pos).unwrap();
+                    }
+                })
+            },
+        );
+    }
+}
+
+#[cfg(not(feature = "rkyv"))]
+fn bench_access_archived(_: &mut Criterion) {}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_access_archived
+}
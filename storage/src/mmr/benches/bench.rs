@@ -1,13 +1,21 @@
 use criterion::criterion_main;
 
+mod access_archived;
 mod append;
 mod append_additional;
+mod build;
+mod prove_all_elements;
 mod prove_many_elements;
 mod prove_single_element;
+mod verify_batch;
 
 criterion_main!(
+    access_archived::benches,
     append::benches,
     append_additional::benches,
+    build::benches,
+    prove_all_elements::benches,
     prove_many_elements::benches,
     prove_single_element::benches,
+    verify_batch::benches,
 );
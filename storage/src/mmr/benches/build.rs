@@ -0,0 +1,43 @@
+use commonware_cryptography::{Digest, Hasher, Sha256};
+use commonware_storage::mmr::mem::Mmr;
+use criterion::{criterion_group, Criterion};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+fn bench_build(c: &mut Criterion) {
+    for n in [100_000, 1_000_000] {
+        // Generate elements
+        let mut sampler = StdRng::seed_from_u64(0);
+        let elements: Vec<_> = (0..n)
+            .map(|_| {
+                let mut digest = vec![0u8; Sha256::len()];
+                sampler.fill_bytes(&mut digest);
+                Digest::from(digest)
+            })
+            .collect();
+
+        // Build the MMR sequentially
+        c.bench_function(
+            &format!("{}/n={} method=sequential", module_path!(), n),
+            |b| b.iter(|| Mmr::<Sha256>::build_from(&elements)),
+        );
+
+        // Build the MMR in parallel
+        for threads in [2, 4, 8] {
+            c.bench_function(
+                &format!(
+                    "{}/n={} method=parallel threads={}",
+                    module_path!(),
+                    n,
+                    threads
+                ),
+                |b| b.iter(|| Mmr::<Sha256>::par_build_from(&elements, threads)),
+            );
+        }
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_build
+}
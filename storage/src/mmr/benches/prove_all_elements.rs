@@ -0,0 +1,43 @@
+use commonware_cryptography::{Digest, Hasher, Sha256};
+use commonware_storage::mmr::mem::Mmr;
+use criterion::{criterion_group, Criterion};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+fn bench_prove_all_elements(c: &mut Criterion) {
+    for n in [10_000, 100_000] {
+        // Populate MMR
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::with_capacity(n);
+        let mut sampler = StdRng::seed_from_u64(0);
+        for _ in 0..n {
+            let mut digest = vec![0u8; Sha256::len()];
+            sampler.fill_bytes(&mut digest);
+            let element = Digest::from(digest);
+            positions.push(mmr.add(&element));
+        }
+
+        // Generate a proof for every element independently
+        c.bench_function(&format!("{}/n={} method=each", module_path!(), n), |b| {
+            b.iter(|| {
+                for pos in positions.iter() {
+                    criterion::black_box(mmr.proof(*pos).unwrap());
+                }
+            })
+        });
+
+        // Generate a proof for every element in a single walk
+        c.bench_function(&format!("{}/n={} method=all", module_path!(), n), |b| {
+            b.iter(|| {
+                for item in mmr.all_proofs() {
+                    criterion::black_box(item);
+                }
+            })
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_prove_all_elements
+}
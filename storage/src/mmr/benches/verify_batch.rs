@@ -0,0 +1,85 @@
+use commonware_cryptography::{Digest, Hasher, Sha256};
+use commonware_storage::mmr::mem::Mmr;
+use criterion::{criterion_group, Criterion};
+use rand::{rngs::StdRng, seq::SliceRandom, RngCore, SeedableRng};
+
+const BATCH_SIZE: usize = 256;
+
+fn bench_verify_batch(c: &mut Criterion) {
+    for n in [10_000, 100_000] {
+        // Populate MMR
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::with_capacity(n);
+        let mut sampler = StdRng::seed_from_u64(0);
+        for _ in 0..n {
+            let mut digest = vec![0u8; Sha256::len()];
+            sampler.fill_bytes(&mut digest);
+            let element = Digest::from(digest);
+            let pos = mmr.add(&element);
+            elements.push((pos, element));
+        }
+        let root_hash = mmr.root_hash();
+
+        // Select a batch of proofs to verify
+        let batch: Vec<_> = elements
+            .choose_multiple(&mut sampler, BATCH_SIZE)
+            .map(|(pos, element)| {
+                (
+                    mmr.proof(*pos).unwrap(),
+                    element.clone(),
+                    *pos,
+                    root_hash.clone(),
+                )
+            })
+            .collect();
+
+        // Verify the batch sequentially
+        c.bench_function(
+            &format!(
+                "{}/n={} batch={} method=sequential",
+                module_path!(),
+                n,
+                BATCH_SIZE
+            ),
+            |b| {
+                let mut hasher = Sha256::new();
+                b.iter(|| {
+                    for (proof, element, pos, root_hash) in batch.iter() {
+                        assert!(proof.verify_element_inclusion(
+                            element,
+                            *pos,
+                            root_hash,
+                            &mut hasher
+                        ));
+                    }
+                })
+            },
+        );
+
+        // Verify the batch in parallel
+        #[cfg(feature = "rayon")]
+        c.bench_function(
+            &format!(
+                "{}/n={} batch={} method=parallel",
+                module_path!(),
+                n,
+                BATCH_SIZE
+            ),
+            |b| {
+                let hasher = Sha256::new();
+                b.iter(|| {
+                    let results = commonware_storage::mmr::verification::verify_batch_parallel(
+                        &batch, &hasher,
+                    );
+                    assert!(results.into_iter().all(|verified| verified));
+                })
+            },
+        );
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_verify_batch
+}
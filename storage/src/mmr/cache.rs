@@ -0,0 +1,293 @@
+//! A bounded cache of proof verification results.
+//!
+//! Verifying a proof requires hashing every element and proof node along the way to the root. When
+//! the same proof is received repeatedly (e.g. gossiped by several peers), a `VerificationCache`
+//! remembers the result of verifying it so the hashing is only done once. Results are keyed by the
+//! serialized proof along with everything else that determines the outcome (the elements, their
+//! positions, the root hash, the hasher, and the scheme of the proof, which isn't serialized), so
+//! the same proof presented for a different element, against a different root, or hashed another
+//! way is always verified afresh.
+
+use crate::mmr::verification::Proof;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    hasher: TypeId,
+    scheme: Vec<u8>,
+    proof: Vec<u8>,
+    elements: Vec<Digest>,
+    start_element_pos: u64,
+    end_element_pos: u64,
+    root_hash: Digest,
+}
+
+/// Memoizes the results of verifying proofs, evicting the least recently used result once
+/// `capacity` results are held.
+pub struct VerificationCache {
+    capacity: usize,
+    // The result of each cached verification, along with the tick at which it was last used.
+    results: HashMap<Key, (bool, u64)>,
+    // Each cached key indexed by the tick at which it was last used, oldest first.
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl VerificationCache {
+    /// Return a new, empty cache holding at most `capacity` results.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        Self {
+            capacity,
+            results: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return the maximum number of results held by the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Return the number of results currently held by the cache.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Return true if the cache holds no results.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Return the number of verifications answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Return the number of verifications that had to be computed.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Discard every cached result. The hit and miss counters are unaffected.
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.recency.clear();
+    }
+
+    /// Return the result of `proof.verify_element_inclusion` for the given arguments, computing it
+    /// only if it isn't already cached.
+    pub fn verify_element_inclusion<H: CHasher + 'static>(
+        &mut self,
+        proof: &Proof,
+        element: &Digest,
+        element_pos: u64,
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        self.verify_range_inclusion(
+            proof,
+            &[element.clone()],
+            element_pos,
+            element_pos,
+            root_hash,
+            hasher,
+        )
+    }
+
+    /// Return the result of `proof.verify_range_inclusion` for the given arguments, computing it
+    /// only if it isn't already cached.
+    pub fn verify_range_inclusion<H: CHasher + 'static>(
+        &mut self,
+        proof: &Proof,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        let key = Key {
+            hasher: TypeId::of::<H>(),
+            scheme: proof.scheme.fingerprint::<H>(),
+            proof: proof.to_bytes(),
+            elements: elements.to_vec(),
+            start_element_pos,
+            end_element_pos,
+            root_hash: root_hash.clone(),
+        };
+        self.tick += 1;
+        if let Some((result, last_used)) = self.results.get_mut(&key) {
+            let key = self.recency.remove(last_used).unwrap();
+            self.recency.insert(self.tick, key);
+            *last_used = self.tick;
+            self.hits += 1;
+            return *result;
+        }
+
+        self.misses += 1;
+        let result = proof.verify_range_inclusion(
+            elements,
+            start_element_pos,
+            end_element_pos,
+            root_hash,
+            hasher,
+        );
+        if self.results.len() == self.capacity {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.results.remove(&oldest);
+        }
+        self.results.insert(key.clone(), (result, self.tick));
+        self.recency.insert(self.tick, key);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmr::mem::Mmr;
+    use crate::mmr::Scheme;
+    use commonware_cryptography::{Blake3, Sha256};
+
+    #[test]
+    fn test_verification_cache() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..20 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let root = mmr.root_hash();
+        let mut hasher = Sha256::default();
+        let mut cache = VerificationCache::new(2);
+        assert!(cache.is_empty());
+
+        // a repeated verification is answered from the cache
+        let proof = mmr.proof(positions[3]).unwrap();
+        for _ in 0..3 {
+            assert!(cache.verify_element_inclusion(
+                &proof,
+                &elements[3],
+                positions[3],
+                &root,
+                &mut hasher
+            ));
+        }
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (2, 1, 1));
+
+        // the same proof against a different root, or for a different element, is re-verified
+        let other_root = Digest::from(vec![0u8; 32]);
+        assert!(!cache.verify_element_inclusion(
+            &proof,
+            &elements[3],
+            positions[3],
+            &other_root,
+            &mut hasher
+        ));
+        assert!(!cache.verify_element_inclusion(
+            &proof,
+            &elements[4],
+            positions[3],
+            &root,
+            &mut hasher
+        ));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (2, 3, 2));
+
+        // failed verifications are cached too
+        assert!(!cache.verify_element_inclusion(
+            &proof,
+            &elements[4],
+            positions[3],
+            &root,
+            &mut hasher
+        ));
+        assert_eq!((cache.hits(), cache.misses()), (3, 3));
+
+        // the original result was evicted as the least recently used, so it is recomputed (evicting
+        // the result against `other_root`) while the more recently used failure remains cached
+        assert!(cache.verify_element_inclusion(
+            &proof,
+            &elements[3],
+            positions[3],
+            &root,
+            &mut hasher
+        ));
+        assert_eq!((cache.hits(), cache.misses()), (3, 4));
+        assert!(!cache.verify_element_inclusion(
+            &proof,
+            &elements[4],
+            positions[3],
+            &root,
+            &mut hasher
+        ));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (4, 4, 2));
+
+        // range proofs are cached alongside element proofs
+        let proof = mmr.range_proof(positions[5], positions[9]).unwrap();
+        for _ in 0..2 {
+            assert!(cache.verify_range_inclusion(
+                &proof,
+                &elements[5..=9],
+                positions[5],
+                positions[9],
+                &root,
+                &mut hasher
+            ));
+        }
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (5, 5, 2));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.capacity(), 2);
+    }
+
+    #[test]
+    fn test_verification_cache_scheme() {
+        let elements: Vec<Digest> = (0u8..11).map(|i| Digest::from(vec![i; 32])).collect();
+        let mut tagged = Mmr::<Sha256>::new();
+        let mut untagged = Mmr::<Sha256>::new_with_scheme(Scheme::Untagged);
+        for element in elements.iter() {
+            tagged.add(element);
+            untagged.add(element);
+        }
+        let root = tagged.root_hash();
+        let mut hasher = Sha256::default();
+        let mut cache = VerificationCache::new(4);
+
+        // the same proof bytes verified under each scheme are cached separately
+        let proof = tagged.proof(0).unwrap();
+        assert!(cache.verify_element_inclusion(&proof, &elements[0], 0, &root, &mut hasher));
+        for scheme in [Scheme::Untagged, Scheme::keyed(b"key")] {
+            let mut other = proof.clone();
+            other.scheme = scheme;
+            assert_eq!(other.to_bytes(), proof.to_bytes());
+            assert!(!cache.verify_element_inclusion(&other, &elements[0], 0, &root, &mut hasher));
+        }
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (0, 3, 3));
+
+        // and a valid untagged proof is still accepted against its own root
+        let proof = untagged.proof(0).unwrap();
+        let root = untagged.root_hash();
+        assert!(cache.verify_element_inclusion(&proof, &elements[0], 0, &root, &mut hasher));
+
+        // the same bytes verified with another hasher are re-verified
+        let proof = tagged.proof(0).unwrap();
+        let root = tagged.root_hash();
+        let mut other_hasher = Blake3::default();
+        assert!(!cache.verify_element_inclusion(&proof, &elements[0], 0, &root, &mut other_hasher));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (0, 5, 4));
+    }
+}
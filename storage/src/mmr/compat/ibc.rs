@@ -0,0 +1,199 @@
+//! ICS-23 (IBC) commitment proofs of MMR inclusion.
+//!
+//! The Inter-Blockchain Communication protocol verifies state with ICS-23 `CommitmentProof`s,
+//! whose `ExistenceProof` hashes a key and value into a leaf with a `LeafOp`, then applies a path of
+//! `InnerOp`s, each hashing `prefix || child || suffix`. `Mmr::encode_for_ibc` maps an MMR inclusion
+//! proof onto this form exactly (for the SHA-256 MMR):
+//!
+//! * The key is the position of the element (big-endian `u64`) and the value is the element, so the
+//!   `LeafOp` (with the leaf tag as its prefix and no prehashing or length prefixing) produces the
+//!   MMR's position-bound leaf hash `Hash(0x00 || pos || element)`.
+//! * Each node along the path from the leaf to its peak (see `Mmr::to_merkle_proof`) becomes an
+//!   `InnerOp` producing `Hash(0x01 || parent_pos || left || right)`: the node tag, the parent's
+//!   position (and the left sibling, if any) in the prefix, and the right sibling (if any) in the
+//!   suffix.
+//! * A final `InnerOp` produces the root hash `Hash(0x02 || size || peaks...)` from the element's
+//!   peak: the root tag, the size, and the peaks before it in the prefix, and the peaks after it in
+//!   the suffix.
+//!
+//! Under `Scheme::Untagged`, the tags are omitted from every prefix (so the `LeafOp` prefix is
+//! empty), and under `Scheme::Keyed` each tag is followed by the length-prefixed key.
+//!
+//! The MMR is not a fixed-arity tree with fixed-length node encodings, so no ICS-23 `ProofSpec`
+//! describes it: inner prefixes vary in length (9 bytes for a left child, 9 plus the digest length
+//! for a right child, and any multiple of the digest length plus 9 for the root, one less under
+//! `Scheme::Untagged` and 8 more plus the key length under `Scheme::Keyed`), the root step
+//! combines any number of peaks, and there is no empty child. The `InnerSpec` (`child_order`,
+//! `child_size`, `min_prefix_length`, `max_prefix_length`, `empty_child`) and the `max_depth` and
+//! `min_depth` of a spec are therefore left unset, and proofs must be verified by recomputing the
+//! root from the `ExistenceProof` rather than against a spec. Only the `Exist` variant of
+//! `CommitmentProof` is produced (the MMR has no non-existence or batch proofs).
+
+use crate::mmr::compat::merkle::Direction;
+use crate::mmr::hasher::{Scheme, LEAF_TAG, NODE_TAG, ROOT_TAG};
+use crate::mmr::iterator::PeakIterator;
+use crate::mmr::mem::Mmr;
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Sha256};
+use ics23::{commitment_proof, CommitmentProof, ExistenceProof, HashOp, InnerOp, LeafOp, LengthOp};
+
+impl Mmr<Sha256> {
+    /// Return an ICS-23 `CommitmentProof` proving that `element` appears at position `element_pos`
+    /// within the MMR, which reproduces the MMR's root hash when applied (see the module
+    /// documentation for the mapping).
+    ///
+    /// Returns `Error::InvalidPos` if `element_pos` isn't the position of a leaf in the MMR, and
+    /// `Error::ElementPruned` if any node required by the proof has been pruned.
+    pub fn encode_for_ibc(
+        &self,
+        element_pos: u64,
+        element: &Digest,
+    ) -> Result<CommitmentProof, Error> {
+        let proof = self.to_merkle_proof(element_pos)?;
+        let tag = |tag: u8| match &proof.scheme {
+            Scheme::Tagged => vec![tag],
+            Scheme::Untagged => Vec::new(),
+            Scheme::Keyed(key) => {
+                let mut prefix = vec![tag];
+                prefix.extend_from_slice(&(key.len() as u64).to_be_bytes());
+                prefix.extend_from_slice(key);
+                prefix
+            }
+        };
+        let inner = |prefix: Vec<u8>, suffix: Vec<u8>| InnerOp {
+            hash: HashOp::Sha256 as i32,
+            prefix,
+            suffix,
+        };
+
+        // Hash each node along the path with its sibling, tracking its position
+        let mut path = Vec::with_capacity(proof.path.len() + 1);
+        let mut pos = element_pos;
+        for (height, (sibling, direction)) in proof.path.iter().enumerate() {
+            let parent_pos = match direction {
+                Direction::Right => pos + (2 << height),
+                Direction::Left => pos + 1,
+            };
+            let mut prefix = tag(NODE_TAG);
+            prefix.extend_from_slice(&parent_pos.to_be_bytes());
+            let mut suffix = Vec::new();
+            match direction {
+                Direction::Right => suffix.extend_from_slice(sibling),
+                Direction::Left => prefix.extend_from_slice(sibling),
+            }
+            path.push(inner(prefix, suffix));
+            pos = parent_pos;
+        }
+        debug_assert!(PeakIterator::new(proof.size).any(|(peak_pos, _)| peak_pos == pos));
+
+        // Combine the peak with the size and the other peaks
+        let (before, after) = proof.peaks.split_at(proof.peak_index);
+        let mut prefix = tag(ROOT_TAG);
+        prefix.extend_from_slice(&proof.size.to_be_bytes());
+        for peak in before {
+            prefix.extend_from_slice(peak);
+        }
+        let suffix = after.iter().flat_map(|peak| peak.iter().copied()).collect();
+        path.push(inner(prefix, suffix));
+
+        let exist = ExistenceProof {
+            key: element_pos.to_be_bytes().to_vec(),
+            value: element.to_vec(),
+            leaf: Some(LeafOp {
+                hash: HashOp::Sha256 as i32,
+                prehash_key: HashOp::NoHash as i32,
+                prehash_value: HashOp::NoHash as i32,
+                length: LengthOp::NoPrefix as i32,
+                prefix: tag(LEAF_TAG),
+            }),
+            path,
+        };
+        Ok(CommitmentProof {
+            proof: Some(commitment_proof::Proof::Exist(exist)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_cryptography::Hasher as CHasher;
+
+    /// Provides the hash functions used by the ICS-23 verifier, of which the SHA-256 MMR only
+    /// requires SHA-256.
+    struct HostFunctions;
+
+    impl ics23::HostFunctionsProvider for HostFunctions {
+        fn sha2_256(message: &[u8]) -> [u8; 32] {
+            let mut hasher = Sha256::default();
+            hasher.update(message);
+            hasher.finalize().as_ref().try_into().unwrap()
+        }
+
+        fn sha2_512(_: &[u8]) -> [u8; 64] {
+            unreachable!("SHA-512 is not used by the MMR proof spec")
+        }
+
+        fn sha2_512_truncated(_: &[u8]) -> [u8; 32] {
+            unreachable!("SHA-512/256 is not used by the MMR proof spec")
+        }
+
+        fn keccak_256(_: &[u8]) -> [u8; 32] {
+            unreachable!("Keccak-256 is not used by the MMR proof spec")
+        }
+
+        fn ripemd160(_: &[u8]) -> [u8; 20] {
+            unreachable!("RIPEMD-160 is not used by the MMR proof spec")
+        }
+
+        fn blake2b_512(_: &[u8]) -> [u8; 64] {
+            unreachable!("BLAKE2b-512 is not used by the MMR proof spec")
+        }
+
+        fn blake2s_256(_: &[u8]) -> [u8; 32] {
+            unreachable!("BLAKE2s-256 is not used by the MMR proof spec")
+        }
+
+        fn blake3(_: &[u8]) -> [u8; 32] {
+            unreachable!("BLAKE3 is not used by the MMR proof spec")
+        }
+    }
+
+    /// Recompute the root committed to by an existence proof with the ICS-23 verifier.
+    fn calculate_root(proof: &ExistenceProof) -> Digest {
+        Digest::from(ics23::calculate_existence_root::<HostFunctions>(proof).unwrap())
+    }
+
+    #[test]
+    fn test_encode_for_ibc() {
+        for scheme in [Scheme::Tagged, Scheme::Untagged, Scheme::keyed(b"chain")] {
+            let mut mmr = Mmr::<Sha256>::new_with_scheme(scheme);
+            let elements: Vec<_> = (0u8..49).map(|i| Digest::from(vec![i; 32])).collect();
+            let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+            let root_hash = mmr.root_hash();
+            for (element, pos) in elements.iter().zip(positions.iter()) {
+                let proof = mmr.encode_for_ibc(*pos, element).unwrap();
+                let Some(commitment_proof::Proof::Exist(exist)) = proof.proof else {
+                    panic!("expected existence proof");
+                };
+                assert_eq!(exist.key, pos.to_be_bytes().to_vec());
+                assert_eq!(calculate_root(&exist), root_hash);
+
+                // The proof doesn't hold for another element
+                let mut forged = exist.clone();
+                forged.value = vec![0xff; 32];
+                assert_ne!(calculate_root(&forged), root_hash);
+            }
+        }
+
+        let mut mmr = Mmr::<Sha256>::new();
+        let elements: Vec<_> = (0u8..3).map(|i| Digest::from(vec![i; 32])).collect();
+        for element in elements.iter() {
+            mmr.add(element);
+        }
+        assert_eq!(
+            mmr.encode_for_ibc(2, &elements[0]).err(),
+            Some(Error::InvalidPos(2))
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! A binary Merkle path representation of MMR inclusion proofs.
+//!
+//! Systems such as OpenTimestamps and Certificate Transparency represent inclusion proofs as a leaf
+//! hash followed by a path of sibling hashes, each tagged with the side it is hashed on. A
+//! `MerkleProof` (produced by `Mmr::to_merkle_proof`) expresses an MMR inclusion proof in this form:
+//! the path leads from the leaf to the peak containing it, hashing with the same position-bound
+//! `leaf_hash`/`node_hash` domain separation as the MMR, and the remaining peaks are then combined
+//! with the recomputed peak (and the MMR size) to reconstruct the MMR root hash.
+
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{pos_to_height, PeakIterator};
+use commonware_cryptography::{Digest, Hasher as CHasher};
+
+/// The side a sibling hash is on when hashed with the node computed so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The sibling is the left child of their parent.
+    Left,
+    /// The sibling is the right child of their parent.
+    Right,
+}
+
+/// An inclusion proof for a single element as a path of sibling hashes from its leaf to its peak.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// The size of the MMR the proof was generated against.
+    pub size: u64,
+    /// The position of the element's leaf.
+    pub element_pos: u64,
+    /// The hash of the element's leaf (see `MerkleProof::leaf_hash`).
+    pub leaf_hash: Digest,
+    /// The sibling hashes from the leaf up to (but excluding) its peak.
+    pub path: Vec<(Digest, Direction)>,
+    /// The hashes of every other peak of the MMR, in decreasing order of height.
+    pub peaks: Vec<Digest>,
+    /// The index of the peak containing the element among all peaks.
+    pub peak_index: usize,
+    /// The scheme the MMR hashes with.
+    pub scheme: Scheme,
+}
+
+impl MerkleProof {
+    /// Computes the hash of the leaf representing `element` at the proven position under the
+    /// proof's scheme, which must match the proof's `leaf_hash` for the proof to be one of
+    /// `element`.
+    pub fn leaf_hash<H: CHasher>(&self, element: &Digest, hasher: &mut H) -> Digest {
+        Hasher::with_scheme(hasher, self.scheme.clone()).leaf_hash(self.element_pos, element)
+    }
+
+    /// Return true if the path leads from the leaf at `element_pos` to the peak at `peak_index` of
+    /// an MMR of the proof's size, and reconstructs a peak hash that, combined with the other
+    /// peaks, produces `root_hash`.
+    ///
+    /// Proofs starting from a node other than a leaf are rejected, as under `Scheme::Untagged` the
+    /// hash of a parent is also the leaf hash of the concatenation of its children.
+    pub fn verify<H: CHasher>(&self, root_hash: &Digest, hasher: &mut H) -> bool {
+        if self.peak_index > self.peaks.len() || pos_to_height(self.element_pos) != 0 {
+            return false;
+        }
+        if !PeakIterator::check_validity(self.size) {
+            return false;
+        }
+        let peak_positions: Vec<u64> = PeakIterator::new(self.size).map(|(pos, _)| pos).collect();
+        if peak_positions.len() != self.peaks.len() + 1 {
+            return false;
+        }
+        let mut hasher = Hasher::with_scheme(hasher, self.scheme.clone());
+        let mut pos = self.element_pos;
+        let mut hash = self.leaf_hash.clone();
+        for (height, (sibling, direction)) in self.path.iter().enumerate() {
+            // A left child's parent follows its right sibling's subtree, while a right child's
+            // parent immediately follows it
+            let parent_pos = match direction {
+                Direction::Right => 1u64
+                    .checked_shl(height as u32 + 1)
+                    .and_then(|offset| pos.checked_add(offset)),
+                Direction::Left => pos.checked_add(1),
+            };
+            let Some(parent_pos) = parent_pos else {
+                return false;
+            };
+            hash = match direction {
+                Direction::Right => hasher.node_hash(parent_pos, &hash, sibling),
+                Direction::Left => hasher.node_hash(parent_pos, sibling, &hash),
+            };
+            pos = parent_pos;
+        }
+        if pos != peak_positions[self.peak_index] {
+            return false;
+        }
+        let peaks = self.peaks[..self.peak_index]
+            .iter()
+            .chain(std::iter::once(&hash))
+            .chain(self.peaks[self.peak_index..].iter());
+        hasher.root_hash(self.size, peaks) == *root_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmr::mem::Mmr;
+    use crate::mmr::Error;
+    use commonware_cryptography::Sha256;
+
+    #[test]
+    fn test_merkle_proof() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..49 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+
+            // Every element verifies against the root at every size
+            let root = mmr.root_hash();
+            for (element, pos) in elements.iter().zip(positions.iter()) {
+                let proof = mmr.to_merkle_proof(*pos).unwrap();
+                assert_eq!(proof.size, mmr.size());
+                assert_eq!(proof.leaf_hash, proof.leaf_hash(element, &mut hasher));
+                assert_eq!(proof.peaks.len() + 1, mmr.peak_positions().len());
+                assert!(proof.verify(&root, &mut hasher));
+            }
+        }
+        let root = mmr.root_hash();
+
+        // The path leads from the leaf to its peak (the first peak for the 18th element)
+        let proof = mmr.to_merkle_proof(positions[17]).unwrap();
+        assert_eq!(proof.peak_index, 0);
+        assert_eq!(proof.path.len(), 5);
+        assert_eq!(proof.path[0].1, Direction::Left);
+        assert_eq!(proof.path[1].1, Direction::Right);
+
+        // Tampered proofs fail
+        let mut tampered = proof.clone();
+        tampered.path[2].1 = match tampered.path[2].1 {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        };
+        assert!(!tampered.verify(&root, &mut hasher));
+        let mut tampered = proof.clone();
+        tampered.element_pos = positions[16];
+        assert!(!tampered.verify(&root, &mut hasher));
+        let mut tampered = proof.clone();
+        tampered.leaf_hash = proof.leaf_hash(&elements[16], &mut hasher);
+        assert!(!tampered.verify(&root, &mut hasher));
+        let mut tampered = proof.clone();
+        tampered.peak_index = 1;
+        assert!(!tampered.verify(&root, &mut hasher));
+        tampered.peak_index = tampered.peaks.len() + 1;
+        assert!(!tampered.verify(&root, &mut hasher));
+        let mut tampered = proof.clone();
+        tampered.size += 1;
+        assert!(!tampered.verify(&root, &mut hasher));
+
+        // Non-leaf, out-of-range, and pruned positions are rejected
+        assert_eq!(mmr.to_merkle_proof(2).err(), Some(Error::InvalidPos(2)));
+        assert_eq!(
+            mmr.to_merkle_proof(mmr.size()).err(),
+            Some(Error::InvalidPos(mmr.size()))
+        );
+        let mut pruned = mmr.clone_pruned();
+        let pos = pruned.add(&elements[0]);
+        assert!(matches!(
+            pruned.to_merkle_proof(positions[0]),
+            Err(Error::ElementPruned(_))
+        ));
+        let proof = pruned.to_merkle_proof(pos).unwrap();
+        assert!(proof.verify(&pruned.root_hash(), &mut hasher));
+    }
+
+    #[test]
+    fn test_merkle_proof_keyed() {
+        let mut mmr = Mmr::<Sha256>::new_keyed(b"commonware");
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..20).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<_> = elements.iter().map(|e| mmr.add(e)).collect();
+        let root = mmr.root_hash();
+
+        // The leaf hash is computed under the proof's scheme
+        let proof = mmr.to_merkle_proof(positions[7]).unwrap();
+        assert_eq!(proof.leaf_hash, proof.leaf_hash(&elements[7], &mut hasher));
+        assert!(proof.verify(&root, &mut hasher));
+        let mut unkeyed = proof.clone();
+        unkeyed.scheme = Scheme::default();
+        assert_ne!(
+            proof.leaf_hash,
+            unkeyed.leaf_hash(&elements[7], &mut hasher)
+        );
+        assert!(!unkeyed.verify(&root, &mut hasher));
+    }
+
+    #[test]
+    fn test_merkle_proof_non_leaf() {
+        let mut mmr = Mmr::<Sha256>::new_with_scheme(Scheme::Untagged);
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..3).map(|i| Digest::from(vec![i; 32])).collect();
+        for element in elements.iter() {
+            mmr.add(element);
+        }
+        let root = mmr.root_hash();
+
+        // Under the untagged scheme, the first peak (position 2) is also the leaf hash of the
+        // concatenation of its children at that position, but isn't accepted as a leaf
+        let proof = mmr.to_merkle_proof(3).unwrap();
+        let children =
+            Digest::from([&mmr.node_at(0).unwrap()[..], &mmr.node_at(1).unwrap()[..]].concat());
+        let mut forged = MerkleProof {
+            element_pos: 2,
+            leaf_hash: mmr.node_at(2).unwrap().clone(),
+            path: Vec::new(),
+            peaks: vec![proof.leaf_hash.clone()],
+            peak_index: 0,
+            ..proof
+        };
+        assert_eq!(forged.leaf_hash, forged.leaf_hash(&children, &mut hasher));
+        assert!(!forged.verify(&root, &mut hasher));
+
+        // A path ending anywhere but the peak at `peak_index` is rejected, even if the root hash
+        // combines the hash it reaches
+        forged.element_pos = 0;
+        forged.leaf_hash = mmr.node_at(0).unwrap().clone();
+        let mut mmr_hasher = Hasher::with_scheme(&mut hasher, Scheme::Untagged);
+        let root = mmr_hasher.root_hash(
+            forged.size,
+            [&forged.leaf_hash, &forged.peaks[0]].into_iter(),
+        );
+        assert!(!forged.verify(&root, &mut hasher));
+    }
+}
@@ -0,0 +1,6 @@
+//! Compatibility layers for producing and verifying proofs over other Merkle tree schemes.
+
+#[cfg(feature = "ibc")]
+pub mod ibc;
+pub mod merkle;
+pub mod rfc6962;
@@ -0,0 +1,502 @@
+//! An RFC 6962 (Certificate Transparency) Merkle tree.
+//!
+//! RFC 6962 trees hash leaves as `H(0x00 || entry)` and internal nodes as `H(0x01 || left ||
+//! right)`, without binding either to a position. The tree over `n` entries splits off the largest
+//! perfect subtree of fewer than `n` leaves on the left and recurses on the remainder, which makes
+//! every left subtree in the recursion a perfect tree aligned to its size. `Tree` stores the hashes
+//! of these perfect subtrees in the same post-order layout as an MMR (so the tree's perfect subtrees
+//! are exactly the MMR's nodes), and builds the (unbalanced) RFC 6962 subtrees from them on demand.
+//!
+//! Unlike the MMR, the root of an RFC 6962 tree is the result of folding its peaks right-to-left
+//! and does not commit to the tree size, so inclusion and consistency proofs carry the sizes
+//! out-of-band.
+
+use crate::mmr::iterator::{nodes_needing_parents, PeakIterator};
+use crate::mmr::util::node_count;
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+
+/// Prefix of the data hashed to produce a leaf hash.
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix of the data hashed to produce an internal node hash.
+const NODE_PREFIX: u8 = 0x01;
+
+/// Computes the RFC 6962 hash of the leaf for `entry`.
+pub fn leaf_hash<H: CHasher>(hasher: &mut H, entry: &[u8]) -> Digest {
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(entry);
+    hasher.finalize()
+}
+
+/// Computes the RFC 6962 hash of an internal node given the hashes of its children.
+pub fn node_hash<H: CHasher>(hasher: &mut H, left: &Digest, right: &Digest) -> Digest {
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize()
+}
+
+/// Returns the largest power of two strictly less than `n` (which must be greater than 1).
+fn split(n: u64) -> u64 {
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// An append-only RFC 6962 Merkle tree.
+pub struct Tree<H: CHasher> {
+    hasher: H,
+
+    /// The hashes of every perfect subtree, in MMR (post-order) layout.
+    nodes: Vec<Digest>,
+
+    /// The number of entries in the tree.
+    leaves: u64,
+}
+
+impl<H: CHasher> Default for Tree<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: CHasher> Tree<H> {
+    /// Return a new (empty) tree.
+    pub fn new() -> Self {
+        Self {
+            hasher: H::new(),
+            nodes: Vec::new(),
+            leaves: 0,
+        }
+    }
+
+    /// Return the number of entries in the tree.
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    /// Add an entry to the tree and return its index.
+    pub fn add(&mut self, entry: &[u8]) -> u64 {
+        let parents = nodes_needing_parents(PeakIterator::new(self.nodes.len() as u64));
+        let mut hash = leaf_hash(&mut self.hasher, entry);
+        self.nodes.push(hash.clone());
+        for sibling_pos in parents.into_iter().rev() {
+            let sibling = self.nodes[sibling_pos as usize].clone();
+            hash = node_hash(&mut self.hasher, &sibling, &hash);
+            self.nodes.push(hash.clone());
+        }
+        self.leaves += 1;
+        self.leaves - 1
+    }
+
+    /// Return the RFC 6962 root hash (MTH) of the tree consisting of its first `tree_size`
+    /// entries, or `Error::InvalidSize` if the tree has fewer entries.
+    pub fn root_hash(&mut self, tree_size: u64) -> Result<Digest, Error> {
+        if tree_size > self.leaves {
+            return Err(Error::InvalidSize(tree_size));
+        }
+        if tree_size == 0 {
+            return Ok(self.hasher.finalize());
+        }
+        Ok(self.subtree_hash(0, tree_size))
+    }
+
+    /// Return the RFC 6962 audit path (PATH) for the entry at `index` in the tree consisting of the
+    /// first `tree_size` entries, ordered from the leaf up.
+    pub fn inclusion_proof(&mut self, index: u64, tree_size: u64) -> Result<Vec<Digest>, Error> {
+        if tree_size > self.leaves {
+            return Err(Error::InvalidSize(tree_size));
+        }
+        if index >= tree_size {
+            return Err(Error::InvalidPos(index));
+        }
+        let mut proof = Vec::new();
+        self.path(index, 0, tree_size, &mut proof);
+        Ok(proof)
+    }
+
+    /// Return the RFC 6962 consistency proof (PROOF) between the trees consisting of the first
+    /// `old_size` and `new_size` entries. Requires `0 < old_size <= new_size <= leaves()`.
+    pub fn consistency_proof(
+        &mut self,
+        old_size: u64,
+        new_size: u64,
+    ) -> Result<Vec<Digest>, Error> {
+        if new_size > self.leaves {
+            return Err(Error::InvalidSize(new_size));
+        }
+        if old_size == 0 || old_size > new_size {
+            return Err(Error::InvalidSize(old_size));
+        }
+        let mut proof = Vec::new();
+        self.subproof(old_size, 0, new_size, true, &mut proof);
+        Ok(proof)
+    }
+
+    /// Computes MTH(D[start:end]) for a non-empty range produced by the RFC 6962 recursion (and
+    /// hence with `start` aligned to the smallest power of two that is at least `end - start`).
+    fn subtree_hash(&mut self, start: u64, end: u64) -> Digest {
+        let n = end - start;
+        if n.is_power_of_two() {
+            let pos = node_count(end - 1) + n.trailing_zeros() as u64;
+            return self.nodes[pos as usize].clone();
+        }
+        let k = split(n);
+        let left = self.subtree_hash(start, start + k);
+        let right = self.subtree_hash(start + k, end);
+        node_hash(&mut self.hasher, &left, &right)
+    }
+
+    /// Appends PATH(index, D[start:end]) to `proof`.
+    fn path(&mut self, index: u64, start: u64, end: u64, proof: &mut Vec<Digest>) {
+        let n = end - start;
+        if n == 1 {
+            return;
+        }
+        let k = split(n);
+        if index < start + k {
+            self.path(index, start, start + k, proof);
+            proof.push(self.subtree_hash(start + k, end));
+        } else {
+            self.path(index, start + k, end, proof);
+            proof.push(self.subtree_hash(start, start + k));
+        }
+    }
+
+    /// Appends SUBPROOF(m, D[start:end], complete) to `proof`.
+    fn subproof(&mut self, m: u64, start: u64, end: u64, complete: bool, proof: &mut Vec<Digest>) {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                proof.push(self.subtree_hash(start, end));
+            }
+            return;
+        }
+        let k = split(n);
+        if m <= k {
+            self.subproof(m, start, start + k, complete, proof);
+            proof.push(self.subtree_hash(start + k, end));
+        } else {
+            self.subproof(m - k, start + k, end, false, proof);
+            proof.push(self.subtree_hash(start, start + k));
+        }
+    }
+}
+
+/// Return true if `proof` is a valid RFC 6962 audit path for the leaf with hash `leaf` at `index`
+/// in the tree of `tree_size` entries with root `root_hash`.
+pub fn verify_inclusion<H: CHasher>(
+    leaf: &Digest,
+    index: u64,
+    tree_size: u64,
+    proof: &[Digest],
+    root_hash: &Digest,
+    hasher: &mut H,
+) -> bool {
+    if index >= tree_size {
+        return false;
+    }
+    let mut f = index;
+    let mut s = tree_size - 1;
+    let mut r = leaf.clone();
+    for p in proof {
+        if s == 0 {
+            return false;
+        }
+        if f & 1 == 1 || f == s {
+            r = node_hash(hasher, p, &r);
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            r = node_hash(hasher, &r, p);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    s == 0 && r == *root_hash
+}
+
+/// Return true if `proof` is a valid RFC 6962 consistency proof between the tree of `old_size`
+/// entries with root `old_root` and the tree of `new_size` entries with root `new_root`.
+pub fn verify_consistency<H: CHasher>(
+    old_size: u64,
+    new_size: u64,
+    proof: &[Digest],
+    old_root: &Digest,
+    new_root: &Digest,
+    hasher: &mut H,
+) -> bool {
+    if old_size == 0 || old_size > new_size {
+        return false;
+    }
+    if old_size == new_size {
+        return proof.is_empty() && old_root == new_root;
+    }
+    if proof.is_empty() {
+        return false;
+    }
+
+    // If the old tree is a perfect subtree of the new one, its root is the first node of the path.
+    let mut hashes = proof.iter();
+    let first = if old_size.is_power_of_two() {
+        old_root
+    } else {
+        hashes.next().unwrap()
+    };
+    let mut f = old_size - 1;
+    let mut s = new_size - 1;
+    while f & 1 == 1 {
+        f >>= 1;
+        s >>= 1;
+    }
+    let mut fr = first.clone();
+    let mut sr = first.clone();
+    for c in hashes {
+        if s == 0 {
+            return false;
+        }
+        if f & 1 == 1 || f == s {
+            fr = node_hash(hasher, c, &fr);
+            sr = node_hash(hasher, c, &sr);
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            sr = node_hash(hasher, &sr, c);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    s == 0 && fr == *old_root && sr == *new_root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_cryptography::Sha256;
+    use commonware_utils::hex;
+
+    /// The entries of the RFC 6962 test vectors used by the Certificate Transparency reference
+    /// implementations.
+    const ENTRIES: [&[u8]; 8] = [
+        b"",
+        b"\x00",
+        b"\x10",
+        b"\x20\x21",
+        b"\x30\x31",
+        b"\x40\x41\x42\x43",
+        b"\x50\x51\x52\x53\x54\x55\x56\x57",
+        b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+    ];
+
+    /// The expected roots of the trees over the first 1 through 8 entries.
+    const ROOTS: [&str; 8] = [
+        "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+    ];
+
+    fn tree() -> Tree<Sha256> {
+        let mut tree = Tree::new();
+        for entry in ENTRIES {
+            tree.add(entry);
+        }
+        tree
+    }
+
+    fn hexes(digests: &[Digest]) -> Vec<String> {
+        digests.iter().map(|d| hex(d)).collect()
+    }
+
+    #[test]
+    fn test_rfc6962_roots() {
+        let mut tree = tree();
+        assert_eq!(
+            hex(&tree.root_hash(0).unwrap()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        for (i, expected) in ROOTS.iter().enumerate() {
+            assert_eq!(hex(&tree.root_hash(i as u64 + 1).unwrap()), *expected);
+        }
+        assert_eq!(tree.root_hash(9), Err(Error::InvalidSize(9)));
+    }
+
+    #[test]
+    fn test_rfc6962_inclusion_vectors() {
+        let vectors: [(u64, u64, &[&str]); 5] = [
+            (0, 1, &[]),
+            (
+                0,
+                8,
+                &[
+                    "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+                    "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                    "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+                ],
+            ),
+            (
+                5,
+                8,
+                &[
+                    "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                    "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                    "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+                ],
+            ),
+            (
+                2,
+                3,
+                &["fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125"],
+            ),
+            (
+                1,
+                5,
+                &[
+                    "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+                    "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                    "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                ],
+            ),
+        ];
+        let mut tree = tree();
+        let mut hasher = Sha256::new();
+        for (index, tree_size, expected) in vectors {
+            let proof = tree.inclusion_proof(index, tree_size).unwrap();
+            assert_eq!(hexes(&proof), expected);
+            let leaf = leaf_hash(&mut hasher, ENTRIES[index as usize]);
+            let root = tree.root_hash(tree_size).unwrap();
+            assert!(verify_inclusion(
+                &leaf,
+                index,
+                tree_size,
+                &proof,
+                &root,
+                &mut hasher
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rfc6962_consistency_vectors() {
+        let vectors: [(u64, u64, &[&str]); 4] = [
+            (1, 1, &[]),
+            (
+                1,
+                8,
+                &[
+                    "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+                    "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                    "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+                ],
+            ),
+            (
+                6,
+                8,
+                &[
+                    "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+                    "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                    "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+                ],
+            ),
+            (
+                2,
+                5,
+                &[
+                    "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                    "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                ],
+            ),
+        ];
+        let mut tree = tree();
+        let mut hasher = Sha256::new();
+        for (old_size, new_size, expected) in vectors {
+            let proof = tree.consistency_proof(old_size, new_size).unwrap();
+            assert_eq!(hexes(&proof), expected);
+            let old_root = tree.root_hash(old_size).unwrap();
+            let new_root = tree.root_hash(new_size).unwrap();
+            assert!(verify_consistency(
+                old_size,
+                new_size,
+                &proof,
+                &old_root,
+                &new_root,
+                &mut hasher
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rfc6962_proofs_exhaustive() {
+        let mut tree = Tree::<Sha256>::new();
+        for i in 0u64..40 {
+            tree.add(&i.to_be_bytes());
+        }
+        let mut hasher = Sha256::new();
+        for tree_size in 1..=40 {
+            let root = tree.root_hash(tree_size).unwrap();
+            for index in 0..tree_size {
+                let proof = tree.inclusion_proof(index, tree_size).unwrap();
+                let leaf = leaf_hash(&mut hasher, &index.to_be_bytes());
+                assert!(verify_inclusion(
+                    &leaf,
+                    index,
+                    tree_size,
+                    &proof,
+                    &root,
+                    &mut hasher
+                ));
+                assert!(!verify_inclusion(
+                    &leaf,
+                    index,
+                    tree_size,
+                    &proof,
+                    &tree.root_hash(tree_size - 1).unwrap(),
+                    &mut hasher
+                ));
+                if index + 1 < tree_size {
+                    assert!(!verify_inclusion(
+                        &leaf,
+                        index + 1,
+                        tree_size,
+                        &proof,
+                        &root,
+                        &mut hasher
+                    ));
+                }
+            }
+            for old_size in 1..=tree_size {
+                let proof = tree.consistency_proof(old_size, tree_size).unwrap();
+                let old_root = tree.root_hash(old_size).unwrap();
+                assert!(verify_consistency(
+                    old_size,
+                    tree_size,
+                    &proof,
+                    &old_root,
+                    &root,
+                    &mut hasher
+                ));
+                if old_size < tree_size {
+                    assert!(!verify_consistency(
+                        old_size,
+                        tree_size,
+                        &proof,
+                        &root,
+                        &root,
+                        &mut hasher
+                    ));
+                }
+            }
+        }
+        assert_eq!(tree.inclusion_proof(5, 5), Err(Error::InvalidPos(5)));
+        assert_eq!(tree.consistency_proof(0, 5), Err(Error::InvalidSize(0)));
+        assert_eq!(tree.consistency_proof(6, 5), Err(Error::InvalidSize(6)));
+        assert_eq!(tree.consistency_proof(5, 41), Err(Error::InvalidSize(41)));
+    }
+}
@@ -0,0 +1,388 @@
+//! A portable file format for moving an (unpruned) MMR between machines, or between the in-memory
+//! `Mmr` and the journaled `Mmr`, as a single artifact.
+//!
+//! # Format
+//!
+//! An export consists of a fixed-size header, followed by the hash of every node in position
+//! order, followed by a CRC32 checksum of everything before it. All integers are big-endian.
+//!
+//! ```text
+//! +--------------+--------------+----------------+---------------------+-----------------+
+//! | Magic (4)    | Version (u8) | Hasher ID (8)  | Digest Length (u32) | Scheme ID (u8)  |
+//! +--------------+--------------+----------------+---------------------+-----------------+
+//! | Key (H)      | Size (u64)   | Root (H)       | Node 0 (H)          | ...             |
+//! +--------------+--------------+----------------+---------------------+-----------------+
+//! | Node size-1 (H)             | Checksum (u32)                                         |
+//! +-----------------------------+--------------------------------------------------------+
+//! ```
+//!
+//! The hasher ID is the first 8 bytes of the hash of the empty message, which identifies the hash
+//! function used to compute the nodes without requiring hashers to be registered anywhere. The
+//! scheme ID and key digest (the hash of the key of a `Scheme::Keyed` MMR, or of the empty message)
+//! identify the scheme the nodes were hashed with, without revealing the key.
+//!
+//! An export may capture the MMR as it was at an earlier size (see `export_at`), in which case the
+//! header records that size and the root hash at it, and only the nodes present at it (a prefix of
+//! the current nodes) are written.
+//!
+//! Imports reject exports with an unknown magic or version, a hasher ID or digest length that
+//! doesn't match the importing hasher, a scheme that doesn't match the importing scheme, a size
+//! that isn't a valid MMR size, a parent that doesn't match the hash of its children, a root that
+//! doesn't match the peaks, or a checksum that doesn't match, and can optionally require the
+//! recorded root hash to match an expected value (which is checked before any node is read).
+
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{pos_to_height, PeakIterator};
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::io::{Read, Write};
+
+/// The bytes every export starts with.
+const MAGIC: [u8; 4] = *b"CMMR";
+
+/// The version of the export format.
+const VERSION: u8 = 3;
+
+/// Return the ID of the hasher `H` (the first 8 bytes of the hash of the empty message).
+fn hasher_id<H: CHasher>() -> [u8; 8] {
+    let mut id = [0u8; 8];
+    let digest = H::new().finalize();
+    let len = digest.len().min(id.len());
+    id[..len].copy_from_slice(&digest[..len]);
+    id
+}
+
+/// Writes the nodes of an MMR in the export format, checksumming them as they are written.
+pub(crate) struct Exporter<W: Write> {
+    writer: W,
+    checksum: crc32fast::Hasher,
+}
+
+impl<W: Write> Exporter<W> {
+    /// Write the header of an export of an MMR of `size` nodes with root hash `root`, hashed with
+    /// `H` under `scheme`.
+    pub(crate) fn new<H: CHasher>(
+        writer: W,
+        size: u64,
+        root: &Digest,
+        scheme: &Scheme,
+    ) -> Result<Self, Error> {
+        let mut exporter = Self {
+            writer,
+            checksum: crc32fast::Hasher::new(),
+        };
+        exporter.write(&MAGIC)?;
+        exporter.write(&[VERSION])?;
+        exporter.write(&hasher_id::<H>())?;
+        exporter.write(&(H::len() as u32).to_be_bytes())?;
+        exporter.write(&scheme.fingerprint::<H>())?;
+        exporter.write(&size.to_be_bytes())?;
+        exporter.write(root)?;
+        Ok(exporter)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.checksum.update(buf);
+        self.writer.write_all(buf).map_err(|e| Error::Io(e.kind()))
+    }
+
+    /// Write the hash of the next node.
+    pub(crate) fn write_node(&mut self, node: &[u8]) -> Result<(), Error> {
+        self.write(node)
+    }
+
+    /// Write the trailing checksum and flush the writer.
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        let checksum = self.checksum.finalize();
+        self.writer
+            .write_all(&checksum.to_be_bytes())
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| Error::Io(e.kind()))
+    }
+}
+
+/// Reads the nodes of an MMR in the export format, checksumming them and checking each parent
+/// against the hash of its children as they are read.
+pub(crate) struct Importer<R: Read, H: CHasher> {
+    reader: R,
+    checksum: crc32fast::Hasher,
+    hasher: H,
+    scheme: Scheme,
+    size: u64,
+    root: Digest,
+    // The position of the next node to read.
+    pos: u64,
+    // The hashes of the nodes read so far that don't have a parent yet, in order of position.
+    orphans: Vec<Digest>,
+}
+
+impl<R: Read, H: CHasher> Importer<R, H> {
+    /// Read the header of an export, validating it against the hasher `H` and `scheme`.
+    pub(crate) fn new(reader: R, scheme: Scheme) -> Result<Self, Error> {
+        let mut importer = Self {
+            reader,
+            checksum: crc32fast::Hasher::new(),
+            hasher: H::new(),
+            scheme,
+            size: 0,
+            root: Digest::from(Vec::new()),
+            pos: 0,
+            orphans: Vec::new(),
+        };
+        let mut header = [0u8; 4 + 1 + 8 + 4];
+        importer.read(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        if header[4] != VERSION {
+            return Err(Error::UnsupportedVersion(header[4]));
+        }
+        if header[5..13] != hasher_id::<H>() {
+            return Err(Error::HasherMismatch);
+        }
+        let digest_len = u32::from_be_bytes(header[13..17].try_into().unwrap()) as usize;
+        if digest_len != H::len() {
+            return Err(Error::InvalidDigestLength(H::len(), digest_len));
+        }
+        let expected = importer.scheme.fingerprint::<H>();
+        let mut fingerprint = vec![0u8; expected.len()];
+        importer.read(&mut fingerprint)?;
+        if fingerprint != expected {
+            return Err(Error::SchemeMismatch);
+        }
+        let mut size = [0u8; 8];
+        importer.read(&mut size)?;
+        let size = u64::from_be_bytes(size);
+        if !PeakIterator::check_validity(size) {
+            return Err(Error::InvalidSize(size));
+        }
+        importer.size = size;
+        let mut root = vec![0u8; H::len()];
+        importer.read(&mut root)?;
+        importer.root = Digest::from(root);
+        Ok(importer)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.reader
+            .read_exact(buf)
+            .map_err(|e| Error::Io(e.kind()))?;
+        self.checksum.update(buf);
+        Ok(())
+    }
+
+    /// Return the number of nodes in the exported MMR.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Return the root hash recorded in the header, which `finish` checks against the nodes.
+    pub(crate) fn root(&self) -> &Digest {
+        &self.root
+    }
+
+    /// Read the hash of the next node, returning `Error::InvalidNode` if it is a parent that
+    /// doesn't match the hash of its children (which are the last two nodes read without a parent).
+    pub(crate) fn read_node(&mut self) -> Result<Digest, Error> {
+        let mut node = vec![0u8; H::len()];
+        self.read(&mut node)?;
+        let node = Digest::from(node);
+        if pos_to_height(self.pos) > 0 {
+            let right = self.orphans.pop().unwrap();
+            let left = self.orphans.pop().unwrap();
+            let expected = Hasher::with_scheme(&mut self.hasher, self.scheme.clone())
+                .node_hash(self.pos, &left, &right);
+            if node != expected {
+                return Err(Error::InvalidNode(self.pos));
+            }
+        }
+        self.orphans.push(node.clone());
+        self.pos += 1;
+        Ok(node)
+    }
+
+    /// Read the trailing checksum (after every node has been read), returning
+    /// `Error::ChecksumMismatch` if it doesn't match the bytes read, and `Error::RootMismatch` if
+    /// the recorded root hash doesn't match the peaks read.
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        let mut checksum = [0u8; 4];
+        self.reader
+            .read_exact(&mut checksum)
+            .map_err(|e| Error::Io(e.kind()))?;
+        if u32::from_be_bytes(checksum) != self.checksum.finalize() {
+            return Err(Error::ChecksumMismatch);
+        }
+
+        // The nodes left without a parent are the peaks
+        let root = Hasher::with_scheme(&mut self.hasher, self.scheme.clone())
+            .root_hash(self.size, self.orphans.iter());
+        if root != self.root {
+            return Err(Error::RootMismatch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmr::mem::Mmr;
+    use commonware_cryptography::Sha256;
+    use rand::{CryptoRng, Rng};
+
+    /// A hasher with the same digest length as `Sha256` but different output.
+    #[derive(Clone)]
+    struct PrefixedSha256(Sha256);
+
+    impl CHasher for PrefixedSha256 {
+        fn new() -> Self {
+            let mut hasher = Sha256::new();
+            hasher.update(b"prefix");
+            Self(hasher)
+        }
+
+        fn update(&mut self, message: &[u8]) {
+            self.0.update(message);
+        }
+
+        fn finalize(&mut self) -> Digest {
+            let digest = self.0.finalize();
+            self.0.update(b"prefix");
+            digest
+        }
+
+        fn reset(&mut self) {
+            *self = Self::new();
+        }
+
+        fn validate(digest: &Digest) -> bool {
+            Sha256::validate(digest)
+        }
+
+        fn len() -> usize {
+            Sha256::len()
+        }
+
+        fn random<R: Rng + CryptoRng>(rng: &mut R) -> Digest {
+            Sha256::random(rng)
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0u8..50 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        let root = mmr.root_hash();
+        let mut bytes = Vec::new();
+        mmr.export(&mut bytes).unwrap();
+        let header_len = 4 + 1 + 8 + 4 + 1 + 32 + 8 + 32;
+        assert_eq!(bytes.len(), header_len + mmr.size() as usize * 32 + 4);
+
+        // The export round-trips, optionally checking the root
+        let mut imported = Mmr::<Sha256>::import(&bytes[..], Scheme::default(), None).unwrap();
+        assert_eq!(imported.root_hash(), root);
+        Mmr::<Sha256>::import(&bytes[..], Scheme::default(), Some(&root)).unwrap();
+        assert_eq!(
+            Mmr::<Sha256>::import(
+                &bytes[..],
+                Scheme::default(),
+                Some(&Digest::from(vec![0u8; 32]))
+            )
+            .err(),
+            Some(Error::RootMismatch)
+        );
+
+        // Exports of a different hasher are rejected before any nodes are read
+        assert_eq!(
+            Mmr::<PrefixedSha256>::import(&bytes[..], Scheme::default(), None).err(),
+            Some(Error::HasherMismatch)
+        );
+
+        // Exports of a different scheme (or key) are rejected before any nodes are read
+        for scheme in [Scheme::Untagged, Scheme::keyed(b"commonware")] {
+            assert_eq!(
+                Mmr::<Sha256>::import(&bytes[..], scheme, None).err(),
+                Some(Error::SchemeMismatch)
+            );
+        }
+        let mut keyed = Mmr::<Sha256>::new_keyed(b"commonware");
+        for i in 0u8..50 {
+            keyed.add(&Digest::from(vec![i; 32]));
+        }
+        let mut keyed_bytes = Vec::new();
+        keyed.export(&mut keyed_bytes).unwrap();
+        let scheme = Scheme::keyed(b"commonware");
+        let mut imported = Mmr::<Sha256>::import(&keyed_bytes[..], scheme.clone(), None).unwrap();
+        assert_eq!(imported.scheme(), &scheme);
+        assert_eq!(imported.root_hash(), keyed.root_hash());
+        assert_eq!(
+            Mmr::<Sha256>::import(&keyed_bytes[..], Scheme::keyed(b"other"), None).err(),
+            Some(Error::SchemeMismatch)
+        );
+
+        // Corrupted and truncated exports are rejected
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            Mmr::<Sha256>::import(&corrupted[..], Scheme::default(), None).err(),
+            Some(Error::ChecksumMismatch)
+        );
+
+        // A tampered node is detected by its parent (or, for a parent, by its children) even if
+        // the checksum is recomputed to match
+        for (pos, parent) in [(0, 2), (2, 2), (mmr.size() - 2, mmr.size() - 1)] {
+            let mut forged = bytes.clone();
+            forged[header_len + pos as usize * 32] ^= 1;
+            let checksum_start = forged.len() - 4;
+            let checksum = crc32fast::hash(&forged[..checksum_start]);
+            forged[checksum_start..].copy_from_slice(&checksum.to_be_bytes());
+            assert_eq!(
+                Mmr::<Sha256>::import(&forged[..], Scheme::default(), None).err(),
+                Some(Error::InvalidNode(parent))
+            );
+        }
+        assert_eq!(
+            Mmr::<Sha256>::import(&bytes[..bytes.len() - 1], Scheme::default(), None).err(),
+            Some(Error::Io(std::io::ErrorKind::UnexpectedEof))
+        );
+        let mut corrupted = bytes.clone();
+        corrupted[0] = b'X';
+        assert_eq!(
+            Mmr::<Sha256>::import(&corrupted[..], Scheme::default(), None).err(),
+            Some(Error::InvalidMagic)
+        );
+        let mut corrupted = bytes.clone();
+        corrupted[4] = VERSION + 1;
+        assert_eq!(
+            Mmr::<Sha256>::import(&corrupted[..], Scheme::default(), None).err(),
+            Some(Error::UnsupportedVersion(VERSION + 1))
+        );
+        let mut corrupted = bytes.clone();
+        corrupted[header_len - 32 - 1] += 2;
+        assert_eq!(
+            Mmr::<Sha256>::import(&corrupted[..], Scheme::default(), None).err(),
+            Some(Error::InvalidSize(mmr.size() + 2))
+        );
+
+        // A recorded root that doesn't match the nodes is rejected, even if the checksum is
+        // recomputed to match
+        let mut forged = bytes.clone();
+        forged[header_len - 1] ^= 1;
+        let checksum_start = forged.len() - 4;
+        let checksum = crc32fast::hash(&forged[..checksum_start]);
+        forged[checksum_start..].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(
+            Mmr::<Sha256>::import(&forged[..], Scheme::default(), None).err(),
+            Some(Error::RootMismatch)
+        );
+
+        // Pruned MMRs can't be exported
+        let pruned = mmr.clone_pruned();
+        assert_eq!(
+            pruned.export(&mut Vec::new()).err(),
+            Some(Error::ElementPruned(0))
+        );
+    }
+}
@@ -1,29 +1,112 @@
+use bytes::Bytes;
 use commonware_cryptography::{Digest, Hasher as CHasher};
 
-/// Hasher decorator the MMR uses for computing leaf, node and root hashes.
-pub(crate) struct Hasher<'a, H: CHasher> {
+/// The domain tag prefixing the input of every leaf hash under `Scheme::Tagged`.
+pub(crate) const LEAF_TAG: u8 = 0x00;
+
+/// The domain tag prefixing the input of every node hash under `Scheme::Tagged`.
+pub(crate) const NODE_TAG: u8 = 0x01;
+
+/// The domain tag prefixing the input of every root hash under `Scheme::Tagged`.
+pub(crate) const ROOT_TAG: u8 = 0x02;
+
+/// The encoding of the inputs to the leaf, node, and root hashes of an MMR. Every hash of an MMR
+/// (and so its root) depends on the scheme, so an MMR must be verified with the scheme it was
+/// built with.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    /// Each input is prefixed with a tag identifying the kind of hash: `0x00` for a leaf, `0x01`
+    /// for a node, and `0x02` for a root. No leaf input can then be mistaken for a node (or root)
+    /// input, regardless of the lengths of the elements hashed.
+    #[default]
+    Tagged,
+
+    /// Inputs are not tagged, so the kind of hash is implied only by the length of its input. This
+    /// is the scheme of MMRs built before tags were introduced, and should only be used to keep
+    /// verifying them.
+    Untagged,
+
+    /// Each input is tagged as under `Scheme::Tagged`, and the tag is followed by the key
+    /// (prefixed with its big-endian `u64` length). MMRs built with different keys therefore have
+    /// unrelated hashes (and roots) even if they hold the same elements, and a proof for one can't
+    /// be verified against another. The key needn't be secret: it can simply identify the
+    /// chain or application an MMR belongs to.
+    Keyed(Bytes),
+}
+
+impl Scheme {
+    /// Return the `Scheme::Keyed` scheme mixing `key` into every hash.
+    pub fn keyed(key: &[u8]) -> Self {
+        Self::Keyed(Bytes::copy_from_slice(key))
+    }
+
+    /// Return the byte identifying the scheme, followed by the digest (under `H`) of its key (or of
+    /// the empty message if it has none). MMRs record this fingerprint when serialized or
+    /// persisted, so they can be rejected if restored under a different scheme without recording
+    /// the key itself.
+    pub(crate) fn fingerprint<H: CHasher>(&self) -> Vec<u8> {
+        let (id, key): (u8, &[u8]) = match self {
+            Self::Tagged => (0, &[]),
+            Self::Untagged => (1, &[]),
+            Self::Keyed(key) => (2, key),
+        };
+        let mut hasher = H::new();
+        hasher.update(key);
+        let mut fingerprint = Vec::with_capacity(1 + H::len());
+        fingerprint.push(id);
+        fingerprint.extend_from_slice(&hasher.finalize());
+        fingerprint
+    }
+
+    /// Return the number of bytes prefixed to the position (or size) in every hash input.
+    pub(crate) fn prefix_len(&self) -> usize {
+        match self {
+            Self::Tagged => 1,
+            Self::Untagged => 0,
+            Self::Keyed(key) => 1 + 8 + key.len(),
+        }
+    }
+}
+
+/// Hasher decorator the MMR uses for computing leaf, node and root hashes, which can also be used
+/// to compute them outside of an MMR (e.g. to check an MMR built by another implementation).
+pub struct Hasher<'a, H: CHasher> {
     hasher: &'a mut H,
+    scheme: Scheme,
 }
 
 impl<'a, H: CHasher> Hasher<'a, H> {
-    pub(crate) fn new(hasher: &'a mut H) -> Self {
-        Self { hasher }
+    /// Return a decorator hashing with the default `Scheme`.
+    pub fn new(hasher: &'a mut H) -> Self {
+        Self::with_scheme(hasher, Scheme::default())
+    }
+
+    /// Return a decorator hashing with the given `scheme`.
+    pub fn with_scheme(hasher: &'a mut H, scheme: Scheme) -> Self {
+        Self { hasher, scheme }
+    }
+
+    /// Return a decorator mixing `key` into every hash (see `Scheme::Keyed`).
+    pub fn new_keyed(hasher: &'a mut H, key: &[u8]) -> Self {
+        Self::with_scheme(hasher, Scheme::keyed(key))
+    }
+
+    /// Return the scheme the decorator hashes with.
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
     }
 
     /// Computes the hash for a leaf given its position and the element it represents.
-    pub(crate) fn leaf_hash(&mut self, pos: u64, element: &Digest) -> Digest {
+    pub fn leaf_hash(&mut self, pos: u64, element: &Digest) -> Digest {
+        self.update_with_tag(LEAF_TAG);
         self.update_with_pos(pos);
         self.update_with_hash(element);
         self.finalize_reset()
     }
 
     /// Computes the hash for a node given its position and the hashes of its children.
-    pub(crate) fn node_hash(
-        &mut self,
-        pos: u64,
-        left_hash: &Digest,
-        right_hash: &Digest,
-    ) -> Digest {
+    pub fn node_hash(&mut self, pos: u64, left_hash: &Digest, right_hash: &Digest) -> Digest {
+        self.update_with_tag(NODE_TAG);
         self.update_with_pos(pos);
         self.update_with_hash(left_hash);
         self.update_with_hash(right_hash);
@@ -32,11 +115,12 @@ impl<'a, H: CHasher> Hasher<'a, H> {
 
     /// Computes the root hash for an MMR given its size and an iterator over the hashes of its
     /// peaks. The iterator should yield the peak hashes in decreasing order of their height.
-    pub(crate) fn root_hash<'b>(
+    pub fn root_hash<'b>(
         &mut self,
         pos: u64,
         peak_hashes: impl Iterator<Item = &'b Digest>,
     ) -> Digest {
+        self.update_with_tag(ROOT_TAG);
         self.update_with_pos(pos);
         for hash in peak_hashes {
             self.update_with_hash(hash);
@@ -44,6 +128,17 @@ impl<'a, H: CHasher> Hasher<'a, H> {
         self.finalize_reset()
     }
 
+    fn update_with_tag(&mut self, tag: u8) {
+        match &self.scheme {
+            Scheme::Tagged => self.hasher.update(&[tag]),
+            Scheme::Untagged => {}
+            Scheme::Keyed(key) => {
+                self.hasher.update(&[tag]);
+                self.hasher.update(&(key.len() as u64).to_be_bytes());
+                self.hasher.update(key);
+            }
+        }
+    }
     pub(crate) fn update_with_pos(&mut self, pos: u64) {
         self.hasher.update(&pos.to_be_bytes());
     }
@@ -57,7 +152,9 @@ impl<'a, H: CHasher> Hasher<'a, H> {
 
 #[cfg(test)]
 mod tests {
+    use super::Scheme;
     use commonware_cryptography::{Digest, Hasher as CHasher, Sha256};
+    use commonware_utils::hex;
 
     #[test]
     fn test_leaf_hash_sha256() {
@@ -74,6 +171,128 @@ mod tests {
         test_root_hash::<Sha256>();
     }
 
+    #[test]
+    fn test_vectors_sha256() {
+        let hash1 = Digest::from(vec![1u8; 32]);
+        let hash2 = Digest::from(vec![2u8; 32]);
+        let vectors = [
+            (
+                Scheme::Tagged,
+                [
+                    "6b466bdf7bfdc80375b629b9e6d6b1c37f5bf729186ff82a7c2278f93095f092",
+                    "6a9fee773d0ca0e15bba6c7ae93e8a5a0ddafccdf687ba1780428aed1b61d450",
+                    "f4944314c95e77ee207b9355f8675bd2be8a7a1a0f4fb6576bf7ba29f088212a",
+                    "4322fd2bc0a137d1375b37b3b2e2b4715b3d3dd7ca9682438d4fea0f8437fad3",
+                ],
+            ),
+            (
+                Scheme::Untagged,
+                [
+                    "1cf642ab4c0d64a7c95947d36100d4ac32d57c33c552864e36545e3ae51cb81b",
+                    "f63686e215bcfa3e51006c93e68da8c2a1a7e08c08648eabdbe731e29e722055",
+                    "e2bebe34f2bf56284ef7f2a9d659415ebede5c2460b9e78c07b58b7723159a18",
+                    "af5570f5a1810b7af78caf4bc70a660f0df51e42baf91d4de5b2328de0e83dfc",
+                ],
+            ),
+            (
+                Scheme::keyed(b"commonware"),
+                [
+                    "9821fde636d39bbc789b0501a134a886d41d65583700a56372f7c2e858fd1e62",
+                    "1cb2f311945d5df914e27d71e91beeaa8bb18d8b9ff9f3f1d86cb1aa6947118f",
+                    "d6efcc298e7bb407dee36aea289dcad33510bc2e268da6be8f86a25edc85bc51",
+                    "08c4f3259d5dbc6d4ce0adffb5fa07479ec53dfe7452dd73d57f56f45a4ae311",
+                ],
+            ),
+        ];
+        let mut hasher = Sha256::new();
+        for (scheme, [leaf, node, root, empty_root]) in vectors {
+            let mut mmr_hasher = super::Hasher::with_scheme(&mut hasher, scheme);
+            assert_eq!(hex(&mmr_hasher.leaf_hash(7, &hash1)), leaf);
+            assert_eq!(hex(&mmr_hasher.node_hash(6, &hash1, &hash2)), node);
+            let peaks = [hash1.clone(), hash2.clone()];
+            assert_eq!(hex(&mmr_hasher.root_hash(19, peaks.iter())), root);
+            assert_eq!(
+                hex(&mmr_hasher.root_hash(0, std::iter::empty())),
+                empty_root
+            );
+        }
+    }
+
+    #[test]
+    fn test_keyed() {
+        let mut hasher = Sha256::new();
+        let element = Digest::from(vec![1u8; 32]);
+        let keyed_leaf = |hasher: &mut Sha256, key: &[u8]| {
+            super::Hasher::new_keyed(hasher, key).leaf_hash(7, &element)
+        };
+
+        // Distinct keys (including the empty key) give distinct hashes, none of them unkeyed
+        let unkeyed = super::Hasher::new(&mut hasher).leaf_hash(7, &element);
+        let keys: [&[u8]; 4] = [b"", b"a", b"ab", b"b"];
+        let hashes: Vec<_> = keys
+            .iter()
+            .map(|key| keyed_leaf(&mut hasher, key))
+            .collect();
+        for (i, hash) in hashes.iter().enumerate() {
+            assert_ne!(*hash, unkeyed);
+            assert!(hashes[i + 1..].iter().all(|other| other != hash));
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        // Every scheme (and key) has a distinct fingerprint of a fixed length
+        let schemes = [
+            Scheme::Tagged,
+            Scheme::Untagged,
+            Scheme::keyed(b""),
+            Scheme::keyed(b"a"),
+        ];
+        let fingerprints: Vec<_> = schemes.iter().map(Scheme::fingerprint::<Sha256>).collect();
+        for (i, fingerprint) in fingerprints.iter().enumerate() {
+            assert_eq!(fingerprint.len(), 1 + Sha256::len());
+            assert!(fingerprints[i + 1..]
+                .iter()
+                .all(|other| other != fingerprint));
+        }
+        assert_eq!(
+            Scheme::keyed(b"a").fingerprint::<Sha256>(),
+            Scheme::keyed(b"a").fingerprint::<Sha256>()
+        );
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let mut hasher = Sha256::new();
+        let left = Digest::from(vec![1u8; 32]);
+        let right = Digest::from(vec![2u8; 32]);
+        let crafted = Digest::from([left.as_ref(), right.as_ref()].concat());
+
+        // Without tags, a leaf whose element is the concatenation of a node's children hashes to
+        // the node (so the node can be passed off as a leaf), and a leaf whose element is the only
+        // peak of an MMR hashes to the root of that MMR if added at the position following it
+        let mut untagged = super::Hasher::with_scheme(&mut hasher, Scheme::Untagged);
+        assert_eq!(
+            untagged.leaf_hash(6, &crafted),
+            untagged.node_hash(6, &left, &right)
+        );
+        assert_eq!(
+            untagged.leaf_hash(3, &left),
+            untagged.root_hash(3, [left.clone()].iter())
+        );
+
+        // Tags make each kind of hash distinct
+        let mut tagged = super::Hasher::with_scheme(&mut hasher, Scheme::Tagged);
+        assert_ne!(
+            tagged.leaf_hash(6, &crafted),
+            tagged.node_hash(6, &left, &right)
+        );
+        assert_ne!(
+            tagged.leaf_hash(3, &left),
+            tagged.root_hash(3, [left.clone()].iter())
+        );
+    }
+
     fn test_leaf_hash<H: CHasher>() {
         let mut hasher = H::new();
         let mut mmr_hasher = super::Hasher::new(&mut hasher);
@@ -1,3 +1,5 @@
+//! Iterators over the peaks and paths of an MMR, and helpers for reasoning about node positions.
+
 /// A PeakIterator returns a (position, height) tuple for each peak in an MMR with the given size,
 /// in decreasing order of height.
 ///
@@ -20,8 +22,10 @@ impl PeakIterator {
         }
         // Compute the position at which to start the search for peaks. This starting position will
         // not be in the MMR unless it happens to be a single perfect binary tree, but that's OK as
-        // we will descend leftward until we find the first peak.
-        let start = u64::MAX >> size.leading_zeros();
+        // we will descend leftward until we find the first peak. (Starting no higher than a tree of
+        // height 62 keeps `two_h` from overflowing, which only excludes the single tree of height 63
+        // spanning every position.)
+        let start = u64::MAX >> size.leading_zeros().max(1);
         let two_h = 1 << start.trailing_ones();
         PeakIterator {
             size,
@@ -29,6 +33,19 @@ impl PeakIterator {
             two_h,
         }
     }
+
+    /// Return true if `size` is the size of some MMR, i.e. it can be expressed as a sum of
+    /// perfect binary tree sizes of strictly decreasing height.
+    pub(crate) fn check_validity(size: u64) -> bool {
+        let mut remaining = size;
+        for height in (0..64).rev() {
+            let tree_size = u64::MAX >> (63 - height);
+            if remaining >= tree_size {
+                remaining -= tree_size;
+            }
+        }
+        remaining == 0
+    }
 }
 
 impl Iterator for PeakIterator {
@@ -52,6 +69,53 @@ impl Iterator for PeakIterator {
     }
 }
 
+/// Returns the height of the node at position `pos` in an MMR.
+pub(crate) fn pos_to_height(mut pos: u64) -> u32 {
+    if pos == 0 {
+        return 0;
+    }
+
+    // Repeatedly subtract the sizes of perfect trees of decreasing height that fit within the
+    // position. The remainder is the height of the node.
+    let mut size = u64::MAX >> pos.leading_zeros();
+    while size != 0 {
+        if pos >= size {
+            pos -= size;
+        }
+        size >>= 1;
+    }
+    pos as u32
+}
+
+/// Returns the height of the node at position `pos` in an MMR: 0 for a leaf, 1 for the parent of
+/// two leaves, and so on. The height of a node doesn't depend on the size of the MMR containing it.
+///
+/// For the example MMR depicted in the `mmr` module documentation, `node_height(14)` is 3 and
+/// `node_height(17)` is 1.
+pub fn node_height(pos: u64) -> u8 {
+    pos_to_height(pos) as u8
+}
+
+/// Returns the size of the largest valid MMR with at most `size` nodes. Because an append adds a
+/// leaf followed by its new parents, this is the size of the MMR after the last append whose nodes
+/// all fit within `size`.
+pub(crate) fn largest_valid_size(mut size: u64) -> u64 {
+    while !PeakIterator::check_validity(size) {
+        size -= 1;
+    }
+    size
+}
+
+/// Returns the position of the first leaf following the leaf at position `leaf_pos`. Any nodes in
+/// between are ancestors of `leaf_pos`.
+pub(crate) fn next_leaf_pos(leaf_pos: u64) -> u64 {
+    let mut pos = leaf_pos + 1;
+    while pos_to_height(pos) != 0 {
+        pos += 1;
+    }
+    pos
+}
+
 /// Returns the set of peaks that will require a new parent after adding the next leaf to an MMR
 /// with the given peaks. This set is non-empty only if there is a height-0 (leaf) peak in the MMR.
 /// The result will contain this leaf peak plus the other MMR peaks with contiguously increasing
@@ -135,3 +199,65 @@ impl Iterator for PathIterator {
         r
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{largest_valid_size, next_leaf_pos, node_height, pos_to_height, PeakIterator};
+
+    #[test]
+    fn test_pos_to_height() {
+        // heights of the nodes in the 19 node example from mod.rs
+        let heights = [0, 0, 1, 0, 0, 1, 2, 0, 0, 1, 0, 0, 1, 2, 3, 0, 0, 1, 0];
+        for (pos, height) in heights.iter().enumerate() {
+            assert_eq!(pos_to_height(pos as u64), *height, "pos={}", pos);
+            assert_eq!(node_height(pos as u64), *height as u8, "pos={}", pos);
+        }
+
+        // the height of every peak matches the height reported by the peak iterator
+        for size in 0..1000 {
+            if !PeakIterator::check_validity(size) {
+                continue;
+            }
+            for (peak_pos, height) in PeakIterator::new(size) {
+                assert_eq!(node_height(peak_pos), height as u8);
+            }
+        }
+        assert_eq!(node_height(u64::MAX - 1), 63);
+    }
+
+    #[test]
+    fn test_next_leaf_pos() {
+        let leaves = [0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18, 19];
+        for pair in leaves.windows(2) {
+            assert_eq!(next_leaf_pos(pair[0]), pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_largest_valid_size() {
+        let valid: Vec<u64> = (0..=20)
+            .filter(|s| PeakIterator::check_validity(*s))
+            .collect();
+        assert_eq!(valid, vec![0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18, 19]);
+        for size in 0..=20 {
+            let expected = *valid.iter().filter(|v| **v <= size).max().unwrap();
+            assert_eq!(largest_valid_size(size), expected);
+        }
+    }
+
+    #[test]
+    fn test_check_validity() {
+        // sizes of the first 11 MMRs from the example in mod.rs
+        let valid = [0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18, 19];
+        for size in 0..20 {
+            assert_eq!(
+                PeakIterator::check_validity(size),
+                valid.contains(&size),
+                "unexpected validity for size {}",
+                size
+            );
+        }
+        assert!(PeakIterator::check_validity(u64::MAX));
+        assert!(!PeakIterator::check_validity(u64::MAX - 1));
+    }
+}
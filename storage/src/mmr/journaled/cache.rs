@@ -0,0 +1,98 @@
+use commonware_cryptography::Digest;
+use std::collections::{BTreeMap, HashMap};
+
+/// A cache of the hashes of stored nodes (keyed by position), evicting the least recently used
+/// node once `capacity` nodes are held. A cache with a capacity of 0 holds nothing.
+pub(super) struct NodeCache {
+    capacity: usize,
+    // The hash of each cached node, along with the tick at which it was last used.
+    nodes: HashMap<u64, (Digest, u64)>,
+    // The position of each cached node indexed by the tick at which it was last used, oldest first.
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl NodeCache {
+    /// Return a new, empty cache holding at most `capacity` nodes.
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            nodes: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Return the hash of the node at `pos` (marking it as the most recently used), if cached.
+    pub(super) fn get(&mut self, pos: u64) -> Option<Digest> {
+        let (node, last_used) = self.nodes.get_mut(&pos)?;
+        self.tick += 1;
+        self.recency.remove(last_used);
+        self.recency.insert(self.tick, pos);
+        *last_used = self.tick;
+        Some(node.clone())
+    }
+
+    /// Cache the hash of the node at `pos`, evicting the least recently used node if full.
+    pub(super) fn insert(&mut self, pos: u64, node: Digest) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.nodes.insert(pos, (node, self.tick)) {
+            self.recency.remove(&last_used);
+        } else if self.nodes.len() > self.capacity {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.nodes.remove(&oldest);
+        }
+        self.recency.insert(self.tick, pos);
+    }
+
+    /// Remove every cached node for which `keep` returns false.
+    pub(super) fn retain(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        self.nodes.retain(|pos, _| keep(*pos));
+        self.recency.retain(|_, pos| keep(*pos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodeCache;
+    use commonware_cryptography::Digest;
+
+    #[test]
+    fn test_node_cache() {
+        let node = |pos: u64| Digest::from(pos.to_be_bytes().to_vec());
+        let mut cache = NodeCache::new(3);
+        for pos in 0..3 {
+            cache.insert(pos, node(pos));
+        }
+        assert_eq!(cache.nodes.len(), 3);
+
+        // The least recently used node is evicted
+        assert_eq!(cache.get(0), Some(node(0)));
+        cache.insert(3, node(3));
+        assert_eq!(cache.nodes.len(), 3);
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(0), Some(node(0)));
+
+        // Re-inserting a node refreshes it without evicting another
+        cache.insert(2, node(2));
+        assert_eq!(cache.nodes.len(), 3);
+        cache.insert(4, node(4));
+        assert_eq!(cache.get(3), None);
+        assert_eq!(cache.get(2), Some(node(2)));
+
+        // Removed nodes are no longer served
+        cache.retain(|pos| pos < 2);
+        assert_eq!(cache.nodes.len(), 1);
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(4), None);
+        assert_eq!(cache.get(0), Some(node(0)));
+
+        // A cache without capacity holds nothing
+        let mut cache = NodeCache::new(0);
+        cache.insert(0, node(0));
+        assert_eq!(cache.get(0), None);
+    }
+}
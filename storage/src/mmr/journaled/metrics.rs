@@ -0,0 +1,151 @@
+use prometheus_client::metrics::{
+    counter::Counter,
+    gauge::Gauge,
+    histogram::{exponential_buckets, Histogram},
+};
+use prometheus_client::registry::Registry;
+use std::time::{Duration, SystemTime};
+
+/// Metrics reported by a journaled `Mmr` (and shared with its snapshots).
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    pub(crate) leaves_appended: Counter,
+    pub(crate) proofs_generated: Counter,
+    pub(crate) verification_failures: Counter,
+    pub(crate) reads: Counter,
+    pub(crate) read_bytes: Counter,
+    pub(crate) writes: Counter,
+    pub(crate) written_bytes: Counter,
+    pub(crate) prunes: Counter,
+    pub(crate) cache_hits: Counter,
+    pub(crate) cache_misses: Counter,
+
+    pub(crate) size: Gauge,
+    pub(crate) leaves: Gauge,
+    pub(crate) durable_size: Gauge,
+    pub(crate) open_sections: Gauge,
+    pub(crate) hot_nodes: Gauge,
+
+    pub(crate) proof_latency: Histogram,
+    pub(crate) flush_latency: Histogram,
+}
+
+impl Metrics {
+    /// Create the metrics and register them with `registry`.
+    pub(crate) fn init(registry: &mut Registry) -> Self {
+        let metrics = Self {
+            leaves_appended: Counter::default(),
+            proofs_generated: Counter::default(),
+            verification_failures: Counter::default(),
+            reads: Counter::default(),
+            read_bytes: Counter::default(),
+            writes: Counter::default(),
+            written_bytes: Counter::default(),
+            prunes: Counter::default(),
+            cache_hits: Counter::default(),
+            cache_misses: Counter::default(),
+
+            size: Gauge::default(),
+            leaves: Gauge::default(),
+            durable_size: Gauge::default(),
+            open_sections: Gauge::default(),
+            hot_nodes: Gauge::default(),
+
+            proof_latency: Histogram::new(exponential_buckets(0.00001, 2.0, 16)),
+            flush_latency: Histogram::new(exponential_buckets(0.00001, 2.0, 16)),
+        };
+        registry.register(
+            "leaves_appended",
+            "Number of leaves appended",
+            metrics.leaves_appended.clone(),
+        );
+        registry.register(
+            "proofs_generated",
+            "Number of proofs generated",
+            metrics.proofs_generated.clone(),
+        );
+        registry.register(
+            "verification_failures",
+            "Number of pages read that failed checksum verification",
+            metrics.verification_failures.clone(),
+        );
+        registry.register("reads", "Number of storage reads", metrics.reads.clone());
+        registry.register(
+            "read_bytes",
+            "Number of bytes read from storage",
+            metrics.read_bytes.clone(),
+        );
+        registry.register("writes", "Number of storage writes", metrics.writes.clone());
+        registry.register(
+            "written_bytes",
+            "Number of bytes written to storage",
+            metrics.written_bytes.clone(),
+        );
+        registry.register(
+            "prunes",
+            "Number of prune operations",
+            metrics.prunes.clone(),
+        );
+        registry.register(
+            "cache_hits",
+            "Number of stored nodes read from the node cache",
+            metrics.cache_hits.clone(),
+        );
+        registry.register(
+            "cache_misses",
+            "Number of stored nodes not found in the node cache",
+            metrics.cache_misses.clone(),
+        );
+        registry.register("size", "Number of nodes in the MMR", metrics.size.clone());
+        registry.register(
+            "leaves",
+            "Number of leaves in the MMR",
+            metrics.leaves.clone(),
+        );
+        registry.register(
+            "durable_size",
+            "Number of nodes synced to storage",
+            metrics.durable_size.clone(),
+        );
+        registry.register(
+            "open_sections",
+            "Number of open sections",
+            metrics.open_sections.clone(),
+        );
+        registry.register(
+            "hot_nodes",
+            "Number of recently added nodes held in memory",
+            metrics.hot_nodes.clone(),
+        );
+        registry.register(
+            "proof_latency",
+            "Latency of proof generation (in seconds)",
+            metrics.proof_latency.clone(),
+        );
+        registry.register(
+            "flush_latency",
+            "Latency of writing buffered nodes to storage (in seconds)",
+            metrics.flush_latency.clone(),
+        );
+        metrics
+    }
+
+    /// Record a read of `bytes` bytes from storage.
+    pub(crate) fn record_read(&self, bytes: usize) {
+        self.reads.inc();
+        self.read_bytes.inc_by(bytes as u64);
+    }
+
+    /// Record a write of `bytes` bytes to storage.
+    pub(crate) fn record_write(&self, bytes: usize) {
+        self.writes.inc();
+        self.written_bytes.inc_by(bytes as u64);
+    }
+}
+
+/// Return the number of seconds elapsed between `start` and `end` (or zero if `end` is earlier).
+pub(crate) fn elapsed_secs(start: SystemTime, end: SystemTime) -> f64 {
+    end.duration_since(start)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64()
+}
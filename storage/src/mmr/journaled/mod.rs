@@ -0,0 +1,3011 @@
+//! An MMR whose nodes are persisted to `Storage`.
+//!
+//! `Mmr` supports the same `add`, `root_hash`, `proof`, and `range_proof` operations as the
+//! in-memory `Mmr` (producing byte-identical proofs), but only keeps the peaks of the MMR and a
+//! small buffer of recently added nodes in memory. It survives restarts and is not limited by the
+//! amount of available memory.
+//!
+//! # Format
+//!
+//! Nodes are stored in `Blobs` within a caller-provided `partition`, grouped into fixed-size
+//! `sections` of `section_nodes` nodes each. The `Blob` for a given `section` is named by the
+//! section index (`u64`, big-endian) and lays out its nodes by position (the node at position
+//! `pos` is stored in section `pos / section_nodes`), split into `pages` of `page_nodes` nodes that
+//! are each preceded by a CRC32 checksum of the nodes they contain:
+//!
+//! ```text
+//! Section 0
+//! +-----------+-------------+-----+-------------+-----------+-------------+-----+
+//! | CRC (u32) | Node 0 (H)  | ... | Node p (H)  | CRC (u32) | Node p+1(H) | ... |
+//! +-----------+-------------+-----+-------------+-----------+-------------+-----+
+//!   Page 0                                        Page 1
+//!
+//! p = page_nodes - 1
+//! ```
+//!
+//! The checksum of a page is verified whenever one of its nodes is read, returning
+//! `Error::Corrupt` if it doesn't match, and `verify_all` checks every page. The last page of a
+//! section may only be partially filled, in which case its checksum covers the nodes it holds and
+//! is rewritten as nodes are appended to it.
+//!
+//! When a section fills up, appends automatically roll over to the next one. The size of the MMR
+//! is derived from the lengths of the stored sections on startup.
+//!
+//! # Open Blobs
+//!
+//! `Mmr` keeps the `Blob` of every stored section open for its lifetime. Callers wishing to bound
+//! the number of open `Blobs` should choose a larger `section_nodes`.
+//!
+//! # Crash Recovery
+//!
+//! Each append writes a leaf followed by the parent nodes it completes, and writes to `Storage`
+//! may only complete partially (including a flush spanning multiple sections where only some of
+//! the sections were written). On startup, `Mmr` only counts nodes stored contiguously from the
+//! first section, discarding any partially written node and everything after the first section
+//! that isn't full. The nodes of a page are written before its checksum, so only the nodes of the
+//! last page of each section that match its checksum are counted (the page is discarded entirely
+//! if none do). It then discards any trailing nodes that don't form a valid MMR (the incomplete
+//! tail of an interrupted append), so an append is either fully recovered or absent. The peaks are
+//! then reloaded from the surviving nodes. `recovery` reports what was discarded, so operators can
+//! be alerted to unexpected losses.
+//!
+//! Checksums only detect pages that were torn or corrupted after being written. If
+//! `verify_on_init` is set, `Mmr` also recomputes every retained parent from its children on
+//! startup (reading every stored node), failing with `Error::InconsistentNode` rather than serving
+//! anything if any stored hash doesn't match.
+//!
+//! # Checkpoints
+//!
+//! Every `sync` commits a checkpoint of the MMR (its size, leaf count, and root hash) to the
+//! metadata partition, but only after the sections holding its nodes have been synced, so a
+//! checkpoint never refers to nodes that aren't durable. The metadata partition is updated
+//! atomically and records both the latest checkpoint and the one before it. On startup, `Mmr`
+//! restores the most recent checkpoint whose root hash can be recomputed from the stored nodes
+//! (see `checkpoint`), or no checkpoint at all if there is none, so it never reports a checkpoint
+//! it can't back with stored nodes. Nodes synced after the restored checkpoint (if the crash
+//! happened before their checkpoint was committed) are still recovered, and are covered by the
+//! next checkpoint.
+//!
+//! A caller that already knows the expected state of the MMR (for example from consensus) can
+//! initialize it with `open_expecting`, which fails unless the recovered MMR matches a checkpoint,
+//! or `open_expecting_at_least`, which also accepts an MMR that extends it.
+//!
+//! # Atomic Batches
+//!
+//! Crash recovery only guarantees that each append is recovered entirely or not at all.
+//! `append_batch_atomic` extends this to a batch of elements: it records the intent to append the
+//! batch (along with the size and root hash of the durable MMR before it) in the metadata
+//! partition before adding any element, and commits the batch (replacing its intent with its id)
+//! only after all of its elements have been synced. On startup, `Mmr` rewinds any batch whose
+//! intent was never committed, so the batch is recovered either entirely or not at all.
+//! `committed_batch` returns the id of the most recently committed batch.
+//!
+//! # Pruning
+//!
+//! `prune_to_pos` discards all nodes before some position, deleting every section that only
+//! contains pruned nodes. The nodes before the pruning boundary that are still needed to add
+//! elements and prove retained elements (the peaks of the MMR at the boundary) are pinned and
+//! persisted, along with the boundary itself, to `Metadata` in a separate `metadata_partition`
+//! before any section is removed. Proofs for elements before the boundary return
+//! `Error::ElementPruned`.
+//!
+//! # Compaction
+//!
+//! The section containing the pruning boundary can't be deleted while it holds retained nodes.
+//! `compact` reclaims the space held by its pruned pages by copying the remaining pages into a new
+//! `Blob` (named by the section index followed by the index of its first page, both big-endian),
+//! which is committed by recording it in the metadata partition before the original is removed.
+//! Nodes keep their positions, so compaction is otherwise invisible. On startup, any compacted
+//! `Blob` that was never committed is discarded, and any original replaced by a committed one is
+//! removed.
+//!
+//! # Rebuilding
+//!
+//! If the stored nodes are corrupt beyond recovery but the elements added to the MMR survive
+//! elsewhere, `rebuild_from` replays them into a separate staging partition and, once the rebuilt
+//! MMR is complete (and optionally matches an expected root hash), commits the rebuild to the
+//! metadata partition before replacing the stored sections with the staged ones. A rebuild
+//! interrupted after it was committed is completed on startup.
+//!
+//! # Garbage Collection
+//!
+//! Startup removes blobs left behind by an interrupted compaction, rebuild, or change to the root
+//! index, but blobs orphaned after startup (or written by other tools) remain until the next
+//! restart. `gc` finds every blob in the partitions of the MMR that neither the sections committed
+//! to the metadata partition nor the root index reference, returning each with its length in a
+//! `GcReport` and removing them unless run as a dry run. The metadata partition is never touched.
+//!
+//! # Metrics
+//!
+//! `Mmr` registers counters (of appended leaves, generated proofs, pages failing checksum
+//! verification, storage reads and writes, node cache hits and misses, and prune operations),
+//! gauges (of its size, leaf count, durable size, and open sections), and histograms (of proof
+//! generation and flush latency) with the configured `registry`, under the configured
+//! `metrics_prefix`.
+//!
+//! # Proof Generation
+//!
+//! The positions of every node in a proof are computed up front from the size of the MMR and the
+//! range being proven, so the stored nodes among them are read concurrently (up to
+//! `max_concurrent_reads` at a time) rather than one after another. On storage with high read
+//! latency, generating a proof then takes about as long as a single read, instead of one read per
+//! level of the tree.
+//!
+//! # Node Cache
+//!
+//! Proofs for nearby elements share many nodes (especially those near the peaks), so `Mmr` keeps
+//! up to `cache_entries` of the stored nodes it most recently read in memory, evicting the least
+//! recently used. Stored nodes are never modified, so cached nodes only need to be discarded when
+//! they are pruned or rewound (see `rewind`).
+//!
+//! # Hot Nodes
+//!
+//! Appends (and proofs for recently added elements) repeatedly touch the peaks and the most
+//! recently added nodes, while older nodes are rarely needed. `Mmr` keeps the peaks and the last
+//! `hot_nodes` nodes written to `Storage` in memory, never reading them back: nodes are only read
+//! from `Storage` if they are older (or were stored before the last restart). Writing nodes to a
+//! partially filled page requires the nodes already in it (to update its checksum), so with
+//! `hot_nodes` of at least `page_nodes - 1` appends never read from `Storage` once enough nodes have
+//! been added since startup, making their latency independent of the latency of storage reads. The
+//! number of hot nodes is reported by the `hot_nodes` gauge.
+//!
+//! # Root Index
+//!
+//! Computing the root hash of the MMR at some earlier size (see `root_hash_at`) requires reading
+//! the peaks of the MMR at that size. If `root_index_interval` is non-zero, `Mmr` also records the
+//! root hash every `root_index_interval` leaves in a root index, a `Blob` (named by the interval,
+//! big-endian) of consecutive fixed-width root hashes stored in a separate partition (the
+//! configured `partition` suffixed with `_roots`). `root_at_leaf` serves the roots it holds with a
+//! single read (including those of pruned sizes), falling back to `root_hash_at` for leaf counts
+//! between them. Roots are written and synced along with the nodes they cover, discarded by
+//! `rewind`, and replaced by `rebuild_from`. On startup, any root beyond the recovered leaves is
+//! discarded and any missing root is recomputed, so the index always matches the stored nodes (and
+//! enabling it for an existing MMR indexes it in full, failing with `Error::ElementPruned` if it has
+//! been pruned). Indexes of other intervals (or every index, if disabled) are removed.
+//!
+//! # Snapshots
+//!
+//! `snapshot` returns an immutable `Snapshot` of the MMR at its current size, which serves proofs
+//! (that verify against its root hash) without coordinating with the `Mmr` as it continues to add
+//! elements. Snapshots read stored nodes through their own handles to each section and hold a copy
+//! of any nodes not yet written, so they never observe nodes (or partially written pages) beyond
+//! their size.
+//!
+//! # Export
+//!
+//! `export` writes every node of an unpruned MMR in the portable format described in
+//! `crate::mmr::export`, and `import` initializes a new `Mmr` from such an export (produced by
+//! either this `Mmr` or the in-memory `Mmr`) after validating it.
+//!
+//! # Buffering
+//!
+//! Nodes added to `Mmr` are buffered in memory and written to `Storage` once `write_buffer` nodes
+//! have accumulated. Written nodes are not guaranteed to be durable until they are synced, which
+//! happens automatically according to the configured `SyncPolicy` (checked whenever an element is
+//! added) or explicitly by calling `sync` (which also writes any buffered nodes). `durable_size`
+//! reports the number of nodes guaranteed to survive a crash, while `size` includes those that
+//! may still be lost. When calling `close`, all pending nodes are automatically synced.
+//!
+//! # Write-Behind
+//!
+//! By default, the `add` that fills the write buffer also writes it to `Storage`, so its latency
+//! includes that of the writes. If `write_behind` is set, `add` instead hands the full buffer to a
+//! background task (spawned on the runtime) and returns immediately, while further nodes accumulate
+//! in a new buffer. Nodes being written stay in memory (serving reads and snapshots) until the task
+//! finishes, and at most one buffer is written at a time: filling the next buffer waits for the
+//! previous task first, so an `add` only blocks when storage can't keep up.
+//!
+//! `wait_for_flush` waits for the task in progress (if any) and returns the error it failed with,
+//! leaving the nodes it was writing buffered to be written again. Every operation that writes to or
+//! removes stored nodes (`sync`, `close`, `rewind`, `prune_to_pos`, `compact`, `gc`, and the next
+//! flush) waits for it first, so a failed background write is reported by the next of them (or by
+//! the `add` filling the next buffer), and nodes are only ever reported as durable (see
+//! `durable_size`) once they have been written and synced.
+//!
+//! # Example
+//!
+//! ```rust
+//! use commonware_cryptography::{Digest, Sha256};
+//! use commonware_runtime::{Runner, deterministic::Executor};
+//! use commonware_storage::mmr::{
+//!     journaled::{Config, Mmr, SyncPolicy},
+//!     Scheme,
+//! };
+//! use prometheus_client::registry::Registry;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let (executor, context, _) = Executor::default();
+//! executor.start(async move {
+//!     // Create an MMR
+//!     let cfg = Config {
+//!         registry: Arc::new(Mutex::new(Registry::default())),
+//!         metrics_prefix: "mmr".to_string(),
+//!         partition: "partition".to_string(),
+//!         metadata_partition: "metadata".to_string(),
+//!         write_buffer: 1024,
+//!         section_nodes: 1 << 16,
+//!         page_nodes: 64,
+//!         sync_policy: SyncPolicy::Never,
+//!         write_behind: false,
+//!         cache_entries: 1024,
+//!         hot_nodes: 1024,
+//!         root_index_interval: 0,
+//!         verify_on_init: false,
+//!         max_concurrent_reads: 16,
+//!         scheme: Scheme::default(),
+//!     };
+//!     let mut mmr = Mmr::<_, _, Sha256>::init(context, cfg).await.unwrap();
+//!
+//!     // Add an element
+//!     let element = Digest::from(vec![1u8; 32]);
+//!     let pos = mmr.add(&element).await.unwrap();
+//!
+//!     // Generate a proof and verify it
+//!     let root_hash = mmr.root_hash();
+//!     let proof = mmr.proof(pos).await.unwrap();
+//!     assert!(proof.verify_element_inclusion(&element, pos, &root_hash, &mut Sha256::default()));
+//!
+//!     // Close the MMR
+//!     mmr.close().await.unwrap();
+//! });
+//! ```
+
+mod cache;
+mod metrics;
+mod section;
+mod snapshot;
+mod storage;
+
+use crate::mmr::hasher::Scheme;
+use prometheus_client::registry::Registry;
+pub(crate) use section::Section;
+pub use snapshot::Snapshot;
+use std::sync::{Arc, Mutex};
+pub use storage::Mmr;
+pub(crate) use storage::{
+    check_scheme, load_compacted, load_intent, load_pruned, open_sections, read_node, stored_nodes,
+    Layout,
+};
+use thiserror::Error;
+
+/// Errors that can occur when interacting with a journaled `Mmr`.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("runtime error: {0}")]
+    Runtime(#[from] commonware_runtime::Error),
+    #[error("element out of range: {0}")]
+    ElementOutOfRange(u64),
+    #[error("invalid blob name: {0}")]
+    InvalidBlobName(String),
+    #[error("section missing: {0}")]
+    SectionMissing(u64),
+    #[error("metadata error: {0}")]
+    Metadata(#[from] crate::metadata::Error),
+    #[error("invalid metadata")]
+    InvalidMetadata,
+    #[error("element pruned: {0}")]
+    ElementPruned(u64),
+    #[error("corrupt page: section={section} page={page}")]
+    Corrupt { section: u64, page: u64 },
+    #[error("mmr error: {0}")]
+    Mmr(#[from] crate::mmr::Error),
+    #[error("not empty: size={0}")]
+    NotEmpty(u64),
+    #[error("inconsistent node: {0}")]
+    InconsistentNode(u64),
+    #[error("size mismatch: expected={expected} actual={actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+    #[error("root mismatch: expected={expected:?} actual={actual:?}")]
+    RootMismatch {
+        expected: commonware_cryptography::Digest,
+        actual: commonware_cryptography::Digest,
+    },
+}
+
+/// When a journaled `Mmr` automatically syncs added elements to `Storage` (and commits a
+/// checkpoint of them). An explicit `sync` always syncs, regardless of the configured policy, as do
+/// `close` and the operations that depend on durable nodes (`append_batch_atomic`, `prune_to_pos`,
+/// and `compact`).
+///
+/// After a crash, `Mmr` recovers at least every element added before the last sync (see "Crash
+/// Recovery"), and may recover some added after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync after every added element, so an element is never lost once `add` has returned.
+    Always,
+
+    /// Sync once the given number of elements have been added since the last sync, so fewer than
+    /// that many elements are lost in a crash.
+    EveryNAppends(u64),
+
+    /// Sync whenever an added element completes `root_index_interval` leaves (which must be
+    /// non-zero), so every checkpoint coincides with a root recorded in the root index (see "Root
+    /// Index") and fewer than `root_index_interval` elements are lost in a crash.
+    OnCheckpoint,
+
+    /// Sync when an element is added at least the given number of milliseconds after the last sync,
+    /// so only elements added within that many milliseconds of the last sync are lost in a crash
+    /// (however long ago they were added, if none has been added since).
+    IntervalMillis(u64),
+
+    /// Never sync automatically, leaving nodes to be written by the operating system until `sync`
+    /// (or `close`) is called. Every element added since the last sync may be lost in a crash.
+    Never,
+}
+
+/// Configuration for a journaled `Mmr`.
+#[derive(Clone)]
+pub struct Config {
+    /// Registry for metrics.
+    pub registry: Arc<Mutex<Registry>>,
+
+    /// The prefix of the names of the metrics registered by the `Mmr`.
+    pub metrics_prefix: String,
+
+    /// The `commonware_runtime::Storage` partition to use for storing nodes.
+    pub partition: String,
+
+    /// The `commonware_runtime::Storage` partition to use for storing the pruning boundary and the
+    /// nodes it pins.
+    pub metadata_partition: String,
+
+    /// The number of nodes to buffer in memory before writing them to `Storage`.
+    pub write_buffer: usize,
+
+    /// The number of nodes stored in each section (`Blob`).
+    pub section_nodes: u64,
+
+    /// The number of nodes covered by each checksum (must evenly divide `section_nodes`).
+    pub page_nodes: u64,
+
+    /// When added elements are automatically synced to `Storage`.
+    pub sync_policy: SyncPolicy,
+
+    /// Whether full write buffers are written to `Storage` by a background task rather than by the
+    /// `add` that fills them (see "Write-Behind").
+    pub write_behind: bool,
+
+    /// The maximum number of stored nodes to cache in memory (0 disables caching).
+    pub cache_entries: usize,
+
+    /// The number of most recently added nodes to keep in memory after writing them to `Storage`
+    /// (see "Hot Nodes").
+    pub hot_nodes: usize,
+
+    /// The number of leaves between the roots recorded in the root index (see "Root Index"), or 0
+    /// to disable the index.
+    pub root_index_interval: u64,
+
+    /// Whether to recompute every retained parent from its children on startup (see "Crash
+    /// Recovery").
+    pub verify_on_init: bool,
+
+    /// The maximum number of stored nodes read concurrently when generating a proof (see "Proof
+    /// Generation"). Must be positive.
+    pub max_concurrent_reads: usize,
+
+    /// The scheme used to hash nodes and the root. The scheme is recorded when the MMR is created,
+    /// and reopening it with another scheme (or key) returns `mmr::Error::SchemeMismatch`.
+    pub scheme: Scheme,
+}
+
+/// A `Blob` found by `Mmr::gc` that the MMR doesn't reference.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Orphan {
+    /// The partition containing the blob.
+    pub partition: String,
+
+    /// The name of the blob.
+    pub name: Vec<u8>,
+
+    /// The length of the blob, in bytes.
+    pub len: u64,
+}
+
+/// The orphaned blobs found (and, unless a dry run, removed) by `Mmr::gc`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The orphaned blobs, in order of partition and name.
+    pub orphans: Vec<Orphan>,
+
+    /// The total length of the orphaned blobs, in bytes.
+    pub reclaimed_bytes: u64,
+}
+
+/// What a journaled `Mmr` discarded from `Storage` on startup to recover a valid MMR (see "Crash
+/// Recovery").
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// The number of complete nodes found stored contiguously from the pruning boundary (counted
+    /// from position 0).
+    pub stored_nodes: u64,
+
+    /// The number of stored nodes discarded because they didn't form a valid MMR (the orphaned
+    /// nodes of an interrupted append).
+    pub orphaned_nodes: u64,
+
+    /// The number of bytes truncated from (or removed along with) sections, including partially
+    /// written nodes and pages that didn't match their checksums.
+    pub truncated_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::section::blob_name;
+    use super::storage::{CHECKSUM_LEN, REBUILD_KEY};
+    use super::*;
+    use crate::metadata::{Config as MetadataConfig, Metadata};
+    use crate::mmr::iterator::{pos_to_height, PeakIterator};
+    use crate::mmr::mem::Mmr as MemMmr;
+    use crate::mmr::readonly::MmrReader;
+    use crate::mmr::tests::Wide;
+    use crate::mmr::util::{leaf_count, node_count};
+    use crate::mmr::verification::Checkpoint;
+    use commonware_cryptography::{Blake3, Digest, Hasher as CHasher, Sha256};
+    use commonware_macros::test_traced;
+    use commonware_runtime::Error as RError;
+    use commonware_runtime::{
+        deterministic::Executor, Blob, Clock, Handle, Runner, Signal, Spawner, Storage,
+    };
+    use prometheus_client::encoding::text::encode;
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    /// The number of nodes covered by each checksum in tests.
+    const PAGE_NODES: u64 = 4;
+
+    fn test_config(write_buffer: usize, section_nodes: u64) -> Config {
+        Config {
+            registry: Arc::new(Mutex::new(Registry::default())),
+            metrics_prefix: "mmr".to_string(),
+            partition: "test".to_string(),
+            metadata_partition: "test_metadata".to_string(),
+            write_buffer,
+            section_nodes,
+            page_nodes: PAGE_NODES,
+            sync_policy: SyncPolicy::Never,
+            write_behind: false,
+            cache_entries: 0,
+            hot_nodes: 0,
+            root_index_interval: 0,
+            verify_on_init: false,
+            max_concurrent_reads: 1,
+            scheme: Scheme::default(),
+        }
+    }
+
+    /// Check the journaled MMR matches the in-memory one (including after a restart) with the
+    /// hasher `H`.
+    fn matches_mem<H: CHasher>() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, H>::init(context.clone(), test_config(7, 16))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<H>::new();
+            assert_eq!(mmr.size(), 0);
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+
+            // Proofs should match the in-memory MMR regardless of which nodes have been flushed
+            let mut positions = Vec::new();
+            for i in 0u8..100 {
+                let element = Digest::from(vec![i; H::len()]);
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                positions.push(pos);
+                assert_eq!(mmr.size(), reference.size());
+                assert_eq!(mmr.root_hash(), reference.root_hash());
+                for pos in positions.iter() {
+                    assert_eq!(
+                        mmr.proof(*pos).await.unwrap(),
+                        reference.proof(*pos).unwrap()
+                    );
+                }
+            }
+            assert_eq!(
+                mmr.range_proof(positions[3], positions[97]).await.unwrap(),
+                reference.range_proof(positions[3], positions[97]).unwrap()
+            );
+
+            // Out of range proofs should fail
+            let size = mmr.size();
+            assert!(matches!(
+                mmr.proof(size).await,
+                Err(Error::ElementOutOfRange(pos)) if pos == size
+            ));
+
+            // The stored nodes should be read back intact
+            mmr.close().await.unwrap();
+            let mut mmr = Mmr::<_, _, H>::init(context, test_config(7, 16))
+                .await
+                .unwrap();
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            for pos in positions.iter() {
+                assert_eq!(
+                    mmr.proof(*pos).await.unwrap(),
+                    reference.proof(*pos).unwrap()
+                );
+            }
+        });
+    }
+
+    #[test_traced]
+    fn test_matches_mem() {
+        matches_mem::<Sha256>();
+    }
+
+    #[test_traced]
+    fn test_matches_mem_blake3() {
+        matches_mem::<Blake3>();
+    }
+
+    #[test_traced]
+    fn test_matches_mem_wide_digest() {
+        matches_mem::<Wide>();
+    }
+
+    #[test_traced]
+    fn test_restart() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1024, 1 << 12))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut elements = Vec::new();
+            for i in 0u64..100_000 {
+                let element = Digest::from(i.to_be_bytes().repeat(4));
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                elements.push((pos, element));
+            }
+            let root_hash = mmr.root_hash();
+            assert_eq!(root_hash, reference.root_hash());
+            mmr.close().await.unwrap();
+
+            // Reopen (from many sections) and confirm the root and a sample of proofs match the
+            // reference
+            assert_eq!(context.scan("test").await.unwrap().len(), 49);
+            let mut mmr = Mmr::<_, _, Sha256>::init(context, test_config(1024, 1 << 12))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), reference.size());
+            assert_eq!(mmr.root_hash(), root_hash);
+            let mut hasher = Sha256::default();
+            for (pos, element) in elements.iter().step_by(997) {
+                let proof = mmr.proof(*pos).await.unwrap();
+                assert_eq!(proof, reference.proof(*pos).unwrap());
+                assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+            }
+            let (start, end) = (elements[1234].0, elements[56789].0);
+            assert_eq!(
+                mmr.range_proof(start, end).await.unwrap(),
+                reference.range_proof(start, end).unwrap()
+            );
+
+            // Continue adding elements after the restart
+            for i in 0u8..10 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+        });
+    }
+
+    #[test_traced]
+    fn test_recover_torn_writes() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Build an MMR and record the root hash of the reference at every size
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(16, 1 << 20))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut roots = HashMap::new();
+            roots.insert(0, reference.root_hash());
+            for i in 0u8..50 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+                roots.insert(reference.size(), reference.root_hash());
+            }
+            mmr.close().await.unwrap();
+
+            // Read the persisted nodes
+            let blob = context.open("test", &0u64.to_be_bytes()).await.unwrap();
+            let len = blob.len().await.unwrap();
+            let mut nodes = vec![0u8; len as usize];
+            blob.read_at(&mut nodes, 0).await.unwrap();
+            blob.close().await.unwrap();
+
+            // Simulate a crash at various byte offsets by truncating the blob (a page truncated
+            // part way through no longer matches its checksum, so its nodes are discarded)
+            let layout = Layout::new(1 << 20, PAGE_NODES, 32);
+            let page_len = layout.section_len(PAGE_NODES);
+            for offset in (0..=len).step_by(7) {
+                let blob = context.open("test", &0u64.to_be_bytes()).await.unwrap();
+                blob.write_at(&nodes, 0).await.unwrap();
+                blob.truncate(offset).await.unwrap();
+                blob.close().await.unwrap();
+
+                // The recovered MMR should be a valid prefix no larger than the surviving nodes
+                let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(16, 1 << 20))
+                    .await
+                    .unwrap();
+                let size = mmr.size();
+                assert!(layout.section_len(size) <= offset);
+                let complete_pages = if offset == len {
+                    len.div_ceil(page_len)
+                } else {
+                    offset / page_len
+                };
+                assert!(complete_pages * PAGE_NODES < size + 8); // never discards a complete page
+                assert_eq!(&mmr.root_hash(), roots.get(&size).unwrap());
+                assert!(mmr.verify_all().await.unwrap().is_empty());
+
+                // The incomplete tail should have been removed from storage
+                mmr.close().await.unwrap();
+                let blob = context.open("test", &0u64.to_be_bytes()).await.unwrap();
+                assert_eq!(blob.len().await.unwrap(), layout.section_len(size));
+                blob.close().await.unwrap();
+            }
+        });
+    }
+
+    #[test_traced]
+    fn test_recover_and_continue() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Persist 4 elements (7 nodes), then tear the 5th append part way through its leaf (before
+            // the checksum of its page was updated)
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1, 1 << 20))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            for i in 0u8..4 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+            }
+            mmr.close().await.unwrap();
+            let blob = context.open("test", &0u64.to_be_bytes()).await.unwrap();
+            let len = blob.len().await.unwrap();
+            blob.write_at(&[9u8; 20], len).await.unwrap();
+            blob.close().await.unwrap();
+
+            // Recover and continue appending
+            let mut mmr = Mmr::<_, _, Sha256>::init(context, test_config(1, 1 << 20))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), 7);
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            for i in 4u8..20 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            let pos = reference.peak_positions()[0];
+            assert_eq!(
+                mmr.proof(pos - 1).await.unwrap(),
+                reference.proof(pos - 1).unwrap()
+            );
+        });
+    }
+
+    /// Overwrite the node at `pos` of the test partition, updating the checksum of its page.
+    async fn write_node<B: Blob, E: Storage<B>>(
+        context: &E,
+        section_nodes: u64,
+        pos: u64,
+        node: &[u8],
+    ) {
+        let layout = Layout::new(section_nodes, PAGE_NODES, 32);
+        let page_len = layout.section_len(PAGE_NODES);
+        let offset = pos % section_nodes;
+        let page_start = offset / PAGE_NODES * page_len;
+        let section = pos / section_nodes;
+        let blob = context.open("test", &section.to_be_bytes()).await.unwrap();
+        blob.write_at(node, page_start + CHECKSUM_LEN + offset % PAGE_NODES * 32)
+            .await
+            .unwrap();
+        let len = blob.len().await.unwrap();
+        let available = (len - page_start - CHECKSUM_LEN).min(PAGE_NODES * 32);
+        let mut nodes = vec![0u8; (available / 32 * 32) as usize];
+        blob.read_at(&mut nodes, page_start + CHECKSUM_LEN)
+            .await
+            .unwrap();
+        blob.write_at(&crc32fast::hash(&nodes).to_be_bytes(), page_start)
+            .await
+            .unwrap();
+        blob.close().await.unwrap();
+    }
+
+    #[test_traced]
+    fn test_recovery_report() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Persist 11 elements (19 nodes), recording the reference with a 12th (22 nodes)
+            let cfg = test_config(1, 16);
+            let mut strict = cfg.clone();
+            strict.verify_on_init = true;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            for i in 0u8..11 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+            }
+            let root_hash = reference.root_hash();
+            reference.add(&Digest::from(vec![11u8; 32]));
+            mmr.close().await.unwrap();
+
+            // Consistent storage is recovered in full, with or without verification
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), strict.clone())
+                .await
+                .unwrap();
+            assert_eq!(
+                mmr.recovery(),
+                &Recovery {
+                    stored_nodes: 19,
+                    orphaned_nodes: 0,
+                    truncated_bytes: 0,
+                }
+            );
+            mmr.close().await.unwrap();
+
+            // Write the leaf of the 12th element and its first parent (but not the second), followed
+            // by part of another node
+            write_node(&context, 16, 19, reference.get_node(19).unwrap()).await;
+            write_node(&context, 16, 20, reference.get_node(20).unwrap()).await;
+            let blob = context.open("test", &1u64.to_be_bytes()).await.unwrap();
+            let len = blob.len().await.unwrap();
+            blob.write_at(&[9u8; 10], len).await.unwrap();
+            blob.close().await.unwrap();
+
+            // The orphaned nodes and the partial node are discarded and reported
+            let layout = Layout::new(16, PAGE_NODES, 32);
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), 19);
+            assert_eq!(mmr.root_hash(), root_hash);
+            assert_eq!(
+                mmr.recovery(),
+                &Recovery {
+                    stored_nodes: 21,
+                    orphaned_nodes: 2,
+                    truncated_bytes: len + 10 - layout.section_len(3),
+                }
+            );
+            mmr.close().await.unwrap();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), strict.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.recovery().truncated_bytes, 0);
+
+            // Verification starts from the nodes pinned by pruning
+            mmr.prune_to_pos(15).await.unwrap();
+            mmr.close().await.unwrap();
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), strict.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.recovery().stored_nodes, 19);
+            mmr.close().await.unwrap();
+
+            // A parent that doesn't match its children (despite matching its checksum) is only
+            // detected with verification
+            write_node(&context, 16, 17, &[0u8; 32]).await;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), 19);
+            assert_ne!(mmr.root_hash(), root_hash);
+            mmr.close().await.unwrap();
+            assert!(matches!(
+                Mmr::<_, _, Sha256>::init(context.clone(), strict).await,
+                Err(Error::InconsistentNode(17))
+            ));
+        });
+    }
+
+    #[test_traced]
+    fn test_section_rollover() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1, 8))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+
+            // Fill the first section exactly (5 elements produce 8 nodes)
+            for i in 0u8..5 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            assert_eq!(mmr.size(), 8);
+            mmr.sync().await.unwrap();
+            assert_eq!(context.scan("test").await.unwrap().len(), 1);
+            let layout = Layout::new(8, PAGE_NODES, 32);
+            let blob = context.open("test", &0u64.to_be_bytes()).await.unwrap();
+            assert_eq!(blob.len().await.unwrap(), layout.section_len(8));
+
+            // The next append should roll over to a new section
+            let element = Digest::from(vec![5; 32]);
+            assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            assert_eq!(mmr.size(), 10);
+            mmr.sync().await.unwrap();
+            assert_eq!(context.scan("test").await.unwrap().len(), 2);
+            let blob = context.open("test", &1u64.to_be_bytes()).await.unwrap();
+            assert_eq!(blob.len().await.unwrap(), layout.section_len(2));
+            mmr.close().await.unwrap();
+
+            // Flushes spanning several sections should be split across them
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(20, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            let mut positions = Vec::new();
+            for i in 6u8..50 {
+                let element = Digest::from(vec![i; 32]);
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                positions.push(pos);
+            }
+            mmr.sync().await.unwrap();
+            let size = mmr.size();
+            assert_eq!(size, reference.size());
+            assert_eq!(
+                context.scan("test").await.unwrap().len() as u64,
+                size.div_ceil(8)
+            );
+
+            // Range proofs read nodes from many sections
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            assert_eq!(
+                mmr.range_proof(positions[0], positions[43]).await.unwrap(),
+                reference.range_proof(positions[0], positions[43]).unwrap()
+            );
+            for pos in positions.iter() {
+                assert_eq!(
+                    mmr.proof(*pos).await.unwrap(),
+                    reference.proof(*pos).unwrap()
+                );
+            }
+        });
+    }
+
+    #[test_traced]
+    fn test_recover_missing_section() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Persist 20 elements (38 nodes) across 5 sections in a single flush
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1024, 8))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut roots = HashMap::new();
+            for i in 0u8..20 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+                roots.insert(reference.size(), reference.root_hash());
+            }
+            mmr.close().await.unwrap();
+            assert_eq!(context.scan("test").await.unwrap().len(), 5);
+
+            // Simulate a crash where the writes to later sections completed but the write to
+            // section 2 did not
+            context
+                .remove("test", Some(&2u64.to_be_bytes()))
+                .await
+                .unwrap();
+
+            // Everything from section 2 onwards should be discarded
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1024, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), 16);
+            assert_eq!(&mmr.root_hash(), roots.get(&16).unwrap());
+            mmr.close().await.unwrap();
+            assert_eq!(context.scan("test").await.unwrap().len(), 2);
+
+            // A partially written section followed by full ones should also be recovered (up to the
+            // last page matching its checksum)
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1024, 8))
+                .await
+                .unwrap();
+            for i in 9u8..20 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            mmr.close().await.unwrap();
+            let blob = context.open("test", &1u64.to_be_bytes()).await.unwrap();
+            let layout = Layout::new(8, PAGE_NODES, 32);
+            blob.truncate(layout.section_len(4) + 5).await.unwrap();
+            blob.close().await.unwrap();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(1024, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), 11);
+            assert_eq!(&mmr.root_hash(), roots.get(&11).unwrap());
+            mmr.close().await.unwrap();
+            assert_eq!(context.scan("test").await.unwrap().len(), 2);
+        });
+    }
+
+    #[test_traced]
+    fn test_checksums() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Flush in batches that don't align with pages, so checksums of partially filled pages
+            // are rewritten as nodes are appended to them
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(3, 16))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut elements = Vec::new();
+            for i in 0u8..40 {
+                let element = Digest::from(vec![i; 32]);
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                elements.push(pos);
+                if i % 5 == 0 {
+                    mmr.sync().await.unwrap();
+                    assert!(mmr.verify_all().await.unwrap().is_empty());
+                }
+            }
+            mmr.close().await.unwrap();
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(3, 16))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), reference.size());
+            assert!(mmr.verify_all().await.unwrap().is_empty());
+            mmr.close().await.unwrap();
+
+            // Flip a byte in a node of 3 pages (none of which is the last page of its section) and
+            // in the checksum of another
+            let layout = Layout::new(16, PAGE_NODES, 32);
+            let page_len = layout.section_len(PAGE_NODES);
+            let corruptions: [(u64, u64, u64); 4] = [(0, 1, 2), (1, 0, 0), (1, 2, 3), (3, 1, 1)];
+            for (section, page, node) in corruptions {
+                let blob = context.open("test", &section.to_be_bytes()).await.unwrap();
+                let offset = if (section, page) == (1, 0) {
+                    0
+                } else {
+                    page * page_len + CHECKSUM_LEN + node * 32 + 7
+                };
+                let mut byte = [0u8; 1];
+                blob.read_at(&mut byte, offset).await.unwrap();
+                blob.write_at(&[byte[0] ^ 1], offset).await.unwrap();
+                blob.close().await.unwrap();
+            }
+
+            // Reading a node from a corrupt page should fail with its location, while nodes in other
+            // pages can still be read
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(3, 16))
+                .await
+                .unwrap();
+            assert!(matches!(
+                mmr.get_node(16 + 2 * PAGE_NODES + 3).await,
+                Err(Error::Corrupt {
+                    section: 1,
+                    page: 2
+                })
+            ));
+            assert!(matches!(
+                mmr.get_node(16).await,
+                Err(Error::Corrupt {
+                    section: 1,
+                    page: 0
+                })
+            ));
+            let pos = elements[0];
+            assert!(matches!(
+                mmr.proof(pos).await,
+                Err(Error::Corrupt {
+                    section: 0,
+                    page: 1
+                })
+            ));
+            assert_eq!(
+                mmr.get_node(2 * PAGE_NODES).await.unwrap(),
+                reference.get_node(2 * PAGE_NODES).unwrap().clone()
+            );
+
+            // The scrubber should find every corrupt page
+            let expected: Vec<(u64, u64)> = corruptions.iter().map(|(s, p, _)| (*s, *p)).collect();
+            assert_eq!(mmr.verify_all().await.unwrap(), expected);
+            mmr.close().await.unwrap();
+
+            // Corrupting the last page of a full section is detected on startup
+            let blob = context.open("test", &2u64.to_be_bytes()).await.unwrap();
+            blob.write_at(&[0u8; 4], 3 * page_len + CHECKSUM_LEN)
+                .await
+                .unwrap();
+            blob.close().await.unwrap();
+            assert!(matches!(
+                Mmr::<_, _, Sha256>::init(context.clone(), test_config(3, 16)).await,
+                Err(Error::Corrupt {
+                    section: 2,
+                    page: 3
+                })
+            ));
+        });
+    }
+
+    #[test_traced]
+    fn test_sync_policy() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            const STEP_MILLIS: u64 = 4;
+            const ROOT_INDEX_INTERVAL: u64 = 4;
+            let policies = [
+                SyncPolicy::Always,
+                SyncPolicy::EveryNAppends(5),
+                SyncPolicy::OnCheckpoint,
+                SyncPolicy::IntervalMillis(10),
+                SyncPolicy::IntervalMillis(25),
+                SyncPolicy::Never,
+            ];
+            for (i, policy) in policies.into_iter().enumerate() {
+                let mut cfg = test_config(4, 16);
+                cfg.partition = format!("test_{i}");
+                cfg.metadata_partition = format!("test_metadata_{i}");
+                cfg.sync_policy = policy;
+                cfg.root_index_interval = ROOT_INDEX_INTERVAL;
+
+                // The most elements each policy may lose in a crash (with an element added every
+                // `STEP_MILLIS`), if it syncs automatically at all
+                let max_lost = match policy {
+                    SyncPolicy::Always => Some(0),
+                    SyncPolicy::EveryNAppends(n) => Some(n - 1),
+                    SyncPolicy::OnCheckpoint => Some(ROOT_INDEX_INTERVAL - 1),
+                    SyncPolicy::IntervalMillis(interval) => {
+                        Some(interval.div_ceil(STEP_MILLIS) - 1)
+                    }
+                    SyncPolicy::Never => None,
+                };
+                let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                    .await
+                    .unwrap();
+                let mut reference = MemMmr::<Sha256>::new();
+                let mut roots = HashMap::new();
+                let mut acknowledged = vec![0];
+                let mut unsynced = 0;
+                for j in 0u8..23 {
+                    context.sleep(Duration::from_millis(STEP_MILLIS)).await;
+                    let element = Digest::from(vec![j; 32]);
+                    mmr.add(&element).await.unwrap();
+                    reference.add(&element);
+                    roots.insert(reference.size(), reference.root_hash());
+                    unsynced += 1;
+                    let synced = match policy {
+                        SyncPolicy::OnCheckpoint => {
+                            (j as u64 + 1).checked_rem(ROOT_INDEX_INTERVAL) == Some(0)
+                        }
+                        _ => Some(unsynced - 1) == max_lost,
+                    };
+                    if synced {
+                        unsynced = 0;
+                        assert_eq!(mmr.durable_size(), mmr.size());
+                        acknowledged.push(mmr.size());
+                    } else {
+                        assert_eq!(mmr.durable_size(), *acknowledged.last().unwrap());
+                        assert!(mmr.durable_size() < mmr.size());
+                    }
+                }
+
+                // Simulate a crash by dropping the MMR without syncing: it should reopen to the last
+                // size acknowledged as durable
+                let durable = mmr.durable_size();
+                drop(mmr);
+                let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                    .await
+                    .unwrap();
+                assert!(mmr.size() >= durable);
+                assert!(acknowledged.contains(&mmr.size()));
+                let lost = leaf_count(reference.size()) - leaf_count(mmr.size());
+                match max_lost {
+                    Some(max_lost) => assert!(lost <= max_lost, "{policy:?} lost {lost}"),
+                    None => assert_eq!(mmr.size(), 0),
+                }
+                assert_eq!(mmr.durable_size(), mmr.size());
+                if mmr.size() > 0 {
+                    assert_eq!(&mmr.root_hash(), roots.get(&mmr.size()).unwrap());
+                    assert_eq!(mmr.checkpoint().unwrap().size, mmr.size());
+                }
+
+                // An explicit sync makes everything durable, regardless of the policy
+                mmr.add(&Digest::from(vec![255u8; 32])).await.unwrap();
+                mmr.sync().await.unwrap();
+                assert_eq!(mmr.durable_size(), mmr.size());
+                let size = mmr.size();
+                drop(mmr);
+                let mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                    .await
+                    .unwrap();
+                assert_eq!(mmr.size(), size);
+                mmr.close().await.unwrap();
+            }
+        });
+    }
+
+    #[test_traced]
+    #[should_panic(expected = "syncing on checkpoints requires a root index")]
+    fn test_sync_policy_on_checkpoint_without_root_index() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut cfg = test_config(4, 16);
+            cfg.sync_policy = SyncPolicy::OnCheckpoint;
+            Mmr::<_, _, Sha256>::init(context, cfg).await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_metrics() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let registry = Arc::new(Mutex::new(Registry::default()));
+            let mut cfg = test_config(4, 8);
+            cfg.registry = registry.clone();
+            cfg.metrics_prefix = "custom_mmr".to_string();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg)
+                .await
+                .unwrap();
+            let encoded = || {
+                let mut buffer = String::new();
+                encode(&mut buffer, &registry.lock().unwrap()).unwrap();
+                buffer
+            };
+            let buffer = encoded();
+            assert!(buffer.contains("custom_mmr_leaves_appended_total 0\n"));
+            assert!(buffer.contains("custom_mmr_size 0\n"));
+            assert!(buffer.contains("custom_mmr_open_sections 0\n"));
+
+            // Appending leaves updates the counters and gauges, while writes are only issued once
+            // buffered nodes are flushed
+            let mut positions = Vec::new();
+            for i in 0u8..10 {
+                positions.push(mmr.add(&Digest::from(vec![i; 32])).await.unwrap());
+            }
+            let buffer = encoded();
+            assert!(buffer.contains("custom_mmr_leaves_appended_total 10\n"));
+            assert!(buffer.contains("custom_mmr_leaves 10\n"));
+            assert!(buffer.contains("custom_mmr_size 18\n"));
+            assert!(buffer.contains("custom_mmr_durable_size 0\n"));
+            assert!(!buffer.contains("custom_mmr_writes_total 0\n"));
+            assert!(!buffer.contains("custom_mmr_flush_latency_count 0\n"));
+            mmr.sync().await.unwrap();
+            let buffer = encoded();
+            assert!(buffer.contains("custom_mmr_durable_size 18\n"));
+            assert!(buffer.contains("custom_mmr_open_sections 3\n"));
+
+            // Generating proofs reads nodes from storage
+            assert!(buffer.contains("custom_mmr_proofs_generated_total 0\n"));
+            mmr.proof(positions[0]).await.unwrap();
+            mmr.range_proof(positions[1], positions[8]).await.unwrap();
+            let buffer = encoded();
+            assert!(buffer.contains("custom_mmr_proofs_generated_total 2\n"));
+            assert!(buffer.contains("custom_mmr_proof_latency_count 2\n"));
+            assert!(!buffer.contains("custom_mmr_read_bytes_total 0\n"));
+
+            // Pruning removes sections
+            mmr.prune_to_pos(positions[5]).await.unwrap();
+            let buffer = encoded();
+            assert!(buffer.contains("custom_mmr_prunes_total 1\n"));
+            assert!(buffer.contains("custom_mmr_open_sections 2\n"));
+            assert!(buffer.contains("custom_mmr_verification_failures_total 0\n"));
+
+            mmr.close().await.unwrap();
+
+            // Corrupt pages are counted when read
+            let blob = context.open("test", &1u64.to_be_bytes()).await.unwrap();
+            blob.write_at(&[0u8; 4], 0).await.unwrap();
+            blob.close().await.unwrap();
+            let registry = Arc::new(Mutex::new(Registry::default()));
+            let mut cfg = test_config(4, 8);
+            cfg.registry = registry.clone();
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg)
+                .await
+                .unwrap();
+            assert!(mmr.proof(positions[6]).await.is_err());
+            let mut buffer = String::new();
+            encode(&mut buffer, &registry.lock().unwrap()).unwrap();
+            assert!(buffer.contains("mmr_verification_failures_total 1\n"));
+        });
+    }
+
+    #[test_traced]
+    fn test_node_cache() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let registry = Arc::new(Mutex::new(Registry::default()));
+            let mut cfg = test_config(8, 32);
+            cfg.registry = registry.clone();
+            cfg.cache_entries = 64;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg)
+                .await
+                .unwrap();
+            let mut positions = Vec::new();
+            for i in 0u8..100 {
+                positions.push(mmr.add(&Digest::from(vec![i; 32])).await.unwrap());
+            }
+            mmr.sync().await.unwrap();
+            let counter = |name: &str| {
+                let mut buffer = String::new();
+                encode(&mut buffer, &registry.lock().unwrap()).unwrap();
+                let prefix = format!("mmr_{}_total ", name);
+                let line = buffer
+                    .lines()
+                    .find(|line| line.starts_with(&prefix))
+                    .unwrap();
+                line[prefix.len()..].parse::<u64>().unwrap()
+            };
+
+            // Only the first request for a range reads from storage (and the peaks it includes
+            // are held in memory rather than cached)
+            let reads = counter("reads");
+            let proof = mmr.range_proof(positions[10], positions[20]).await.unwrap();
+            assert!(counter("reads") > reads);
+            assert_eq!(counter("cache_hits"), 0);
+            let reads = counter("reads");
+            let misses = counter("cache_misses");
+            for _ in 0..3 {
+                let cached = mmr.range_proof(positions[10], positions[20]).await.unwrap();
+                assert_eq!(cached, proof);
+            }
+            assert_eq!(counter("reads"), reads);
+            assert_eq!(counter("cache_misses"), misses);
+            let peaks: Vec<u64> = PeakIterator::new(mmr.size())
+                .map(|(peak_pos, _)| peak_pos)
+                .collect();
+            let stored = proof
+                .positions(positions[10], positions[20])
+                .into_iter()
+                .filter(|pos| !peaks.contains(pos))
+                .count();
+            assert!(stored < proof.hashes.len());
+            assert_eq!(counter("cache_hits"), 3 * stored as u64);
+
+            // Rewinding purges the discarded nodes, so the nodes that replace them are read from
+            // storage rather than served from the cache
+            let size = positions[15];
+            let proof = mmr.range_proof(positions[5], positions[40]).await.unwrap();
+            mmr.rewind(size).await.unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            for i in 0u8..15 {
+                reference.add(&Digest::from(vec![i; 32]));
+            }
+            for i in 15u8..100 {
+                let element = Digest::from(vec![255 - i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            mmr.sync().await.unwrap();
+            let rewound = mmr.range_proof(positions[5], positions[40]).await.unwrap();
+            assert_ne!(rewound, proof);
+            assert_eq!(
+                rewound,
+                reference.range_proof(positions[5], positions[40]).unwrap()
+            );
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+
+            // Pruning purges the pruned nodes
+            mmr.prune_to_pos(positions[50]).await.unwrap();
+            let proof = mmr.range_proof(positions[60], positions[70]).await.unwrap();
+            assert_eq!(
+                proof,
+                reference.range_proof(positions[60], positions[70]).unwrap()
+            );
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_rewind() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            let mut mem_mmr = MemMmr::<Sha256>::new();
+            let mut roots = HashMap::new();
+            roots.insert(0, mem_mmr.root_hash());
+            let mut positions = Vec::new();
+            for i in 0u8..60 {
+                let element = Digest::from(vec![i; 32]);
+                positions.push(mmr.add(&element).await.unwrap());
+                mem_mmr.add(&element);
+                roots.insert(mem_mmr.size(), mem_mmr.root_hash());
+            }
+            mmr.sync().await.unwrap();
+
+            // Rewind within the buffered nodes, then across stored sections
+            for i in 50u8..53 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            mmr.rewind(mem_mmr.size()).await.unwrap();
+            assert_eq!(mmr.size(), mem_mmr.size());
+            assert_eq!(mmr.root_hash(), mem_mmr.root_hash());
+            for leaves in [41, 40, 33, 17, 16, 9, 1, 0] {
+                let size = node_count(leaves as u64);
+                mmr.rewind(size).await.unwrap();
+                assert_eq!(mmr.size(), size);
+                assert_eq!(mmr.durable_size(), size);
+                assert_eq!(&mmr.root_hash(), roots.get(&size).unwrap());
+                assert_eq!(mmr.verify_all().await.unwrap(), Vec::new());
+
+                // The rewound state survives a restart, and elements can be added after it
+                mmr.close().await.unwrap();
+                mmr = Mmr::init(context.clone(), test_config(4, 8)).await.unwrap();
+                assert_eq!(mmr.size(), size);
+                assert_eq!(&mmr.root_hash(), roots.get(&size).unwrap());
+                for (i, pos) in positions.iter().enumerate().take(leaves) {
+                    let proof = mmr.proof(*pos).await.unwrap();
+                    let element = Digest::from(vec![i as u8; 32]);
+                    let root = mmr.root_hash();
+                    assert!(proof.verify_element_inclusion(
+                        &element,
+                        *pos,
+                        &root,
+                        &mut Sha256::default()
+                    ));
+                }
+                let element = Digest::from(vec![leaves as u8; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), positions[leaves]);
+                mmr.rewind(size).await.unwrap();
+            }
+
+            // Rewinding before the pruning boundary fails
+            for i in 0u8..60 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            mmr.prune_to_pos(positions[30]).await.unwrap();
+            assert!(matches!(
+                mmr.rewind(positions[10]).await,
+                Err(Error::ElementPruned(_))
+            ));
+
+            // Rewinding to an invalid size (or beyond the current one) fails without discarding
+            // anything
+            let size = mmr.size();
+            let unaligned = (positions[40]..size)
+                .find(|size| !PeakIterator::check_validity(*size))
+                .unwrap();
+            for invalid in [unaligned, size + 1] {
+                assert!(matches!(
+                    mmr.rewind(invalid).await,
+                    Err(Error::Mmr(crate::mmr::Error::InvalidSize(pos))) if pos == invalid
+                ));
+            }
+            assert_eq!(mmr.size(), size);
+            mmr.rewind(positions[40]).await.unwrap();
+            assert_eq!(mmr.size(), positions[40]);
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_snapshot() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            let mut mem_mmr = MemMmr::<Sha256>::new();
+            let mut positions = Vec::new();
+            for i in 0u8..50 {
+                let element = Digest::from(vec![i; 32]);
+                positions.push(mmr.add(&element).await.unwrap());
+                mem_mmr.add(&element);
+            }
+            mmr.sync().await.unwrap();
+            for i in 50u8..53 {
+                let element = Digest::from(vec![i; 32]);
+                positions.push(mmr.add(&element).await.unwrap());
+                mem_mmr.add(&element);
+            }
+
+            // A snapshot is unaffected by subsequent additions, flushes, and syncs
+            let snapshot = mmr.snapshot();
+            let root = mmr.root_hash();
+            assert_eq!(snapshot.size(), mmr.size());
+            assert_eq!(snapshot.durable_size(), mmr.durable_size());
+            assert!(snapshot.durable_size() < snapshot.size());
+            assert_eq!(snapshot.root_hash(), root);
+            for i in 53u8..100 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            mmr.sync().await.unwrap();
+            assert_ne!(mmr.root_hash(), root);
+            assert_eq!(snapshot.root_hash(), root);
+            let mut hasher = Sha256::default();
+            for (i, pos) in positions.iter().enumerate() {
+                let proof = snapshot.proof(*pos).await.unwrap();
+                assert_eq!(proof, mem_mmr.proof(*pos).unwrap());
+                let element = Digest::from(vec![i as u8; 32]);
+                assert!(proof.verify_element_inclusion(&element, *pos, &root, &mut hasher));
+            }
+            assert!(matches!(
+                snapshot.proof(snapshot.size()).await,
+                Err(Error::ElementOutOfRange(_))
+            ));
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_snapshot_concurrent_proofs() {
+        const ELEMENTS: u64 = 500;
+        const READERS: usize = 4;
+
+        // The element added at each position is derived from that position
+        let element_for = |pos: u64| Digest::from(pos.to_be_bytes().repeat(4));
+
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 16))
+                .await
+                .unwrap();
+            let latest = Arc::new(Mutex::new(mmr.snapshot()));
+            let done = Arc::new(AtomicBool::new(false));
+
+            // Appender: publishes a snapshot after every addition
+            let appender = context.spawn("appender", {
+                let context = context.clone();
+                let latest = latest.clone();
+                let done = done.clone();
+                async move {
+                    for i in 0..ELEMENTS {
+                        let pos = mmr.size();
+                        assert_eq!(mmr.add(&element_for(pos)).await.unwrap(), pos);
+                        if i % 16 == 15 {
+                            mmr.sync().await.unwrap();
+                        }
+                        *latest.lock().unwrap() = mmr.snapshot();
+                        context.sleep(Duration::from_millis(1)).await;
+                    }
+                    done.store(true, Ordering::SeqCst);
+                    mmr
+                }
+            });
+
+            // Proof servers: verify a sample of elements against the root of the same snapshot
+            let mut readers = Vec::new();
+            for reader in 0..READERS {
+                let context = context.clone();
+                let latest = latest.clone();
+                let done = done.clone();
+                readers.push(context.clone().spawn("reader", async move {
+                    let mut hasher = Sha256::default();
+                    let mut verified = 0;
+                    loop {
+                        let finished = done.load(Ordering::SeqCst);
+                        let snapshot = latest.lock().unwrap().clone();
+                        let root = snapshot.root_hash();
+                        let leaves = (0..snapshot.size())
+                            .skip(reader)
+                            .step_by(7)
+                            .filter(|pos| pos_to_height(*pos) == 0);
+                        for pos in leaves {
+                            let proof = snapshot.proof(pos).await.unwrap();
+                            assert!(proof.verify_element_inclusion(
+                                &element_for(pos),
+                                pos,
+                                &root,
+                                &mut hasher
+                            ));
+                            verified += 1;
+                        }
+                        if finished {
+                            break;
+                        }
+                        context.sleep(Duration::from_millis(1)).await;
+                    }
+                    verified
+                }));
+            }
+            for reader in readers {
+                assert!(reader.await.unwrap() > 0);
+            }
+            let mmr = appender.await.unwrap();
+            assert_eq!(mmr.size(), node_count(ELEMENTS));
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_export() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            let mut mem_mmr = MemMmr::<Sha256>::new();
+            let mut positions = Vec::new();
+            for i in 0u8..50 {
+                let element = Digest::from(vec![i; 32]);
+                positions.push(mmr.add(&element).await.unwrap());
+                mem_mmr.add(&element);
+            }
+            let root = mmr.root_hash();
+
+            // Exports of both implementations are identical (including buffered nodes)
+            let mut bytes = Vec::new();
+            mmr.export(&mut bytes).await.unwrap();
+            let mut mem_bytes = Vec::new();
+            mem_mmr.export(&mut mem_bytes).unwrap();
+            assert_eq!(bytes, mem_bytes);
+
+            // A journaled export imports into the in-memory implementation
+            let mut imported =
+                MemMmr::<Sha256>::import(&bytes[..], Scheme::default(), Some(&root)).unwrap();
+            assert_eq!(imported.root_hash(), root);
+
+            // An in-memory export imports into (and persists) the journaled implementation
+            let cfg = || Config {
+                partition: "imported".to_string(),
+                metadata_partition: "imported_metadata".to_string(),
+                ..test_config(4, 8)
+            };
+            let imported =
+                Mmr::<_, _, Sha256>::import(context.clone(), cfg(), &mem_bytes[..], Some(&root))
+                    .await
+                    .unwrap();
+            assert_eq!(imported.durable_size(), mem_mmr.size());
+            imported.close().await.unwrap();
+            let mut imported = Mmr::<_, _, Sha256>::init(context.clone(), cfg())
+                .await
+                .unwrap();
+            assert_eq!(imported.root_hash(), root);
+            for pos in positions.iter() {
+                assert_eq!(
+                    imported.proof(*pos).await.unwrap(),
+                    mmr.proof(*pos).await.unwrap()
+                );
+            }
+            let element = Digest::from(vec![50u8; 32]);
+            assert_eq!(imported.add(&element).await.unwrap(), mem_mmr.add(&element));
+            assert_eq!(imported.root_hash(), mem_mmr.root_hash());
+
+            // Importing into a non-empty partition fails
+            mmr.sync().await.unwrap();
+            assert!(matches!(
+                Mmr::<_, _, Sha256>::import(context.clone(), test_config(4, 8), &bytes[..], None)
+                    .await,
+                Err(Error::NotEmpty(_))
+            ));
+
+            // Exports of a different hasher, or whose checksum or root doesn't match, are rejected
+            // (removing any nodes written before they were)
+            mmr.close().await.unwrap();
+            imported.close().await.unwrap();
+            context.remove("imported", None).await.unwrap();
+            let mut mismatched = bytes.clone();
+            mismatched[5] ^= 1;
+            let mut corrupted = bytes.clone();
+            *corrupted.last_mut().unwrap() ^= 1;
+            let mut forged = bytes.clone();
+            forged[100] ^= 1;
+            let checksum_start = forged.len() - 4;
+            let checksum = crc32fast::hash(&forged[..checksum_start]);
+            forged[checksum_start..].copy_from_slice(&checksum.to_be_bytes());
+            let other_root = Digest::from(vec![0u8; 32]);
+            for (bytes, expected_root, expected) in [
+                (&mismatched, None, crate::mmr::Error::HasherMismatch),
+                (&corrupted, None, crate::mmr::Error::ChecksumMismatch),
+                (&forged, None, crate::mmr::Error::InvalidNode(2)),
+                (&bytes, Some(&other_root), crate::mmr::Error::RootMismatch),
+            ] {
+                let result =
+                    Mmr::<_, _, Sha256>::import(context.clone(), cfg(), &bytes[..], expected_root)
+                        .await;
+                assert!(matches!(result, Err(Error::Mmr(err)) if err == expected));
+                let mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg())
+                    .await
+                    .unwrap();
+                assert_eq!(mmr.size(), 0);
+                mmr.close().await.unwrap();
+            }
+
+            // Exports at earlier sizes (including sizes whose nodes are only partly written) match
+            // those of the in-memory implementation at that size
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            for i in 50u8..53 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            let mut reference = MemMmr::<Sha256>::new();
+            for leaf_count in 0u64..=53 {
+                let mut bytes = Vec::new();
+                mmr.export_at(&mut bytes, leaf_count).await.unwrap();
+                let mut expected = Vec::new();
+                reference.export(&mut expected).unwrap();
+                assert_eq!(bytes, expected);
+                reference.add(&Digest::from(vec![leaf_count as u8; 32]));
+            }
+            assert!(matches!(
+                mmr.export_at(&mut Vec::new(), 54).await,
+                Err(Error::ElementOutOfRange(54))
+            ));
+
+            // Pruned MMRs can't be exported
+            mmr.close().await.unwrap();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            mmr.prune_to_pos(positions[10]).await.unwrap();
+            assert!(matches!(
+                mmr.export(&mut Vec::new()).await,
+                Err(Error::ElementPruned(0))
+            ));
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_scheme_recorded() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let scheme = Scheme::keyed(b"commonware");
+            let keyed = || Config {
+                scheme: scheme.clone(),
+                ..test_config(4, 8)
+            };
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), keyed())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new_with_scheme(scheme.clone());
+            for i in 0u8..20 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+            }
+            let root = mmr.root_hash();
+            assert_eq!(root, reference.root_hash());
+            let mut bytes = Vec::new();
+            mmr.export(&mut bytes).await.unwrap();
+            mmr.close().await.unwrap();
+
+            // Reopening (or reading) the MMR with another scheme or key is rejected
+            for other in [Scheme::default(), Scheme::keyed(b"other")] {
+                let cfg = Config {
+                    scheme: other.clone(),
+                    ..test_config(4, 8)
+                };
+                assert!(matches!(
+                    MmrReader::<_, Sha256>::open(&context, &cfg).await,
+                    Err(Error::Mmr(crate::mmr::Error::SchemeMismatch))
+                ));
+                assert!(matches!(
+                    Mmr::<_, _, Sha256>::init(context.clone(), cfg).await,
+                    Err(Error::Mmr(crate::mmr::Error::SchemeMismatch))
+                ));
+                assert_eq!(
+                    MemMmr::<Sha256>::import(&bytes[..], other, None).err(),
+                    Some(crate::mmr::Error::SchemeMismatch)
+                );
+            }
+
+            // Reopening it with its own scheme restores its root
+            let reader = MmrReader::<_, Sha256>::open(&context, &keyed())
+                .await
+                .unwrap();
+            assert_eq!(reader.root_hash(), root);
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), keyed())
+                .await
+                .unwrap();
+            assert_eq!(mmr.root_hash(), root);
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_prune() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(8, 16))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut elements = Vec::new();
+            for i in 0u8..200 {
+                let element = Digest::from(vec![i; 32]);
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                elements.push((pos, element));
+            }
+            mmr.sync().await.unwrap();
+            let sections = context.scan("test").await.unwrap().len();
+            assert_eq!(sections as u64, mmr.size().div_ceil(16));
+
+            // Prune to (at most) the position of the 121st element
+            let boundary = elements[120].0;
+            mmr.prune_to_pos(boundary).await.unwrap();
+            let pruned_to_pos = mmr.pruned_to_pos();
+            assert!(pruned_to_pos <= boundary);
+            assert!(boundary - pruned_to_pos < 8);
+
+            // Sections before the boundary should have been removed
+            let remaining = context.scan("test").await.unwrap().len();
+            assert_eq!(remaining, sections - (pruned_to_pos / 16) as usize);
+            assert!(remaining < sections);
+
+            // Proofs for pruned elements should fail, while retained elements should still match
+            // the reference
+            let root_hash = mmr.root_hash();
+            assert_eq!(root_hash, reference.root_hash());
+            let mut hasher = Sha256::default();
+            for (pos, element) in elements.iter() {
+                if *pos < pruned_to_pos {
+                    assert!(matches!(
+                        mmr.proof(*pos).await,
+                        Err(Error::ElementPruned(p)) if p == *pos
+                    ));
+                    continue;
+                }
+                let proof = mmr.proof(*pos).await.unwrap();
+                assert_eq!(proof, reference.proof(*pos).unwrap());
+                assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+            }
+            assert!(matches!(
+                mmr.range_proof(elements[0].0, elements[199].0).await,
+                Err(Error::ElementPruned(0))
+            ));
+
+            // Pruning to an earlier position is a no-op
+            mmr.prune_to_pos(elements[10].0).await.unwrap();
+            assert_eq!(mmr.pruned_to_pos(), pruned_to_pos);
+            mmr.close().await.unwrap();
+
+            // Simulate a crash before pruned sections were removed
+            let blob = context.open("test", &0u64.to_be_bytes()).await.unwrap();
+            blob.write_at(&[7u8; 16 * 32], 0).await.unwrap();
+            blob.close().await.unwrap();
+
+            // Restart and confirm the pruning boundary survived
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(8, 16))
+                .await
+                .unwrap();
+            assert_eq!(context.scan("test").await.unwrap().len(), remaining);
+            assert_eq!(mmr.pruned_to_pos(), pruned_to_pos);
+            assert_eq!(mmr.size(), reference.size());
+            assert_eq!(mmr.root_hash(), root_hash);
+            for (pos, _) in elements.iter().filter(|(pos, _)| *pos >= pruned_to_pos) {
+                assert_eq!(
+                    mmr.proof(*pos).await.unwrap(),
+                    reference.proof(*pos).unwrap()
+                );
+            }
+
+            // Continue adding elements (merging with pinned peaks) and prune again
+            for i in 200u8..=255 {
+                let element = Digest::from(vec![i; 32]);
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                elements.push((pos, element));
+            }
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            let (start, end) = (elements[150].0, elements[255].0);
+            assert_eq!(
+                mmr.range_proof(start, end).await.unwrap(),
+                reference.range_proof(start, end).unwrap()
+            );
+            mmr.prune_to_pos(mmr.size()).await.unwrap();
+            assert_eq!(mmr.pruned_to_pos(), mmr.size());
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            let element = Digest::from(vec![0u8; 32]);
+            assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            mmr.close().await.unwrap();
+        });
+    }
+
+    /// Return the name and length of every blob storing `section`.
+    async fn section_blobs<B: Blob, E: Storage<B>>(
+        context: &E,
+        section: u64,
+    ) -> Vec<(Vec<u8>, u64)> {
+        let mut blobs = Vec::new();
+        for name in context.scan("test").await.unwrap() {
+            if name[..8] == section.to_be_bytes() {
+                let blob = context.open("test", &name).await.unwrap();
+                blobs.push((name, blob.len().await.unwrap()));
+                blob.close().await.unwrap();
+            }
+        }
+        blobs
+    }
+
+    #[test_traced]
+    fn test_compact() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            const SECTION_NODES: u64 = 32;
+            let page_len = CHECKSUM_LEN + PAGE_NODES * 32;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(8, SECTION_NODES))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut elements = Vec::new();
+            for i in 0u8..200 {
+                let element = Digest::from(vec![i; 32]);
+                let pos = mmr.add(&element).await.unwrap();
+                assert_eq!(pos, reference.add(&element));
+                elements.push((pos, element));
+            }
+            mmr.sync().await.unwrap();
+            let root_hash = reference.root_hash();
+
+            // Prune at awkward offsets within sections (recompacting the same section, and moving on
+            // to the next), compacting after each
+            for index in [70, 77, 90, 150] {
+                mmr.prune_to_pos(elements[index].0).await.unwrap();
+                let pruned_to_pos = mmr.pruned_to_pos();
+                let section = pruned_to_pos / SECTION_NODES;
+                let first_page = (pruned_to_pos % SECTION_NODES - 1) / PAGE_NODES;
+                let snapshot = mmr.snapshot();
+                mmr.compact().await.unwrap();
+
+                // The boundary section (which is full) is replaced by a compacted blob omitting the
+                // pruned pages
+                let blobs = section_blobs(&context, section).await;
+                assert_eq!(blobs.len(), 1);
+                let (name, len) = &blobs[0];
+                assert_eq!(name[8..], first_page.to_be_bytes());
+                assert_eq!(*len, (SECTION_NODES / PAGE_NODES - first_page) * page_len);
+
+                // Compacting again is a no-op
+                mmr.compact().await.unwrap();
+                assert_eq!(section_blobs(&context, section).await, blobs);
+
+                // Retained elements are served by the `Mmr` (before and after a restart), by
+                // snapshots taken before compaction, and by readers
+                assert_eq!(mmr.verify_all().await.unwrap(), Vec::new());
+                for (pos, _) in elements.iter().filter(|(pos, _)| *pos >= pruned_to_pos) {
+                    let proof = reference.proof(*pos).unwrap();
+                    assert_eq!(mmr.proof(*pos).await.unwrap(), proof);
+                    assert_eq!(snapshot.proof(*pos).await.unwrap(), proof);
+                }
+                mmr.close().await.unwrap();
+                mmr = Mmr::init(context.clone(), test_config(8, SECTION_NODES))
+                    .await
+                    .unwrap();
+                assert_eq!(mmr.root_hash(), root_hash);
+                assert_eq!(mmr.pruned_to_pos(), pruned_to_pos);
+                let reader = MmrReader::<_, Sha256>::open(&context, &test_config(8, SECTION_NODES))
+                    .await
+                    .unwrap();
+                assert_eq!(reader.root_hash(), root_hash);
+                for (pos, _) in elements.iter().filter(|(pos, _)| *pos >= pruned_to_pos) {
+                    let proof = reference.proof(*pos).unwrap();
+                    assert_eq!(mmr.proof(*pos).await.unwrap(), proof);
+                    assert_eq!(reader.proof(*pos).await.unwrap(), proof);
+                }
+            }
+
+            // Rewinding to the boundary and re-adding elements writes through the compacted section
+            let pruned_to_pos = mmr.pruned_to_pos();
+            mmr.rewind(pruned_to_pos).await.unwrap();
+            for (pos, element) in elements.iter().skip(150) {
+                assert_eq!(mmr.add(element).await.unwrap(), *pos);
+            }
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.root_hash(), root_hash);
+            assert_eq!(mmr.verify_all().await.unwrap(), Vec::new());
+
+            // Simulate a crash part way through writing a compacted blob (before it was committed)
+            mmr.prune_to_pos(elements[185].0).await.unwrap();
+            let pruned_to_pos = mmr.pruned_to_pos();
+            let section = pruned_to_pos / SECTION_NODES;
+            let first_page = (pruned_to_pos % SECTION_NODES - 1) / PAGE_NODES;
+            assert!(first_page > 0);
+            let original = section_blobs(&context, section).await;
+            mmr.close().await.unwrap();
+            let partial = context
+                .open("test", &blob_name(section, first_page))
+                .await
+                .unwrap();
+            partial.write_at(&[7u8; 50], 0).await.unwrap();
+            partial.close().await.unwrap();
+
+            // Restart and confirm the uncommitted blob was discarded
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(8, SECTION_NODES))
+                .await
+                .unwrap();
+            assert_eq!(section_blobs(&context, section).await, original);
+            assert_eq!(mmr.root_hash(), root_hash);
+
+            // Simulate a crash after committing a compacted blob but before removing the original
+            let blob = context.open("test", &original[0].0).await.unwrap();
+            let mut bytes = vec![0u8; original[0].1 as usize];
+            blob.read_at(&mut bytes, 0).await.unwrap();
+            blob.close().await.unwrap();
+            mmr.compact().await.unwrap();
+            mmr.close().await.unwrap();
+            let blob = context.open("test", &original[0].0).await.unwrap();
+            blob.write_at(&bytes, 0).await.unwrap();
+            blob.close().await.unwrap();
+            assert_eq!(section_blobs(&context, section).await.len(), 2);
+
+            // Restart and confirm the original was removed in favor of the compacted blob
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(8, SECTION_NODES))
+                .await
+                .unwrap();
+            let blobs = section_blobs(&context, section).await;
+            assert_eq!(blobs.len(), 1);
+            assert_eq!(blobs[0].0, blob_name(section, first_page));
+            assert_eq!(mmr.root_hash(), root_hash);
+            for (pos, _) in elements.iter().filter(|(pos, _)| *pos >= pruned_to_pos) {
+                assert_eq!(
+                    mmr.proof(*pos).await.unwrap(),
+                    reference.proof(*pos).unwrap()
+                );
+            }
+
+            // Elements can still be added after compaction
+            for i in 200u8..=255 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            mmr.close().await.unwrap();
+        });
+    }
+
+    /// Return the name and contents of every blob in `partition`.
+    async fn read_blobs<B: Blob, E: Storage<B>>(
+        context: &E,
+        partition: &str,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut blobs = Vec::new();
+        for name in context.scan(partition).await.unwrap() {
+            let blob = context.open(partition, &name).await.unwrap();
+            let mut bytes = vec![0u8; blob.len().await.unwrap() as usize];
+            blob.read_at(&mut bytes, 0).await.unwrap();
+            blob.close().await.unwrap();
+            blobs.push((name, bytes));
+        }
+        blobs
+    }
+
+    #[test_traced]
+    fn test_open_expecting() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Persist 20 elements, recording the checkpoints of a reference with 30 and of one
+            // that diverged after 10
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut diverged = MemMmr::<Sha256>::new();
+            let mut checkpoints = vec![reference.checkpoint()];
+            let mut diverged_checkpoints = vec![diverged.checkpoint()];
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            for i in 0u8..30 {
+                let element = Digest::from(vec![i; 32]);
+                if i < 20 {
+                    mmr.add(&element).await.unwrap();
+                }
+                reference.add(&element);
+                checkpoints.push(reference.checkpoint());
+                let element = if i < 10 {
+                    element
+                } else {
+                    Digest::from(vec![0xff; 32])
+                };
+                diverged.add(&element);
+                diverged_checkpoints.push(diverged.checkpoint());
+            }
+            mmr.close().await.unwrap();
+            let open = |expected: Checkpoint| {
+                Mmr::<_, _, Sha256>::open_expecting(context.clone(), test_config(4, 8), expected)
+            };
+            let open_at_least = |expected: Checkpoint| {
+                Mmr::<_, _, Sha256>::open_expecting_at_least(
+                    context.clone(),
+                    test_config(4, 8),
+                    expected,
+                )
+            };
+
+            // Matched
+            let mmr = open(checkpoints[20].clone()).await.unwrap();
+            mmr.close().await.unwrap();
+            let mmr = open_at_least(checkpoints[20].clone()).await.unwrap();
+            mmr.close().await.unwrap();
+
+            // Ahead of the expectation (only allowed when expecting a minimum)
+            assert!(matches!(
+                open(checkpoints[15].clone()).await,
+                Err(Error::SizeMismatch {
+                    expected: 15,
+                    actual: 20
+                })
+            ));
+            let mmr = open_at_least(checkpoints[15].clone()).await.unwrap();
+            assert_eq!(mmr.size(), checkpoints[20].size);
+            mmr.close().await.unwrap();
+
+            // Behind the expectation
+            assert!(matches!(
+                open(checkpoints[25].clone()).await,
+                Err(Error::SizeMismatch {
+                    expected: 25,
+                    actual: 20
+                })
+            ));
+            assert!(matches!(
+                open_at_least(checkpoints[25].clone()).await,
+                Err(Error::SizeMismatch {
+                    expected: 25,
+                    actual: 20
+                })
+            ));
+
+            // Diverged from the expectation (at the expected size)
+            assert!(matches!(
+                open(diverged_checkpoints[20].clone()).await,
+                Err(Error::RootMismatch { expected, actual })
+                    if expected == diverged_checkpoints[20].root
+                        && actual == checkpoints[20].root
+            ));
+            assert!(matches!(
+                open_at_least(diverged_checkpoints[15].clone()).await,
+                Err(Error::RootMismatch { expected, actual })
+                    if expected == diverged_checkpoints[15].root
+                        && actual == checkpoints[15].root
+            ));
+            let mmr = open_at_least(diverged_checkpoints[10].clone())
+                .await
+                .unwrap();
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_checkpoint() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let elements: Vec<_> = (0u8..30).map(|i| Digest::from(vec![i; 32])).collect();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut checkpoints = vec![reference.checkpoint()];
+            for element in elements.iter() {
+                reference.add(element);
+                checkpoints.push(reference.checkpoint());
+            }
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.checkpoint(), None);
+
+            // Syncing commits a checkpoint of the synced MMR
+            for element in elements[..10].iter() {
+                mmr.add(element).await.unwrap();
+            }
+            assert_eq!(mmr.checkpoint(), None);
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[10]));
+
+            // Simulate a crash before the sections written by a sync were synced (the checkpoint and
+            // the nodes backing it survive)
+            for element in elements[10..20].iter() {
+                mmr.add(element).await.unwrap();
+            }
+            drop(mmr);
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), checkpoints[10].size);
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[10]));
+
+            // Simulate a crash after the sections were synced but before the checkpoint was
+            // committed (the previous checkpoint is restored alongside the synced nodes, and the
+            // next sync checkpoints them)
+            let metadata = read_blobs(&context, "test_metadata").await;
+            for element in elements[10..20].iter() {
+                mmr.add(element).await.unwrap();
+            }
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[20]));
+            drop(mmr);
+            for (name, bytes) in metadata {
+                let blob = context.open("test_metadata", &name).await.unwrap();
+                blob.write_at(&bytes, 0).await.unwrap();
+                blob.truncate(bytes.len() as u64).await.unwrap();
+                blob.close().await.unwrap();
+            }
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), checkpoints[20].size);
+            assert_eq!(mmr.root_hash(), checkpoints[20].root);
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[10]));
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[20]));
+
+            // Simulate a crash after the checkpoint was committed
+            drop(mmr);
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[20]));
+
+            // If storage loses nodes the latest checkpoint depends on, the previous checkpoint is
+            // restored instead
+            for element in elements[20..30].iter() {
+                mmr.add(element).await.unwrap();
+            }
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[30]));
+            drop(mmr);
+            context
+                .remove("test", Some(&6u64.to_be_bytes()))
+                .await
+                .unwrap();
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert!(mmr.size() < checkpoints[30].size);
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[20]));
+
+            // If it loses nodes every checkpoint depends on, no checkpoint is restored
+            drop(mmr);
+            context
+                .remove("test", Some(&3u64.to_be_bytes()))
+                .await
+                .unwrap();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), checkpoints[13].size);
+            assert_eq!(mmr.checkpoint(), None);
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[13]));
+
+            // Rewinding below the checkpoint checkpoints the rewound MMR before discarding nodes
+            mmr.rewind(checkpoints[10].size).await.unwrap();
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[10]));
+            drop(mmr);
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), checkpoints[10].size);
+            assert_eq!(mmr.checkpoint(), Some(&checkpoints[10]));
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_rebuild() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Persist 100 elements, pruning and compacting some of them
+            let elements: Vec<_> = (0u8..100).map(|i| Digest::from(vec![i; 32])).collect();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut positions = Vec::new();
+            for element in elements.iter() {
+                positions.push(mmr.add(element).await.unwrap());
+                reference.add(element);
+            }
+            mmr.prune_to_pos(positions[21]).await.unwrap();
+            mmr.compact().await.unwrap();
+            mmr.close().await.unwrap();
+            let root = reference.root_hash();
+
+            // Corrupt the last page of a full section
+            let blob = context.open("test", &5u64.to_be_bytes()).await.unwrap();
+            let len = blob.len().await.unwrap();
+            blob.write_at(&[0u8; 8], len - 8).await.unwrap();
+            blob.close().await.unwrap();
+            assert!(matches!(
+                Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8)).await,
+                Err(Error::Corrupt { section: 5, .. })
+            ));
+            let corrupted = read_blobs(&context, "test").await;
+
+            // A rebuild producing the wrong root is discarded without touching the stored nodes
+            let other_root = Digest::from(vec![0u8; 32]);
+            let result = Mmr::<_, _, Sha256>::rebuild_from(
+                context.clone(),
+                test_config(4, 8),
+                elements.iter().cloned(),
+                Some(&other_root),
+                |_| {},
+            )
+            .await;
+            assert!(matches!(
+                result,
+                Err(Error::Mmr(crate::mmr::Error::RootMismatch))
+            ));
+            assert_eq!(read_blobs(&context, "test").await, corrupted);
+            assert!(context.scan("test_rebuild").await.is_err());
+
+            // Rebuilding from the elements (ignoring any staged nodes left by an interrupted
+            // rebuild) restores every node, reporting progress along the way
+            let staged = context
+                .open("test_rebuild", &0u64.to_be_bytes())
+                .await
+                .unwrap();
+            staged.write_at(&[7u8; 50], 0).await.unwrap();
+            staged.close().await.unwrap();
+            let mut reported = Vec::new();
+            let mut mmr = Mmr::<_, _, Sha256>::rebuild_from(
+                context.clone(),
+                test_config(4, 8),
+                elements.iter().cloned(),
+                Some(&root),
+                |replayed| reported.push(replayed),
+            )
+            .await
+            .unwrap();
+            assert!(reported.len() > 1);
+            assert!(reported.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(reported.last(), Some(&100));
+            assert_eq!(mmr.root_hash(), root);
+            assert_eq!(mmr.pruned_to_pos(), 0);
+            assert_eq!(mmr.checkpoint(), Some(&reference.checkpoint()));
+            assert_eq!(mmr.verify_all().await.unwrap(), Vec::new());
+            for pos in positions.iter() {
+                assert_eq!(
+                    mmr.proof(*pos).await.unwrap(),
+                    reference.proof(*pos).unwrap()
+                );
+            }
+            mmr.close().await.unwrap();
+            assert!(context.scan("test_rebuild").await.is_err());
+            let rebuilt = read_blobs(&context, "test").await;
+
+            // Simulate a crash after a rebuild was committed but before the stored sections were
+            // replaced (the next init completes it)
+            for (name, bytes) in rebuilt.iter() {
+                let staged = context.open("test_rebuild", name).await.unwrap();
+                staged.write_at(bytes, 0).await.unwrap();
+                staged.close().await.unwrap();
+            }
+            for (name, bytes) in corrupted.iter() {
+                let blob = context.open("test", name).await.unwrap();
+                blob.write_at(bytes, 0).await.unwrap();
+                blob.truncate(bytes.len() as u64).await.unwrap();
+                blob.close().await.unwrap();
+            }
+            let mut metadata = Metadata::init(
+                context.clone(),
+                MetadataConfig {
+                    registry: Arc::new(Mutex::new(Registry::default())),
+                    partition: "test_metadata".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+            metadata.put(REBUILD_KEY, bytes::Bytes::new());
+            metadata.close().await.unwrap();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), test_config(4, 8))
+                .await
+                .unwrap();
+            assert_eq!(read_blobs(&context, "test").await, rebuilt);
+            assert!(context.scan("test_rebuild").await.is_err());
+            assert_eq!(mmr.root_hash(), root);
+            for pos in positions.iter() {
+                assert_eq!(
+                    mmr.proof(*pos).await.unwrap(),
+                    reference.proof(*pos).unwrap()
+                );
+            }
+            mmr.close().await.unwrap();
+        });
+    }
+
+    /// A `Storage` counting the reads made from the blobs it opens (delaying each by
+    /// `read_latency_ms` and failing them while `fail_reads` is set), failing writes while
+    /// `fail_writes` is set, and failing every sync once `syncs_left` have been made.
+    #[derive(Clone)]
+    struct CountingStorage<E> {
+        inner: E,
+        reads: Arc<AtomicU64>,
+        read_latency_ms: Arc<AtomicU64>,
+        fail_reads: Arc<AtomicBool>,
+        fail_writes: Arc<AtomicBool>,
+        syncs_left: Arc<AtomicU64>,
+    }
+
+    impl<E> CountingStorage<E> {
+        fn new(inner: E) -> Self {
+            Self {
+                inner,
+                reads: Arc::new(AtomicU64::new(0)),
+                read_latency_ms: Arc::new(AtomicU64::new(0)),
+                fail_reads: Arc::new(AtomicBool::new(false)),
+                fail_writes: Arc::new(AtomicBool::new(false)),
+                syncs_left: Arc::new(AtomicU64::new(u64::MAX)),
+            }
+        }
+    }
+
+    /// A `Blob` opened by a `CountingStorage`, sleeping on `clock` to delay reads.
+    #[derive(Clone)]
+    struct CountingBlob<B, C> {
+        inner: B,
+        clock: C,
+        reads: Arc<AtomicU64>,
+        read_latency_ms: Arc<AtomicU64>,
+        fail_reads: Arc<AtomicBool>,
+        fail_writes: Arc<AtomicBool>,
+        syncs_left: Arc<AtomicU64>,
+    }
+
+    impl<E: Clock> Clock for CountingStorage<E> {
+        fn current(&self) -> SystemTime {
+            self.inner.current()
+        }
+
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+            self.inner.sleep(duration)
+        }
+
+        fn sleep_until(&self, deadline: SystemTime) -> impl Future<Output = ()> + Send + 'static {
+            self.inner.sleep_until(deadline)
+        }
+    }
+
+    impl<E: Spawner> Spawner for CountingStorage<E> {
+        fn spawn<F, T>(&self, label: &str, f: F) -> Handle<T>
+        where
+            F: Future<Output = T> + Send + 'static,
+            T: Send + 'static,
+        {
+            self.inner.spawn(label, f)
+        }
+
+        fn stop(&self, value: i32) {
+            self.inner.stop(value)
+        }
+
+        fn stopped(&self) -> Signal {
+            self.inner.stopped()
+        }
+    }
+
+    impl<B: Blob, E: Clock + Storage<B>> Storage<CountingBlob<B, E>> for CountingStorage<E> {
+        async fn open(&self, partition: &str, name: &[u8]) -> Result<CountingBlob<B, E>, RError> {
+            Ok(CountingBlob {
+                inner: self.inner.open(partition, name).await?,
+                clock: self.inner.clone(),
+                reads: self.reads.clone(),
+                read_latency_ms: self.read_latency_ms.clone(),
+                fail_reads: self.fail_reads.clone(),
+                fail_writes: self.fail_writes.clone(),
+                syncs_left: self.syncs_left.clone(),
+            })
+        }
+
+        async fn remove(&self, partition: &str, name: Option<&[u8]>) -> Result<(), RError> {
+            self.inner.remove(partition, name).await
+        }
+
+        async fn scan(&self, partition: &str) -> Result<Vec<Vec<u8>>, RError> {
+            self.inner.scan(partition).await
+        }
+    }
+
+    impl<B: Blob, C: Clock> Blob for CountingBlob<B, C> {
+        async fn len(&self) -> Result<u64, RError> {
+            self.inner.len().await
+        }
+
+        async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), RError> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            let latency = self.read_latency_ms.load(Ordering::Relaxed);
+            if latency > 0 {
+                self.clock.sleep(Duration::from_millis(latency)).await;
+            }
+            if self.fail_reads.load(Ordering::Relaxed) {
+                return Err(RError::ReadFailed);
+            }
+            self.inner.read_at(buf, offset).await
+        }
+
+        async fn write_at(&self, buf: &[u8], offset: u64) -> Result<(), RError> {
+            if self.fail_writes.load(Ordering::Relaxed) {
+                return Err(RError::WriteFailed);
+            }
+            self.inner.write_at(buf, offset).await
+        }
+
+        async fn truncate(&self, len: u64) -> Result<(), RError> {
+            self.inner.truncate(len).await
+        }
+
+        async fn sync(&self) -> Result<(), RError> {
+            self.syncs_left
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    left.checked_sub(1)
+                })
+                .map_err(|_| RError::WriteFailed)?;
+            self.inner.sync().await
+        }
+
+        async fn close(self) -> Result<(), RError> {
+            self.inner.close().await
+        }
+    }
+
+    #[test_traced]
+    fn test_hot_nodes() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let storage = CountingStorage::new(context.clone());
+            let reads = storage.reads.clone();
+
+            // Flushing 3 nodes at a time leaves most pages partially filled, but with the nodes
+            // already in them hot, appends (and the syncs they trigger) never read from storage
+            let registry = Arc::new(Mutex::new(Registry::default()));
+            let mut cfg = test_config(3, 32);
+            cfg.registry = registry.clone();
+            cfg.sync_policy = SyncPolicy::EveryNAppends(16);
+            cfg.hot_nodes = PAGE_NODES as usize - 1;
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let initial = reads.load(Ordering::Relaxed);
+            let mut last = 0;
+            for i in 0u8..200 {
+                let element = Digest::from(vec![i; 32]);
+                last = mmr.add(&element).await.unwrap();
+                assert_eq!(last, reference.add(&element));
+            }
+            mmr.sync().await.unwrap();
+            assert_eq!(reads.load(Ordering::Relaxed), initial);
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            let mut buffer = String::new();
+            encode(&mut buffer, &registry.lock().unwrap()).unwrap();
+            assert!(buffer.contains(&format!("mmr_hot_nodes {}\n", PAGE_NODES - 1)));
+
+            // Proofs match whether their nodes are hot or read from storage
+            for pos in [0, last] {
+                assert_eq!(mmr.proof(pos).await.unwrap(), reference.proof(pos).unwrap());
+            }
+            assert!(reads.load(Ordering::Relaxed) > initial);
+
+            // After a restart, the hot nodes are only read back until they are warm again
+            mmr.close().await.unwrap();
+            cfg.registry = Arc::new(Mutex::new(Registry::default()));
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            for i in 200u8..210 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            let warm = reads.load(Ordering::Relaxed);
+            for i in 210u8..=255 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            mmr.sync().await.unwrap();
+            assert_eq!(reads.load(Ordering::Relaxed), warm);
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+
+            // Rewinding discards the hot nodes after the new size
+            let mut rewound = MemMmr::<Sha256>::new();
+            for i in 0u8..128 {
+                rewound.add(&Digest::from(vec![i; 32]));
+            }
+            mmr.rewind(rewound.size()).await.unwrap();
+            assert_eq!(mmr.root_hash(), rewound.root_hash());
+            let element = Digest::from(vec![0u8; 32]);
+            let pos = mmr.add(&element).await.unwrap();
+            assert_eq!(pos, rewound.add(&element));
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.proof(pos).await.unwrap(), rewound.proof(pos).unwrap());
+            mmr.close().await.unwrap();
+
+            // Without hot nodes, the same appends read partially filled pages back from storage
+            let mut cfg = test_config(3, 32);
+            cfg.partition = "cold".to_string();
+            cfg.metadata_partition = "cold_metadata".to_string();
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage, cfg).await.unwrap();
+            let initial = reads.load(Ordering::Relaxed);
+            for i in 0u8..200 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            mmr.sync().await.unwrap();
+            assert!(reads.load(Ordering::Relaxed) > initial);
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_proof_retry() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let storage = CountingStorage::new(context.clone());
+            let fail_reads = storage.fail_reads.clone();
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage, test_config(3, 32))
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            for i in 0u8..100 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            mmr.sync().await.unwrap();
+
+            // A proof requiring stored nodes fails while reads fail...
+            fail_reads.store(true, Ordering::Relaxed);
+            assert!(matches!(
+                mmr.proof(0).await,
+                Err(Error::Runtime(RError::ReadFailed))
+            ));
+            assert!(matches!(
+                mmr.range_proof(0, 10).await,
+                Err(Error::Runtime(RError::ReadFailed))
+            ));
+
+            // ...and succeeds once they recover
+            fail_reads.store(false, Ordering::Relaxed);
+            assert_eq!(mmr.proof(0).await.unwrap(), reference.proof(0).unwrap());
+            assert_eq!(
+                mmr.range_proof(0, 10).await.unwrap(),
+                reference.range_proof(0, 10).unwrap()
+            );
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_write_behind() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let storage = CountingStorage::new(context.clone());
+            let fail_writes = storage.fail_writes.clone();
+            let mut cfg = test_config(4, 16);
+            cfg.write_behind = true;
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut roots = HashMap::new();
+
+            // Nodes being written in the background are served like any other
+            let mut positions = Vec::new();
+            for i in 0u8..50 {
+                let element = Digest::from(vec![i; 32]);
+                positions.push(mmr.add(&element).await.unwrap());
+                reference.add(&element);
+                roots.insert(reference.size(), reference.root_hash());
+                assert_eq!(mmr.size(), reference.size());
+                assert_eq!(mmr.root_hash(), reference.root_hash());
+                for pos in positions.iter() {
+                    assert_eq!(
+                        mmr.proof(*pos).await.unwrap(),
+                        reference.proof(*pos).unwrap()
+                    );
+                }
+            }
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.durable_size(), mmr.size());
+
+            // A failed background write is reported by the barrier, keeping its nodes buffered
+            fail_writes.store(true, Ordering::Relaxed);
+            let durable = mmr.durable_size();
+            let mut i = 50u8;
+            while mmr.size() - durable < 4 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+                roots.insert(reference.size(), reference.root_hash());
+                i += 1;
+            }
+            assert!(matches!(
+                mmr.wait_for_flush().await,
+                Err(Error::Runtime(RError::WriteFailed))
+            ));
+            assert_eq!(mmr.size(), reference.size());
+            assert_eq!(mmr.durable_size(), durable);
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+            assert!(mmr.sync().await.is_err());
+
+            // ...and written again once storage recovers
+            fail_writes.store(false, Ordering::Relaxed);
+            mmr.sync().await.unwrap();
+            assert_eq!(mmr.durable_size(), reference.size());
+            drop(mmr);
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), reference.size());
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+
+            // Simulate a crash with a background write in progress: the MMR reopens to at least the
+            // durable size, at a size it had when an element was added
+            for i in 100u8..130 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+                roots.insert(reference.size(), reference.root_hash());
+            }
+            let durable = mmr.durable_size();
+            drop(mmr);
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage, cfg).await.unwrap();
+            assert!(mmr.size() >= durable);
+            assert_eq!(&mmr.root_hash(), roots.get(&mmr.size()).unwrap());
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_concurrent_proof_reads() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let storage = CountingStorage::new(context.clone());
+            let reads = storage.reads.clone();
+            let read_latency_ms = storage.read_latency_ms.clone();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut cfg = test_config(3, 64);
+            cfg.max_concurrent_reads = 1;
+            let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            for i in 0u8..200 {
+                let element = Digest::from(vec![i; 32]);
+                assert_eq!(mmr.add(&element).await.unwrap(), reference.add(&element));
+            }
+            mmr.sync().await.unwrap();
+            mmr.close().await.unwrap();
+
+            // Read sequentially, a proof takes one round trip per stored node it requires
+            read_latency_ms.store(10, Ordering::Relaxed);
+            let mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let before = (reads.load(Ordering::Relaxed), context.current());
+            let proof = mmr.proof(0).await.unwrap();
+            let node_reads = reads.load(Ordering::Relaxed) - before.0;
+            let elapsed = context.current().duration_since(before.1).unwrap();
+            assert_eq!(proof, reference.proof(0).unwrap());
+            assert!(node_reads > 3);
+            assert_eq!(elapsed, Duration::from_millis(10 * node_reads));
+            mmr.close().await.unwrap();
+
+            // Read concurrently, it takes a single round trip
+            cfg.max_concurrent_reads = 16;
+            let mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let before = (reads.load(Ordering::Relaxed), context.current());
+            assert_eq!(mmr.proof(0).await.unwrap(), proof);
+            assert_eq!(reads.load(Ordering::Relaxed) - before.0, node_reads);
+            let elapsed = context.current().duration_since(before.1).unwrap();
+            assert_eq!(elapsed, Duration::from_millis(10));
+
+            // Snapshots read concurrently as well
+            let snapshot = mmr.snapshot();
+            let start = context.current();
+            assert_eq!(
+                snapshot.range_proof(0, 10).await.unwrap(),
+                reference.range_proof(0, 10).unwrap()
+            );
+            let elapsed = context.current().duration_since(start).unwrap();
+            assert_eq!(elapsed, Duration::from_millis(10));
+            mmr.close().await.unwrap();
+        });
+    }
+
+    #[test_traced]
+    fn test_append_batch_atomic() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let storage = CountingStorage::new(context.clone());
+            let syncs_left = storage.syncs_left.clone();
+            let mut before = MemMmr::<Sha256>::new();
+            let mut after = MemMmr::<Sha256>::new();
+            for i in 0u8..100 {
+                let element = Digest::from(vec![i; 32]);
+                before.add(&element);
+                after.add(&element);
+            }
+            let batch: Vec<_> = (100u8..150).map(|i| Digest::from(vec![i; 32])).collect();
+            let positions: Vec<_> = batch.iter().map(|element| after.add(element)).collect();
+
+            // Crash at every sync made while appending the batch: the MMR is recovered either
+            // before or after the batch, never in between
+            let mut crashes = 0;
+            for attempt in 0.. {
+                let mut cfg = test_config(3, 32);
+                cfg.partition = format!("batch_{attempt}");
+                cfg.metadata_partition = format!("batch_metadata_{attempt}");
+                let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                    .await
+                    .unwrap();
+                for i in 0u8..100 {
+                    mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+                }
+                mmr.sync().await.unwrap();
+                assert_eq!(mmr.committed_batch(), None);
+
+                syncs_left.store(attempt, Ordering::Relaxed);
+                let result = mmr.append_batch_atomic(&batch, 7).await;
+                drop(mmr);
+                syncs_left.store(u64::MAX, Ordering::Relaxed);
+                let committed = match result {
+                    Ok(added) => {
+                        assert_eq!(added, positions);
+                        true
+                    }
+                    Err(_) => {
+                        crashes += 1;
+                        false
+                    }
+                };
+
+                // Readers ignore a batch that was never committed
+                let reader = MmrReader::<_, Sha256>::open(&storage, &cfg).await.unwrap();
+                if reader.root_hash() == after.root_hash() {
+                    assert_eq!(reader.size(), after.size());
+                } else {
+                    assert!(!committed);
+                    assert_eq!(reader.root_hash(), before.root_hash());
+                }
+
+                cfg.registry = Arc::new(Mutex::new(Registry::default()));
+                let mut mmr = Mmr::<_, _, Sha256>::init(storage.clone(), cfg.clone())
+                    .await
+                    .unwrap();
+                if mmr.root_hash() == after.root_hash() {
+                    assert_eq!(mmr.committed_batch(), Some(7));
+                    assert_eq!(mmr.checkpoint().unwrap().size, after.size());
+                } else {
+                    assert!(!committed);
+                    assert_eq!(mmr.root_hash(), before.root_hash());
+                    assert_eq!(mmr.committed_batch(), None);
+                    assert!(mmr.checkpoint().unwrap().size <= before.size());
+
+                    // The rolled back batch can be appended again
+                    assert_eq!(mmr.append_batch_atomic(&batch, 7).await.unwrap(), positions);
+                    assert_eq!(mmr.root_hash(), after.root_hash());
+                }
+                mmr.close().await.unwrap();
+                if committed {
+                    break;
+                }
+            }
+            assert!(crashes > 2);
+        });
+    }
+
+    #[test_traced]
+    fn test_root_index() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let elements: Vec<_> = (0u8..100).map(|i| Digest::from(vec![i; 32])).collect();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut roots = vec![reference.root_hash()];
+            for element in elements.iter() {
+                reference.add(element);
+                roots.push(reference.root_hash());
+            }
+
+            // Roots recorded every 3 leaves match those computed from the peaks
+            let mut cfg = test_config(7, 16);
+            cfg.root_index_interval = 3;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut positions = Vec::new();
+            for (i, element) in elements.iter().enumerate() {
+                positions.push(mmr.add(element).await.unwrap());
+                if i % 10 == 0 {
+                    mmr.sync().await.unwrap();
+                }
+            }
+            for (leaf_count, root) in roots.iter().enumerate() {
+                let leaf_count = leaf_count as u64;
+                assert_eq!(&mmr.root_at_leaf(leaf_count).await.unwrap(), root);
+                let size = node_count(leaf_count);
+                assert_eq!(&mmr.root_hash_at(size).await.unwrap(), root);
+            }
+            assert!(matches!(
+                mmr.root_at_leaf(101).await,
+                Err(Error::ElementOutOfRange(101))
+            ));
+            mmr.sync().await.unwrap();
+            let index = read_blobs(&context, "test_roots").await;
+            assert_eq!(index.len(), 1);
+            assert_eq!(index[0].0, 3u64.to_be_bytes().to_vec());
+            assert_eq!(index[0].1.len(), 33 * 32);
+
+            // Roots of leaves lost in a crash are discarded, then recorded again
+            let mut other = Vec::new();
+            for i in 100u8..110 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                other.push(element);
+            }
+            drop(mmr);
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.size(), reference.size());
+            for (leaf_count, root) in roots.iter().enumerate() {
+                assert_eq!(&mmr.root_at_leaf(leaf_count as u64).await.unwrap(), root);
+            }
+
+            // Rewinding discards the roots of the rewound leaves
+            mmr.rewind(positions[50]).await.unwrap();
+            let mut rewound = MemMmr::<Sha256>::new();
+            let mut rewound_roots = vec![rewound.root_hash()];
+            for element in elements[..50].iter().chain(other.iter()) {
+                rewound.add(element);
+                rewound_roots.push(rewound.root_hash());
+            }
+            for element in other.iter() {
+                mmr.add(element).await.unwrap();
+            }
+            mmr.sync().await.unwrap();
+            for (leaf_count, root) in rewound_roots.iter().enumerate() {
+                assert_eq!(&mmr.root_at_leaf(leaf_count as u64).await.unwrap(), root);
+            }
+            mmr.close().await.unwrap();
+
+            // Enabling the index with another interval indexes the stored MMR in full, while
+            // disabling it removes the index
+            cfg.root_index_interval = 4;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            for (leaf_count, root) in rewound_roots.iter().enumerate() {
+                assert_eq!(&mmr.root_at_leaf(leaf_count as u64).await.unwrap(), root);
+            }
+            let index = read_blobs(&context, "test_roots").await;
+            assert_eq!(index.len(), 1);
+            assert_eq!(index[0].0, 4u64.to_be_bytes().to_vec());
+            assert_eq!(index[0].1.len(), 15 * 32);
+
+            // Indexed roots survive pruning
+            mmr.prune_to_pos(rewound.size()).await.unwrap();
+            assert_eq!(mmr.root_at_leaf(20).await.unwrap(), rewound_roots[20]);
+            assert!(matches!(
+                mmr.root_at_leaf(21).await,
+                Err(Error::ElementPruned(_))
+            ));
+            mmr.close().await.unwrap();
+            cfg.root_index_interval = 0;
+            let mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            mmr.close().await.unwrap();
+            assert!(context.scan("test_roots").await.is_err());
+
+            // A rebuild replaces the index along with the nodes
+            cfg.root_index_interval = 3;
+            let mut mmr = Mmr::<_, _, Sha256>::rebuild_from(
+                context.clone(),
+                cfg.clone(),
+                elements.iter().cloned(),
+                None,
+                |_| {},
+            )
+            .await
+            .unwrap();
+            for (leaf_count, root) in roots.iter().enumerate() {
+                assert_eq!(&mmr.root_at_leaf(leaf_count as u64).await.unwrap(), root);
+            }
+            mmr.close().await.unwrap();
+            assert!(context.scan("test_rebuild_roots").await.is_err());
+        });
+    }
+
+    #[test_traced]
+    fn test_gc() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let mut cfg = test_config(7, 16);
+            cfg.root_index_interval = 3;
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            for i in 0u8..40 {
+                mmr.add(&Digest::from(vec![i; 32])).await.unwrap();
+            }
+            mmr.sync().await.unwrap();
+            let root = mmr.root_hash();
+            let live = read_blobs(&context, "test").await;
+
+            // Nothing is orphaned in a consistent MMR
+            assert_eq!(mmr.gc(false).await.unwrap(), GcReport::default());
+
+            // Fabricate a stale compacted section, a section that was never committed, a staged
+            // rebuild, and the root index of another interval
+            let fabricated = [
+                ("test", blob_name(0, 1), 10u64),
+                ("test", 99u64.to_be_bytes().to_vec(), 20),
+                ("test_rebuild", 0u64.to_be_bytes().to_vec(), 30),
+                ("test_roots", 4u64.to_be_bytes().to_vec(), 40),
+            ];
+            for (partition, name, len) in fabricated.iter() {
+                let blob = context.open(partition, name).await.unwrap();
+                blob.write_at(&vec![1u8; *len as usize], 0).await.unwrap();
+                blob.close().await.unwrap();
+            }
+            let mut expected: Vec<_> = fabricated
+                .iter()
+                .map(|(partition, name, len)| Orphan {
+                    partition: partition.to_string(),
+                    name: name.clone(),
+                    len: *len,
+                })
+                .collect();
+            expected.sort_by(|a, b| {
+                let order = |p: &str| {
+                    ["test", "test_roots", "test_rebuild"]
+                        .iter()
+                        .position(|q| *q == p)
+                };
+                (order(&a.partition), &a.name).cmp(&(order(&b.partition), &b.name))
+            });
+
+            // A dry run reports the orphans without removing them
+            let report = mmr.gc(true).await.unwrap();
+            assert_eq!(report.orphans, expected);
+            assert_eq!(report.reclaimed_bytes, 100);
+            assert_eq!(read_blobs(&context, "test").await.len(), live.len() + 2);
+            assert_eq!(read_blobs(&context, "test_rebuild").await.len(), 1);
+
+            // Collecting removes only the orphans
+            assert_eq!(mmr.gc(false).await.unwrap(), report);
+            assert_eq!(read_blobs(&context, "test").await, live);
+            assert!(read_blobs(&context, "test_rebuild").await.is_empty());
+            let index = read_blobs(&context, "test_roots").await;
+            assert_eq!(index.len(), 1);
+            assert_eq!(index[0].0, 3u64.to_be_bytes().to_vec());
+            assert_eq!(mmr.gc(false).await.unwrap(), GcReport::default());
+
+            // The MMR is intact after a restart
+            mmr.close().await.unwrap();
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            assert_eq!(mmr.root_hash(), root);
+            assert_eq!(mmr.root_at_leaf(40).await.unwrap(), root);
+            mmr.close().await.unwrap();
+        });
+    }
+}
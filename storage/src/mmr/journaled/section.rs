@@ -0,0 +1,86 @@
+use commonware_runtime::{Blob, Error as RError};
+
+/// The `Blob` storing a section, which may have been compacted (see `Mmr::compact`) to omit the
+/// pages before `first_page`.
+///
+/// Offsets into a `Section` are always relative to the start of the uncompacted section, so the
+/// placement of nodes (see `Layout`) is the same whether or not the section has been compacted.
+/// Reading or writing before the first stored page fails with `RError::BlobInsufficientLength`.
+#[derive(Clone)]
+pub(crate) struct Section<B: Blob> {
+    blob: B,
+    // The index of the first page stored in `blob`.
+    first_page: u64,
+    // The offset (within the uncompacted section) of the first byte stored in `blob`.
+    offset: u64,
+}
+
+impl<B: Blob> Section<B> {
+    /// Return a section stored (uncompacted) in `blob`.
+    pub(crate) fn new(blob: B) -> Self {
+        Self::compacted(blob, 0, 0)
+    }
+
+    /// Return a section whose pages starting at `first_page` (at `offset` within the uncompacted
+    /// section) are stored in `blob`.
+    pub(crate) fn compacted(blob: B, first_page: u64, offset: u64) -> Self {
+        Self {
+            blob,
+            first_page,
+            offset,
+        }
+    }
+
+    /// Return the index of the first page stored in the section's blob.
+    pub(crate) fn first_page(&self) -> u64 {
+        self.first_page
+    }
+
+    /// Return the name of the blob storing `section`: the big-endian section index, followed by
+    /// the big-endian index of the first stored page if the section has been compacted.
+    pub(crate) fn name(&self, section: u64) -> Vec<u8> {
+        blob_name(section, self.first_page)
+    }
+
+    fn offset(&self, offset: u64) -> Result<u64, RError> {
+        offset
+            .checked_sub(self.offset)
+            .ok_or(RError::BlobInsufficientLength)
+    }
+}
+
+/// Return the name of the blob storing `section` compacted to start at `first_page` (or
+/// uncompacted, if `first_page` is 0).
+pub(crate) fn blob_name(section: u64, first_page: u64) -> Vec<u8> {
+    let mut name = section.to_be_bytes().to_vec();
+    if first_page > 0 {
+        name.extend_from_slice(&first_page.to_be_bytes());
+    }
+    name
+}
+
+impl<B: Blob> Blob for Section<B> {
+    async fn len(&self) -> Result<u64, RError> {
+        Ok(self.offset + self.blob.len().await?)
+    }
+
+    async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), RError> {
+        self.blob.read_at(buf, self.offset(offset)?).await
+    }
+
+    async fn write_at(&self, buf: &[u8], offset: u64) -> Result<(), RError> {
+        self.blob.write_at(buf, self.offset(offset)?).await
+    }
+
+    async fn truncate(&self, len: u64) -> Result<(), RError> {
+        self.blob.truncate(self.offset(len)?).await
+    }
+
+    async fn sync(&self) -> Result<(), RError> {
+        self.blob.sync().await
+    }
+
+    async fn close(self) -> Result<(), RError> {
+        self.blob.close().await
+    }
+}
@@ -0,0 +1,118 @@
+use super::metrics::Metrics;
+use super::section::Section;
+use super::storage::{read_node, Layout};
+use super::Error;
+use crate::mmr::hasher::Scheme;
+use crate::mmr::verification::{nodes_required_for_range_proof, Proof};
+use commonware_cryptography::Digest;
+use commonware_runtime::Blob;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// An immutable view of a journaled `Mmr` as of some size, returned by `Mmr::snapshot`.
+///
+/// A snapshot serves the same proofs the `Mmr` would have served when the snapshot was taken (which
+/// verify against the snapshot's root hash), no matter how many elements are added, flushed, or
+/// synced afterwards. It only reads nodes below its size: those already written to storage are
+/// read through its own handles to their sections, while those still buffered by the `Mmr` when
+/// the snapshot was taken are held by the snapshot itself. Because nodes are never modified once
+/// written, and the checksum of a page is only updated after the nodes it covers are written, a
+/// snapshot never observes a partially written node.
+///
+/// Nodes pruned from the `Mmr` after the snapshot was taken may no longer be readable, in which
+/// case generating a proof that requires them returns an error. Likewise, a snapshot taken before
+/// the `Mmr` is rewound to a smaller size must not be used afterwards, since it may read nodes that
+/// have since been replaced (producing proofs that fail verification).
+#[derive(Clone)]
+pub struct Snapshot<B: Blob> {
+    pub(super) sections: BTreeMap<u64, Section<B>>,
+    pub(super) layout: Layout,
+    pub(super) metrics: Metrics,
+
+    pub(super) size: u64,
+    pub(super) durable_size: u64,
+    pub(super) root: Digest,
+
+    // Nodes buffered by the `Mmr` when the snapshot was taken, starting at position `persisted`.
+    pub(super) persisted: u64,
+    pub(super) pending: Arc<[Digest]>,
+
+    pub(super) pruned_to_pos: u64,
+    pub(super) pinned_nodes: HashMap<u64, Digest>,
+
+    pub(super) max_concurrent_reads: usize,
+    pub(super) scheme: Scheme,
+}
+
+impl<B: Blob> Snapshot<B> {
+    /// Return the total number of nodes in the MMR as of this snapshot.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Return the number of nodes that had been synced to storage as of this snapshot.
+    pub fn durable_size(&self) -> u64 {
+        self.durable_size
+    }
+
+    /// Return the root hash of the MMR as of this snapshot.
+    pub fn root_hash(&self) -> Digest {
+        self.root.clone()
+    }
+
+    /// Return the position of the oldest node that had not been pruned as of this snapshot.
+    pub fn pruned_to_pos(&self) -> u64 {
+        self.pruned_to_pos
+    }
+
+    async fn get_node(&self, pos: u64) -> Result<Digest, Error> {
+        if pos >= self.persisted {
+            return Ok(self.pending[(pos - self.persisted) as usize].clone());
+        }
+        if pos < self.pruned_to_pos {
+            return self
+                .pinned_nodes
+                .get(&pos)
+                .cloned()
+                .ok_or(Error::ElementPruned(pos));
+        }
+        read_node(&self.sections, &self.layout, pos, Some(&self.metrics)).await
+    }
+
+    /// Return an inclusion proof for the specified element.
+    pub async fn proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        self.range_proof(element_pos, element_pos).await
+    }
+
+    /// Return an inclusion proof for the specified range of elements. The range is inclusive of
+    /// both endpoints. Up to `max_concurrent_reads` of the stored nodes it requires are read
+    /// concurrently.
+    ///
+    /// Returns `Error::ElementPruned` if the range starts before `pruned_to_pos`.
+    pub async fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        if end_element_pos >= self.size {
+            return Err(Error::ElementOutOfRange(end_element_pos));
+        }
+        if start_element_pos < self.pruned_to_pos {
+            return Err(Error::ElementPruned(start_element_pos));
+        }
+        let positions =
+            nodes_required_for_range_proof(self.size, start_element_pos, end_element_pos);
+        let hashes = stream::iter(positions)
+            .map(|pos| self.get_node(pos))
+            .buffered(self.max_concurrent_reads)
+            .try_collect()
+            .await?;
+        self.metrics.proofs_generated.inc();
+        Ok(Proof {
+            size: self.size,
+            hashes,
+            scheme: self.scheme.clone(),
+        })
+    }
+}
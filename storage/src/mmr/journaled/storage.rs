@@ -0,0 +1,2049 @@
+use super::cache::NodeCache;
+use super::metrics::{elapsed_secs, Metrics};
+use super::section::{blob_name, Section};
+use super::{Config, Error, GcReport, Orphan, Recovery, Snapshot, SyncPolicy};
+use crate::metadata::{Config as MetadataConfig, Metadata};
+use crate::mmr::export::{Exporter, Importer};
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{
+    largest_valid_size, nodes_needing_parents, pos_to_height, PeakIterator,
+};
+use crate::mmr::util::{self, leaf_count, node_count};
+use crate::mmr::verification::{nodes_required_for_range_proof, Checkpoint, Proof};
+use bytes::Bytes;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use commonware_runtime::{Blob, Clock, Error as RError, Handle, Spawner, Storage};
+use commonware_utils::hex;
+use futures::{stream, StreamExt, TryStreamExt};
+use prometheus_client::registry::Registry;
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// The metadata key storing the position the MMR has been pruned to.
+const PRUNED_TO_POS_KEY: u32 = 0;
+
+/// The metadata key storing the hashes of the nodes pinned by pruning.
+const PINNED_NODES_KEY: u32 = 1;
+
+/// The metadata key storing the section most recently compacted and the first page it retains.
+const COMPACTED_SECTION_KEY: u32 = 2;
+
+/// The metadata key storing the most recent checkpoint (see `Mmr::sync`).
+const CHECKPOINT_KEY: u32 = 3;
+
+/// The metadata key storing the checkpoint preceding the most recent one (if it covers no more
+/// nodes).
+const PREVIOUS_CHECKPOINT_KEY: u32 = 4;
+
+/// The metadata key marking a committed `Mmr::rebuild_from` whose staged sections may not have
+/// replaced the original ones yet.
+pub(super) const REBUILD_KEY: u32 = 5;
+
+/// The metadata key storing the intent of a batch being appended by `Mmr::append_batch_atomic`
+/// that has not been committed yet.
+const INTENT_KEY: u32 = 6;
+
+/// The metadata key storing the id of the batch most recently committed by
+/// `Mmr::append_batch_atomic`.
+const COMMITTED_BATCH_KEY: u32 = 7;
+
+/// The metadata key storing the fingerprint of the scheme the MMR was created with (see
+/// `Scheme::fingerprint`).
+const SCHEME_KEY: u32 = 8;
+
+/// The number of bytes of the checksum stored at the start of each page.
+pub(super) const CHECKSUM_LEN: u64 = 4;
+
+/// Open all section blobs stored in `partition`, keyed by section index, given the `compacted`
+/// section (and the first page it retains) last committed by `Mmr::compact` (see
+/// `load_compacted`).
+///
+/// Also returns the names of stale blobs that were not opened: compacted blobs that were never
+/// committed (left by an interrupted compaction), and the blobs a committed compaction replaced.
+pub(crate) async fn open_sections<B: Blob, E: Storage<B>>(
+    runtime: &E,
+    partition: &str,
+    layout: &Layout,
+    compacted: Option<(u64, u64)>,
+) -> Result<(BTreeMap<u64, Section<B>>, Vec<Vec<u8>>), Error> {
+    let mut sections = BTreeMap::new();
+    let mut stale = Vec::new();
+    let stored_blobs = match runtime.scan(partition).await {
+        Ok(blobs) => blobs,
+        Err(RError::PartitionMissing(_)) => Vec::new(),
+        Err(err) => return Err(Error::Runtime(err)),
+    };
+    for name in stored_blobs {
+        let hex_name = hex(&name);
+        let (section, first_page) = match name.len() {
+            8 => (u64::from_be_bytes(name[..8].try_into().unwrap()), 0),
+            16 => (
+                u64::from_be_bytes(name[..8].try_into().unwrap()),
+                u64::from_be_bytes(name[8..].try_into().unwrap()),
+            ),
+            _ => return Err(Error::InvalidBlobName(hex_name)),
+        };
+        let committed = match compacted {
+            Some((compacted_section, compacted_first_page)) if compacted_section == section => {
+                compacted_first_page
+            }
+            _ => 0,
+        };
+        if first_page != committed {
+            debug!(section, first_page, blob = hex_name, "found stale section");
+            stale.push(name);
+            continue;
+        }
+        let blob = runtime.open(partition, &name).await?;
+        debug!(section, first_page, blob = hex_name, "loaded section");
+        let offset = first_page * layout.page_len();
+        sections.insert(section, Section::compacted(blob, first_page, offset));
+    }
+    Ok((sections, stale))
+}
+
+/// The records of a metadata partition: either a `Metadata` open for writing, or the data read
+/// from one (without modifying it) with `Metadata::read`.
+pub(crate) trait Records {
+    /// Return the record stored under `key` (if any).
+    fn record(&self, key: u32) -> Option<&Bytes>;
+}
+
+impl<B: Blob, E: Clock + Storage<B>> Records for Metadata<B, E> {
+    fn record(&self, key: u32) -> Option<&Bytes> {
+        self.get(key)
+    }
+}
+
+impl Records for BTreeMap<u32, Bytes> {
+    fn record(&self, key: u32) -> Option<&Bytes> {
+        self.get(&key)
+    }
+}
+
+/// Return whether `metadata` records the scheme the MMR was created with (none is recorded for a
+/// new MMR, or one created before schemes were recorded), returning `mmr::Error::SchemeMismatch`
+/// if it records a scheme other than `scheme`.
+pub(crate) fn check_scheme<H: CHasher>(
+    metadata: &impl Records,
+    scheme: &Scheme,
+) -> Result<bool, Error> {
+    let Some(fingerprint) = metadata.record(SCHEME_KEY) else {
+        return Ok(false);
+    };
+    if fingerprint[..] != scheme.fingerprint::<H>()[..] {
+        return Err(crate::mmr::Error::SchemeMismatch.into());
+    }
+    Ok(true)
+}
+
+/// Load the section most recently compacted (and the first page it retains) from `metadata`.
+pub(crate) fn load_compacted(metadata: &impl Records) -> Result<Option<(u64, u64)>, Error> {
+    let Some(compacted) = metadata.record(COMPACTED_SECTION_KEY) else {
+        return Ok(None);
+    };
+    if compacted.len() != 16 {
+        return Err(Error::InvalidMetadata);
+    }
+    let section = u64::from_be_bytes(compacted[..8].try_into().unwrap());
+    let first_page = u64::from_be_bytes(compacted[8..].try_into().unwrap());
+    Ok(Some((section, first_page)))
+}
+
+/// Serialize a checkpoint, followed by the big-endian number of sections spanned by its nodes (its
+/// section high watermark).
+fn encode_checkpoint(checkpoint: &Checkpoint, layout: &Layout) -> Bytes {
+    let mut record = checkpoint.serialize();
+    let sections = checkpoint.size.div_ceil(layout.section_nodes);
+    record.extend_from_slice(&sections.to_be_bytes());
+    Bytes::from(record)
+}
+
+/// Load the checkpoints recorded in `metadata` by `Mmr::sync`, most recent first, returning
+/// `Error::InvalidMetadata` if any is malformed or spans a different number of sections than
+/// `layout` places its nodes in.
+fn load_checkpoints<B: Blob, E: Clock + Storage<B>, H: CHasher>(
+    metadata: &Metadata<B, E>,
+    layout: &Layout,
+) -> Result<Vec<Checkpoint>, Error> {
+    let mut checkpoints = Vec::new();
+    for key in [CHECKPOINT_KEY, PREVIOUS_CHECKPOINT_KEY] {
+        let Some(record) = metadata.get(key) else {
+            continue;
+        };
+        if record.len() < 8 {
+            return Err(Error::InvalidMetadata);
+        }
+        let (checkpoint, sections) = record.split_at(record.len() - 8);
+        let checkpoint = Checkpoint::deserialize::<H>(checkpoint).ok_or(Error::InvalidMetadata)?;
+        let sections = u64::from_be_bytes(sections.try_into().unwrap());
+        if sections != checkpoint.size.div_ceil(layout.section_nodes) {
+            return Err(Error::InvalidMetadata);
+        }
+        checkpoints.push(checkpoint);
+    }
+    Ok(checkpoints)
+}
+
+/// The intent recorded by `Mmr::append_batch_atomic` before appending a batch, from which an
+/// interrupted batch is rolled back.
+pub(crate) struct Intent {
+    /// The id of the batch.
+    pub(crate) batch_id: u64,
+    /// The number of elements in the batch.
+    pub(crate) leaf_count: u64,
+    /// The (durable) size of the MMR before the batch.
+    pub(crate) pre_size: u64,
+    /// The root hash of the MMR before the batch.
+    pub(crate) pre_root: Digest,
+}
+
+impl Intent {
+    /// Serialize the intent as its big-endian batch id, leaf count, and size, followed by its root
+    /// hash.
+    fn serialize(&self) -> Bytes {
+        let mut record = Vec::with_capacity(24 + self.pre_root.len());
+        record.extend_from_slice(&self.batch_id.to_be_bytes());
+        record.extend_from_slice(&self.leaf_count.to_be_bytes());
+        record.extend_from_slice(&self.pre_size.to_be_bytes());
+        record.extend_from_slice(&self.pre_root);
+        Bytes::from(record)
+    }
+}
+
+/// Load the intent of the batch `Mmr::append_batch_atomic` was appending when it was interrupted
+/// (if any) from `metadata`.
+pub(crate) fn load_intent(
+    metadata: &impl Records,
+    digest_len: usize,
+) -> Result<Option<Intent>, Error> {
+    let Some(record) = metadata.record(INTENT_KEY) else {
+        return Ok(None);
+    };
+    if record.len() != 24 + digest_len {
+        return Err(Error::InvalidMetadata);
+    }
+    let intent = Intent {
+        batch_id: u64::from_be_bytes(record[..8].try_into().unwrap()),
+        leaf_count: u64::from_be_bytes(record[8..16].try_into().unwrap()),
+        pre_size: u64::from_be_bytes(record[16..24].try_into().unwrap()),
+        pre_root: Digest::copy_from_slice(&record[24..]),
+    };
+    if !PeakIterator::check_validity(intent.pre_size) {
+        return Err(Error::InvalidMetadata);
+    }
+    Ok(Some(intent))
+}
+
+/// Return the partition `Mmr::rebuild_from` stages the contents of `partition` in.
+fn rebuild_partition(partition: &str) -> String {
+    format!("{partition}_rebuild")
+}
+
+/// Return the partition storing the root index of the MMR whose nodes are stored in `partition`.
+fn roots_partition(partition: &str) -> String {
+    format!("{partition}_roots")
+}
+
+/// Return the number of leaves of an MMR of the given (valid) `size`.
+/// Remove `partition` and every blob in it (if it exists).
+async fn remove_partition<B: Blob, E: Storage<B>>(
+    runtime: &E,
+    partition: &str,
+) -> Result<(), Error> {
+    match runtime.remove(partition, None).await {
+        Ok(()) | Err(RError::PartitionMissing(_)) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Replace the sections (and root index) stored in `cfg.partition` with those staged by a committed
+/// `Mmr::rebuild_from`, then clear the pruning boundary, compaction, and checkpoints of the
+/// replaced sections from `metadata` (along with the commit itself and any batch intent or commit)
+/// and remove the staged sections. Safe to repeat if interrupted before the commit is cleared.
+async fn finish_rebuild<B: Blob, E: Clock + Storage<B>>(
+    runtime: &E,
+    cfg: &Config,
+    metadata: &mut Metadata<B, E>,
+) -> Result<(), Error> {
+    let staging = rebuild_partition(&cfg.partition);
+    for (staging, partition) in [
+        (staging.clone(), cfg.partition.clone()),
+        (roots_partition(&staging), roots_partition(&cfg.partition)),
+    ] {
+        remove_partition(runtime, &partition).await?;
+        let names = match runtime.scan(&staging).await {
+            Ok(names) => names,
+            Err(RError::PartitionMissing(_)) => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        for name in names {
+            let staged = runtime.open(&staging, &name).await?;
+            let mut buf = vec![0u8; staged.len().await? as usize];
+            staged.read_at(&mut buf, 0).await?;
+            staged.close().await?;
+            let blob = runtime.open(&partition, &name).await?;
+            blob.write_at(&buf, 0).await?;
+            blob.sync().await?;
+            blob.close().await?;
+        }
+    }
+    for key in [
+        PRUNED_TO_POS_KEY,
+        PINNED_NODES_KEY,
+        COMPACTED_SECTION_KEY,
+        CHECKPOINT_KEY,
+        PREVIOUS_CHECKPOINT_KEY,
+        REBUILD_KEY,
+        INTENT_KEY,
+        COMMITTED_BATCH_KEY,
+    ] {
+        metadata.remove(key);
+    }
+    metadata.sync().await?;
+    remove_partition(runtime, &staging).await?;
+    remove_partition(runtime, &roots_partition(&staging)).await?;
+    remove_partition(runtime, &rebuild_partition(&cfg.metadata_partition)).await
+}
+
+/// The placement of nodes, and the checksums of the pages containing them, within sections.
+#[derive(Clone, Copy)]
+pub(crate) struct Layout {
+    section_nodes: u64,
+    page_nodes: u64,
+    digest_len: u64,
+}
+
+impl Layout {
+    /// Return the layout of sections holding `section_nodes` nodes (of `digest_len` bytes each),
+    /// split into pages of `page_nodes` nodes.
+    pub(crate) fn new(section_nodes: u64, page_nodes: u64, digest_len: usize) -> Self {
+        assert!(section_nodes > 0, "sections must be non-empty");
+        assert!(page_nodes > 0, "pages must be non-empty");
+        assert_eq!(
+            section_nodes % page_nodes,
+            0,
+            "pages must evenly divide sections"
+        );
+        Self {
+            section_nodes,
+            page_nodes,
+            digest_len: digest_len as u64,
+        }
+    }
+
+    /// Return the number of nodes in each section.
+    pub(crate) fn section_nodes(&self) -> u64 {
+        self.section_nodes
+    }
+
+    /// Return the number of bytes occupied by a full page.
+    pub(crate) fn page_len(&self) -> u64 {
+        CHECKSUM_LEN + self.page_nodes * self.digest_len
+    }
+
+    /// Return the length of a section holding `nodes` nodes.
+    pub(crate) fn section_len(&self, nodes: u64) -> u64 {
+        let (pages, remaining) = (nodes / self.page_nodes, nodes % self.page_nodes);
+        let mut len = pages * self.page_len();
+        if remaining > 0 {
+            len += CHECKSUM_LEN + remaining * self.digest_len;
+        }
+        len
+    }
+
+    /// Return the section containing the node at `pos`, the page within that section, and the
+    /// index of the node within that page.
+    fn locate(&self, pos: u64) -> (u64, u64, u64) {
+        let offset = pos % self.section_nodes;
+        (
+            pos / self.section_nodes,
+            offset / self.page_nodes,
+            offset % self.page_nodes,
+        )
+    }
+}
+
+/// Read the nodes stored in `page` of `blob`, returning `None` if they don't match the page's
+/// checksum.
+///
+/// The last page of a blob may hold nodes written after its checksum was last updated (by a write
+/// that was interrupted before the checksum was), so the longest prefix of its nodes that matches
+/// its checksum is returned. Every other page must be full and match its checksum exactly.
+///
+/// If `metrics` are provided, the read (and any checksum mismatch) is recorded.
+async fn read_page<B: Blob>(
+    blob: &B,
+    layout: &Layout,
+    page: u64,
+    metrics: Option<&Metrics>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let start = page * layout.page_len();
+    let len = blob.len().await?;
+    let available = len.saturating_sub(start).min(layout.page_len());
+    if available < CHECKSUM_LEN {
+        return Ok(None);
+    }
+    let mut buf = vec![0u8; available as usize];
+    blob.read_at(&mut buf, start).await?;
+    if let Some(metrics) = metrics {
+        metrics.record_read(buf.len());
+    }
+    let mut nodes = buf.split_off(CHECKSUM_LEN as usize);
+    let checksum = u32::from_be_bytes(buf.try_into().unwrap());
+
+    // Find the longest prefix of complete nodes matching the checksum
+    let count = nodes.len() / layout.digest_len as usize;
+    let min_count = if start + available == len { 0 } else { count };
+    let mut hasher = crc32fast::Hasher::new();
+    let mut matched = None;
+    for (i, node) in nodes.chunks_exact(layout.digest_len as usize).enumerate() {
+        if i >= min_count && hasher.clone().finalize() == checksum {
+            matched = Some(i);
+        }
+        hasher.update(node);
+    }
+    if hasher.finalize() == checksum {
+        matched = Some(count);
+    }
+    if let (None, Some(metrics)) = (matched, metrics) {
+        metrics.verification_failures.inc();
+    }
+    Ok(matched.map(|count| {
+        nodes.truncate(count * layout.digest_len as usize);
+        nodes
+    }))
+}
+
+/// Recompute and write the checksum of the last page of a section holding `nodes` nodes.
+async fn write_tail_checksum<B: Blob>(
+    blob: &B,
+    layout: &Layout,
+    nodes: u64,
+    metrics: &Metrics,
+) -> Result<(), Error> {
+    let page = (nodes - 1) / layout.page_nodes;
+    let start = page * layout.page_len();
+    let mut buf = vec![0u8; ((nodes - page * layout.page_nodes) * layout.digest_len) as usize];
+    blob.read_at(&mut buf, start + CHECKSUM_LEN).await?;
+    metrics.record_read(buf.len());
+    blob.write_at(&crc32fast::hash(&buf).to_be_bytes(), start)
+        .await?;
+    metrics.record_write(CHECKSUM_LEN as usize);
+    Ok(())
+}
+
+/// Write `nodes` to `sections` starting at position `pos` (opening any section that doesn't exist
+/// yet in `partition`), rolling over to a new section whenever the current one fills up, and return
+/// the sections written to.
+///
+/// Pages are written in order, each by first writing its new nodes and then its updated checksum
+/// (covering the nodes it already held as well), so an interrupted write leaves every page but the
+/// last one written complete. The nodes already in the page containing `pos` are taken from
+/// `existing` if given, and otherwise read from storage.
+#[allow(clippy::too_many_arguments)]
+async fn write_nodes<B: Blob, E: Storage<B>>(
+    runtime: &E,
+    partition: &str,
+    layout: &Layout,
+    sections: &mut BTreeMap<u64, Section<B>>,
+    mut pos: u64,
+    mut existing: Option<Vec<u8>>,
+    mut nodes: &[Digest],
+    metrics: &Metrics,
+) -> Result<BTreeSet<u64>, Error> {
+    let mut written = BTreeSet::new();
+    while !nodes.is_empty() {
+        let (section, page, index) = layout.locate(pos);
+        let count = (layout.page_nodes - index).min(nodes.len() as u64) as usize;
+
+        // Get existing section or create a new one
+        let blob = match sections.entry(section) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let name = section.to_be_bytes();
+                let blob = runtime.open(partition, &name).await?;
+                debug!(section, "created section");
+                metrics.open_sections.inc();
+                entry.insert(Section::new(blob))
+            }
+        };
+
+        // Gather the nodes already in the page (if any) to checksum them with the new ones
+        let start = page * layout.page_len() + CHECKSUM_LEN;
+        let mut buf = match existing.take() {
+            Some(buf) => buf,
+            None => {
+                let mut buf = vec![0u8; (index * layout.digest_len) as usize];
+                if !buf.is_empty() {
+                    blob.read_at(&mut buf, start).await?;
+                    metrics.record_read(buf.len());
+                }
+                buf
+            }
+        };
+        for hash in nodes[..count].iter() {
+            buf.extend_from_slice(hash);
+        }
+        let existing_len = (index * layout.digest_len) as usize;
+        blob.write_at(&buf[existing_len..], start + existing_len as u64)
+            .await?;
+        blob.write_at(&crc32fast::hash(&buf).to_be_bytes(), start - CHECKSUM_LEN)
+            .await?;
+        metrics.record_write(buf.len() - existing_len);
+        metrics.record_write(CHECKSUM_LEN as usize);
+        written.insert(section);
+        pos += count as u64;
+        nodes = &nodes[count..];
+    }
+    Ok(written)
+}
+
+/// Return the position following the last complete node stored contiguously in `sections`,
+/// starting from `oldest_section` (sections before it are ignored). Counting stops at the first
+/// missing or partially filled section.
+///
+/// The last page of each section is verified against its checksum, and only the nodes it covers
+/// are counted. If none of them match, a partially filled page is considered torn (and none of its
+/// nodes are counted), while a full page is considered corrupt (returning `Error::Corrupt`).
+pub(crate) async fn stored_nodes<B: Blob>(
+    sections: &BTreeMap<u64, B>,
+    layout: &Layout,
+    oldest_section: u64,
+    metrics: Option<&Metrics>,
+) -> Result<u64, Error> {
+    let mut nodes = oldest_section * layout.section_nodes;
+    for (expected, (&section, blob)) in (oldest_section..).zip(sections.range(oldest_section..)) {
+        if section != expected {
+            break;
+        }
+        let len = blob.len().await?;
+        let mut section_len = 0;
+        if len > 0 {
+            let page = (len - 1) / layout.page_len();
+            let page_nodes = match read_page(blob, layout, page, metrics).await? {
+                Some(page_nodes) => page_nodes.len() as u64 / layout.digest_len,
+                None if len % layout.page_len() == 0 => {
+                    return Err(Error::Corrupt { section, page });
+                }
+                None => {
+                    warn!(section, page, "torn page: discarding");
+                    0
+                }
+            };
+            section_len = (page * layout.page_nodes + page_nodes).min(layout.section_nodes);
+        }
+        nodes += section_len;
+        if section_len < layout.section_nodes {
+            break;
+        }
+    }
+    Ok(nodes)
+}
+
+/// Read the node at `pos` from the section that contains it, verifying the checksum of its page.
+pub(crate) async fn read_node<B: Blob>(
+    sections: &BTreeMap<u64, B>,
+    layout: &Layout,
+    pos: u64,
+    metrics: Option<&Metrics>,
+) -> Result<Digest, Error> {
+    let (section, page, index) = layout.locate(pos);
+    let blob = sections
+        .get(&section)
+        .ok_or(Error::SectionMissing(section))?;
+    let nodes = read_page(blob, layout, page, metrics)
+        .await?
+        .ok_or(Error::Corrupt { section, page })?;
+    let start = (index * layout.digest_len) as usize;
+    let node = nodes
+        .get(start..start + layout.digest_len as usize)
+        .ok_or(Error::Corrupt { section, page })?;
+    Ok(Digest::copy_from_slice(node))
+}
+
+/// Recompute every stored parent from its children (starting from the peaks pinned by pruning at
+/// `pruned_to_pos`) under `scheme`, returning `Error::InconsistentNode` with the position of the
+/// first whose stored hash doesn't match.
+async fn verify_nodes<B: Blob, H: CHasher>(
+    sections: &BTreeMap<u64, B>,
+    layout: &Layout,
+    pinned_nodes: &HashMap<u64, Digest>,
+    pruned_to_pos: u64,
+    size: u64,
+    scheme: Scheme,
+    metrics: &Metrics,
+) -> Result<(), Error> {
+    let mut hasher = H::new();
+    let mut hasher = Hasher::with_scheme(&mut hasher, scheme);
+
+    // Nodes are stored in post-order, so the children of each parent are the last two nodes that
+    // don't yet have one
+    let mut orphans: Vec<Digest> = PeakIterator::new(pruned_to_pos)
+        .map(|(peak_pos, _)| pinned_nodes[&peak_pos].clone())
+        .collect();
+    let mut page: Option<((u64, u64), Vec<u8>)> = None;
+    for pos in pruned_to_pos..size {
+        let (section, page_index, index) = layout.locate(pos);
+        if page.as_ref().map(|(key, _)| *key) != Some((section, page_index)) {
+            let blob = sections
+                .get(&section)
+                .ok_or(Error::SectionMissing(section))?;
+            let nodes = read_page(blob, layout, page_index, Some(metrics))
+                .await?
+                .ok_or(Error::Corrupt {
+                    section,
+                    page: page_index,
+                })?;
+            page = Some(((section, page_index), nodes));
+        }
+        let start = (index * layout.digest_len) as usize;
+        let node = page
+            .as_ref()
+            .unwrap()
+            .1
+            .get(start..start + layout.digest_len as usize)
+            .ok_or(Error::Corrupt {
+                section,
+                page: page_index,
+            })?;
+        let node = Digest::copy_from_slice(node);
+        if pos_to_height(pos) > 0 {
+            let right = orphans.pop().unwrap();
+            let left = orphans.pop().unwrap();
+            if hasher.node_hash(pos, &left, &right) != node {
+                return Err(Error::InconsistentNode(pos));
+            }
+        }
+        orphans.push(node);
+    }
+    Ok(())
+}
+
+/// Load the position the MMR has been pruned to, along with the nodes pinned by that pruning (the
+/// peaks of the MMR of that size, keyed by position), from `metadata`.
+pub(crate) fn load_pruned(
+    metadata: &impl Records,
+    digest_len: usize,
+) -> Result<(u64, HashMap<u64, Digest>), Error> {
+    let Some(pruned_to_pos) = metadata.record(PRUNED_TO_POS_KEY) else {
+        return Ok((0, HashMap::new()));
+    };
+    let pruned_to_pos: [u8; 8] = pruned_to_pos
+        .as_ref()
+        .try_into()
+        .map_err(|_| Error::InvalidMetadata)?;
+    let pruned_to_pos = u64::from_be_bytes(pruned_to_pos);
+    if !PeakIterator::check_validity(pruned_to_pos) {
+        return Err(Error::InvalidMetadata);
+    }
+    let pinned = metadata
+        .record(PINNED_NODES_KEY)
+        .ok_or(Error::InvalidMetadata)?;
+    if pinned.len() != PeakIterator::new(pruned_to_pos).count() * digest_len {
+        return Err(Error::InvalidMetadata);
+    }
+    let pinned_nodes = PeakIterator::new(pruned_to_pos)
+        .zip(pinned.chunks_exact(digest_len))
+        .map(|((peak_pos, _), hash)| (peak_pos, Digest::copy_from_slice(hash)))
+        .collect();
+    Ok((pruned_to_pos, pinned_nodes))
+}
+
+/// The sections opened or written to by a background flush (see `write_nodes`).
+struct Flushed<B: Blob> {
+    sections: BTreeMap<u64, Section<B>>,
+    written: BTreeSet<u64>,
+}
+
+/// Implementation of a disk-backed `Mmr`.
+pub struct Mmr<B: Blob, E: Clock + Spawner + Storage<B>, H: CHasher> {
+    runtime: E,
+    cfg: Config,
+    layout: Layout,
+    hasher: H,
+    metadata: Metadata<B, E>,
+    metrics: Metrics,
+
+    // The blob of each section, keyed by section index.
+    sections: BTreeMap<u64, Section<B>>,
+    // Sections written to since the last sync.
+    unsynced: BTreeSet<u64>,
+
+    // The number of nodes written to `sections`.
+    persisted: u64,
+    // The number of nodes written to `sections` as of the last sync.
+    durable: u64,
+    // The number of elements added since the last sync.
+    unsynced_appends: u64,
+    // The time of the last sync (or of initialization).
+    last_sync: SystemTime,
+    // Nodes added since the last flush, starting at position `persisted`.
+    pending: Vec<Digest>,
+    // The background flush writing the first `in_flight` nodes of `pending` (if any).
+    flushing: Option<Handle<Result<Flushed<B>, Error>>>,
+    // The number of nodes of `pending` being written by `flushing`.
+    in_flight: usize,
+    // The (at most `hot_nodes`) nodes most recently written to `sections`, ending at position
+    // `persisted`.
+    hot: VecDeque<Digest>,
+    // The positions and hashes of the peaks of the MMR, in decreasing order of height.
+    peaks: Vec<(u64, Digest)>,
+    // Recently read nodes from `sections`.
+    cache: Mutex<NodeCache>,
+
+    // The position of the oldest node that has not been pruned.
+    pruned_to_pos: u64,
+    // Nodes before `pruned_to_pos` that are still needed to add elements and generate proofs.
+    pinned_nodes: HashMap<u64, Digest>,
+
+    // The most recently committed checkpoint.
+    checkpoint: Option<Checkpoint>,
+    // What was discarded from storage on startup.
+    recovery: Recovery,
+
+    // The blob of the root index (if enabled), holding a root hash every `root_index_interval`
+    // leaves.
+    roots: Option<B>,
+    // The number of roots written to `roots`.
+    indexed: u64,
+    // Roots not yet written to `roots`, following the first `indexed`.
+    pending_roots: Vec<Digest>,
+    // Whether roots have been written to `roots` since the last sync.
+    roots_unsynced: bool,
+}
+
+impl<B: Blob, E: Clock + Spawner + Storage<B>, H: CHasher> Mmr<B, E, H> {
+    /// Initialize a new `Mmr` instance, restoring any nodes previously persisted to the configured
+    /// partition.
+    ///
+    /// If the last write before a crash was incomplete, persisted nodes are truncated back to the
+    /// last append whose nodes were all written (see `size` and `root_hash` for the recovered
+    /// state, and `recovery` for what was discarded). If `verify_on_init` is set, returns
+    /// `Error::InconsistentNode` if any retained parent doesn't match the hash of its children.
+    pub async fn init(runtime: E, cfg: Config) -> Result<Self, Error> {
+        assert!(cfg.write_buffer > 0, "write buffer must be non-empty");
+        assert!(
+            cfg.max_concurrent_reads > 0,
+            "max concurrent reads must be positive"
+        );
+        assert!(
+            cfg.sync_policy != SyncPolicy::OnCheckpoint || cfg.root_index_interval > 0,
+            "syncing on checkpoints requires a root index"
+        );
+        let layout = Layout::new(cfg.section_nodes, cfg.page_nodes, H::len());
+
+        // Finish any rebuild interrupted after it was committed, then load the pruning boundary
+        // (if any)
+        let mut metadata = Metadata::init(
+            runtime.clone(),
+            MetadataConfig {
+                registry: cfg.registry.clone(),
+                partition: cfg.metadata_partition.clone(),
+            },
+        )
+        .await?;
+        if metadata.get(REBUILD_KEY).is_some() {
+            warn!("finishing interrupted rebuild");
+            finish_rebuild(&runtime, &cfg, &mut metadata).await?;
+        }
+
+        // Reject an MMR created with another scheme, recording the scheme of one that has none
+        // recorded yet
+        if !check_scheme::<H>(&metadata, &cfg.scheme)? {
+            metadata.put(SCHEME_KEY, Bytes::from(cfg.scheme.fingerprint::<H>()));
+            metadata.sync().await?;
+        }
+        let (pruned_to_pos, pinned_nodes) = load_pruned(&metadata, H::len())?;
+        let oldest_section = pruned_to_pos / cfg.section_nodes;
+
+        // Initialize metrics
+        let metrics = {
+            let mut registry = cfg.registry.lock().unwrap();
+            Metrics::init(registry.sub_registry_with_prefix(&cfg.metrics_prefix))
+        };
+
+        // Open sections (removing any left stale by an interrupted compaction) and derive the size
+        // of the persisted MMR from their lengths
+        let compacted = load_compacted(&metadata)?;
+        let (mut sections, stale) =
+            open_sections(&runtime, &cfg.partition, &layout, compacted).await?;
+        for name in stale {
+            warn!(blob = hex(&name), "removing stale section");
+            runtime.remove(&cfg.partition, Some(&name)).await?;
+        }
+        let stored = stored_nodes(&sections, &layout, oldest_section, Some(&metrics)).await?;
+        let persisted = largest_valid_size(stored);
+        let mut recovery = Recovery {
+            stored_nodes: stored,
+            orphaned_nodes: stored - persisted,
+            truncated_bytes: 0,
+        };
+
+        // Open the root index (removing those of other intervals), discarding any partially
+        // written root and any root beyond the persisted leaves
+        let partition = roots_partition(&cfg.partition);
+        let mut roots = None;
+        let mut indexed = 0;
+        if cfg.root_index_interval == 0 {
+            remove_partition(&runtime, &partition).await?;
+        } else {
+            let name = cfg.root_index_interval.to_be_bytes();
+            let stored_indexes = match runtime.scan(&partition).await {
+                Ok(names) => names,
+                Err(RError::PartitionMissing(_)) => Vec::new(),
+                Err(err) => return Err(Error::Runtime(err)),
+            };
+            for stale in stored_indexes {
+                if stale != name {
+                    debug!(index = hex(&stale), "removing stale root index");
+                    runtime.remove(&partition, Some(&stale)).await?;
+                }
+            }
+            let blob = runtime.open(&partition, &name).await?;
+            let len = blob.len().await?;
+            indexed = (len / H::len() as u64).min(leaf_count(persisted) / cfg.root_index_interval);
+            if len != indexed * H::len() as u64 {
+                warn!(len, indexed, "truncating root index");
+                blob.truncate(indexed * H::len() as u64).await?;
+                blob.sync().await?;
+            }
+            roots = Some(blob);
+        }
+
+        // A write may have been interrupted part way through a node, before all of the nodes
+        // created by some append were written, or before all sections spanned by a flush were
+        // written, so we discard everything beyond the last complete append (updating the checksum
+        // of the new last page to match). Sections before the pruning boundary may also remain if
+        // we crashed while pruning.
+        let stored_sections: Vec<u64> = sections.keys().copied().collect();
+        for section in stored_sections {
+            let start = section.saturating_mul(cfg.section_nodes);
+            let keep = if section < oldest_section {
+                0
+            } else {
+                persisted.saturating_sub(start).min(cfg.section_nodes)
+            };
+            let blob = sections.get(&section).unwrap();
+            let len = blob.len().await?;
+            if len == layout.section_len(keep) {
+                continue;
+            }
+            if section < oldest_section {
+                debug!(section, "removing pruned section");
+            } else {
+                recovery.truncated_bytes += len.saturating_sub(layout.section_len(keep));
+                warn!(
+                    section,
+                    len,
+                    recovered = persisted,
+                    "incomplete tail: truncating to last complete append"
+                );
+            }
+            if keep == 0 {
+                let blob = sections.remove(&section).unwrap();
+                let name = blob.name(section);
+                blob.close().await?;
+                runtime.remove(&cfg.partition, Some(&name)).await?;
+            } else {
+                blob.truncate(layout.section_len(keep)).await?;
+                write_tail_checksum(blob, &layout, keep, &metrics).await?;
+                blob.sync().await?;
+            }
+        }
+
+        // Confirm the stored nodes are consistent with each other before serving any of them
+        if cfg.verify_on_init {
+            verify_nodes::<_, H>(
+                &sections,
+                &layout,
+                &pinned_nodes,
+                pruned_to_pos,
+                persisted,
+                cfg.scheme.clone(),
+                &metrics,
+            )
+            .await?;
+        }
+
+        // Load peaks
+        let mut peaks = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(persisted) {
+            let hash = match pinned_nodes.get(&peak_pos) {
+                Some(hash) => hash.clone(),
+                None => read_node(&sections, &layout, peak_pos, Some(&metrics)).await?,
+            };
+            peaks.push((peak_pos, hash));
+        }
+        let leaves = leaf_count(persisted);
+        metrics.size.set(persisted as i64);
+        metrics.leaves.set(leaves as i64);
+        metrics.durable_size.set(persisted as i64);
+        metrics.open_sections.set(sections.len() as i64);
+        debug!(
+            size = persisted,
+            pruned_to_pos,
+            peaks = peaks.len(),
+            sections = sections.len(),
+            "restored mmr"
+        );
+
+        let mut mmr = Self {
+            last_sync: runtime.current(),
+            cache: Mutex::new(NodeCache::new(cfg.cache_entries)),
+            runtime,
+            pending: Vec::with_capacity(cfg.write_buffer),
+            flushing: None,
+            in_flight: 0,
+            hot: VecDeque::with_capacity(cfg.hot_nodes),
+            cfg,
+            layout,
+            hasher: H::new(),
+            metadata,
+            metrics,
+
+            sections,
+            unsynced: BTreeSet::new(),
+
+            persisted,
+            durable: persisted,
+            unsynced_appends: 0,
+            peaks,
+
+            pruned_to_pos,
+            pinned_nodes,
+            checkpoint: None,
+            recovery,
+
+            roots,
+            indexed,
+            pending_roots: Vec::new(),
+            roots_unsynced: false,
+        };
+
+        // Restore the most recent checkpoint whose nodes are all stored, falling back to the one
+        // before it (or to none) if storage lost nodes the most recent one depends on
+        let checkpoints = load_checkpoints::<_, _, H>(&mmr.metadata, &layout)?;
+        for checkpoint in checkpoints.iter() {
+            if checkpoint.size <= mmr.persisted
+                && mmr.root_hash_at(checkpoint.size).await.ok() == Some(checkpoint.root.clone())
+            {
+                mmr.checkpoint = Some(checkpoint.clone());
+                break;
+            }
+            warn!(
+                size = checkpoint.size,
+                recovered = mmr.persisted,
+                "checkpoint not backed by stored nodes"
+            );
+        }
+
+        // Roll back any batch interrupted before it was committed (the MMR before it was durable
+        // before its intent was recorded, so its nodes must all be stored)
+        if let Some(intent) = load_intent(&mmr.metadata, H::len())? {
+            if intent.pre_size > mmr.persisted
+                || mmr.root_hash_at(intent.pre_size).await? != intent.pre_root
+            {
+                return Err(Error::InvalidMetadata);
+            }
+            warn!(
+                batch_id = intent.batch_id,
+                leaf_count = intent.leaf_count,
+                size = intent.pre_size,
+                recovered = mmr.persisted,
+                "rolling back uncommitted batch"
+            );
+            mmr.rewind(intent.pre_size).await?;
+            mmr.metadata.remove(INTENT_KEY);
+            mmr.metadata.sync().await?;
+        }
+
+        // Index the roots of any persisted leaves missing from the root index
+        mmr.write_roots().await?;
+        if mmr.roots_unsynced {
+            mmr.roots.as_ref().unwrap().sync().await?;
+            mmr.roots_unsynced = false;
+        }
+        Ok(mmr)
+    }
+
+    /// Return what was discarded from storage to recover a valid MMR on startup.
+    pub fn recovery(&self) -> &Recovery {
+        &self.recovery
+    }
+
+    /// Initialize an `Mmr` (see `init`), returning an error if the recovered MMR isn't the one
+    /// described by `expected` (such as one recorded by consensus): `Error::SizeMismatch` with both
+    /// leaf counts if it has a different number of leaves, or `Error::RootMismatch` with both root
+    /// hashes if it has the same number of leaves but a different root hash.
+    pub async fn open_expecting(
+        runtime: E,
+        cfg: Config,
+        expected: Checkpoint,
+    ) -> Result<Self, Error> {
+        let mut mmr = Self::init(runtime, cfg).await?;
+        let actual = leaf_count(mmr.size());
+        if actual != expected.leaf_count {
+            return Err(Error::SizeMismatch {
+                expected: expected.leaf_count,
+                actual,
+            });
+        }
+        mmr.check_root(&expected).await?;
+        Ok(mmr)
+    }
+
+    /// Initialize an `Mmr` (see `init`) that may be ahead of `expected`, returning
+    /// `Error::SizeMismatch` with both leaf counts if the recovered MMR has fewer leaves, or
+    /// `Error::RootMismatch` with both root hashes if its root hash at the expected size differs
+    /// (it diverged from the expected MMR).
+    pub async fn open_expecting_at_least(
+        runtime: E,
+        cfg: Config,
+        expected: Checkpoint,
+    ) -> Result<Self, Error> {
+        let mut mmr = Self::init(runtime, cfg).await?;
+        let actual = leaf_count(mmr.size());
+        if actual < expected.leaf_count {
+            return Err(Error::SizeMismatch {
+                expected: expected.leaf_count,
+                actual,
+            });
+        }
+        mmr.check_root(&expected).await?;
+        Ok(mmr)
+    }
+
+    /// Return `Error::RootMismatch` if the root hash of the MMR at the size of `expected` differs
+    /// from its root hash.
+    async fn check_root(&mut self, expected: &Checkpoint) -> Result<(), Error> {
+        let actual = self.root_hash_at(expected.size).await?;
+        if actual != expected.root {
+            return Err(Error::RootMismatch {
+                expected: expected.root.clone(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Return the total number of nodes in the MMR, including those not yet flushed to storage.
+    pub fn size(&self) -> u64 {
+        self.persisted + self.pending.len() as u64
+    }
+
+    /// Return the number of nodes guaranteed to survive a crash (those synced to storage).
+    pub fn durable_size(&self) -> u64 {
+        self.durable
+    }
+
+    /// Return the most recently committed checkpoint (the size, leaf count, and root hash of the
+    /// MMR as of the last sync), all of whose nodes are durably stored, or `None` if the MMR has
+    /// never been synced.
+    pub fn checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Return the id of the batch most recently committed by `append_batch_atomic`, or `None` if
+    /// no batch has been committed.
+    pub fn committed_batch(&self) -> Option<u64> {
+        let record = self.metadata.get(COMMITTED_BATCH_KEY)?;
+        Some(u64::from_be_bytes(record.as_ref().try_into().ok()?))
+    }
+
+    /// Return the position of the oldest node that has not been pruned. Proofs can only be
+    /// generated for elements at or after this position.
+    pub fn pruned_to_pos(&self) -> u64 {
+        self.pruned_to_pos
+    }
+
+    /// Return the hash of the node at `pos`, reading it from the node cache (or from storage if it
+    /// isn't cached) if it has been flushed.
+    pub(crate) async fn get_node(&self, pos: u64) -> Result<Digest, Error> {
+        if pos >= self.persisted {
+            return Ok(self.pending[(pos - self.persisted) as usize].clone());
+        }
+        if pos < self.pruned_to_pos {
+            return self
+                .pinned_nodes
+                .get(&pos)
+                .cloned()
+                .ok_or(Error::ElementPruned(pos));
+        }
+
+        // Hot nodes and peaks are never read back from storage
+        let hot_start = self.persisted - self.hot.len() as u64;
+        if pos >= hot_start {
+            return Ok(self.hot[(pos - hot_start) as usize].clone());
+        }
+        if let Ok(index) = self
+            .peaks
+            .binary_search_by_key(&pos, |(peak_pos, _)| *peak_pos)
+        {
+            return Ok(self.peaks[index].1.clone());
+        }
+        let cached = self.cache.lock().unwrap().get(pos);
+        if let Some(node) = cached {
+            self.metrics.cache_hits.inc();
+            return Ok(node);
+        }
+        self.metrics.cache_misses.inc();
+        let node = read_node(&self.sections, &self.layout, pos, Some(&self.metrics)).await?;
+        self.cache.lock().unwrap().insert(pos, node.clone());
+        Ok(node)
+    }
+
+    /// Add an element to the MMR and return its position in the MMR. Nodes are buffered in memory
+    /// and written to storage once `write_buffer` of them have accumulated (by a background task if
+    /// `write_behind` is set, see "Write-Behind"), and synced according to the configured
+    /// `SyncPolicy`.
+    pub async fn add(&mut self, element: &Digest) -> Result<u64, Error> {
+        let element_pos = self.push(element);
+        self.unsynced_appends += 1;
+        let sync = match self.cfg.sync_policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryNAppends(n) => self.unsynced_appends >= n,
+            SyncPolicy::OnCheckpoint => {
+                leaf_count(self.size()).checked_rem(self.cfg.root_index_interval) == Some(0)
+            }
+            SyncPolicy::IntervalMillis(interval) => {
+                let elapsed = self.runtime.current().duration_since(self.last_sync);
+                elapsed.is_ok_and(|elapsed| elapsed >= Duration::from_millis(interval))
+            }
+            SyncPolicy::Never => false,
+        };
+        if sync {
+            self.sync().await?;
+        } else if self.pending.len() - self.in_flight >= self.cfg.write_buffer {
+            if self.cfg.write_behind {
+                self.flush_behind().await?;
+            } else {
+                self.flush().await?;
+            }
+        }
+        Ok(element_pos)
+    }
+
+    /// Add `elements` to the MMR as a single batch identified by `batch_id`, returning their
+    /// positions, such that the MMR is recovered either with or without the entire batch after a
+    /// crash (never with only some of its elements).
+    ///
+    /// The MMR is first synced, then the intent to append the batch (its id, length, and the size
+    /// and root hash of the MMR before it) is recorded in the metadata partition. The elements are
+    /// then added (ignoring the configured `SyncPolicy`) and synced, and finally the batch is
+    /// committed by replacing its intent with its id (see `committed_batch`). On startup, the
+    /// elements of a batch whose intent was recorded but never committed are discarded (see
+    /// `rewind`). If this returns an error, the batch may have been partially added and should be
+    /// rolled back by reinitializing the `Mmr`.
+    pub async fn append_batch_atomic(
+        &mut self,
+        elements: &[Digest],
+        batch_id: u64,
+    ) -> Result<Vec<u64>, Error> {
+        self.sync().await?;
+        let intent = Intent {
+            batch_id,
+            leaf_count: elements.len() as u64,
+            pre_size: self.size(),
+            pre_root: self.root_hash(),
+        };
+        self.metadata.put(INTENT_KEY, intent.serialize());
+        self.metadata.sync().await?;
+
+        let mut positions = Vec::with_capacity(elements.len());
+        for element in elements {
+            positions.push(self.push(element));
+            if self.pending.len() >= self.cfg.write_buffer {
+                self.flush().await?;
+            }
+        }
+        self.sync().await?;
+
+        self.metadata.remove(INTENT_KEY);
+        self.metadata.put(
+            COMMITTED_BATCH_KEY,
+            Bytes::copy_from_slice(&batch_id.to_be_bytes()),
+        );
+        self.metadata.sync().await?;
+        debug!(batch_id, size = self.size(), "committed batch");
+        Ok(positions)
+    }
+
+    /// Add an element to the (in-memory) nodes pending a flush and return its position.
+    fn push(&mut self, element: &Digest) -> u64 {
+        let merges = nodes_needing_parents(PeakIterator::new(self.size())).len();
+        let element_pos = self.size();
+        let hasher = &mut Hasher::with_scheme(&mut self.hasher, self.cfg.scheme.clone());
+
+        // Insert the element into the MMR as a leaf, then merge it with each peak that needs a new
+        // parent.
+        let mut hash = hasher.leaf_hash(element_pos, element);
+        self.pending.push(hash.clone());
+        for _ in 0..merges {
+            let (_, sibling) = self.peaks.pop().unwrap();
+            let parent_pos = self.persisted + self.pending.len() as u64;
+            hash = hasher.node_hash(parent_pos, &sibling, &hash);
+            self.pending.push(hash.clone());
+        }
+        self.peaks.push((self.size() - 1, hash));
+        self.metrics.leaves_appended.inc();
+        self.metrics.leaves.inc();
+        self.metrics.size.set(self.size() as i64);
+
+        // Record the root in the root index every `root_index_interval` leaves
+        let interval = self.cfg.root_index_interval;
+        if leaf_count(self.size()).checked_rem(interval) == Some(0) {
+            let root = self.root_hash();
+            self.pending_roots.push(root);
+        }
+        element_pos
+    }
+
+    /// Computes the root hash of the MMR.
+    pub fn root_hash(&mut self) -> Digest {
+        let size = self.size();
+        let hasher = &mut Hasher::with_scheme(&mut self.hasher, self.cfg.scheme.clone());
+        hasher.root_hash(size, self.peaks.iter().map(|(_, hash)| hash))
+    }
+
+    /// Computes the root hash of the MMR as it was when it had `size` nodes, reading its peaks from
+    /// storage.
+    ///
+    /// Returns `Error::Mmr` if `size` is not a valid MMR size no greater than the current size, and
+    /// `Error::ElementPruned` if any of its peaks has been pruned.
+    pub async fn root_hash_at(&mut self, size: u64) -> Result<Digest, Error> {
+        if size > self.size() || !PeakIterator::check_validity(size) {
+            return Err(crate::mmr::Error::InvalidSize(size).into());
+        }
+        let mut peaks = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(size) {
+            peaks.push(self.get_node(peak_pos).await?);
+        }
+        Ok(
+            Hasher::with_scheme(&mut self.hasher, self.cfg.scheme.clone())
+                .root_hash(size, peaks.iter()),
+        )
+    }
+
+    /// Return the root hash of the MMR as it was when it had `leaf_count` leaves.
+    ///
+    /// If `leaf_count` is a multiple of `root_index_interval`, the root is served from the root
+    /// index with a single read. Otherwise it is computed from the peaks of the MMR at that size
+    /// (see `root_hash_at`).
+    ///
+    /// Returns `Error::ElementOutOfRange` if the MMR has fewer than `leaf_count` leaves.
+    pub async fn root_at_leaf(&mut self, leaf_count: u64) -> Result<Digest, Error> {
+        if leaf_count > util::leaf_count(self.size()) {
+            return Err(Error::ElementOutOfRange(leaf_count));
+        }
+        let interval = self.cfg.root_index_interval;
+        if leaf_count > 0 && leaf_count.checked_rem(interval) == Some(0) {
+            let index = leaf_count / interval - 1;
+            if index >= self.indexed {
+                return Ok(self.pending_roots[(index - self.indexed) as usize].clone());
+            }
+            let mut buf = vec![0u8; H::len()];
+            let roots = self.roots.as_ref().unwrap();
+            roots.read_at(&mut buf, index * H::len() as u64).await?;
+            self.metrics.record_read(buf.len());
+            return Ok(Digest::from(buf));
+        }
+        self.root_hash_at(node_count(leaf_count)).await
+    }
+
+    /// Return an inclusion proof for the specified element. The proof is identical to the one the
+    /// in-memory `Mmr` would return after adding the same elements.
+    pub async fn proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        self.range_proof(element_pos, element_pos).await
+    }
+
+    /// Return an inclusion proof for the specified range of elements. The range is inclusive of
+    /// both endpoints. Up to `max_concurrent_reads` of the stored nodes it requires are read
+    /// concurrently.
+    ///
+    /// Returns `Error::ElementPruned` if the range starts before `pruned_to_pos`. If reading a node
+    /// from storage fails, the error is returned and the `Mmr` is left unchanged (no partially read
+    /// node is cached), so generating the proof can be retried.
+    pub async fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        let size = self.size();
+        if end_element_pos >= size {
+            return Err(Error::ElementOutOfRange(end_element_pos));
+        }
+        if start_element_pos < self.pruned_to_pos {
+            return Err(Error::ElementPruned(start_element_pos));
+        }
+        let start = self.runtime.current();
+        let positions = nodes_required_for_range_proof(size, start_element_pos, end_element_pos);
+        let hashes = stream::iter(positions)
+            .map(|pos| self.get_node(pos))
+            .buffered(self.cfg.max_concurrent_reads)
+            .try_collect()
+            .await?;
+        self.metrics.proofs_generated.inc();
+        self.metrics
+            .proof_latency
+            .observe(elapsed_secs(start, self.runtime.current()));
+        Ok(Proof {
+            size,
+            hashes,
+            scheme: self.cfg.scheme.clone(),
+        })
+    }
+
+    /// Return an immutable `Snapshot` of the MMR at its current size, which can serve proofs
+    /// concurrently with (and without coordinating with) subsequent operations on the `Mmr`.
+    ///
+    /// Taking a snapshot copies the handle of every section, the pinned nodes, and any nodes not yet
+    /// written to storage (at most `write_buffer` of them), but no stored nodes.
+    pub fn snapshot(&self) -> Snapshot<B> {
+        let mut hasher = H::new();
+        let root = Hasher::with_scheme(&mut hasher, self.cfg.scheme.clone())
+            .root_hash(self.size(), self.peaks.iter().map(|(_, hash)| hash));
+        Snapshot {
+            sections: self.sections.clone(),
+            layout: self.layout,
+            metrics: self.metrics.clone(),
+
+            size: self.size(),
+            durable_size: self.durable,
+            root,
+
+            persisted: self.persisted,
+            pending: self.pending.iter().cloned().collect(),
+
+            pruned_to_pos: self.pruned_to_pos,
+            pinned_nodes: self.pinned_nodes.clone(),
+
+            max_concurrent_reads: self.cfg.max_concurrent_reads,
+            scheme: self.cfg.scheme.clone(),
+        }
+    }
+
+    /// Return the nodes already written to the page containing the next node to be written, if
+    /// they are all hot.
+    fn hot_page_nodes(&self) -> Option<Vec<u8>> {
+        let (_, _, index) = self.layout.locate(self.persisted);
+        if index as usize > self.hot.len() {
+            return None;
+        }
+        let mut buf = Vec::with_capacity((index * self.layout.digest_len) as usize);
+        for hash in self.hot.range(self.hot.len() - index as usize..) {
+            buf.extend_from_slice(hash);
+        }
+        Some(buf)
+    }
+
+    /// Record the first `count` pending nodes as written to `written` (of `sections`), making them
+    /// hot, and write any roots they complete to the root index.
+    async fn finish_flush(
+        &mut self,
+        count: usize,
+        sections: BTreeMap<u64, Section<B>>,
+        written: BTreeSet<u64>,
+    ) -> Result<(), Error> {
+        for (section, blob) in sections {
+            self.sections.entry(section).or_insert(blob);
+        }
+        self.unsynced.extend(written);
+        self.hot.extend(self.pending.drain(..count));
+        while self.hot.len() > self.cfg.hot_nodes {
+            self.hot.pop_front();
+        }
+        self.persisted += count as u64;
+        self.metrics.hot_nodes.set(self.hot.len() as i64);
+        self.write_roots().await
+    }
+
+    /// Write any buffered nodes to storage (without syncing), waiting for any background flush to
+    /// finish first (see `write_nodes`).
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.wait_for_flush().await?;
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let flush_start = self.runtime.current();
+        let existing = self.hot_page_nodes();
+        let written = write_nodes(
+            &self.runtime,
+            &self.cfg.partition,
+            &self.layout,
+            &mut self.sections,
+            self.persisted,
+            existing,
+            &self.pending,
+            &self.metrics,
+        )
+        .await?;
+        self.finish_flush(self.pending.len(), BTreeMap::new(), written)
+            .await?;
+        self.metrics
+            .flush_latency
+            .observe(elapsed_secs(flush_start, self.runtime.current()));
+        Ok(())
+    }
+
+    /// Start writing the buffered nodes to storage in a background task (once any background flush
+    /// already in progress has finished), returning without waiting for it (see "Write-Behind").
+    ///
+    /// The nodes stay in `pending` (serving reads) until `wait_for_flush` records them as written.
+    async fn flush_behind(&mut self) -> Result<(), Error> {
+        self.wait_for_flush().await?;
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let runtime = self.runtime.clone();
+        let partition = self.cfg.partition.clone();
+        let layout = self.layout;
+        let pos = self.persisted;
+        let existing = self.hot_page_nodes();
+        let nodes = self.pending.clone();
+        let metrics = self.metrics.clone();
+
+        // The task only needs the sections the flush writes to, starting with the one holding `pos`
+        let (section, _, _) = layout.locate(pos);
+        let mut sections: BTreeMap<_, _> = self
+            .sections
+            .range(section..)
+            .map(|(section, blob)| (*section, blob.clone()))
+            .collect();
+        self.in_flight = nodes.len();
+        self.flushing = Some(self.runtime.spawn("flush", async move {
+            let flush_start = runtime.current();
+            let written = write_nodes(
+                &runtime,
+                &partition,
+                &layout,
+                &mut sections,
+                pos,
+                existing,
+                &nodes,
+                &metrics,
+            )
+            .await?;
+            metrics
+                .flush_latency
+                .observe(elapsed_secs(flush_start, runtime.current()));
+            Ok(Flushed { sections, written })
+        }));
+        Ok(())
+    }
+
+    /// Wait for the background flush in progress (if any) to finish (see "Write-Behind").
+    ///
+    /// If the flush failed, its error is returned and the nodes it was writing remain buffered, to
+    /// be written again by the next flush.
+    pub async fn wait_for_flush(&mut self) -> Result<(), Error> {
+        let Some(flushing) = self.flushing.take() else {
+            return Ok(());
+        };
+        let count = std::mem::take(&mut self.in_flight);
+        let Flushed { sections, written } = flushing.await??;
+        self.finish_flush(count, sections, written).await
+    }
+
+    /// Write the roots pending in `pending_roots` to the root index (if enabled), computing those of
+    /// any persisted leaves still missing from it (from nodes not added with `add`, or lost in a
+    /// crash) from their peaks.
+    async fn write_roots(&mut self) -> Result<(), Error> {
+        let interval = self.cfg.root_index_interval;
+        if interval == 0 {
+            return Ok(());
+        }
+        let target = leaf_count(self.persisted) / interval;
+        while self.indexed + (self.pending_roots.len() as u64) < target {
+            let leaves = (self.indexed + self.pending_roots.len() as u64 + 1) * interval;
+            let root = self.root_hash_at(node_count(leaves)).await?;
+            self.pending_roots.push(root);
+        }
+        if self.pending_roots.is_empty() {
+            return Ok(());
+        }
+        let buf: Vec<u8> = self
+            .pending_roots
+            .iter()
+            .flat_map(|root| root.iter().copied())
+            .collect();
+        let roots = self.roots.as_ref().unwrap();
+        roots.write_at(&buf, self.indexed * H::len() as u64).await?;
+        self.metrics.record_write(buf.len());
+        self.indexed += self.pending_roots.len() as u64;
+        self.pending_roots.clear();
+        self.roots_unsynced = true;
+        Ok(())
+    }
+
+    /// Write any buffered nodes to storage and ensure they are durably persisted, then commit a
+    /// checkpoint of the MMR (see `checkpoint`).
+    ///
+    /// Checkpointing is two-phase: every section written to is synced before the checkpoint is
+    /// written to the metadata partition (which is updated atomically), so a crash at any point
+    /// leaves the checkpoint of either this or the previous sync, and nodes backing it.
+    pub async fn sync(&mut self) -> Result<(), Error> {
+        self.flush().await?;
+        for section in self.unsynced.iter() {
+            self.sections.get(section).unwrap().sync().await?;
+        }
+        self.unsynced.clear();
+        if self.roots_unsynced {
+            self.roots.as_ref().unwrap().sync().await?;
+            self.roots_unsynced = false;
+        }
+        self.durable = self.persisted;
+        self.metrics.durable_size.set(self.durable as i64);
+        self.unsynced_appends = 0;
+        self.last_sync = self.runtime.current();
+        if self.checkpoint.as_ref().map(|checkpoint| checkpoint.size) != Some(self.durable) {
+            let root = self.root_hash();
+            self.commit_checkpoint(self.durable, root).await?;
+        }
+        Ok(())
+    }
+
+    /// Record a checkpoint of the MMR at `size` (whose nodes must all be durable) with root hash
+    /// `root` in the metadata partition, keeping the current checkpoint as the previous one only if
+    /// it covers no more nodes.
+    async fn commit_checkpoint(&mut self, size: u64, root: Digest) -> Result<(), Error> {
+        let leaf_count = util::leaf_count(size);
+        let checkpoint = Checkpoint {
+            size,
+            leaf_count,
+            root,
+        };
+        match &self.checkpoint {
+            Some(previous) if previous.size <= size => {
+                let record = encode_checkpoint(previous, &self.layout);
+                self.metadata.put(PREVIOUS_CHECKPOINT_KEY, record);
+            }
+            _ => self.metadata.remove(PREVIOUS_CHECKPOINT_KEY),
+        }
+        let record = encode_checkpoint(&checkpoint, &self.layout);
+        self.metadata.put(CHECKPOINT_KEY, record);
+        self.metadata.sync().await?;
+        debug!(size, "committed checkpoint");
+        self.checkpoint = Some(checkpoint);
+        Ok(())
+    }
+
+    /// Prune all nodes before `pos` (rounded down to the largest valid MMR size that doesn't
+    /// exceed it or the current size), deleting every section that only contains pruned nodes.
+    ///
+    /// The nodes still needed to add elements and generate proofs for retained elements (the peaks
+    /// of the MMR at the pruning boundary) are pinned and persisted, along with the boundary
+    /// itself, to the metadata partition before any section is removed. Pruning to a position
+    /// before the current boundary is a no-op.
+    pub async fn prune_to_pos(&mut self, pos: u64) -> Result<(), Error> {
+        // Ensure every node after the boundary is durable before discarding those before it
+        self.sync().await?;
+        let pruned_to_pos = largest_valid_size(pos.min(self.persisted));
+        if pruned_to_pos <= self.pruned_to_pos {
+            return Ok(());
+        }
+
+        // Persist the new boundary and its pinned nodes
+        let mut pinned_nodes = HashMap::new();
+        let mut pinned = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(pruned_to_pos) {
+            let hash = self.get_node(peak_pos).await?;
+            pinned.extend_from_slice(&hash);
+            pinned_nodes.insert(peak_pos, hash);
+        }
+        self.metadata.put(
+            PRUNED_TO_POS_KEY,
+            Bytes::copy_from_slice(&pruned_to_pos.to_be_bytes()),
+        );
+        self.metadata.put(PINNED_NODES_KEY, Bytes::from(pinned));
+        self.metadata.sync().await?;
+        self.pruned_to_pos = pruned_to_pos;
+        self.pinned_nodes = pinned_nodes;
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|pos| pos >= pruned_to_pos);
+        self.metrics.prunes.inc();
+
+        // Remove sections that only contain pruned nodes
+        let oldest_section = pruned_to_pos / self.cfg.section_nodes;
+        while let Some((&section, _)) = self.sections.first_key_value() {
+            if section >= oldest_section {
+                break;
+            }
+            let blob = self.sections.remove(&section).unwrap();
+            let name = blob.name(section);
+            blob.close().await?;
+            self.runtime
+                .remove(&self.cfg.partition, Some(&name))
+                .await?;
+            self.metrics.open_sections.dec();
+            debug!(section, "pruned section");
+        }
+        Ok(())
+    }
+
+    /// Reclaim the space occupied by pruned nodes in the oldest retained section, which
+    /// `prune_to_pos` can't delete while it still holds retained nodes, by rewriting the pages of
+    /// the section that hold retained nodes (along with the page holding the last pruned node, so
+    /// the section never becomes empty) into a new blob and deleting the original. Nodes keep their
+    /// positions, so compaction is invisible to everything but the storage footprint. Compacting a
+    /// section with no (or no more) whole pages to reclaim is a no-op.
+    ///
+    /// Compaction is crash-safe: the new blob is written and synced, then committed by recording it
+    /// in the metadata partition (and syncing), and only then is the original removed. On startup,
+    /// a compacted blob that was never committed is discarded (in favor of the original), while the
+    /// original of a committed one is removed. `Snapshot`s taken before compaction keep reading the
+    /// original blob through their own handle.
+    pub async fn compact(&mut self) -> Result<(), Error> {
+        let layout = self.layout;
+        let pruned_offset = self.pruned_to_pos % layout.section_nodes;
+        if pruned_offset == 0 {
+            return Ok(());
+        }
+        let section = self.pruned_to_pos / layout.section_nodes;
+        let first_page = (pruned_offset - 1) / layout.page_nodes;
+        match self.sections.get(&section) {
+            Some(blob) if blob.first_page() < first_page => {}
+            _ => return Ok(()),
+        }
+
+        // Write and sync the retained pages to a new blob
+        self.sync().await?;
+        let blob = self.sections.get(&section).unwrap();
+        let start = first_page * layout.page_len();
+        let mut buf = vec![0u8; (blob.len().await? - start) as usize];
+        blob.read_at(&mut buf, start).await?;
+        self.metrics.record_read(buf.len());
+        let name = blob_name(section, first_page);
+        let compacted = self.runtime.open(&self.cfg.partition, &name).await?;
+        compacted.write_at(&buf, 0).await?;
+        compacted.truncate(buf.len() as u64).await?;
+        compacted.sync().await?;
+        self.metrics.record_write(buf.len());
+
+        // Commit the compacted blob, then swap it in and remove the original
+        let mut committed = section.to_be_bytes().to_vec();
+        committed.extend_from_slice(&first_page.to_be_bytes());
+        self.metadata
+            .put(COMPACTED_SECTION_KEY, Bytes::from(committed));
+        self.metadata.sync().await?;
+        let compacted = Section::compacted(compacted, first_page, start);
+        let original = self.sections.insert(section, compacted).unwrap();
+        let original_name = original.name(section);
+        original.close().await?;
+        self.runtime
+            .remove(&self.cfg.partition, Some(&original_name))
+            .await?;
+        debug!(section, first_page, "compacted section");
+        Ok(())
+    }
+
+    /// Rewind the MMR to `size` (which must be a valid MMR size), discarding every node at or after
+    /// it, as if the elements added after it never were. Discarded nodes are removed from storage
+    /// (and the node cache) before returning, so they don't reappear after a crash.
+    ///
+    /// Returns `mmr::Error::InvalidSize` if `size` is not a valid MMR size no greater than the
+    /// current size, and `Error::ElementPruned` if it is before `pruned_to_pos`.
+    pub async fn rewind(&mut self, size: u64) -> Result<(), Error> {
+        self.wait_for_flush().await?;
+        if size > self.size() || !PeakIterator::check_validity(size) {
+            return Err(crate::mmr::Error::InvalidSize(size).into());
+        }
+        if size < self.pruned_to_pos {
+            return Err(Error::ElementPruned(size));
+        }
+        if size >= self.persisted {
+            self.pending.truncate((size - self.persisted) as usize);
+        } else {
+            // Checkpoint the rewound MMR before discarding any node a later checkpoint depends on
+            if self
+                .checkpoint
+                .as_ref()
+                .is_some_and(|checkpoint| checkpoint.size > size)
+            {
+                let root = self.root_hash_at(size).await?;
+                self.commit_checkpoint(size, root).await?;
+            }
+            self.pending.clear();
+            let layout = self.layout;
+            let stale: Vec<u64> = self
+                .sections
+                .range(size / layout.section_nodes..)
+                .map(|(&section, _)| section)
+                .collect();
+            for section in stale {
+                self.unsynced.remove(&section);
+                let keep = size.saturating_sub(section * layout.section_nodes);
+                if keep == 0 {
+                    let blob = self.sections.remove(&section).unwrap();
+                    let name = blob.name(section);
+                    blob.close().await?;
+                    self.runtime
+                        .remove(&self.cfg.partition, Some(&name))
+                        .await?;
+                    self.metrics.open_sections.dec();
+                    debug!(section, "removed rewound section");
+                    continue;
+                }
+
+                // Make the page holding the last kept node the last page of the section before
+                // updating its checksum (so the checksum only ever covers a prefix of the page if
+                // interrupted), and only then discard the nodes after it
+                let blob = self.sections.get(&section).unwrap();
+                let page_end = layout.section_len(keep.next_multiple_of(layout.page_nodes));
+                if blob.len().await? > page_end {
+                    blob.truncate(page_end).await?;
+                }
+                write_tail_checksum(blob, &layout, keep, &self.metrics).await?;
+                blob.truncate(layout.section_len(keep)).await?;
+                blob.sync().await?;
+            }
+            let hot_start = self.persisted - self.hot.len() as u64;
+            self.hot.truncate(size.saturating_sub(hot_start) as usize);
+            self.metrics.hot_nodes.set(self.hot.len() as i64);
+            self.persisted = size;
+            self.durable = self.durable.min(size);
+        }
+        self.cache.lock().unwrap().retain(|pos| pos < size);
+
+        // Discard the roots of the discarded leaves from the root index
+        if let Some(keep) = leaf_count(size).checked_div(self.cfg.root_index_interval) {
+            if keep < self.indexed {
+                let roots = self.roots.as_ref().unwrap();
+                roots.truncate(keep * H::len() as u64).await?;
+                roots.sync().await?;
+                self.indexed = keep;
+                self.roots_unsynced = false;
+                self.pending_roots.clear();
+            } else {
+                self.pending_roots.truncate((keep - self.indexed) as usize);
+            }
+        }
+
+        // Reload the peaks of the rewound MMR
+        let mut peaks = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(size) {
+            peaks.push((peak_pos, self.get_node(peak_pos).await?));
+        }
+        self.peaks = peaks;
+        let leaves = leaf_count(size);
+        self.metrics.size.set(size as i64);
+        self.metrics.leaves.set(leaves as i64);
+        self.metrics.durable_size.set(self.durable as i64);
+        debug!(size, "rewound mmr");
+        Ok(())
+    }
+
+    /// Write every node of the MMR to `writer` in the portable export format (see
+    /// `crate::mmr::export`), which can be imported by either this `Mmr` or the in-memory `Mmr`.
+    /// Stored nodes are read a page at a time (bypassing the node cache).
+    ///
+    /// Returns `Error::ElementPruned` if any node has been pruned.
+    pub async fn export<W: Write>(&self, writer: W) -> Result<(), Error> {
+        self.export_at(writer, leaf_count(self.size())).await
+    }
+
+    /// Write the nodes of the MMR as it was when it had `leaf_count` leaves (a prefix of its nodes)
+    /// to `writer` in the portable export format (see `export`), recording that size, so a backup
+    /// can capture an earlier size while elements continue to be added. Importing the export
+    /// yields the MMR built from the first `leaf_count` elements.
+    ///
+    /// Returns `Error::ElementOutOfRange` if the MMR has fewer than `leaf_count` leaves, and
+    /// `Error::ElementPruned` if any node has been pruned.
+    pub async fn export_at<W: Write>(&self, writer: W, leaf_count: u64) -> Result<(), Error> {
+        if leaf_count > util::leaf_count(self.size()) {
+            return Err(Error::ElementOutOfRange(leaf_count));
+        }
+        if self.pruned_to_pos > 0 {
+            return Err(Error::ElementPruned(0));
+        }
+        let size = node_count(leaf_count);
+        let mut peaks = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(size) {
+            peaks.push(self.get_node(peak_pos).await?);
+        }
+        let root = Hasher::with_scheme(&mut H::new(), self.cfg.scheme.clone())
+            .root_hash(size, peaks.iter());
+        let stored = self.persisted.min(size);
+        let layout = &self.layout;
+        let mut exporter = Exporter::new::<H>(writer, size, &root, &self.cfg.scheme)?;
+        let mut pos = 0;
+        while pos < stored {
+            let (section, page, index) = layout.locate(pos);
+            let blob = self
+                .sections
+                .get(&section)
+                .ok_or(Error::SectionMissing(section))?;
+            let nodes = read_page(blob, layout, page, Some(&self.metrics))
+                .await?
+                .ok_or(Error::Corrupt { section, page })?;
+            let count = (nodes.len() as u64 / layout.digest_len)
+                .saturating_sub(index)
+                .min(stored - pos);
+            if count == 0 {
+                return Err(Error::Corrupt { section, page });
+            }
+            let start = (index * layout.digest_len) as usize;
+            for node in nodes[start..]
+                .chunks_exact(layout.digest_len as usize)
+                .take(count as usize)
+            {
+                exporter.write_node(node)?;
+            }
+            pos += count;
+        }
+        for node in self.pending.iter().take((size - stored) as usize) {
+            exporter.write_node(node)?;
+        }
+        exporter.finish()?;
+        Ok(())
+    }
+
+    /// Initialize a new `Mmr` in the configured (empty) partition holding the MMR exported (by
+    /// either this `Mmr` or the in-memory `Mmr`) to `reader`, syncing its nodes before returning.
+    ///
+    /// Returns `Error::NotEmpty` if the partition already holds nodes, and `Error::Mmr` if the
+    /// export is invalid (see `crate::mmr::mem::Mmr::import`, including a parent that doesn't
+    /// match the hash of its children), was built with a scheme other than the configured one, or
+    /// its root hash differs from `expected_root` (if provided). Any nodes written before an
+    /// invalid export is rejected are removed.
+    pub async fn import<R: Read>(
+        runtime: E,
+        cfg: Config,
+        reader: R,
+        expected_root: Option<&Digest>,
+    ) -> Result<Self, Error> {
+        let mut mmr = Self::init(runtime, cfg).await?;
+        if mmr.size() > 0 {
+            return Err(Error::NotEmpty(mmr.size()));
+        }
+        if let Err(err) = mmr.import_nodes(reader, expected_root).await {
+            mmr.rewind(0).await?;
+            return Err(err);
+        }
+        mmr.sync().await?;
+        Ok(mmr)
+    }
+
+    /// Initialize an `Mmr` in the configured partition by replaying `elements` (every element ever
+    /// added to the MMR, in order), replacing any nodes previously stored there. This recovers an
+    /// MMR whose stored nodes are corrupt (or missing) from a surviving journal of its elements,
+    /// even if `init` fails. `progress` is called with the number of elements replayed each time a
+    /// full write buffer of nodes is written (and once more after the last element).
+    ///
+    /// The rebuilt nodes are written to a separate staging partition (the configured `partition`
+    /// suffixed with `_rebuild`) and synced, leaving the stored nodes untouched until the rebuild
+    /// completes. If `expected_root` is provided and differs from the root hash of the rebuilt MMR,
+    /// the staged nodes are discarded and `Error::Mmr` is returned. Otherwise the rebuild is
+    /// committed to the metadata partition, and only then are the stored sections (along with any
+    /// pruning boundary, compaction, and checkpoints) replaced by the staged ones. A crash before
+    /// the commit leaves the original nodes in place, while a crash after it is completed by the
+    /// next `init`.
+    pub async fn rebuild_from(
+        runtime: E,
+        cfg: Config,
+        elements: impl Iterator<Item = Digest>,
+        expected_root: Option<&Digest>,
+        mut progress: impl FnMut(u64),
+    ) -> Result<Self, Error> {
+        // Replay the elements into an empty staging partition
+        let staging_cfg = Config {
+            registry: Arc::new(Mutex::new(Registry::default())),
+            partition: rebuild_partition(&cfg.partition),
+            metadata_partition: rebuild_partition(&cfg.metadata_partition),
+            sync_policy: SyncPolicy::Never,
+            write_behind: false,
+            ..cfg.clone()
+        };
+        remove_partition(&runtime, &staging_cfg.partition).await?;
+        remove_partition(&runtime, &roots_partition(&staging_cfg.partition)).await?;
+        remove_partition(&runtime, &staging_cfg.metadata_partition).await?;
+        let mut staging = Self::init(runtime.clone(), staging_cfg.clone()).await?;
+        let mut replayed = 0;
+        for element in elements {
+            staging.add(&element).await?;
+            replayed += 1;
+            if staging.pending.is_empty() {
+                progress(replayed);
+            }
+        }
+        if !staging.pending.is_empty() {
+            progress(replayed);
+        }
+        let root = staging.root_hash();
+        staging.close().await?;
+        if expected_root.is_some_and(|expected_root| *expected_root != root) {
+            remove_partition(&runtime, &staging_cfg.partition).await?;
+            remove_partition(&runtime, &roots_partition(&staging_cfg.partition)).await?;
+            remove_partition(&runtime, &staging_cfg.metadata_partition).await?;
+            return Err(crate::mmr::Error::RootMismatch.into());
+        }
+
+        // Commit the rebuild, then replace the stored sections with the staged ones
+        let mut metadata = Metadata::init(
+            runtime.clone(),
+            MetadataConfig {
+                registry: Arc::new(Mutex::new(Registry::default())),
+                partition: cfg.metadata_partition.clone(),
+            },
+        )
+        .await?;
+        metadata.put(REBUILD_KEY, Bytes::new());
+        metadata.sync().await?;
+        finish_rebuild(&runtime, &cfg, &mut metadata).await?;
+        metadata.close().await?;
+        debug!(elements = replayed, "rebuilt mmr");
+
+        let mut mmr = Self::init(runtime, cfg).await?;
+        mmr.sync().await?;
+        Ok(mmr)
+    }
+
+    /// Append the nodes exported to `reader` to an empty MMR.
+    async fn import_nodes<R: Read>(
+        &mut self,
+        reader: R,
+        expected_root: Option<&Digest>,
+    ) -> Result<(), Error> {
+        let mut importer = Importer::<_, H>::new(reader, self.cfg.scheme.clone())?;
+        if expected_root.is_some_and(|root| root != importer.root()) {
+            return Err(crate::mmr::Error::RootMismatch.into());
+        }
+        let size = importer.size();
+        let mut peaks = PeakIterator::new(size)
+            .map(|(peak_pos, _)| peak_pos)
+            .peekable();
+        for pos in 0..size {
+            let node = importer.read_node()?;
+            if peaks.next_if_eq(&pos).is_some() {
+                self.peaks.push((pos, node.clone()));
+            }
+            self.pending.push(node);
+            if self.pending.len() >= self.cfg.write_buffer {
+                self.flush().await?;
+            }
+        }
+        importer.finish()?;
+
+        let leaves = leaf_count(size);
+        self.metrics.size.set(size as i64);
+        self.metrics.leaves.set(leaves as i64);
+        Ok(())
+    }
+
+    /// Verify the checksum of every page of every section, returning the `(section, page)` of each
+    /// page whose nodes don't match their checksum (or are missing). Only nodes written to storage
+    /// are checked (buffered nodes are written by `sync`).
+    pub async fn verify_all(&self) -> Result<Vec<(u64, u64)>, Error> {
+        let layout = &self.layout;
+        let mut corrupt = Vec::new();
+        for (&section, blob) in self.sections.iter() {
+            let section_start = section * layout.section_nodes;
+            let nodes = self
+                .persisted
+                .saturating_sub(section_start)
+                .min(layout.section_nodes);
+            let pages = nodes
+                .div_ceil(layout.page_nodes)
+                .max(blob.len().await?.div_ceil(layout.page_len()));
+            for page in blob.first_page()..pages {
+                let expected = nodes
+                    .saturating_sub(page * layout.page_nodes)
+                    .min(layout.page_nodes);
+                let valid = match read_page(blob, layout, page, Some(&self.metrics)).await? {
+                    Some(page_nodes) => {
+                        let valid = page_nodes.len() as u64 == expected * layout.digest_len;
+                        if !valid {
+                            self.metrics.verification_failures.inc();
+                        }
+                        valid
+                    }
+                    None => false,
+                };
+                if !valid {
+                    warn!(section, page, "corrupt page");
+                    corrupt.push((section, page));
+                }
+            }
+        }
+        Ok(corrupt)
+    }
+
+    /// Find every `Blob` in the partitions of the MMR that it doesn't reference, removing them
+    /// unless `dry_run` is set, and return them along with the number of bytes they hold.
+    ///
+    /// The sections of the MMR (as committed to the metadata partition) and its root index are
+    /// referenced, so orphans are blobs in those partitions left by an interrupted compaction or
+    /// by other writes that were never committed, root indexes of other intervals, and every blob
+    /// staged by a `rebuild_from` that was never committed (any committed rebuild is completed by
+    /// `init`). The metadata partition itself is never touched.
+    pub async fn gc(&mut self, dry_run: bool) -> Result<GcReport, Error> {
+        // Sections created by a background flush are only referenced once it has finished
+        self.wait_for_flush().await?;
+        let staging = rebuild_partition(&self.cfg.partition);
+        let live_index = self
+            .roots
+            .as_ref()
+            .map(|_| self.cfg.root_index_interval.to_be_bytes().to_vec());
+        let live_sections: BTreeSet<Vec<u8>> = self
+            .sections
+            .iter()
+            .map(|(section, blob)| blob.name(*section))
+            .collect();
+        let partitions = [
+            (self.cfg.partition.clone(), live_sections),
+            (
+                roots_partition(&self.cfg.partition),
+                live_index.into_iter().collect(),
+            ),
+            (roots_partition(&staging), BTreeSet::new()),
+            (staging, BTreeSet::new()),
+            (
+                rebuild_partition(&self.cfg.metadata_partition),
+                BTreeSet::new(),
+            ),
+        ];
+        let mut report = GcReport::default();
+        for (partition, live) in partitions {
+            let mut names = match self.runtime.scan(&partition).await {
+                Ok(names) => names,
+                Err(RError::PartitionMissing(_)) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            names.sort();
+            for name in names {
+                if live.contains(&name) {
+                    continue;
+                }
+                let blob = self.runtime.open(&partition, &name).await?;
+                let len = blob.len().await?;
+                blob.close().await?;
+                if !dry_run {
+                    warn!(partition, blob = hex(&name), len, "removing orphaned blob");
+                    self.runtime.remove(&partition, Some(&name)).await?;
+                }
+                report.reclaimed_bytes += len;
+                report.orphans.push(Orphan {
+                    partition: partition.clone(),
+                    name,
+                    len,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sync outstanding nodes and close the `Mmr`.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.sync().await?;
+        for (_, blob) in self.sections.into_iter() {
+            blob.close().await?;
+        }
+        self.metadata.close().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! An append-only log of raw elements authenticated by an MMR.
+//!
+//! A `Log` stores the bytes of each element it is given, assigns it the next sequential index, and
+//! adds the hash of the bytes to an in-memory `Mmr`, handling the mapping between indices and leaf
+//! positions that consumers of an `Mmr` would otherwise track themselves. An element's inclusion is
+//! proven against the root hash of the log with `verify_log_inclusion`.
+
+use crate::mmr::mem::Mmr;
+use crate::mmr::util::node_count;
+use crate::mmr::verification::Proof;
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+
+/// Return true if `proof` proves that `element` was appended at `index` to the `Log` with root hash
+/// `root_hash`.
+pub fn verify_log_inclusion<H: CHasher>(
+    proof: &Proof,
+    index: u64,
+    element: &[u8],
+    root_hash: &Digest,
+    hasher: &mut H,
+) -> bool {
+    hasher.update(element);
+    let element = hasher.finalize();
+    proof.verify_element_inclusion(&element, node_count(index), root_hash, hasher)
+}
+
+/// Implementation of `Log`.
+pub struct Log<H: CHasher> {
+    hasher: H,
+    mmr: Mmr<H>,
+    // The bytes of each element, by index.
+    elements: Vec<Vec<u8>>,
+}
+
+impl<H: CHasher> Default for Log<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: CHasher> Log<H> {
+    /// Return a new (empty) `Log`.
+    pub fn new() -> Self {
+        Self {
+            hasher: H::new(),
+            mmr: Mmr::new(),
+            elements: Vec::new(),
+        }
+    }
+
+    /// Return the underlying MMR.
+    pub fn mmr(&self) -> &Mmr<H> {
+        &self.mmr
+    }
+
+    /// Return the number of elements in the log.
+    pub fn len(&self) -> u64 {
+        self.elements.len() as u64
+    }
+
+    /// Return true if the log contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Append `element` to the log, returning its index along with a proof of its inclusion (which
+    /// verifies against the root hash of the log until the next append).
+    pub fn append(&mut self, element: &[u8]) -> (u64, Proof) {
+        let index = self.len();
+        self.hasher.update(element);
+        let pos = self.mmr.add(&self.hasher.finalize());
+        debug_assert_eq!(pos, node_count(index));
+        self.elements.push(element.to_vec());
+        let proof = self
+            .mmr
+            .proof(pos)
+            .expect("nodes of the log are never pruned");
+        (index, proof)
+    }
+
+    /// Return the element at `index`, if any.
+    pub fn get(&self, index: u64) -> Option<&[u8]> {
+        let index = usize::try_from(index).ok()?;
+        self.elements.get(index).map(|element| element.as_slice())
+    }
+
+    /// Return a proof of the inclusion of the element at `index`.
+    ///
+    /// Returns `Error::IndexOutOfRange` if the log has no element at `index`.
+    pub fn prove(&self, index: u64) -> Result<Proof, Error> {
+        if index >= self.len() {
+            return Err(Error::IndexOutOfRange(index));
+        }
+        self.mmr.proof(node_count(index))
+    }
+
+    /// Computes the root hash of the log.
+    pub fn root_hash(&mut self) -> Digest {
+        self.mmr.root_hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_cryptography::Sha256;
+
+    #[test]
+    fn test_log() {
+        let mut log = Log::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        assert!(log.is_empty());
+        let elements: Vec<Vec<u8>> = (0u8..49).map(|i| vec![i; i as usize]).collect();
+
+        // Each append is proven against the root hash that follows it
+        for (i, element) in elements.iter().enumerate() {
+            let (index, proof) = log.append(element);
+            assert_eq!(index, i as u64);
+            let root_hash = log.root_hash();
+            assert!(verify_log_inclusion(
+                &proof,
+                index,
+                element,
+                &root_hash,
+                &mut hasher
+            ));
+        }
+        assert_eq!(log.len(), 49);
+
+        // Leaves are the hashes of the elements
+        let mut reference = Mmr::<Sha256>::new();
+        for element in elements.iter() {
+            hasher.update(element);
+            reference.add(&hasher.finalize());
+        }
+        let root_hash = log.root_hash();
+        assert_eq!(root_hash, reference.root_hash());
+
+        // Every element is retrievable and proven at its index
+        for (i, element) in elements.iter().enumerate() {
+            let index = i as u64;
+            assert_eq!(log.get(index), Some(element.as_slice()));
+            let proof = log.prove(index).unwrap();
+            assert!(verify_log_inclusion(
+                &proof,
+                index,
+                element,
+                &root_hash,
+                &mut hasher
+            ));
+        }
+
+        // Proofs don't verify for other elements or indices
+        let proof = log.prove(10).unwrap();
+        assert!(!verify_log_inclusion(
+            &proof,
+            10,
+            &elements[11],
+            &root_hash,
+            &mut hasher
+        ));
+        assert!(!verify_log_inclusion(
+            &proof,
+            11,
+            &elements[10],
+            &root_hash,
+            &mut hasher
+        ));
+
+        // Indices beyond the end hold no element
+        assert_eq!(log.get(49), None);
+        assert_eq!(log.prove(49), Err(Error::IndexOutOfRange(49)));
+    }
+}
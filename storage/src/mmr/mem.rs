@@ -1,17 +1,113 @@
-//! A bare-bones MMR structure without pruning and where all nodes are hashes & maintained in
-//! memory within a single vector.
+//! A basic MMR where all retained nodes are hashes & maintained in memory.
 
-use crate::mmr::hasher::Hasher;
-use crate::mmr::iterator::{nodes_needing_parents, PathIterator, PeakIterator};
-use crate::mmr::verification::Proof;
+use crate::mmr::accumulator::{add_to_peaks, Accumulator, PeakOnlyMmr};
+#[cfg(feature = "rkyv")]
+use crate::mmr::archived::{ArchivedNodeStore, NodeStore};
+use crate::mmr::compat::merkle::{Direction, MerkleProof};
+use crate::mmr::export::{Exporter, Importer};
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{
+    largest_valid_size, next_leaf_pos, nodes_needing_parents, pos_to_height, PeakIterator,
+};
+use crate::mmr::sync::{last_leaf_pos, SyncChunk};
+use crate::mmr::util::{self, leaf_count, node_count};
+use crate::mmr::verification::{
+    nodes_required_for_range_proof, nodes_required_for_sparse_proof, present_positions, Checkpoint,
+    ElementWitness, MmrDelta, Proof, ProofContext, ProofCost, ProofStrategy,
+};
+use crate::mmr::Error;
 use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+/// The version of the format produced by `Mmr::serialize`.
+const SERIALIZATION_VERSION: u8 = 2;
+
+/// Return the nodes of the perfect subtree whose leaves are `elements` (a power of two of them),
+/// the first of which is the leaf with index `start_leaf`.
+#[cfg(not(target_arch = "wasm32"))]
+fn hash_subtree<H: CHasher>(
+    hasher: &mut Hasher<H>,
+    start_leaf: u64,
+    elements: &[Digest],
+) -> Vec<Digest> {
+    let start_pos = node_count(start_leaf);
+    let mut nodes = Vec::with_capacity(2 * elements.len() - 1);
+    let mut roots = Vec::new();
+    for element in elements {
+        nodes.push(hasher.leaf_hash(start_pos + nodes.len() as u64, element));
+        merge_roots(hasher, start_pos, &mut nodes, &mut roots, 0);
+    }
+    nodes
+}
+
+/// Record the last of `nodes` (the first of which is at `start_pos`) as the root of a perfect
+/// subtree of the given `height`, hashing a parent for it (and for each resulting subtree) while
+/// the preceding subtree in `roots` (the heights and indices of the nodes without parents) has the
+/// same height.
+#[cfg(not(target_arch = "wasm32"))]
+fn merge_roots<H: CHasher>(
+    hasher: &mut Hasher<H>,
+    start_pos: u64,
+    nodes: &mut Vec<Digest>,
+    roots: &mut Vec<(u32, usize)>,
+    mut height: u32,
+) {
+    roots.push((height, nodes.len() - 1));
+    while roots.len() >= 2 && roots[roots.len() - 2].0 == height {
+        let (_, right) = roots.pop().unwrap();
+        let (_, left) = roots.pop().unwrap();
+        let parent = hasher.node_hash(start_pos + nodes.len() as u64, &nodes[left], &nodes[right]);
+        nodes.push(parent);
+        height += 1;
+        roots.push((height, nodes.len() - 1));
+    }
+}
+
+/// Determines which nodes an `Mmr` created with `Mmr::with_pruning_policy` prunes automatically as
+/// elements are added.
+///
+/// Pruning discards nodes from the start of the MMR, pinning only the pruned peaks still required
+/// to add elements, compute the root hash, and generate proofs for the retained elements. Proofs
+/// requiring any other pruned node fail with `Error::ElementPruned`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PruningPolicy {
+    /// Never prune.
+    #[default]
+    RetainAll,
+    /// Retain the nodes required to prove (at least) the last `n` elements added. To amortize the
+    /// cost of pruning, nodes are only pruned once as many can be discarded as would be retained,
+    /// so the MMR holds at most about twice the nodes of the last `n` elements.
+    RetainLastN(u64),
+    /// Retain every node from (at least) the given position on, pruning those before it once the
+    /// MMR reaches it.
+    RetainAfter(u64),
+}
 
 /// Implementation of `Mmr`.
 pub struct Mmr<H: CHasher> {
     hasher: H,
-    // The nodes of the MMR, laid out according to a post-order traversal of the MMR trees, starting
-    // from the from tallest tree to shortest.
+    // The scheme used to hash leaves, internal nodes, and the root.
+    scheme: Scheme,
+    // The policy applied after each addition to prune old nodes.
+    pruning_policy: PruningPolicy,
+    // The retained nodes of the MMR that have been shared with a `ProofGenerator`, laid out
+    // according to a post-order traversal of the MMR trees, starting from the from tallest tree to
+    // shortest.
+    frozen: Arc<[Digest]>,
+    // The retained nodes added since `frozen` was last extended, which directly follow it.
     nodes: Vec<Digest>,
+    // The position of the first node in `frozen`. Nodes before this position have been pruned,
+    // except for those in `pinned_nodes`.
+    oldest_retained_pos: u64,
+    // Pruned nodes that are still required to compute the root hash and add new elements.
+    pinned_nodes: HashMap<u64, Digest>,
+    // An optional index from each added element to the positions of the leaves representing it.
+    // Only maintained if the MMR was created with `new_with_position_index`.
+    positions: Option<HashMap<Digest, Vec<u64>>>,
+    // Bytes written through `io::Write` that don't yet form a complete element.
+    partial_element: Vec<u8>,
 }
 
 impl<H: CHasher> Default for Mmr<H> {
@@ -20,50 +116,486 @@ impl<H: CHasher> Default for Mmr<H> {
     }
 }
 
+impl<H: CHasher> From<Vec<Digest>> for Mmr<H> {
+    /// Build an MMR by adding each element of `elements` in order.
+    fn from(elements: Vec<Digest>) -> Self {
+        let mut mmr = Self::new();
+        for element in elements.iter() {
+            mmr.add(element);
+        }
+        mmr
+    }
+}
+
 impl<H: CHasher> Mmr<H> {
     /// Return a new (empty) `Mmr`.
     pub fn new() -> Self {
         Self {
             hasher: H::new(),
+            scheme: Scheme::default(),
+            pruning_policy: PruningPolicy::default(),
+            frozen: Arc::default(),
+            nodes: Vec::new(),
+            oldest_retained_pos: 0,
+            pinned_nodes: HashMap::new(),
+            positions: None,
+            partial_element: Vec::new(),
+        }
+    }
+
+    /// Return a new (empty) `Mmr` that additionally maintains an index from each added element to
+    /// the position(s) of its leaves, allowing elements to be located with `positions_of`.
+    pub fn new_with_position_index() -> Self {
+        Self {
+            hasher: H::new(),
+            scheme: Scheme::default(),
+            pruning_policy: PruningPolicy::default(),
+            frozen: Arc::default(),
             nodes: Vec::new(),
+            oldest_retained_pos: 0,
+            pinned_nodes: HashMap::new(),
+            positions: Some(HashMap::new()),
+            partial_element: Vec::new(),
+        }
+    }
+
+    /// Return a new (empty) `Mmr` that hashes with the given `scheme`, e.g. `Scheme::Untagged` to
+    /// reproduce root hashes computed before nodes were domain separated.
+    pub fn new_with_scheme(scheme: Scheme) -> Self {
+        Self {
+            scheme,
+            ..Self::new()
+        }
+    }
+
+    /// Return a new (empty) `Mmr` that mixes `key` into every hash (see `Scheme::Keyed`), so that
+    /// its root hash differs from that of any MMR built with another key (or none).
+    pub fn new_keyed(key: &[u8]) -> Self {
+        Self::new_with_scheme(Scheme::keyed(key))
+    }
+
+    /// Return a new (empty) `Mmr` that automatically prunes its nodes according to `policy` after
+    /// each addition, bounding its memory use over a long run.
+    pub fn with_pruning_policy(policy: PruningPolicy) -> Self {
+        Self {
+            pruning_policy: policy,
+            ..Self::new()
+        }
+    }
+
+    /// Return the scheme the MMR hashes with.
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    /// Return a mutable reference to the hasher used by the MMR, e.g. to inspect the inputs
+    /// recorded by a `RecordingHasher`.
+    pub fn hasher(&mut self) -> &mut H {
+        &mut self.hasher
+    }
+
+    /// Return the total number of nodes in the MMR, including those that have been pruned.
+    pub fn size(&self) -> u64 {
+        self.oldest_retained_pos + (self.frozen.len() + self.nodes.len()) as u64
+    }
+
+    /// Return true if no elements have been added to the MMR.
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Return the position of the oldest node that has not been pruned. Nodes before this position
+    /// can't be used to generate proofs.
+    pub fn oldest_retained_pos(&self) -> u64 {
+        self.oldest_retained_pos
+    }
+
+    /// Return the hash of the node at `pos`, or `None` if it has been pruned (or doesn't exist).
+    pub(crate) fn get_node(&self, pos: u64) -> Option<&Digest> {
+        if pos < self.oldest_retained_pos {
+            return self.pinned_nodes.get(&pos);
+        }
+        let index = (pos - self.oldest_retained_pos) as usize;
+        match index.checked_sub(self.frozen.len()) {
+            Some(index) => self.nodes.get(index),
+            None => Some(&self.frozen[index]),
+        }
+    }
+
+    /// Return the root hash of an empty MMR: the hash of its size (0) alone, as it has no peaks.
+    pub fn empty_root(hasher: &mut H) -> Digest {
+        Hasher::new(hasher).root_hash(0, std::iter::empty())
+    }
+
+    /// Return the hash of the node at `pos`, or `None` if it has been pruned (and isn't pinned) or
+    /// doesn't exist.
+    pub fn node_at(&self, pos: u64) -> Option<&Digest> {
+        self.get_node(pos)
+    }
+
+    /// Return the hash of the leaf with the given index (the number of leaves preceding it), or
+    /// `None` if it has been pruned (and isn't pinned) or doesn't exist.
+    pub fn leaf_at(&self, leaf_index: usize) -> Option<&Digest> {
+        // The position of a leaf beyond the MMR may not be representable
+        let leaf_index = leaf_index as u64;
+        if leaf_index >= leaf_count(self.size()) {
+            return None;
+        }
+        self.get_node(node_count(leaf_index))
+    }
+
+    /// Return the number of nodes the node store can hold without reallocating.
+    pub fn capacity_nodes(&self) -> usize {
+        self.frozen.len() + self.nodes.capacity()
+    }
+
+    /// Return the number of nodes currently held in the node store, including any pruned nodes
+    /// that remain pinned.
+    pub fn retained_nodes(&self) -> usize {
+        self.frozen.len() + self.nodes.len() + self.pinned_nodes.len()
+    }
+
+    /// Release any excess capacity held by the node store, the pinned nodes, and the position index
+    /// (if enabled).
+    /// This has no effect on the contents of the MMR.
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.pinned_nodes.shrink_to_fit();
+        if let Some(positions) = self.positions.as_mut() {
+            for leaves in positions.values_mut() {
+                leaves.shrink_to_fit();
+            }
+            positions.shrink_to_fit();
         }
     }
 
-    pub fn size(&self) -> usize {
-        self.nodes.len()
+    /// Return the positions of every retained leaf representing `element`, in increasing order.
+    /// The same element may be added more than once, in which case multiple positions are returned.
+    ///
+    /// Returns `None` if the MMR was not created with `new_with_position_index`.
+    pub fn positions_of(&self, element: &Digest) -> Option<&[u64]> {
+        let positions = self.positions.as_ref()?;
+        match positions.get(element) {
+            Some(positions) => Some(positions),
+            None => Some(&[]),
+        }
     }
 
     /// Return a new iterator over the peaks of the MMR.
     fn peak_iterator(&self) -> PeakIterator {
-        PeakIterator::new(self.nodes.len() as u64)
+        PeakIterator::new(self.size())
+    }
+
+    /// Return the position and hash of each peak of the MMR, in decreasing order of height.
+    pub fn peak_hashes(&self) -> Vec<(u64, Digest)> {
+        self.peak_iterator()
+            .map(|(peak_pos, _)| (peak_pos, self.get_node(peak_pos).unwrap().clone()))
+            .collect()
+    }
+
+    /// Return the position of each peak of the MMR, in decreasing order of height.
+    pub fn peak_positions(&self) -> Vec<u64> {
+        self.peak_iterator().map(|(peak_pos, _)| peak_pos).collect()
+    }
+
+    /// Return the height of the tallest peak of the MMR (the position of the most significant bit
+    /// of its leaf count), or 0 if the MMR is empty.
+    pub fn height(&self) -> u32 {
+        self.peak_iterator().next().map_or(0, |(_, height)| height)
+    }
+
+    /// Return true if the MMR has exactly one peak (and is therefore a perfect binary tree).
+    pub fn is_perfect(&self) -> bool {
+        self.peak_iterator().count() == 1
     }
 
     /// Add an element to the MMR and return its position in the MMR.
     pub fn add(&mut self, element: &Digest) -> u64 {
-        let peaks = nodes_needing_parents(self.peak_iterator());
-        let element_pos = self.nodes.len() as u64;
-        let hasher = &mut Hasher::new(&mut self.hasher);
+        let element_pos = self.size();
+        let hash = Hasher::with_scheme(&mut self.hasher, self.scheme.clone())
+            .leaf_hash(element_pos, element);
+        self.add_leaf_hash(hash);
+        if let Some(positions) = self.positions.as_mut() {
+            positions
+                .entry(element.clone())
+                .or_default()
+                .push(element_pos);
+        }
+        element_pos
+    }
 
-        // Insert the element into the MMR as a leaf.
-        let mut hash = hasher.leaf_hash(element_pos, element);
+    /// Insert an (already hashed) leaf into the MMR, along with the parent nodes it completes, and
+    /// return its position.
+    fn add_leaf_hash(&mut self, mut hash: Digest) -> u64 {
+        let peaks = nodes_needing_parents(self.peak_iterator());
+        let element_pos = self.size();
         self.nodes.push(hash.clone());
 
         // Compute the new parent nodes, if any, and insert them into the MMR.
         for sibling_pos in peaks.into_iter().rev() {
-            let parent_pos = self.nodes.len() as u64;
-            hash = hasher.node_hash(parent_pos, &self.nodes[sibling_pos as usize], &hash);
+            let parent_pos = self.size();
+            let sibling = self.get_node(sibling_pos).unwrap().clone();
+            hash = Hasher::with_scheme(&mut self.hasher, self.scheme.clone())
+                .node_hash(parent_pos, &sibling, &hash);
             self.nodes.push(hash.clone());
         }
+        self.apply_pruning_policy();
         element_pos
     }
 
+    /// Prune the nodes the pruning policy no longer requires the MMR to retain (if any).
+    fn apply_pruning_policy(&mut self) {
+        let prune_to_pos = match self.pruning_policy {
+            PruningPolicy::RetainAll => return,
+            PruningPolicy::RetainLastN(n) => {
+                // Only prune once at least as many nodes can be discarded as would be retained,
+                // so moving the retained nodes is amortized over the additions since the last
+                // prune
+                let pos = node_count(leaf_count(self.size()).saturating_sub(n));
+                if pos.saturating_sub(self.oldest_retained_pos) < self.size() - pos {
+                    return;
+                }
+                pos
+            }
+            PruningPolicy::RetainAfter(pos) if pos <= self.size() => largest_valid_size(pos),
+            PruningPolicy::RetainAfter(_) => return,
+        };
+        if prune_to_pos > self.oldest_retained_pos {
+            self.prune_to_pos(prune_to_pos);
+        }
+    }
+
+    /// Discard every node before `pos` (a valid MMR size), pinning the peaks of the MMR of that
+    /// size, which are the only pruned nodes required to add elements, compute the root hash, and
+    /// prove any element at or after `pos`.
+    fn prune_to_pos(&mut self, pos: u64) {
+        let pinned_nodes = PeakIterator::new(pos)
+            .map(|(peak_pos, _)| (peak_pos, self.get_node(peak_pos).unwrap().clone()))
+            .collect();
+        let pruned = (pos - self.oldest_retained_pos) as usize;
+        self.nodes = self
+            .frozen
+            .iter()
+            .chain(self.nodes.iter())
+            .skip(pruned)
+            .cloned()
+            .collect();
+        self.frozen = Arc::default();
+        self.oldest_retained_pos = pos;
+        self.pinned_nodes = pinned_nodes;
+        self.retain_positions(|leaf_pos| leaf_pos >= pos);
+    }
+
+    /// Drop every position index entry (if enabled) for which `keep` returns false, along with any
+    /// element left without positions.
+    fn retain_positions(&mut self, keep: impl Fn(u64) -> bool) {
+        if let Some(positions) = self.positions.as_mut() {
+            positions.retain(|_, leaves| {
+                leaves.retain(|leaf_pos| keep(*leaf_pos));
+                !leaves.is_empty()
+            });
+        }
+    }
+
+    /// Rewind the MMR to `size` (which must be a valid MMR size), discarding every node at or after
+    /// it (and their position index entries, if enabled), as if the elements added after it never
+    /// were.
+    ///
+    /// Returns `Error::InvalidSize` if `size` is not a valid MMR size no greater than the current
+    /// size, and `Error::ElementPruned` if it is before `oldest_retained_pos`.
+    pub fn rewind(&mut self, size: u64) -> Result<(), Error> {
+        if size > self.size() || !PeakIterator::check_validity(size) {
+            return Err(Error::InvalidSize(size));
+        }
+        if size < self.oldest_retained_pos {
+            return Err(Error::ElementPruned(size));
+        }
+        let retained = (size - self.oldest_retained_pos) as usize;
+        match retained.checked_sub(self.frozen.len()) {
+            Some(retained) => self.nodes.truncate(retained),
+            None => {
+                self.nodes = self.frozen[..retained].to_vec();
+                self.frozen = Arc::default();
+            }
+        }
+        self.retain_positions(|leaf_pos| leaf_pos < size);
+        Ok(())
+    }
+
+    /// Remove the last element added to the MMR (along with the parent nodes it completed) and
+    /// return its position, or `None` if the MMR is empty.
+    ///
+    /// Returns `Error::ElementPruned` if the last element has been pruned.
+    pub fn pop(&mut self) -> Result<Option<u64>, Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let element_pos = node_count(leaf_count(self.size()) - 1);
+        self.rewind(element_pos)?;
+        Ok(Some(element_pos))
+    }
+
+    /// Add every element of `other` to this MMR in order, producing the same result as adding each
+    /// of them sequentially.
+    ///
+    /// Since the nodes of an MMR only store hashes, the elements of `other` can only be recovered
+    /// if it maintains a position index (see `new_with_position_index`). Returns
+    /// `Error::ElementsNotRetained` (leaving this MMR unmodified) if `other` is non-empty and
+    /// doesn't.
+    pub fn append_mmr(&mut self, other: &Mmr<H>) -> Result<(), Error> {
+        if other.is_empty() {
+            return Ok(());
+        }
+        let Some(positions) = other.positions.as_ref() else {
+            return Err(Error::ElementsNotRetained);
+        };
+        let mut elements: Vec<(u64, &Digest)> = positions
+            .iter()
+            .flat_map(|(element, positions)| positions.iter().map(move |pos| (*pos, element)))
+            .collect();
+        elements.sort_unstable_by_key(|(pos, _)| *pos);
+        for (_, element) in elements {
+            self.add(element);
+        }
+        Ok(())
+    }
+
     /// Computes the root hash of the MMR.
+    ///
+    /// The root hash is a single hash over the size of the MMR (big-endian) followed by the hashes
+    /// of its peaks (see `peak_hashes`), rather than a fold that bags the peaks pairwise, so the
+    /// peak hashes are the only intermediate values to compare against another implementation.
+    /// Under `Scheme::Tagged` the input is prefixed with the root tag `0x02`, and under
+    /// `Scheme::Keyed` the tag is followed by the key (prefixed with its big-endian `u64` length).
     pub fn root_hash(&mut self) -> Digest {
+        let size = self.size();
+        let peaks = self.peak_hashes();
+        let hasher = &mut Hasher::with_scheme(&mut self.hasher, self.scheme.clone());
+        hasher.root_hash(size, peaks.iter().map(|(_, hash)| hash))
+    }
+
+    /// Return each value produced while bagging the peaks into the root hash with `hasher`, from
+    /// the rightmost peak inward and ending in the root hash.
+    ///
+    /// As the root hash is a single hash over the size and every peak (see `root_hash`) rather than
+    /// a pairwise fold, no accumulator is produced between the peaks: the values are the peak hashes
+    /// (in increasing order of height) followed by the root hash, whose input is the size and the
+    /// same peaks in the reverse order.
+    pub fn bag_peaks(&self, hasher: &mut H) -> Vec<Digest> {
+        let peaks = self.peak_hashes();
+        let root = Hasher::with_scheme(hasher, self.scheme.clone())
+            .root_hash(self.size(), peaks.iter().map(|(_, hash)| hash));
+        peaks
+            .into_iter()
+            .rev()
+            .map(|(_, hash)| hash)
+            .chain(std::iter::once(root))
+            .collect()
+    }
+
+    /// Return the hashes of every peak other than the one at index `peak_idx` (in decreasing order
+    /// of height), or `None` if the MMR has no such peak.
+    ///
+    /// The root hash commits to the size of the MMR followed by all peak hashes in decreasing order
+    /// of height, so a consumer holding the target peak's hash can reconstruct the root by inserting
+    /// it into the witness at `peak_idx` and hashing the result with the size.
+    pub fn witness_for_peak(&self, peak_idx: usize) -> Option<Vec<Digest>> {
+        let mut peaks: Vec<Digest> = self
+            .peak_hashes()
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .collect();
+        if peak_idx >= peaks.len() {
+            return None;
+        }
+        peaks.remove(peak_idx);
+        Some(peaks)
+    }
+
+    /// Discard every node except the current peaks, returning an `Accumulator` that can continue to
+    /// add elements and compute the root hash, but can no longer generate proofs.
+    ///
+    /// This operation is irreversible: the discarded nodes cannot be recovered from the returned
+    /// accumulator.
+    pub fn prune_to_accumulator(self) -> Accumulator<H> {
+        let size = self.size();
+        let peaks = self
+            .peak_hashes()
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .collect();
+        Accumulator::from_peaks(self.hasher, self.scheme, size, peaks)
+    }
+
+    /// Return a `PeakOnlyMmr` holding just the current peaks of the MMR (leaving the MMR itself
+    /// intact), which can add elements and compute the root hash in O(log n) space, but can't
+    /// generate proofs.
+    pub fn clone_peaks_only(&self) -> PeakOnlyMmr<H> {
         let peaks = self
-            .peak_iterator()
-            .map(|(peak_pos, _)| &self.nodes[peak_pos as usize]);
-        let hasher = &mut Hasher::new(&mut self.hasher);
-        hasher.root_hash(self.nodes.len() as u64, peaks)
+            .peak_hashes()
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .collect();
+        Accumulator::from_peaks(H::new(), self.scheme.clone(), self.size(), peaks)
+    }
+
+    /// Return a copy of the MMR pruned to just its current peaks. The copy can add elements and
+    /// compute the root hash, but returns `Error::ElementPruned` when generating a proof that
+    /// requires any node that existed before the copy was made. The position index (if enabled) is
+    /// not copied.
+    pub fn clone_pruned(&self) -> Self {
+        Self {
+            hasher: H::new(),
+            scheme: self.scheme.clone(),
+            pruning_policy: self.pruning_policy,
+            frozen: Arc::default(),
+            nodes: Vec::new(),
+            oldest_retained_pos: self.size(),
+            pinned_nodes: self.peak_hashes().into_iter().collect(),
+            positions: None,
+            partial_element: Vec::new(),
+        }
+    }
+
+    /// Computes the root hash of the MMR built (with `scheme`) by adding `leaf_count` elements read
+    /// from `reader`, where each element is a digest of `H::len()` bytes. Only the peaks of the MMR
+    /// are kept in memory, so this requires O(log n) space.
+    pub fn root_hash_streaming<R: io::Read>(
+        mut reader: R,
+        leaf_count: usize,
+        hasher: &mut H,
+        scheme: Scheme,
+    ) -> io::Result<Digest> {
+        let hasher = &mut Hasher::with_scheme(hasher, scheme);
+        let mut size = 0;
+        let mut peaks = Vec::new();
+        let mut buf = vec![0u8; H::len()];
+        for _ in 0..leaf_count {
+            reader.read_exact(&mut buf)?;
+            add_to_peaks(hasher, &mut size, &mut peaks, &Digest::from(buf.clone()));
+        }
+        Ok(hasher.root_hash(size, peaks.iter()))
+    }
+
+    /// Return a checkpoint of the current size, leaf count, and root hash of the MMR.
+    ///
+    /// Unlike `root_hash`, this doesn't require exclusive access to the MMR, so the returned
+    /// values are guaranteed to be consistent with one another when the MMR is shared behind a
+    /// lock.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut hasher = H::new();
+        let mut leaf_count = 0;
+        let peaks = self.peak_iterator().map(|(peak_pos, height)| {
+            leaf_count += 1 << height;
+            self.get_node(peak_pos).unwrap()
+        });
+        let root =
+            Hasher::with_scheme(&mut hasher, self.scheme.clone()).root_hash(self.size(), peaks);
+        Checkpoint {
+            size: self.size(),
+            leaf_count,
+            root,
+        }
     }
 
     /// Return an inclusion proof for the specified element that consists of the size of the MMR and
@@ -71,173 +603,3161 @@ impl<H: CHasher> Mmr<H> {
     /// the perfect tree containing the element, followed by: (2) the nodes in the remaining perfect
     /// tree necessary for reconstructing its peak hash from the specified element. Both segments
     /// are ordered by decreasing height.
-    pub fn proof(&self, element_pos: u64) -> Proof {
-        self.range_proof(element_pos, element_pos)
+    ///
+    /// Returns `Error::ElementPruned` if any node required by the proof has been pruned.
+    ///
+    /// With the `tracing` feature enabled, emits a debug event recording the size of the MMR, the
+    /// element's position, the number of hashes in the proof, and the time taken to generate it.
+    pub fn proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+        let start = std::time::Instant::now();
+        let proof = self.range_proof(element_pos, element_pos);
+        #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+        if let Ok(proof) = &proof {
+            tracing::debug!(
+                mmr_size = proof.size,
+                element_pos,
+                num_hashes = proof.hashes.len(),
+                duration_ns = start.elapsed().as_nanos() as u64,
+                "generated proof"
+            );
+        }
+        proof
     }
 
-    // Return an inclusion proof for the specified range of elements. The range is inclusive of
-    // both endpoints.
-    pub fn range_proof(&self, start_element_pos: u64, end_element_pos: u64) -> Proof {
-        let mut hashes: Vec<Digest> = Vec::new();
-        let mut start_tree_with_element = (u64::MAX, 0);
-        let mut end_tree_with_element = (u64::MAX, 0);
-
-        // Include peak hashes only for trees that have no elements from the range, and keep track
-        // of the starting and ending trees of those that do contain some.
-        let mut peak_iterator = self.peak_iterator();
-        while let Some(item) = peak_iterator.next() {
-            if start_tree_with_element.0 == u64::MAX && item.0 >= start_element_pos {
-                // found the first tree to contain an element in the range
-                start_tree_with_element = item;
-                if item.0 >= end_element_pos {
-                    // start and end tree are the same
-                    end_tree_with_element = item;
-                    continue;
-                }
-                for item in peak_iterator.by_ref() {
-                    if item.0 >= end_element_pos {
-                        // found the last tree to contain an element in the range
-                        end_tree_with_element = item;
-                        break;
-                    }
-                }
-            } else {
-                hashes.push(self.nodes[item.0 as usize].clone());
+    /// Return an inclusion proof for the element at `element_pos` together with a witness carrying
+    /// `element_bytes`, the pre-image of the element (which must have been added as the hash of
+    /// those bytes). The MMR stores only hashes, so the pre-image must be supplied by the caller;
+    /// a witness for the wrong bytes fails `Proof::verify_element_with_witness`.
+    ///
+    /// Returns `Error::ElementPruned` if any node required by the proof has been pruned.
+    pub fn proof_with_witness(
+        &self,
+        element_pos: u64,
+        element_bytes: &[u8],
+    ) -> Result<(Proof, ElementWitness), Error> {
+        let proof = self.proof(element_pos)?;
+        let witness = ElementWitness {
+            element_bytes: element_bytes.to_vec(),
+        };
+        Ok((proof, witness))
+    }
+
+    /// Return the number of node reads and proof hashes required to generate a proof for the
+    /// specified range of elements, computed from position math alone without reading any nodes.
+    /// The range is inclusive of both endpoints.
+    ///
+    /// Returns `Error::InvalidPos` if either endpoint isn't the position of a leaf in the MMR (or
+    /// they're out of order).
+    pub fn proof_cost(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<ProofCost, Error> {
+        self.check_leaf_range(start_element_pos, end_element_pos)?;
+        Ok(ProofCost::for_range(
+            self.size(),
+            start_element_pos,
+            end_element_pos,
+        ))
+    }
+
+    /// Return `Error::InvalidPos` unless `start_element_pos` and `end_element_pos` are the
+    /// positions of leaves in the MMR, in order.
+    fn check_leaf_range(&self, start_element_pos: u64, end_element_pos: u64) -> Result<(), Error> {
+        for pos in [start_element_pos, end_element_pos] {
+            if pos >= self.size() || pos_to_height(pos) != 0 {
+                return Err(Error::InvalidPos(pos));
             }
         }
-        assert!(start_tree_with_element.0 != u64::MAX);
-        assert!(end_tree_with_element.0 != u64::MAX);
+        if start_element_pos > end_element_pos {
+            return Err(Error::InvalidPos(start_element_pos));
+        }
+        Ok(())
+    }
 
-        // For the trees containing elements in the range, add left-sibling hashes of nodes along
-        // the leftmost path, and right-sibling hashes of nodes along the rightmost path, in
-        // decreasing order of the position of the parent node.
-        let left_path_iter = PathIterator::new(
-            start_element_pos,
-            start_tree_with_element.0,
-            start_tree_with_element.1,
-        );
-
-        let mut siblings = Vec::<(u64, u64)>::new();
-        if start_element_pos == end_element_pos {
-            // For the (common) case of a single element range, the right and left path are the
-            // same so no need to process each independently.
-            siblings.extend(left_path_iter);
-        } else {
-            let right_path_iter = PathIterator::new(
-                end_element_pos,
-                end_tree_with_element.0,
-                end_tree_with_element.1,
-            );
-            // filter the right path for right siblings only
-            siblings.extend(right_path_iter.filter(|(parent_pos, pos)| *parent_pos == *pos + 1));
-            // filter the left path for left siblings only
-            siblings.extend(left_path_iter.filter(|(parent_pos, pos)| *parent_pos != *pos + 1));
+    /// Return proofs covering every element in `positions` that minimize the total number of proof
+    /// hashes, along with the strategy used for each.
+    ///
+    /// Elements are sorted (and deduplicated), then split into runs of consecutive elements that
+    /// are each proven either individually (if alone in their run) or with a single range proof
+    /// spanning the run, which shares the hashes common to its elements. Because a range proof can
+    /// only be verified given every element in its span, any elements in the span that weren't
+    /// requested count towards its cost alongside the proof's hashes. Range proofs are only used
+    /// when they strictly reduce this cost.
+    ///
+    /// Returns `Error::InvalidPos` if any position isn't the position of a leaf in the MMR, and
+    /// `Error::ElementPruned` if any node required by a proof has been pruned.
+    pub fn proof_batch_optimal(
+        &self,
+        positions: &[u64],
+    ) -> Result<Vec<(ProofStrategy, Proof)>, Error> {
+        let mut positions = positions.to_vec();
+        positions.sort_unstable();
+        positions.dedup();
+        for pos in positions.iter() {
+            self.check_leaf_range(*pos, *pos)?;
+        }
 
-            // If the range spans more than one tree, then the hashes must already be in the correct
-            // order. Otherwise, we enforce the desired order through sorting.
-            if start_tree_with_element.0 == end_tree_with_element.0 {
-                siblings.sort_by(|a, b| b.0.cmp(&a.0));
+        // best[i] holds the minimum cost of proving the first i elements, along with the start of
+        // the last run in that solution.
+        let mut best = vec![(0, 0); positions.len() + 1];
+        for end in 1..=positions.len() {
+            best[end] = (usize::MAX, 0);
+            for start in (0..end).rev() {
+                let cost = best[start].0
+                    + self.run_cost(positions[start], positions[end - 1], end - start);
+                if cost < best[end].0 {
+                    best[end] = (cost, start);
+                }
             }
         }
-        hashes.extend(
-            siblings
-                .iter()
-                .map(|(_, pos)| self.nodes[*pos as usize].clone()),
-        );
-        Proof {
-            size: self.nodes.len() as u64,
-            hashes,
+
+        // Walk back through the chosen runs and generate their proofs
+        let mut proofs = Vec::new();
+        let mut end = positions.len();
+        while end > 0 {
+            let start = best[end].1;
+            let (first, last) = (positions[start], positions[end - 1]);
+            let strategy = if start + 1 == end {
+                ProofStrategy::Individual(first)
+            } else {
+                ProofStrategy::Range(first, last)
+            };
+            proofs.push((strategy, self.range_proof(first, last)?));
+            end = start;
         }
+        proofs.reverse();
+        Ok(proofs)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::mmr::hasher::Hasher;
-    use crate::mmr::iterator::nodes_needing_parents;
-    use crate::mmr::mem::Mmr;
-    use commonware_cryptography::{Digest, Sha256};
+    /// Return the cost of proving `requested` elements spanning `start_element_pos` to
+    /// `end_element_pos` with a single proof: the hashes in the proof plus the elements in the span
+    /// that weren't requested (but must be supplied to verify it). Both positions must be leaves
+    /// in the MMR, in order.
+    fn run_cost(&self, start_element_pos: u64, end_element_pos: u64, requested: usize) -> usize {
+        let span = leaf_count(end_element_pos) - leaf_count(start_element_pos) + 1;
+        ProofCost::for_range(self.size(), start_element_pos, end_element_pos).proof_hashes
+            + span as usize
+            - requested
+    }
 
-    #[test]
-    /// Test MMR building by consecutively adding 11 equal elements to a new MMR, producing the
-    /// structure in the example documented at the top of the mmr crate's mod.rs file with 19 nodes
-    /// and 3 peaks.
-    fn test_add_eleven_values() {
-        let mut mmr: Mmr<Sha256> = Mmr::<Sha256>::new();
-        assert_eq!(
-            mmr.peak_iterator().next(),
-            None,
-            "empty iterator should have no peaks"
-        );
+    /// Return the length in bytes of the serialized (see `Proof::to_bytes`) inclusion proof for the
+    /// specified element, given the length of each digest, computed without generating the proof.
+    ///
+    /// Returns `Error::InvalidPos` if `element_pos` isn't the position of a leaf in the MMR.
+    pub fn proof_size_bytes(&self, element_pos: u64, digest_len: usize) -> Result<usize, Error> {
+        self.range_proof_size_bytes(element_pos, element_pos, digest_len)
+    }
 
-        let element = Digest::from_static(b"01234567012345670123456701234567");
-        let mut leaves: Vec<u64> = Vec::new();
-        for _ in 0..11 {
-            leaves.push(mmr.add(&element));
-            let peaks: Vec<(u64, u32)> = mmr.peak_iterator().collect();
-            assert_ne!(peaks.len(), 0);
-            assert!(peaks.len() <= mmr.nodes.len());
-            let nodes_needing_parents = nodes_needing_parents(mmr.peak_iterator());
-            assert!(nodes_needing_parents.len() <= peaks.len());
+    /// Return the length in bytes of the serialized (see `Proof::to_bytes`) inclusion proof for the
+    /// specified range of elements, given the length of each digest, computed without generating
+    /// the proof. The range is inclusive of both endpoints.
+    ///
+    /// Returns `Error::InvalidPos` if either endpoint isn't the position of a leaf in the MMR (or
+    /// they're out of order).
+    pub fn range_proof_size_bytes(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+        digest_len: usize,
+    ) -> Result<usize, Error> {
+        let cost = self.proof_cost(start_element_pos, end_element_pos)?;
+        Ok(8 + 4 + cost.proof_hashes * digest_len)
+    }
+
+    /// Return an inclusion proof for the specified element as a binary Merkle path from its leaf
+    /// to its peak (see `MerkleProof`).
+    ///
+    /// Returns `Error::InvalidPos` if `element_pos` isn't the position of a leaf in the MMR, and
+    /// `Error::ElementPruned` if any node required by the proof has been pruned.
+    pub fn to_merkle_proof(&self, element_pos: u64) -> Result<MerkleProof, Error> {
+        if element_pos >= self.size() || pos_to_height(element_pos) != 0 {
+            return Err(Error::InvalidPos(element_pos));
         }
-        assert_eq!(mmr.nodes.len(), 19, "mmr not of expected size");
-        assert_eq!(
-            leaves,
-            vec![0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18],
-            "mmr leaf positions not as expected"
-        );
-        let peaks: Vec<(u64, u32)> = mmr.peak_iterator().collect();
-        assert_eq!(
+        let node = |pos: u64| self.get_node(pos).cloned().ok_or(Error::ElementPruned(pos));
+        let mut peaks = Vec::new();
+        let mut path = Vec::new();
+        let mut peak_index = 0;
+        for (index, (peak_pos, height)) in self.peak_iterator().enumerate() {
+            let leftmost_pos = peak_pos + 2 - (1 << (height + 1));
+            if !(leftmost_pos..=peak_pos).contains(&element_pos) {
+                peaks.push(node(peak_pos)?);
+                continue;
+            }
+
+            // Descend from the peak to the leaf, recording the sibling of each node along the way
+            peak_index = index;
+            let mut pos = peak_pos;
+            for height in (1..=height).rev() {
+                let (left_pos, right_pos) = (pos - (1 << height), pos - 1);
+                if element_pos <= left_pos {
+                    path.push((node(right_pos)?, Direction::Right));
+                    pos = left_pos;
+                } else {
+                    path.push((node(left_pos)?, Direction::Left));
+                    pos = right_pos;
+                }
+            }
+        }
+        path.reverse();
+        Ok(MerkleProof {
+            size: self.size(),
+            element_pos,
+            leaf_hash: node(element_pos)?,
+            path,
             peaks,
-            vec![(14, 3), (17, 1), (18, 0)],
-            "mmr peaks not as expected"
-        );
+            peak_index,
+            scheme: self.scheme.clone(),
+        })
+    }
 
-        // Test nodes_needing_parents on the final MMR. Since there's a height gap between the
-        // highest peak (14) and the next, only the lower two peaks (17, 18) should be returned.
-        let peaks_needing_parents = nodes_needing_parents(mmr.peak_iterator());
-        assert_eq!(
-            peaks_needing_parents,
-            vec![17, 18],
-            "mmr nodes needing parents not as expected"
-        );
+    /// Return the authentication path of the specified element from its leaf up to (but excluding)
+    /// its peak, as the sequence of `(is_left, sibling_hash)` decisions a circuit folds over: for
+    /// each node along the path (starting with the leaf), whether it is the left child of its
+    /// parent, and the hash of its sibling. Folding the leaf hash with `node_hash` along the path
+    /// yields the element's peak, which is bagged with the other peaks (see `peak_hashes`) into the
+    /// root hash. Each parent's position (needed by `node_hash`) follows from the decisions: the
+    /// parent of a left child at height `h` is `2^(h+1)` positions after it, and the parent of a
+    /// right child is the next position.
+    ///
+    /// Returns `Error::InvalidPos` if `element_pos` isn't the position of a leaf in the MMR, and
+    /// `Error::ElementPruned` if any node along the path has been pruned.
+    pub fn circuit_witness(&self, element_pos: u64) -> Result<Vec<(bool, Digest)>, Error> {
+        let proof = self.to_merkle_proof(element_pos)?;
+        Ok(proof
+            .path
+            .into_iter()
+            .map(|(sibling, direction)| (direction == Direction::Right, sibling))
+            .collect())
+    }
 
-        // verify leaf hashes
-        let mut hasher = Sha256::default();
-        let mut mmr_hasher = Hasher::new(&mut hasher);
-        for leaf in leaves.iter().by_ref() {
-            let hash = mmr_hasher.leaf_hash(*leaf, &element);
-            assert_eq!(mmr.nodes[*leaf as usize], hash);
+    /// Return an inclusion proof for the specified element along with the context (root hash and
+    /// size) of the MMR it was generated against.
+    pub fn proof_with_context(&mut self, element_pos: u64) -> Result<(Proof, ProofContext), Error> {
+        self.range_proof_with_context(element_pos, element_pos)
+    }
+
+    /// Return an inclusion proof for the specified range of elements along with the context (root
+    /// hash and size) of the MMR it was generated against. The range is inclusive of both
+    /// endpoints.
+    pub fn range_proof_with_context(
+        &mut self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<(Proof, ProofContext), Error> {
+        let proof = self.range_proof(start_element_pos, end_element_pos)?;
+        let context = ProofContext {
+            root: self.root_hash(),
+            size: proof.size,
+        };
+        Ok((proof, context))
+    }
+
+    /// Return true if the stored hash of the internal node at `pos` matches the hash recomputed
+    /// from the stored hashes of its children (using `hasher`).
+    ///
+    /// Returns `Error::InvalidPos` if `pos` is beyond the end of the MMR or is a leaf, or
+    /// `Error::ElementPruned` if the node or either child has been pruned.
+    pub fn verify_internal_node(&self, pos: u64, hasher: &mut H) -> Result<bool, Error> {
+        let height = pos_to_height(pos);
+        if pos >= self.size() || height == 0 {
+            return Err(Error::InvalidPos(pos));
         }
+        let get_node = |pos| self.get_node(pos).ok_or(Error::ElementPruned(pos));
+        let node = get_node(pos)?;
+        let left = get_node(pos - (1 << height))?;
+        let right = get_node(pos - 1)?;
+        Ok(Hasher::with_scheme(hasher, self.scheme.clone()).node_hash(pos, left, right) == *node)
+    }
 
-        // verify height=1 hashes
-        let hash2 = mmr_hasher.node_hash(2, &mmr.nodes[0], &mmr.nodes[1]);
-        assert_eq!(mmr.nodes[2], hash2);
-        let hash5 = mmr_hasher.node_hash(5, &mmr.nodes[3], &mmr.nodes[4]);
-        assert_eq!(mmr.nodes[5], hash5);
-        let hash9 = mmr_hasher.node_hash(9, &mmr.nodes[7], &mmr.nodes[8]);
-        assert_eq!(mmr.nodes[9], hash9);
-        let hash12 = mmr_hasher.node_hash(12, &mmr.nodes[10], &mmr.nodes[11]);
-        assert_eq!(mmr.nodes[12], hash12);
-        let hash17 = mmr_hasher.node_hash(17, &mmr.nodes[15], &mmr.nodes[16]);
-        assert_eq!(mmr.nodes[17], hash17);
+    /// Return an inclusion proof for the specified element along with the root hash it verifies
+    /// against. Both are computed under the same (shared) borrow of the MMR, so unlike calling
+    /// `proof` and `root_hash` separately, the MMR can't grow in between (e.g. when shared behind
+    /// a `RwLock`).
+    pub fn proof_and_root(&self, element_pos: u64) -> Result<(Proof, Digest), Error> {
+        let proof = self.proof(element_pos)?;
+        let peaks = self.peak_hashes();
+        let mut hasher = H::new();
+        let root = Hasher::with_scheme(&mut hasher, self.scheme.clone())
+            .root_hash(proof.size, peaks.iter().map(|(_, hash)| hash));
+        Ok((proof, root))
+    }
 
-        // verify height=2 hashes
-        let hash6 = mmr_hasher.node_hash(6, &mmr.nodes[2], &mmr.nodes[5]);
-        assert_eq!(mmr.nodes[6], hash6);
-        let hash13 = mmr_hasher.node_hash(13, &mmr.nodes[9], &mmr.nodes[12]);
-        assert_eq!(mmr.nodes[13], hash13);
-        let hash17 = mmr_hasher.node_hash(17, &mmr.nodes[15], &mmr.nodes[16]);
-        assert_eq!(mmr.nodes[17], hash17);
+    /// Return a chunk of at most `max_nodes` nodes starting at `start_pos`, which must be the size
+    /// of the MMR after some append (e.g. the end of the previous chunk). The chunk ends at the
+    /// largest valid MMR size within the limit, or the smallest one beyond `start_pos` if
+    /// `max_nodes` is too small to hold a complete append.
+    ///
+    /// Returns `Error::InvalidSize` if `start_pos` is not a valid MMR size smaller than the size of
+    /// the MMR, or `Error::ElementPruned` if any required node has been pruned.
+    pub fn sync_chunk(&self, start_pos: u64, max_nodes: u64) -> Result<SyncChunk, Error> {
+        let size = self.size();
+        if start_pos >= size || !PeakIterator::check_validity(start_pos) {
+            return Err(Error::InvalidSize(start_pos));
+        }
+        let mut end_pos = start_pos.saturating_add(max_nodes).min(size);
+        while end_pos > start_pos && !PeakIterator::check_validity(end_pos) {
+            end_pos -= 1;
+        }
+        if end_pos == start_pos {
+            end_pos += 1;
+            while !PeakIterator::check_validity(end_pos) {
+                end_pos += 1;
+            }
+        }
 
-        // verify topmost hash
-        let hash14 = mmr_hasher.node_hash(14, &mmr.nodes[6], &mmr.nodes[13]);
-        assert_eq!(mmr.nodes[14], hash14);
+        let nodes = (start_pos..end_pos)
+            .map(|pos| {
+                let node = self.get_node(pos).ok_or(Error::ElementPruned(pos))?;
+                Ok((pos, node.clone()))
+            })
+            .collect::<Result<_, Error>>()?;
+        let proof = self.range_proof(0, last_leaf_pos(end_pos))?;
+        Ok(SyncChunk { nodes, proof })
+    }
 
-        // verify root hash
-        let root_hash = mmr.root_hash();
-        let peak_hashes = [hash14, hash17, mmr.nodes[18].clone()];
-        let expected_root_hash = mmr_hasher.root_hash(19, peak_hashes.iter());
-        assert_eq!(root_hash, expected_root_hash, "incorrect root hash");
+    /// Return a proof that the element at `element_pos` and the element following it are adjacent,
+    /// i.e. there is no other element between them. The proof can be verified with
+    /// `Proof::verify_adjacency`.
+    ///
+    /// Returns `Error::InvalidPos` if `element_pos` isn't the position of an element with a
+    /// successor in the MMR, and `Error::ElementPruned` if any node required by the proof has been
+    /// pruned.
+    pub fn adjacency_proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        if element_pos >= self.size() || pos_to_height(element_pos) != 0 {
+            return Err(Error::InvalidPos(element_pos));
+        }
+        let next_element_pos = next_leaf_pos(element_pos);
+        if next_element_pos >= self.size() {
+            return Err(Error::InvalidPos(element_pos));
+        }
+        self.range_proof(element_pos, next_element_pos)
+    }
+
+    /// Return an iterator over `(element_pos, proof)` for every element in the MMR, in increasing
+    /// order of position. Each yielded proof is identical to the one returned by `proof`, but the
+    /// iterator walks each tree only once and reuses the path from the previous element, keeping
+    /// its state bounded by the height of the tallest tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any node required by a proof has been pruned.
+    pub fn all_proofs(&self) -> impl Iterator<Item = (u64, Proof)> + '_ {
+        AllProofsIterator::new(self)
+    }
+
+    // Return an inclusion proof for the specified range of elements. The range is inclusive of
+    // both endpoints.
+    pub fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        self.range_proof_at_size(self.size(), start_element_pos, end_element_pos)
+    }
+
+    /// Return a proof that the leaves between `start_element_pos` and `end_element_pos`
+    /// (inclusive) whose entries in `present` (one per leaf in the range, in order) are set hold
+    /// their elements, verifiable with `Proof::verify_sparse_inclusion` given only those elements.
+    /// The proof holds the hashes of the largest subtrees containing none of the present leaves,
+    /// in increasing order of position, so it shares the hashes common to the present leaves just
+    /// as a range proof does (and holds the same hashes as `range_proof`, in a different order,
+    /// when every leaf is present).
+    ///
+    /// Returns `Error::InvalidPos` if either endpoint isn't the position of a leaf in the MMR (or
+    /// they're out of order), `Error::InvalidBitmapLength` if `present` doesn't have one entry per
+    /// leaf in the range, and `Error::ElementPruned` if any node required by the proof has been
+    /// pruned.
+    pub fn sparse_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+        present: &[bool],
+    ) -> Result<Proof, Error> {
+        self.check_leaf_range(start_element_pos, end_element_pos)?;
+        let leaves = present_positions(start_element_pos, end_element_pos, present)
+            .ok_or(Error::InvalidBitmapLength(present.len()))?;
+        let hashes = nodes_required_for_sparse_proof(self.size(), &leaves)
+            .into_iter()
+            .map(|pos| self.get_node(pos).cloned().ok_or(Error::ElementPruned(pos)))
+            .collect::<Result<_, _>>()?;
+        Ok(Proof {
+            size: self.size(),
+            hashes,
+            scheme: self.scheme.clone(),
+        })
+    }
+
+    /// Return the nodes appended to the MMR since it had `size` nodes, with which proofs generated
+    /// at that size can be updated to the current size (see `Proof::apply_delta`).
+    ///
+    /// Returns `Error::InvalidSize` if `size` is not a valid MMR size no greater than the current
+    /// size, or `Error::ElementPruned` if any appended node has been pruned.
+    pub fn delta_since(&self, size: u64) -> Result<MmrDelta, Error> {
+        if size > self.size() || !PeakIterator::check_validity(size) {
+            return Err(Error::InvalidSize(size));
+        }
+        let nodes = (size..self.size())
+            .map(|pos| self.get_node(pos).cloned().ok_or(Error::ElementPruned(pos)))
+            .collect::<Result<_, _>>()?;
+        Ok(MmrDelta { size, nodes })
+    }
+
+    /// Return an inclusion proof for the specified range of elements within the MMR as it existed
+    /// when it had `size` nodes. Because nodes are never modified once added, the proof is
+    /// identical to the one this MMR would have produced at that size, and will verify against
+    /// the root hash it had at that time. The range is inclusive of both endpoints.
+    ///
+    /// Returns `Error::InvalidSize` if `size` is not a valid MMR size no greater than the current
+    /// size, `Error::InvalidPos` if the range extends beyond it, and `Error::ElementPruned` if any
+    /// node required by the proof has been pruned.
+    pub fn historical_range_proof(
+        &self,
+        size: u64,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        if size > self.size() || !PeakIterator::check_validity(size) {
+            return Err(Error::InvalidSize(size));
+        }
+        if end_element_pos >= size {
+            return Err(Error::InvalidPos(end_element_pos));
+        }
+        self.range_proof_at_size(size, start_element_pos, end_element_pos)
+    }
+
+    fn range_proof_at_size(
+        &self,
+        size: u64,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        let hashes = nodes_required_for_range_proof(size, start_element_pos, end_element_pos)
+            .into_iter()
+            .map(|pos| self.get_node(pos).cloned().ok_or(Error::ElementPruned(pos)))
+            .collect::<Result<_, _>>()?;
+        Ok(Proof {
+            size,
+            hashes,
+            scheme: self.scheme.clone(),
+        })
+    }
+
+    /// Write the size of the MMR (as a big-endian `u64`) followed by the hash of every node in
+    /// position order to `writer`, without buffering the output.
+    ///
+    /// Returns an `io::ErrorKind::InvalidInput` error if any node has been pruned.
+    pub fn to_compact_serialised(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        if self.oldest_retained_pos != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                Error::ElementPruned(0),
+            ));
+        }
+        writer.write_all(&self.size().to_be_bytes())?;
+        for node in self.frozen.iter().chain(self.nodes.iter()) {
+            writer.write_all(node)?;
+        }
+        Ok(())
+    }
+
+    /// Read an MMR written by `to_compact_serialised` from `reader`, which must have been built with
+    /// `scheme` (the format doesn't record it). The position index is not restored.
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if the size is not a valid MMR size, or an
+    /// `io::ErrorKind::UnexpectedEof` error if `reader` ends before every node has been read.
+    pub fn from_compact_serialised(reader: &mut impl io::Read, scheme: Scheme) -> io::Result<Self> {
+        let mut size = [0u8; 8];
+        reader.read_exact(&mut size)?;
+        let size = u64::from_be_bytes(size);
+        if !PeakIterator::check_validity(size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Error::InvalidSize(size),
+            ));
+        }
+
+        // Nodes are added as they are read (rather than reserving space for `size` of them up
+        // front) so that a corrupt size can't trigger a huge allocation.
+        let mut nodes = Vec::new();
+        for _ in 0..size {
+            let mut node = vec![0u8; H::len()];
+            reader.read_exact(&mut node)?;
+            nodes.push(Digest::from(node));
+        }
+        Ok(Self::from_nodes(nodes, scheme))
+    }
+
+    /// Write the size of the MMR followed by the position and hash of each of its peaks (in
+    /// decreasing order of height) to `writer`, with positions and the size as big-endian `u64`s.
+    /// Peaks are always retained, so pruned MMRs can be dumped.
+    pub fn dump_peaks_to_writer<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.size().to_be_bytes())?;
+        for (peak_pos, hash) in self.peak_hashes() {
+            writer.write_all(&peak_pos.to_be_bytes())?;
+            writer.write_all(&hash)?;
+        }
+        Ok(())
+    }
+
+    /// Read the peaks written by `dump_peaks_to_writer` from `reader`, returning an `Accumulator`
+    /// for the MMR they belong to, which must have been built with `scheme` (the format doesn't
+    /// record it).
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if the size is not a valid MMR size or any
+    /// position is not that of the corresponding peak, or an `io::ErrorKind::UnexpectedEof` error
+    /// if `reader` ends before every peak has been read.
+    pub fn load_peaks_from_reader<R: io::Read>(
+        reader: &mut R,
+        scheme: Scheme,
+    ) -> io::Result<Accumulator<H>> {
+        let mut size = [0u8; 8];
+        reader.read_exact(&mut size)?;
+        let size = u64::from_be_bytes(size);
+        if !PeakIterator::check_validity(size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Error::InvalidSize(size),
+            ));
+        }
+        let mut peaks = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(size) {
+            let mut pos = [0u8; 8];
+            reader.read_exact(&mut pos)?;
+            let pos = u64::from_be_bytes(pos);
+            if pos != peak_pos {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    Error::InvalidPos(pos),
+                ));
+            }
+            let mut hash = vec![0u8; H::len()];
+            reader.read_exact(&mut hash)?;
+            peaks.push(Digest::from(hash));
+        }
+        Ok(Accumulator::from_peaks(H::new(), scheme, size, peaks))
+    }
+
+    /// Serialize the MMR, including any pruned nodes that remain pinned, as a version byte and the
+    /// fingerprint of its scheme (its ID and key digest), followed by the big-endian size, oldest
+    /// retained position, and number of pinned nodes, then the position and hash of each pinned
+    /// node, and finally the hash of every retained node in position order. The position index (if
+    /// enabled) is not serialized.
+    pub fn serialize(&self) -> Vec<u8> {
+        let digest_len = H::len();
+        let retained = (self.size() - self.oldest_retained_pos) as usize;
+        let fingerprint = self.scheme.fingerprint::<H>();
+        let mut bytes = Vec::with_capacity(
+            1 + fingerprint.len()
+                + 8
+                + 8
+                + 4
+                + self.pinned_nodes.len() * (8 + digest_len)
+                + retained * digest_len,
+        );
+        bytes.push(SERIALIZATION_VERSION);
+        bytes.extend_from_slice(&fingerprint);
+        bytes.extend_from_slice(&self.size().to_be_bytes());
+        bytes.extend_from_slice(&self.oldest_retained_pos.to_be_bytes());
+        bytes.extend_from_slice(&(self.pinned_nodes.len() as u32).to_be_bytes());
+        let mut pinned: Vec<_> = self.pinned_nodes.iter().collect();
+        pinned.sort_unstable_by_key(|(pos, _)| **pos);
+        for (pos, node) in pinned {
+            bytes.extend_from_slice(&pos.to_be_bytes());
+            bytes.extend_from_slice(node);
+        }
+        for node in self.frozen.iter().chain(self.nodes.iter()) {
+            bytes.extend_from_slice(node);
+        }
+        bytes
+    }
+
+    /// Deserialize an MMR produced by `serialize` from (possibly untrusted) bytes, which must have
+    /// been serialized by an MMR built with `scheme`.
+    ///
+    /// Returns `Error::UnsupportedVersion` if the bytes were produced by an incompatible version,
+    /// `Error::SchemeMismatch` if the MMR was built with another scheme (or key),
+    /// `Error::InvalidSize` if the size or oldest retained position is not a valid MMR size,
+    /// `Error::InvalidPos` if a pinned node is not before the oldest retained position,
+    /// `Error::ElementPruned` if a pruned peak (required to add elements) isn't pinned, and
+    /// `Error::InvalidEncoding` if the number of bytes doesn't match the number of nodes.
+    pub fn deserialize(bytes: &[u8], scheme: Scheme) -> Result<Self, Error> {
+        let Some(&version) = bytes.first() else {
+            return Err(Error::InvalidEncoding(0));
+        };
+        if version != SERIALIZATION_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let fingerprint = scheme.fingerprint::<H>();
+        let header_len = 1 + fingerprint.len() + 8 + 8 + 4;
+        if bytes.len() < header_len {
+            return Err(Error::InvalidEncoding(bytes.len()));
+        }
+        let (header, body) = bytes[1..header_len].split_at(fingerprint.len());
+        if header != fingerprint {
+            return Err(Error::SchemeMismatch);
+        }
+        let size = u64::from_be_bytes(body[..8].try_into().unwrap());
+        let oldest_retained_pos = u64::from_be_bytes(body[8..16].try_into().unwrap());
+        let pinned_count = u32::from_be_bytes(body[16..20].try_into().unwrap()) as u64;
+        if !PeakIterator::check_validity(size) {
+            return Err(Error::InvalidSize(size));
+        }
+        if oldest_retained_pos > size || !PeakIterator::check_validity(oldest_retained_pos) {
+            return Err(Error::InvalidSize(oldest_retained_pos));
+        }
+
+        // Check the number of nodes matches the length of the body before allocating anything
+        let digest_len = H::len() as u64;
+        let body = &bytes[header_len..];
+        let expected = pinned_count
+            .checked_mul(8 + digest_len)
+            .zip((size - oldest_retained_pos).checked_mul(digest_len))
+            .and_then(|(pinned, retained)| pinned.checked_add(retained));
+        if expected != Some(body.len() as u64) {
+            return Err(Error::InvalidEncoding(bytes.len()));
+        }
+        let (pinned, retained) = body.split_at((pinned_count * (8 + digest_len)) as usize);
+        let mut pinned_nodes = HashMap::new();
+        for entry in pinned.chunks_exact(8 + digest_len as usize) {
+            let pos = u64::from_be_bytes(entry[..8].try_into().unwrap());
+            if pos >= oldest_retained_pos {
+                return Err(Error::InvalidPos(pos));
+            }
+            pinned_nodes.insert(pos, Digest::copy_from_slice(&entry[8..]));
+        }
+        if let Some((peak_pos, _)) = PeakIterator::new(oldest_retained_pos)
+            .find(|(peak_pos, _)| !pinned_nodes.contains_key(peak_pos))
+        {
+            return Err(Error::ElementPruned(peak_pos));
+        }
+        let nodes = retained
+            .chunks_exact(digest_len as usize)
+            .map(Digest::copy_from_slice)
+            .collect();
+        Ok(Self {
+            nodes,
+            oldest_retained_pos,
+            pinned_nodes,
+            ..Self::new_with_scheme(scheme)
+        })
+    }
+
+    /// Write every node of the MMR to `writer` in the portable export format (see the `export`
+    /// module), which can be imported by either this `Mmr` or the journaled `Mmr`.
+    ///
+    /// Returns `Error::ElementPruned` if any node has been pruned.
+    pub fn export<W: io::Write>(&self, writer: W) -> Result<(), Error> {
+        self.export_at(writer, leaf_count(self.size()))
+    }
+
+    /// Write the nodes of the MMR as it was when it had `leaf_count` leaves (a prefix of its nodes)
+    /// to `writer` in the portable export format, recording that size. Importing the export yields
+    /// the MMR built from the first `leaf_count` elements (whose root hash can be required by
+    /// `import`).
+    ///
+    /// Returns `Error::IndexOutOfRange` if the MMR has fewer than `leaf_count` leaves, and
+    /// `Error::ElementPruned` if any node has been pruned.
+    pub fn export_at<W: io::Write>(&self, writer: W, leaf_count: u64) -> Result<(), Error> {
+        if leaf_count > util::leaf_count(self.size()) {
+            return Err(Error::IndexOutOfRange(leaf_count));
+        }
+        if self.oldest_retained_pos > 0 {
+            return Err(Error::ElementPruned(0));
+        }
+        let size = node_count(leaf_count);
+        let peaks: Vec<&Digest> = PeakIterator::new(size)
+            .map(|(peak_pos, _)| self.get_node(peak_pos).unwrap())
+            .collect();
+        let root = Hasher::with_scheme(&mut H::new(), self.scheme.clone())
+            .root_hash(size, peaks.into_iter());
+        let mut exporter = Exporter::new::<H>(writer, size, &root, &self.scheme)?;
+        for node in self
+            .frozen
+            .iter()
+            .chain(self.nodes.iter())
+            .take(size as usize)
+        {
+            exporter.write_node(node)?;
+        }
+        exporter.finish()
+    }
+
+    /// Read an MMR exported by `export` (or by the journaled `Mmr`) from `reader`, which must have
+    /// been built with `scheme`.
+    ///
+    /// Returns `Error::HasherMismatch` (or `Error::InvalidDigestLength`) if the export was produced
+    /// with a different hasher, `Error::SchemeMismatch` if it was built with another scheme (or
+    /// key), `Error::InvalidNode` if a parent doesn't match the hash of its children,
+    /// `Error::ChecksumMismatch` if its checksum doesn't match, and `Error::RootMismatch` if its
+    /// recorded root hash doesn't match its nodes or `expected_root` (if provided).
+    pub fn import<R: io::Read>(
+        reader: R,
+        scheme: Scheme,
+        expected_root: Option<&Digest>,
+    ) -> Result<Self, Error> {
+        let mut importer = Importer::<_, H>::new(reader, scheme.clone())?;
+        if expected_root.is_some_and(|root| root != importer.root()) {
+            return Err(Error::RootMismatch);
+        }
+        let mut nodes = Vec::new();
+        for _ in 0..importer.size() {
+            nodes.push(importer.read_node()?);
+        }
+        importer.finish()?;
+        Ok(Self::from_nodes(nodes, scheme))
+    }
+
+    /// Return an `Mmr` with the given nodes, which must form a valid MMR hashed with `scheme`.
+    pub(crate) fn from_nodes(nodes: Vec<Digest>, scheme: Scheme) -> Self {
+        Self {
+            nodes,
+            ..Self::new_with_scheme(scheme)
+        }
+    }
+
+    /// Return the hash of every leaf of the MMR (rather than the elements they represent), in
+    /// order of position.
+    ///
+    /// Returns `Error::ElementPruned` if any leaf has been pruned.
+    pub fn export_leaf_hashes(&self) -> Result<Vec<Digest>, Error> {
+        if self.oldest_retained_pos > 0 {
+            return Err(Error::ElementPruned(0));
+        }
+        Ok((0..self.size())
+            .filter(|pos| pos_to_height(*pos) == 0)
+            .map(|pos| self.get_node(pos).unwrap().clone())
+            .collect())
+    }
+
+    /// Rebuild an MMR from the hashes of its leaves (as returned by `export_leaf_hashes` by an MMR
+    /// built with `scheme`), treating each as an already hashed leaf rather than an element.
+    pub fn import_leaf_hashes(leaves: &[Digest], scheme: Scheme) -> Self {
+        let mut mmr = Self::new_with_scheme(scheme);
+        for leaf in leaves {
+            mmr.add_leaf_hash(leaf.clone());
+        }
+        mmr
+    }
+
+    /// Return an MMR containing `elements`, as if each were added in order.
+    pub fn build_from(elements: &[Digest]) -> Self {
+        let mut mmr = Self::new();
+        for element in elements {
+            mmr.add(element);
+        }
+        mmr
+    }
+
+    /// Return an MMR containing `elements` (identical to the one returned by `build_from`), hashing
+    /// independent perfect subtrees on up to `threads` threads.
+    ///
+    /// The leaves are split into perfect subtrees of roughly `elements.len() / threads` leaves
+    /// (aligned to the trees of the MMR), which are hashed in parallel and then stitched together
+    /// by hashing the few parents above them sequentially.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is 0.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn par_build_from(elements: &[Digest], threads: usize) -> Self {
+        assert!(threads > 0, "threads must be non-zero");
+        let leaves = elements.len() as u64;
+        let chunk_height = (leaves / threads as u64).max(1).ilog2();
+
+        // Split each tree of the MMR into subtrees of at most `chunk_height`, recording the index
+        // of the first leaf and the height of each
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for (_, height) in PeakIterator::new(node_count(leaves)) {
+            let chunk_height = height.min(chunk_height);
+            for _ in 0..1u64 << (height - chunk_height) {
+                chunks.push((start, chunk_height));
+                start += 1 << chunk_height;
+            }
+        }
+
+        // Hash the subtrees in parallel, each thread taking a contiguous group of them
+        let group_len = chunks.len().div_ceil(threads).max(1);
+        let subtrees: Vec<Vec<Digest>> = std::thread::scope(|s| {
+            let handles: Vec<_> = chunks
+                .chunks(group_len)
+                .map(|group| {
+                    s.spawn(move || {
+                        let mut hasher = H::new();
+                        let mut hasher = Hasher::new(&mut hasher);
+                        group
+                            .iter()
+                            .map(|(start, height)| {
+                                let start = *start as usize;
+                                let end = start + (1 << height);
+                                hash_subtree(&mut hasher, start as u64, &elements[start..end])
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        // Stitch the subtrees together, hashing the parents above them
+        let mut hasher = H::new();
+        let mut hasher = Hasher::new(&mut hasher);
+        let mut nodes = Vec::with_capacity(node_count(leaves) as usize);
+        let mut roots = Vec::new();
+        for ((_, height), subtree) in chunks.into_iter().zip(subtrees) {
+            nodes.extend(subtree);
+            merge_roots(&mut hasher, 0, &mut nodes, &mut roots, height);
+        }
+        Self::from_nodes(nodes, Scheme::default())
+    }
+
+    /// Return an immutable `ProofGenerator` for the MMR at its current size, which can be sent to
+    /// (or shared between) other threads to serve proofs while this MMR continues to add elements.
+    ///
+    /// Retained nodes are moved into storage shared by every generator, and subsequently added
+    /// nodes go into a fresh buffer that is only merged into the shared storage the next time a
+    /// generator is requested. Requesting a generator therefore clones each retained node (a
+    /// reference count increment) if any were added since the last one, and is otherwise free of
+    /// node copies. `SharedMmr` publishes a generator after every addition, behind a lock that
+    /// lets other threads append to the MMR.
+    pub fn proof_generator(&mut self) -> ProofGenerator {
+        if !self.nodes.is_empty() {
+            let nodes = std::mem::take(&mut self.nodes);
+            self.frozen = self.frozen.iter().cloned().chain(nodes).collect();
+        }
+        ProofGenerator {
+            checkpoint: self.checkpoint(),
+            scheme: self.scheme.clone(),
+            oldest_retained_pos: self.oldest_retained_pos,
+            nodes: self.frozen.clone(),
+            pinned_nodes: self.pinned_nodes.clone(),
+        }
+    }
+}
+
+/// An immutable snapshot of an `Mmr` at a fixed size, returned by `Mmr::proof_generator`.
+///
+/// Every proof produced by a generator is identical to the one the MMR would have produced at the
+/// generator's size, and verifies against the generator's root hash, no matter how many elements
+/// have been added to the MMR since. Generators are cheap to clone, share their nodes with the MMR
+/// and with one another, and require no locking to use from multiple threads. Nodes pruned before
+/// the generator was created remain unavailable to it.
+#[derive(Clone)]
+pub struct ProofGenerator {
+    checkpoint: Checkpoint,
+    scheme: Scheme,
+    oldest_retained_pos: u64,
+    nodes: Arc<[Digest]>,
+    pinned_nodes: HashMap<u64, Digest>,
+}
+
+impl ProofGenerator {
+    /// Return the size of the MMR as of this snapshot.
+    pub fn size(&self) -> u64 {
+        self.checkpoint.size
+    }
+
+    /// Return the root hash of the MMR as of this snapshot.
+    pub fn root_hash(&self) -> Digest {
+        self.checkpoint.root.clone()
+    }
+
+    /// Return the size, leaf count, and root hash of the MMR as of this snapshot.
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Return an inclusion proof for the specified element.
+    ///
+    /// Returns `Error::ElementPruned` if any node required by the proof has been pruned.
+    pub fn proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        self.range_proof(element_pos, element_pos)
+    }
+
+    /// Return an inclusion proof for the specified range of elements. The range is inclusive of
+    /// both endpoints.
+    ///
+    /// Returns `Error::InvalidPos` if the range extends beyond the size of the snapshot, and
+    /// `Error::ElementPruned` if any node required by the proof has been pruned.
+    pub fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        let size = self.size();
+        if end_element_pos >= size {
+            return Err(Error::InvalidPos(end_element_pos));
+        }
+        let hashes = nodes_required_for_range_proof(size, start_element_pos, end_element_pos)
+            .into_iter()
+            .map(|pos| self.get_node(pos).cloned().ok_or(Error::ElementPruned(pos)))
+            .collect::<Result<_, _>>()?;
+        Ok(Proof {
+            size,
+            hashes,
+            scheme: self.scheme.clone(),
+        })
+    }
+
+    /// Return an inclusion proof for the specified range of elements along with the context (root
+    /// hash and size) of the snapshot it was generated against.
+    pub fn range_proof_with_context(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<(Proof, ProofContext), Error> {
+        let proof = self.range_proof(start_element_pos, end_element_pos)?;
+        let context = ProofContext {
+            root: self.root_hash(),
+            size: proof.size,
+        };
+        Ok((proof, context))
+    }
+
+    fn get_node(&self, pos: u64) -> Option<&Digest> {
+        if pos < self.oldest_retained_pos {
+            return self.pinned_nodes.get(&pos);
+        }
+        self.nodes.get((pos - self.oldest_retained_pos) as usize)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl<H: CHasher> Mmr<H> {
+    /// Convert the MMR into a `NodeStore`, whose archived bytes can be accessed without
+    /// deserialization. The position index (if enabled) and any partially written element are
+    /// discarded.
+    pub fn into_archived(self) -> NodeStore {
+        NodeStore::new(
+            H::len(),
+            self.scheme.fingerprint::<H>(),
+            self.oldest_retained_pos,
+            self.frozen.iter().chain(self.nodes.iter()),
+            self.pinned_nodes.iter(),
+        )
+    }
+
+    /// Return an `Mmr` holding a copy of the nodes of an archived `NodeStore` (see
+    /// `NodeStore::access`) of an MMR built with `scheme`. The position index is not restored.
+    ///
+    /// Returns `Error::InvalidDigestLength` if the archived nodes were not produced by this hasher,
+    /// and `Error::SchemeMismatch` if they were built with another scheme (or key).
+    pub fn from_archived(archived: &ArchivedNodeStore, scheme: Scheme) -> Result<Self, Error> {
+        if archived.digest_len() != H::len() {
+            return Err(Error::InvalidDigestLength(H::len(), archived.digest_len()));
+        }
+        if archived.scheme() != scheme.fingerprint::<H>() {
+            return Err(Error::SchemeMismatch);
+        }
+        let oldest_retained_pos = archived.oldest_retained_pos();
+        let pinned_nodes = (0..oldest_retained_pos)
+            .filter_map(|pos| Some((pos, Digest::copy_from_slice(archived.get_node(pos)?))))
+            .collect();
+        let nodes = (oldest_retained_pos..archived.size())
+            .map(|pos| Digest::copy_from_slice(archived.get_node(pos).unwrap()))
+            .collect();
+        Ok(Self {
+            nodes,
+            oldest_retained_pos,
+            pinned_nodes,
+            ..Self::new_with_scheme(scheme)
+        })
+    }
+}
+
+impl<H: CHasher> io::Write for Mmr<H> {
+    /// Add each complete `H::len()`-byte chunk of the written bytes to the MMR as an element,
+    /// buffering any trailing partial chunk until the rest of it is written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let digest_len = H::len();
+        let mut remaining = buf;
+
+        // Complete any partial element from a previous write
+        if !self.partial_element.is_empty() {
+            let needed = digest_len - self.partial_element.len();
+            let taken = needed.min(remaining.len());
+            self.partial_element.extend_from_slice(&remaining[..taken]);
+            remaining = &remaining[taken..];
+            if self.partial_element.len() < digest_len {
+                return Ok(buf.len());
+            }
+            let element = Digest::from(std::mem::take(&mut self.partial_element));
+            self.add(&element);
+        }
+
+        // Add complete elements and buffer the rest
+        let mut chunks = remaining.chunks_exact(digest_len);
+        for chunk in chunks.by_ref() {
+            self.add(&Digest::copy_from_slice(chunk));
+        }
+        self.partial_element.extend_from_slice(chunks.remainder());
+        Ok(buf.len())
+    }
+
+    /// Returns an error if a partial element has been written, rather than silently dropping it.
+    /// The partial element remains buffered and is completed by subsequent writes.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.partial_element.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "incomplete element: {} of {} bytes",
+                    self.partial_element.len(),
+                    H::len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// A step along the path from a peak to the element currently being proven.
+struct PathStep {
+    parent_pos: u64,
+    height: u32, // height of the parent
+    left: bool,  // whether the path descends into the left child of the parent
+}
+
+// Iterator returned by `Mmr::all_proofs`.
+struct AllProofsIterator<'a, H: CHasher> {
+    mmr: &'a Mmr<H>,
+    peaks: Vec<(u64, u32)>,
+    peak_index: usize,
+    path: Vec<PathStep>,
+    element_pos: Option<u64>,
+}
+
+impl<'a, H: CHasher> AllProofsIterator<'a, H> {
+    fn new(mmr: &'a Mmr<H>) -> Self {
+        let peaks: Vec<(u64, u32)> = mmr.peak_iterator().collect();
+        let mut iter = Self {
+            mmr,
+            peaks,
+            peak_index: 0,
+            path: Vec::new(),
+            element_pos: None,
+        };
+        if let Some(&(peak_pos, height)) = iter.peaks.first() {
+            iter.element_pos = Some(iter.descend(peak_pos, height));
+        }
+        iter
+    }
+
+    // Descend along the leftmost path from the given node to a leaf, returning the leaf position.
+    fn descend(&mut self, mut pos: u64, mut height: u32) -> u64 {
+        while height > 0 {
+            self.path.push(PathStep {
+                parent_pos: pos,
+                height,
+                left: true,
+            });
+            pos -= 1 << height;
+            height -= 1;
+        }
+        pos
+    }
+
+    // Move to the element following the current one, if any.
+    fn advance(&mut self) -> Option<u64> {
+        // Unwind the path until we find a step that descended left, then descend into its right
+        // child instead.
+        while let Some(step) = self.path.last_mut() {
+            if step.left {
+                step.left = false;
+                let (right_pos, height) = (step.parent_pos - 1, step.height - 1);
+                return Some(self.descend(right_pos, height));
+            }
+            self.path.pop();
+        }
+
+        // All elements in the current tree have been visited, so move to the next tree.
+        self.peak_index += 1;
+        let &(peak_pos, height) = self.peaks.get(self.peak_index)?;
+        Some(self.descend(peak_pos, height))
+    }
+}
+
+impl<H: CHasher> Iterator for AllProofsIterator<'_, H> {
+    type Item = (u64, Proof);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let element_pos = self.element_pos?;
+
+        // The proof consists of the hashes of every other peak followed by the siblings along the
+        // path from the peak to the element (in decreasing order of height).
+        let mut hashes = Vec::with_capacity(self.peaks.len() - 1 + self.path.len());
+        for (i, (peak_pos, _)) in self.peaks.iter().enumerate() {
+            if i != self.peak_index {
+                hashes.push(self.mmr.get_node(*peak_pos).unwrap().clone());
+            }
+        }
+        for step in self.path.iter() {
+            let sibling_pos = if step.left {
+                step.parent_pos - 1
+            } else {
+                step.parent_pos - (1 << step.height)
+            };
+            hashes.push(self.mmr.get_node(sibling_pos).expect("node pruned").clone());
+        }
+        let proof = Proof {
+            size: self.mmr.size(),
+            hashes,
+            scheme: self.mmr.scheme.clone(),
+        };
+
+        self.element_pos = self.advance();
+        Some((element_pos, proof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmr::hasher::{Hasher, Scheme, ROOT_TAG};
+    use crate::mmr::iterator::{nodes_needing_parents, pos_to_height, PeakIterator};
+    use crate::mmr::tests::Wide;
+    use crate::mmr::util::{leaf_count, node_count};
+    use crate::mmr::verification::{Checkpoint, ElementWitness, Proof, ProofStrategy};
+    use crate::mmr::{
+        mem::{Mmr, PruningPolicy},
+        Error,
+    };
+    use commonware_cryptography::{Blake3, Digest, Hasher as CHasher, Sha256};
+    use commonware_utils::hex;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::io::{self, Cursor, Write};
+    use std::sync::RwLock;
+
+    #[test]
+    /// Test MMR building by consecutively adding 11 equal elements to a new MMR, producing the
+    /// structure in the example documented at the top of the mmr crate's mod.rs file with 19 nodes
+    /// and 3 peaks.
+    fn test_add_eleven_values() {
+        let mut mmr: Mmr<Sha256> = Mmr::<Sha256>::new();
+        assert_eq!(
+            mmr.peak_iterator().next(),
+            None,
+            "empty iterator should have no peaks"
+        );
+        assert!(mmr.is_empty());
+
+        let element = Digest::from_static(b"01234567012345670123456701234567");
+        let mut leaves: Vec<u64> = Vec::new();
+        for _ in 0..11 {
+            leaves.push(mmr.add(&element));
+            let peaks: Vec<(u64, u32)> = mmr.peak_iterator().collect();
+            assert_ne!(peaks.len(), 0);
+            assert!(peaks.len() <= mmr.nodes.len());
+            let nodes_needing_parents = nodes_needing_parents(mmr.peak_iterator());
+            assert!(nodes_needing_parents.len() <= peaks.len());
+        }
+        assert_eq!(mmr.nodes.len(), 19, "mmr not of expected size");
+        assert_eq!(mmr.size(), 19);
+        assert!(!mmr.is_empty());
+        assert_eq!(
+            leaves,
+            vec![0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18],
+            "mmr leaf positions not as expected"
+        );
+        let peaks: Vec<(u64, u32)> = mmr.peak_iterator().collect();
+        assert_eq!(
+            peaks,
+            vec![(14, 3), (17, 1), (18, 0)],
+            "mmr peaks not as expected"
+        );
+
+        // Test nodes_needing_parents on the final MMR. Since there's a height gap between the
+        // highest peak (14) and the next, only the lower two peaks (17, 18) should be returned.
+        let peaks_needing_parents = nodes_needing_parents(mmr.peak_iterator());
+        assert_eq!(
+            peaks_needing_parents,
+            vec![17, 18],
+            "mmr nodes needing parents not as expected"
+        );
+
+        // verify leaf hashes
+        let mut hasher = Sha256::default();
+        let mut mmr_hasher = Hasher::new(&mut hasher);
+        for leaf in leaves.iter().by_ref() {
+            let hash = mmr_hasher.leaf_hash(*leaf, &element);
+            assert_eq!(mmr.nodes[*leaf as usize], hash);
+        }
+
+        // verify height=1 hashes
+        let hash2 = mmr_hasher.node_hash(2, &mmr.nodes[0], &mmr.nodes[1]);
+        assert_eq!(mmr.nodes[2], hash2);
+        let hash5 = mmr_hasher.node_hash(5, &mmr.nodes[3], &mmr.nodes[4]);
+        assert_eq!(mmr.nodes[5], hash5);
+        let hash9 = mmr_hasher.node_hash(9, &mmr.nodes[7], &mmr.nodes[8]);
+        assert_eq!(mmr.nodes[9], hash9);
+        let hash12 = mmr_hasher.node_hash(12, &mmr.nodes[10], &mmr.nodes[11]);
+        assert_eq!(mmr.nodes[12], hash12);
+        let hash17 = mmr_hasher.node_hash(17, &mmr.nodes[15], &mmr.nodes[16]);
+        assert_eq!(mmr.nodes[17], hash17);
+
+        // verify height=2 hashes
+        let hash6 = mmr_hasher.node_hash(6, &mmr.nodes[2], &mmr.nodes[5]);
+        assert_eq!(mmr.nodes[6], hash6);
+        let hash13 = mmr_hasher.node_hash(13, &mmr.nodes[9], &mmr.nodes[12]);
+        assert_eq!(mmr.nodes[13], hash13);
+        let hash17 = mmr_hasher.node_hash(17, &mmr.nodes[15], &mmr.nodes[16]);
+        assert_eq!(mmr.nodes[17], hash17);
+
+        // verify topmost hash
+        let hash14 = mmr_hasher.node_hash(14, &mmr.nodes[6], &mmr.nodes[13]);
+        assert_eq!(mmr.nodes[14], hash14);
+
+        // verify root hash
+        let root_hash = mmr.root_hash();
+        let peak_hashes = [hash14, hash17, mmr.nodes[18].clone()];
+        let expected_root_hash = mmr_hasher.root_hash(19, peak_hashes.iter());
+        assert_eq!(root_hash, expected_root_hash, "incorrect root hash");
+    }
+
+    #[test]
+    fn test_positions_of() {
+        let mut mmr = Mmr::<Sha256>::new_with_position_index();
+        let element1 = Digest::from(vec![1u8; 32]);
+        let element2 = Digest::from(vec![2u8; 32]);
+        let element3 = Digest::from(vec![3u8; 32]);
+        assert_eq!(mmr.positions_of(&element1), Some(&[][..]));
+
+        // add elements, including duplicates
+        let pos0 = mmr.add(&element1);
+        let pos1 = mmr.add(&element2);
+        let pos2 = mmr.add(&element1);
+        let pos3 = mmr.add(&element1);
+        assert_eq!(mmr.positions_of(&element1), Some(&[pos0, pos2, pos3][..]));
+        assert_eq!(mmr.positions_of(&element2), Some(&[pos1][..]));
+        assert_eq!(mmr.positions_of(&element3), Some(&[][..]));
+
+        // confirm the indexed positions are the leaves storing the element
+        let mut hasher = Sha256::default();
+        let mut mmr_hasher = Hasher::new(&mut hasher);
+        for pos in mmr.positions_of(&element1).unwrap() {
+            assert_eq!(
+                mmr.nodes[*pos as usize],
+                mmr_hasher.leaf_hash(*pos, &element1)
+            );
+        }
+
+        // the index should not change the structure of the MMR
+        let mut mmr2 = Mmr::<Sha256>::new();
+        for element in [&element1, &element2, &element1, &element1] {
+            mmr2.add(element);
+        }
+        assert_eq!(mmr.root_hash(), mmr2.root_hash());
+    }
+
+    #[test]
+    fn test_positions_of_disabled() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let element = Digest::from(vec![1u8; 32]);
+        assert_eq!(mmr.positions_of(&element), None);
+        mmr.add(&element);
+        assert_eq!(mmr.positions_of(&element), None);
+    }
+
+    #[test]
+    fn test_positions_of_pruned() {
+        let mut mmr = Mmr::<Sha256>::new_with_position_index();
+        let element1 = Digest::from(vec![1u8; 32]);
+        let element2 = Digest::from(vec![2u8; 32]);
+        let pos0 = mmr.add(&element1);
+        let pos1 = mmr.add(&element2);
+        let pos2 = mmr.add(&element1);
+        let pos3 = mmr.add(&element2);
+        assert_eq!(mmr.positions_of(&element1), Some(&[pos0, pos2][..]));
+        assert_eq!(mmr.positions_of(&element2), Some(&[pos1, pos3][..]));
+
+        // pruning drops the entries of every pruned leaf, and elements left without any
+        mmr.prune_to_pos(pos2);
+        assert_eq!(mmr.positions_of(&element1), Some(&[pos2][..]));
+        assert_eq!(mmr.positions_of(&element2), Some(&[pos3][..]));
+        mmr.prune_to_pos(pos3);
+        assert_eq!(mmr.positions_of(&element1), Some(&[][..]));
+        assert!(!mmr.positions.as_ref().unwrap().contains_key(&element1));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let elements: Vec<Digest> = (0..11u8).map(|i| Digest::from(vec![i; 32])).collect();
+        let mut mmr = Mmr::<Sha256>::new_with_position_index();
+        let mut roots = vec![mmr.root_hash()];
+        let mut sizes = vec![0];
+        for element in elements.iter() {
+            mmr.add(element);
+            roots.push(mmr.root_hash());
+            sizes.push(mmr.size());
+        }
+
+        // rewinding restores the MMR (and its index) to each earlier size
+        for leaves in (0..elements.len()).rev() {
+            // freeze the nodes to cover rewinding into a proof generator's snapshot
+            mmr.proof_generator();
+            mmr.rewind(sizes[leaves]).unwrap();
+            assert_eq!(mmr.size(), sizes[leaves]);
+            assert_eq!(mmr.root_hash(), roots[leaves]);
+            assert_eq!(mmr.positions_of(&elements[leaves]), Some(&[][..]));
+            if leaves > 0 {
+                let pos = node_count(leaves as u64 - 1);
+                assert_eq!(mmr.positions_of(&elements[leaves - 1]), Some(&[pos][..]));
+            }
+        }
+
+        // the rewound MMR grows as if the discarded elements were never added
+        for element in elements.iter() {
+            mmr.add(element);
+        }
+        assert_eq!(mmr.root_hash(), roots[elements.len()]);
+
+        // rewinding to before the pruning boundary fails, leaving the MMR unmodified
+        mmr.prune_to_pos(sizes[4]);
+        assert!(matches!(mmr.rewind(sizes[3]), Err(Error::ElementPruned(_))));
+        assert_eq!(mmr.root_hash(), roots[elements.len()]);
+        mmr.rewind(sizes[4]).unwrap();
+        assert_eq!(mmr.root_hash(), roots[4]);
+    }
+
+    #[test]
+    fn test_rewind_invalid_size() {
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0..4u8 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        let root = mmr.root_hash();
+        assert_eq!(mmr.rewind(2), Err(Error::InvalidSize(2)));
+        assert_eq!(mmr.rewind(8), Err(Error::InvalidSize(8)));
+        assert_eq!(mmr.rewind(u64::MAX), Err(Error::InvalidSize(u64::MAX)));
+        assert_eq!(mmr.size(), 7);
+        assert_eq!(mmr.root_hash(), root);
+    }
+
+    #[test]
+    fn test_pop() {
+        let element1 = Digest::from(vec![1u8; 32]);
+        let element2 = Digest::from(vec![2u8; 32]);
+        let mut mmr = Mmr::<Sha256>::new_with_position_index();
+        assert_eq!(mmr.pop(), Ok(None));
+
+        let pos0 = mmr.add(&element1);
+        let root = mmr.root_hash();
+        let pos1 = mmr.add(&element2);
+        let pos2 = mmr.add(&element1);
+        assert_eq!(mmr.pop(), Ok(Some(pos2)));
+        assert_eq!(mmr.positions_of(&element1), Some(&[pos0][..]));
+        assert_eq!(mmr.pop(), Ok(Some(pos1)));
+        assert_eq!(mmr.positions_of(&element2), Some(&[][..]));
+        assert_eq!(mmr.root_hash(), root);
+
+        // popping a pruned element fails
+        mmr.add(&element2);
+        mmr.prune_to_pos(mmr.size());
+        assert_eq!(mmr.pop(), Err(Error::ElementPruned(pos1)));
+    }
+
+    #[test]
+    fn test_range_proof_with_context() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0..11 {
+            elements.push(Digest::from(vec![i as u8; 32]));
+            positions.push(mmr.add(elements.last().unwrap()));
+        }
+
+        let (proof, context) = mmr
+            .range_proof_with_context(positions[2], positions[8])
+            .unwrap();
+        assert_eq!(proof, mmr.range_proof(positions[2], positions[8]).unwrap());
+        assert_eq!(context.size, 19);
+        assert_eq!(context.root, mmr.root_hash());
+        let mut hasher = Sha256::default();
+        assert!(proof.verify_range_inclusion(
+            &elements[2..9],
+            positions[2],
+            positions[8],
+            &context.root,
+            &mut hasher
+        ));
+
+        // the context should continue to reflect the MMR the proof was generated against
+        mmr.add(&elements[0]);
+        assert_ne!(context.root, mmr.root_hash());
+        let (proof, new_context) = mmr.proof_with_context(positions[3]).unwrap();
+        assert_eq!(new_context.size, 22);
+        assert!(proof.verify_element_inclusion(
+            &elements[3],
+            positions[3],
+            &new_context.root,
+            &mut hasher
+        ));
+        assert!(!proof.verify_element_inclusion(
+            &elements[3],
+            positions[3],
+            &context.root,
+            &mut hasher
+        ));
+    }
+
+    #[test]
+    fn test_verify_internal_node() {
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0u8..11 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        let mut hasher = Sha256::default();
+        for pos in 0..mmr.size() {
+            if pos_to_height(pos) == 0 {
+                assert_eq!(
+                    mmr.verify_internal_node(pos, &mut hasher),
+                    Err(Error::InvalidPos(pos))
+                );
+            } else {
+                assert_eq!(mmr.verify_internal_node(pos, &mut hasher), Ok(true));
+            }
+        }
+        assert_eq!(
+            mmr.verify_internal_node(19, &mut hasher),
+            Err(Error::InvalidPos(19))
+        );
+
+        // corrupting a node should be detected by the node itself and its parent
+        mmr.nodes[5] = Digest::from(vec![0u8; 32]);
+        assert_eq!(mmr.verify_internal_node(5, &mut hasher), Ok(false));
+        assert_eq!(mmr.verify_internal_node(6, &mut hasher), Ok(false));
+        assert_eq!(mmr.verify_internal_node(2, &mut hasher), Ok(true));
+        assert_eq!(mmr.verify_internal_node(14, &mut hasher), Ok(true));
+
+        // pruned nodes can't be checked
+        let pruned = mmr.clone_pruned();
+        assert_eq!(
+            pruned.verify_internal_node(14, &mut hasher),
+            Err(Error::ElementPruned(6))
+        );
+    }
+
+    #[test]
+    fn test_proof_and_root() {
+        let mmr = RwLock::new(Mmr::<Sha256>::new());
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..50 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.write().unwrap().add(&element));
+            elements.push(element);
+
+            // every proof should verify against the root returned with it
+            let mut hasher = Sha256::default();
+            let guard = mmr.read().unwrap();
+            for (pos, element) in positions.iter().zip(elements.iter()) {
+                let (proof, root) = guard.proof_and_root(*pos).unwrap();
+                assert_eq!(proof, guard.proof(*pos).unwrap());
+                assert!(proof.verify_element_inclusion(element, *pos, &root, &mut hasher));
+            }
+        }
+        let (_, root) = mmr.read().unwrap().proof_and_root(positions[0]).unwrap();
+        assert_eq!(root, mmr.write().unwrap().root_hash());
+
+        // pruned elements can't be proven
+        let pruned = mmr.read().unwrap().clone_pruned();
+        assert!(matches!(
+            pruned.proof_and_root(positions[0]),
+            Err(Error::ElementPruned(_))
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let checkpoint = mmr.checkpoint();
+        assert_eq!(checkpoint.size, 0);
+        assert_eq!(checkpoint.leaf_count, 0);
+        assert_eq!(checkpoint.root, mmr.root_hash());
+
+        for i in 0..100u64 {
+            mmr.add(&Digest::from(vec![i as u8; 32]));
+            let checkpoint = mmr.checkpoint();
+            assert_eq!(checkpoint.size, mmr.size());
+            assert_eq!(checkpoint.leaf_count, i + 1);
+            assert_eq!(checkpoint.root, mmr.root_hash());
+        }
+    }
+
+    #[test]
+    fn test_all_proofs() {
+        let mut mmr = Mmr::<Sha256>::new();
+        assert_eq!(mmr.all_proofs().count(), 0);
+
+        let mut positions = Vec::new();
+        for i in 0..200u64 {
+            positions.push(mmr.add(&Digest::from(vec![i as u8; 32])));
+
+            // every yielded proof should match the individually generated proof
+            let mut count = 0;
+            for (j, (element_pos, proof)) in mmr.all_proofs().enumerate() {
+                assert_eq!(element_pos, positions[j]);
+                assert_eq!(
+                    proof,
+                    mmr.proof(element_pos).unwrap(),
+                    "size={} pos={}",
+                    i + 1,
+                    element_pos
+                );
+                count += 1;
+            }
+            assert_eq!(count, positions.len());
+        }
+    }
+
+    #[test]
+    fn test_adjacency_proof() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0..11 {
+            elements.push(Digest::from(vec![i as u8; 32]));
+            positions.push(mmr.add(elements.last().unwrap()));
+        }
+        let root_hash = mmr.root_hash();
+        let mut hasher = Sha256::default();
+
+        for i in 0..positions.len() - 1 {
+            let proof = mmr.adjacency_proof(positions[i]).unwrap();
+            assert!(proof.verify_adjacency(
+                &elements[i],
+                positions[i],
+                &elements[i + 1],
+                &root_hash,
+                &mut hasher
+            ));
+
+            // swapping the elements should fail
+            assert!(!proof.verify_adjacency(
+                &elements[i + 1],
+                positions[i],
+                &elements[i],
+                &root_hash,
+                &mut hasher
+            ));
+        }
+
+        // a proof over non-adjacent elements shouldn't verify as an adjacency proof
+        let proof = mmr.range_proof(positions[2], positions[4]).unwrap();
+        assert!(!proof.verify_adjacency(
+            &elements[2],
+            positions[2],
+            &elements[4],
+            &root_hash,
+            &mut hasher
+        ));
+
+        // non-leaf positions should be rejected
+        let proof = mmr.adjacency_proof(positions[1]).unwrap();
+        assert!(!proof.verify_adjacency(&elements[1], 2, &elements[2], &root_hash, &mut hasher));
+    }
+
+    #[test]
+    fn test_adjacency_proof_invalid() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut last_pos = 0;
+        for i in 0..11 {
+            last_pos = mmr.add(&Digest::from(vec![i as u8; 32]));
+        }
+
+        // the last element has no successor, and non-leaf or out-of-range positions aren't elements
+        for pos in [last_pos, 2, mmr.size(), u64::MAX] {
+            assert_eq!(mmr.adjacency_proof(pos).err(), Some(Error::InvalidPos(pos)));
+        }
+    }
+
+    #[test]
+    fn test_historical_range_proof() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        let mut checkpoints = Vec::new();
+        for i in 0..30 {
+            elements.push(Digest::from(vec![i as u8; 32]));
+            positions.push(mmr.add(elements.last().unwrap()));
+            checkpoints.push(mmr.checkpoint());
+        }
+        let mut hasher = Sha256::default();
+
+        // proofs generated at a historical size should match those generated by the MMR at that
+        // size and verify against its root
+        let mut historical_mmr = Mmr::<Sha256>::new();
+        for (i, checkpoint) in checkpoints.iter().enumerate() {
+            historical_mmr.add(&elements[i]);
+            for j in 0..=i {
+                let proof = mmr
+                    .historical_range_proof(checkpoint.size, positions[j], positions[i])
+                    .unwrap();
+                assert_eq!(
+                    proof,
+                    historical_mmr
+                        .range_proof(positions[j], positions[i])
+                        .unwrap()
+                );
+                assert!(proof.verify_historical_range_inclusion(
+                    &elements[j..=i],
+                    positions[j],
+                    positions[i],
+                    checkpoint.size,
+                    &checkpoint.root,
+                    &mut hasher
+                ));
+            }
+        }
+
+        // a historical proof shouldn't verify against the current root or a different size
+        let checkpoint = &checkpoints[10];
+        let proof = mmr
+            .historical_range_proof(checkpoint.size, positions[3], positions[7])
+            .unwrap();
+        assert!(!proof.verify_range_inclusion(
+            &elements[3..=7],
+            positions[3],
+            positions[7],
+            &mmr.root_hash(),
+            &mut hasher
+        ));
+        assert!(!proof.verify_historical_range_inclusion(
+            &elements[3..=7],
+            positions[3],
+            positions[7],
+            checkpoints[11].size,
+            &checkpoint.root,
+            &mut hasher
+        ));
+
+        // a range extending past the trusted size should be rejected
+        assert!(!proof.verify_historical_range_inclusion(
+            &elements[3..=7],
+            positions[3],
+            checkpoint.size,
+            checkpoint.size,
+            &checkpoint.root,
+            &mut hasher
+        ));
+    }
+
+    #[test]
+    fn test_historical_range_proof_invalid() {
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0..11 {
+            mmr.add(&Digest::from(vec![i as u8; 32]));
+        }
+
+        // the range must lie within a valid size no greater than the current size
+        assert_eq!(
+            mmr.historical_range_proof(10, 7, 10).err(),
+            Some(Error::InvalidPos(10))
+        );
+        assert_eq!(
+            mmr.historical_range_proof(9, 7, 8).err(),
+            Some(Error::InvalidSize(9))
+        );
+        let size = mmr.size() + 1;
+        assert_eq!(
+            mmr.historical_range_proof(size, 0, 0).err(),
+            Some(Error::InvalidSize(size))
+        );
+    }
+
+    #[test]
+    fn test_from_elements() {
+        let elements: Vec<Digest> = (0..49).map(|i| Digest::from(vec![i as u8; 32])).collect();
+        let mut mmr = Mmr::<Sha256>::new();
+        for element in elements.iter() {
+            mmr.add(element);
+        }
+        let mut mmr_from = Mmr::<Sha256>::from(elements);
+        assert_eq!(mmr_from.nodes, mmr.nodes);
+        assert_eq!(mmr_from.root_hash(), mmr.root_hash());
+
+        let mut empty = Mmr::<Sha256>::from(Vec::new());
+        assert_eq!(empty.size(), 0);
+        assert!(empty.is_empty());
+        assert!(!mmr_from.is_empty());
+        assert_eq!(empty.root_hash(), Mmr::<Sha256>::new().root_hash());
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut mmr = Mmr::<Sha256>::new_with_position_index();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0..1000 {
+            elements.push(Digest::from(vec![(i % 256) as u8; 32]));
+            positions.push(mmr.add(elements.last().unwrap()));
+        }
+        let root_hash = mmr.root_hash();
+        let retained = mmr.retained_nodes();
+        assert_eq!(retained as u64, mmr.size());
+        assert!(mmr.capacity_nodes() >= retained);
+
+        mmr.shrink_to_fit();
+        assert_eq!(mmr.capacity_nodes(), retained);
+        assert_eq!(mmr.retained_nodes(), retained);
+        assert_eq!(mmr.root_hash(), root_hash);
+        assert_eq!(
+            mmr.positions_of(&elements[3]),
+            Some(&[positions[3], positions[259], positions[515], positions[771]][..])
+        );
+
+        // proofs should be unaffected by the shrink
+        let mut hasher = Sha256::default();
+        for (element, pos) in elements.iter().zip(positions.iter()) {
+            let proof = mmr.proof(*pos).unwrap();
+            assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+        }
+        let proof = mmr.range_proof(positions[100], positions[900]).unwrap();
+        assert!(proof.verify_range_inclusion(
+            &elements[100..=900],
+            positions[100],
+            positions[900],
+            &root_hash,
+            &mut hasher
+        ));
+
+        // subsequent additions should behave identically to an MMR that was never shrunk
+        let mut reference = Mmr::<Sha256>::from(elements.clone());
+        for i in 0..100 {
+            let element = Digest::from(vec![i as u8; 32]);
+            assert_eq!(mmr.add(&element), reference.add(&element));
+            assert_eq!(mmr.root_hash(), reference.root_hash());
+        }
+    }
+
+    #[test]
+    fn test_append_mmr() {
+        let elements: Vec<Digest> = (0u8..30).map(|i| Digest::from(vec![i % 7; 32])).collect();
+        let mut reference = Mmr::<Sha256>::from(elements.clone());
+
+        for split in [0, 1, 11, 29, 30] {
+            let mut first = Mmr::<Sha256>::from(elements[..split].to_vec());
+            let mut second = Mmr::<Sha256>::new_with_position_index();
+            for element in elements[split..].iter() {
+                second.add(element);
+            }
+            first.append_mmr(&second).unwrap();
+            assert_eq!(first.size(), reference.size());
+            assert_eq!(first.root_hash(), reference.root_hash());
+        }
+    }
+
+    #[test]
+    fn test_append_mmr_elements_not_retained() {
+        let mut first = Mmr::<Sha256>::from(vec![Digest::from(vec![1u8; 32])]);
+        let root_hash = first.root_hash();
+
+        // an empty MMR can always be appended
+        first.append_mmr(&Mmr::new()).unwrap();
+        assert_eq!(first.root_hash(), root_hash);
+
+        let second = Mmr::<Sha256>::from(vec![Digest::from(vec![2u8; 32])]);
+        assert_eq!(first.append_mmr(&second), Err(Error::ElementsNotRetained));
+        assert_eq!(first.root_hash(), root_hash);
+    }
+
+    #[test]
+    fn test_proof_cost() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::new();
+        for i in 0u64..500 {
+            positions.push(mmr.add(&Digest::from(i.to_be_bytes().to_vec())));
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let mut start = rng.gen_range(0..positions.len());
+            let mut end = rng.gen_range(0..positions.len());
+            if start > end {
+                std::mem::swap(&mut start, &mut end);
+            }
+            let cost = mmr.proof_cost(positions[start], positions[end]).unwrap();
+            let proof = mmr.range_proof(positions[start], positions[end]).unwrap();
+            assert_eq!(cost.proof_hashes, proof.hashes.len());
+            assert_eq!(cost.node_reads, proof.hashes.len());
+            assert_eq!(
+                mmr.range_proof_size_bytes(positions[start], positions[end], Sha256::len()),
+                Ok(proof.to_bytes().len())
+            );
+            let proof = mmr.proof(positions[start]).unwrap();
+            assert_eq!(
+                mmr.proof_size_bytes(positions[start], Sha256::len()),
+                Ok(proof.to_bytes().len())
+            );
+        }
+
+        // out-of-range, non-leaf, and out-of-order positions are rejected
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0u8..11 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        assert_eq!(mmr.proof_cost(0, 100), Err(Error::InvalidPos(100)));
+        assert_eq!(mmr.proof_cost(2, 3), Err(Error::InvalidPos(2)));
+        assert_eq!(mmr.proof_cost(3, 0), Err(Error::InvalidPos(3)));
+        assert_eq!(mmr.proof_size_bytes(100, 32), Err(Error::InvalidPos(100)));
+        assert_eq!(
+            mmr.range_proof_size_bytes(0, u64::MAX, 32),
+            Err(Error::InvalidPos(u64::MAX))
+        );
+        assert_eq!(
+            mmr.proof_batch_optimal(&[0, 100]).err(),
+            Some(Error::InvalidPos(100))
+        );
+    }
+
+    #[test]
+    fn test_root_hash_streaming() {
+        let mut hasher = Sha256::default();
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut stream = Vec::new();
+        for i in 0u8..200 {
+            let element = Digest::from(vec![i; 32]);
+            stream.extend_from_slice(&element);
+            mmr.add(&element);
+
+            let root_hash = Mmr::root_hash_streaming(
+                &stream[..],
+                i as usize + 1,
+                &mut hasher,
+                Scheme::default(),
+            )
+            .unwrap();
+            assert_eq!(root_hash, mmr.root_hash());
+        }
+
+        // an empty stream yields the root hash of an empty MMR
+        let root_hash =
+            Mmr::root_hash_streaming(&[][..], 0, &mut hasher, Scheme::default()).unwrap();
+        assert_eq!(root_hash, Mmr::<Sha256>::new().root_hash());
+
+        // a stream with too few digests should fail
+        let err =
+            Mmr::root_hash_streaming(&stream[..31], 1, &mut hasher, Scheme::default()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_peaks() {
+        let mut mmr = Mmr::<Sha256>::new();
+        assert!(mmr.peak_hashes().is_empty());
+        assert!(mmr.peak_positions().is_empty());
+        for i in 0u8..50 {
+            mmr.add(&Digest::from(vec![i; 32]));
+            let expected: Vec<u64> = PeakIterator::new(mmr.size()).map(|(pos, _)| pos).collect();
+            assert_eq!(mmr.peak_positions(), expected);
+            let peak_hashes = mmr.peak_hashes();
+            assert_eq!(
+                peak_hashes.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(),
+                expected
+            );
+            for (pos, hash) in peak_hashes {
+                assert_eq!(hash, mmr.nodes[pos as usize]);
+            }
+        }
+
+        // 11 elements yield the 3 peaks from the example in the module documentation
+        let mut mmr = Mmr::<Sha256>::from(
+            (0u8..11)
+                .map(|i| Digest::from(vec![i; 32]))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(mmr.peak_positions(), vec![14, 17, 18]);
+
+        // The root hash is a single hash over the root tag, the size, and the peaks
+        let mut hasher = Sha256::default();
+        hasher.update(&[ROOT_TAG]);
+        hasher.update(&mmr.size().to_be_bytes());
+        for (_, hash) in mmr.peak_hashes() {
+            hasher.update(&hash);
+        }
+        assert_eq!(hasher.finalize(), mmr.root_hash());
+    }
+
+    #[test]
+    fn test_bag_peaks() {
+        let mut hasher = Sha256::default();
+        for scheme in [Scheme::Tagged, Scheme::Untagged, Scheme::keyed(b"key")] {
+            let mut mmr = Mmr::<Sha256>::new_with_scheme(scheme);
+            assert_eq!(mmr.bag_peaks(&mut hasher), vec![mmr.root_hash()]);
+
+            // 11 elements yield 3 peaks, bagged from the rightmost inward
+            for i in 0u8..11 {
+                mmr.add(&Digest::from(vec![i; 32]));
+            }
+            let bagged = mmr.bag_peaks(&mut hasher);
+            let mut peaks: Vec<Digest> = mmr.peak_hashes().into_iter().map(|(_, h)| h).collect();
+            peaks.reverse();
+            assert_eq!(bagged.len(), 4);
+            assert_eq!(&bagged[..3], &peaks[..]);
+            assert_eq!(bagged[3], mmr.root_hash());
+        }
+    }
+
+    #[test]
+    fn test_height() {
+        let mut mmr = Mmr::<Sha256>::new();
+        assert_eq!(mmr.height(), 0);
+        assert!(!mmr.is_perfect());
+        let mut leaves = 0u64;
+        for i in 0u8..=128 {
+            mmr.add(&Digest::from(vec![i; 32]));
+            leaves += 1;
+            assert_eq!(mmr.height(), 63 - leaves.leading_zeros());
+            assert_eq!(mmr.is_perfect(), leaves.is_power_of_two());
+            if leaves.is_power_of_two() {
+                assert_eq!(mmr.height(), leaves.trailing_zeros());
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_with_witness() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let preimages: Vec<_> = (0u8..20).map(|i| vec![i; i as usize + 1]).collect();
+        let positions: Vec<_> = preimages
+            .iter()
+            .map(|bytes| {
+                hasher.update(bytes);
+                mmr.add(&hasher.finalize())
+            })
+            .collect();
+        let root_hash = mmr.root_hash();
+        for (bytes, pos) in preimages.iter().zip(positions.iter()) {
+            let (proof, witness) = mmr.proof_with_witness(*pos, bytes).unwrap();
+            assert_eq!(proof, mmr.proof(*pos).unwrap());
+            assert!(proof.verify_element_with_witness(&witness, *pos, &root_hash, &mut hasher));
+        }
+
+        // The pre-image of another element fails verification
+        let (proof, _) = mmr.proof_with_witness(positions[3], &preimages[3]).unwrap();
+        let witness = ElementWitness {
+            element_bytes: preimages[4].clone(),
+        };
+        assert!(!proof.verify_element_with_witness(
+            &witness,
+            positions[3],
+            &root_hash,
+            &mut hasher
+        ));
+    }
+
+    #[test]
+    fn test_sparse_proof() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..49).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+        let root_hash = mmr.root_hash();
+
+        // Random subsets of random ranges verify given only their elements
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let start = rng.gen_range(0..elements.len());
+            let end = rng.gen_range(start..elements.len());
+            let present: Vec<bool> = (start..=end).map(|_| rng.gen_bool(0.3)).collect();
+            let selected: Vec<_> = elements[start..=end]
+                .iter()
+                .zip(present.iter())
+                .filter(|(_, is_present)| **is_present)
+                .map(|(element, _)| element.clone())
+                .collect();
+            let proof = mmr
+                .sparse_proof(positions[start], positions[end], &present)
+                .unwrap();
+            assert!(proof.verify_sparse_inclusion(
+                &selected,
+                positions[start],
+                positions[end],
+                &present,
+                &root_hash,
+                &mut hasher
+            ));
+
+            // Flipping a bit (without adjusting the elements) fails verification
+            let mut flipped = present.clone();
+            let index = rng.gen_range(0..flipped.len());
+            flipped[index] = !flipped[index];
+            assert!(!proof.verify_sparse_inclusion(
+                &selected,
+                positions[start],
+                positions[end],
+                &flipped,
+                &root_hash,
+                &mut hasher
+            ));
+        }
+
+        // With every leaf present, the proof holds the hashes of the range proof
+        let present = vec![true; 11];
+        let proof = mmr
+            .sparse_proof(positions[7], positions[17], &present)
+            .unwrap();
+        let mut expected = mmr.range_proof(positions[7], positions[17]).unwrap();
+        let mut hashes = proof.hashes.clone();
+        hashes.sort();
+        expected.hashes.sort();
+        assert_eq!(hashes, expected.hashes);
+        assert!(proof.verify_sparse_inclusion(
+            &elements[7..=17],
+            positions[7],
+            positions[17],
+            &present,
+            &root_hash,
+            &mut hasher
+        ));
+
+        // A sparse proof shares the hashes common to its leaves
+        let mut present = vec![false; 16];
+        present[0] = true;
+        present[15] = true;
+        let proof = mmr
+            .sparse_proof(positions[0], positions[15], &present)
+            .unwrap();
+        let selected = [elements[0].clone(), elements[15].clone()];
+        assert_eq!(
+            proof.hashes.len(),
+            mmr.proof(positions[0]).unwrap().hashes.len() + 2
+        );
+        assert!(proof.verify_sparse_inclusion(
+            &selected,
+            positions[0],
+            positions[15],
+            &present,
+            &root_hash,
+            &mut hasher
+        ));
+
+        // Wrong elements, extra hashes, and missing hashes fail verification
+        let swapped = [elements[15].clone(), elements[0].clone()];
+        assert!(!proof.verify_sparse_inclusion(
+            &swapped,
+            positions[0],
+            positions[15],
+            &present,
+            &root_hash,
+            &mut hasher
+        ));
+        let mut extended = proof.clone();
+        extended.hashes.push(elements[0].clone());
+        assert!(!extended.verify_sparse_inclusion(
+            &selected,
+            positions[0],
+            positions[15],
+            &present,
+            &root_hash,
+            &mut hasher
+        ));
+        let mut truncated = proof.clone();
+        truncated.hashes.pop();
+        assert!(!truncated.verify_sparse_inclusion(
+            &selected,
+            positions[0],
+            positions[15],
+            &present,
+            &root_hash,
+            &mut hasher
+        ));
+
+        // Invalid requests are rejected
+        assert_eq!(
+            mmr.sparse_proof(positions[3], positions[4], &[true; 3]),
+            Err(Error::InvalidBitmapLength(3))
+        );
+        assert_eq!(
+            mmr.sparse_proof(positions[3], positions[4], &[]),
+            Err(Error::InvalidBitmapLength(0))
+        );
+        assert_eq!(
+            mmr.sparse_proof(2, positions[4], &[true; 3]),
+            Err(Error::InvalidPos(2))
+        );
+        assert_eq!(
+            mmr.sparse_proof(positions[4], positions[3], &[true; 2]),
+            Err(Error::InvalidPos(positions[4]))
+        );
+        assert_eq!(
+            mmr.sparse_proof(positions[0], mmr.size(), &[true]),
+            Err(Error::InvalidPos(mmr.size()))
+        );
+    }
+
+    #[test]
+    fn test_circuit_witness() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..49).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+        let peaks: Vec<_> = mmr.peak_iterator().map(|(pos, _)| pos).collect();
+        for (element, element_pos) in elements.iter().zip(positions.iter()) {
+            // Folding the witness from the leaf reconstructs the element's peak
+            let witness = mmr.circuit_witness(*element_pos).unwrap();
+            let mut pos = *element_pos;
+            let mut hash = Hasher::new(&mut hasher).leaf_hash(pos, element);
+            for (height, (is_left, sibling)) in witness.iter().enumerate() {
+                let parent_pos = if *is_left {
+                    pos + (2 << height)
+                } else {
+                    pos + 1
+                };
+                hash = if *is_left {
+                    Hasher::new(&mut hasher).node_hash(parent_pos, &hash, sibling)
+                } else {
+                    Hasher::new(&mut hasher).node_hash(parent_pos, sibling, &hash)
+                };
+                pos = parent_pos;
+            }
+            assert!(peaks.contains(&pos));
+            assert_eq!(&hash, mmr.get_node(pos).unwrap());
+        }
+
+        // The last element is alone in its tree
+        assert!(mmr
+            .circuit_witness(*positions.last().unwrap())
+            .unwrap()
+            .is_empty());
+        assert_eq!(mmr.circuit_witness(2).err(), Some(Error::InvalidPos(2)));
+    }
+
+    #[test]
+    fn test_proof_size_logarithmic() {
+        // A single-element proof contains one sibling per level of the tree containing the element
+        // (at most floor(log2(leaves)) since the tallest tree has at most `leaves` leaves) plus the
+        // hash of every other peak (one per set bit of `leaves`, less the element's own tree):
+        //
+        //     hashes.len() <= floor(log2(leaves)) + popcount(leaves) - 1
+        for leaves in [10u64, 100, 1_000, 10_000] {
+            let mut mmr = Mmr::<Sha256>::new();
+            let mut positions = Vec::new();
+            for i in 0..leaves {
+                positions.push(mmr.add(&Digest::from(i.to_be_bytes().repeat(4))));
+            }
+            let log2_leaves = 63 - leaves.leading_zeros() as usize;
+            let peaks = leaves.count_ones() as usize;
+            assert_eq!(mmr.peak_positions().len(), peaks);
+            let bound = log2_leaves + peaks - 1;
+            let mut largest = 0;
+            for pos in positions.iter() {
+                let proof = mmr.proof(*pos).unwrap();
+                assert!(
+                    proof.hashes.len() <= bound,
+                    "proof for {pos} has {} hashes (bound {bound})",
+                    proof.hashes.len()
+                );
+                largest = largest.max(proof.hashes.len());
+            }
+
+            // elements in the tallest tree reach the bound exactly
+            assert_eq!(largest, bound);
+        }
+    }
+
+    #[test]
+    fn test_export_at() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut reference = Mmr::<Sha256>::new();
+        let mut exports = Vec::new();
+        for i in 0u8..50 {
+            let element = Digest::from(vec![i; 32]);
+            mmr.add(&element);
+            let mut bytes = Vec::new();
+            reference.export(&mut bytes).unwrap();
+            exports.push((bytes, reference.root_hash()));
+            reference.add(&element);
+        }
+
+        // exports mid-history match those of the MMR when it had that many leaves
+        for (leaf_count, (expected, root)) in exports.iter().enumerate() {
+            let mut bytes = Vec::new();
+            mmr.export_at(&mut bytes, leaf_count as u64).unwrap();
+            assert_eq!(&bytes, expected);
+            let mut imported =
+                Mmr::<Sha256>::import(&bytes[..], Scheme::default(), Some(root)).unwrap();
+            assert_eq!(imported.size(), node_count(leaf_count as u64));
+            assert_eq!(&imported.root_hash(), root);
+        }
+        let mut bytes = Vec::new();
+        mmr.export_at(&mut bytes, 50).unwrap();
+        let mut expected = Vec::new();
+        mmr.export(&mut expected).unwrap();
+        assert_eq!(bytes, expected);
+
+        // sizes beyond the MMR, or in a pruned MMR, can't be exported
+        assert_eq!(
+            mmr.export_at(&mut Vec::new(), 51),
+            Err(Error::IndexOutOfRange(51))
+        );
+        assert_eq!(
+            mmr.export_at(&mut Vec::new(), u64::MAX),
+            Err(Error::IndexOutOfRange(u64::MAX))
+        );
+        assert_eq!(
+            mmr.clone_pruned().export_at(&mut Vec::new(), 10),
+            Err(Error::ElementPruned(0))
+        );
+    }
+
+    #[test]
+    fn test_node_at() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let mut positions = Vec::new();
+        for i in 0u8..11 {
+            positions.push(mmr.add(&Digest::from(vec![i; 32])));
+        }
+
+        // every node is accessible by position, and every leaf by index
+        for pos in 0..mmr.size() {
+            assert_eq!(mmr.node_at(pos), mmr.get_node(pos));
+        }
+        assert_eq!(mmr.node_at(mmr.size()), None);
+        for (index, pos) in positions.iter().enumerate() {
+            let expected =
+                Hasher::new(&mut hasher).leaf_hash(*pos, &Digest::from(vec![index as u8; 32]));
+            assert_eq!(mmr.leaf_at(index), Some(&expected));
+        }
+        assert_eq!(mmr.leaf_at(11), None);
+        assert_eq!(mmr.leaf_at(usize::MAX), None);
+
+        // pruned nodes are unavailable, except for pinned peaks
+        let pruned = mmr.clone_pruned();
+        assert_eq!(pruned.leaf_at(0), None);
+        assert_eq!(pruned.node_at(2), None);
+        for peak_pos in mmr.peak_positions() {
+            assert_eq!(pruned.node_at(peak_pos), mmr.node_at(peak_pos));
+        }
+        assert_eq!(pruned.leaf_at(10), mmr.leaf_at(10)); // the last leaf is a peak
+    }
+
+    #[test]
+    fn test_par_build_from() {
+        let elements: Vec<_> = (0u16..1000)
+            .map(|i| Digest::from(vec![i as u8; 32]))
+            .collect();
+        for count in [0, 1, 2, 3, 7, 8, 100, 1000] {
+            let elements = &elements[..count];
+            let mut reference = Mmr::<Sha256>::build_from(elements);
+            let mut incremental = Mmr::<Sha256>::new();
+            for element in elements {
+                incremental.add(element);
+            }
+            assert_eq!(reference.root_hash(), incremental.root_hash());
+            for threads in [1, 2, 3, 4, 8, 64] {
+                let mmr = Mmr::<Sha256>::par_build_from(elements, threads);
+                assert_eq!(mmr.size(), reference.size());
+                for pos in 0..mmr.size() {
+                    assert_eq!(mmr.get_node(pos), reference.get_node(pos));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dump_peaks() {
+        for count in [0u8, 1, 11, 100] {
+            let mut mmr = Mmr::<Sha256>::new();
+            for i in 0..count {
+                mmr.add(&Digest::from(vec![i; 32]));
+            }
+            let mut buf = Vec::new();
+            mmr.dump_peaks_to_writer(&mut buf).unwrap();
+            let peaks = mmr.peak_hashes();
+            assert_eq!(buf.len(), 8 + peaks.len() * (8 + 32));
+            assert_eq!(buf[..8], mmr.size().to_be_bytes());
+
+            // the restored accumulator should track the original
+            let mut restored =
+                Mmr::<Sha256>::load_peaks_from_reader(&mut Cursor::new(&buf), Scheme::default())
+                    .unwrap();
+            assert_eq!(restored.size(), mmr.size());
+            assert_eq!(restored.root_hash(), mmr.root_hash());
+            let element = Digest::from(vec![255u8; 32]);
+            assert_eq!(restored.add(&element), mmr.add(&element));
+            assert_eq!(restored.root_hash(), mmr.root_hash());
+
+            // pruning retains the peaks, so a pruned MMR dumps the same peaks
+            let mut pruned_buf = Vec::new();
+            mmr.clone_pruned()
+                .dump_peaks_to_writer(&mut pruned_buf)
+                .unwrap();
+            let mut expected = Vec::new();
+            mmr.dump_peaks_to_writer(&mut expected).unwrap();
+            assert_eq!(pruned_buf, expected);
+
+            // truncated input should fail
+            let truncated = &buf[..buf.len() - 1];
+            let err = Mmr::<Sha256>::load_peaks_from_reader(
+                &mut Cursor::new(truncated),
+                Scheme::default(),
+            )
+            .err()
+            .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        // an invalid size or peak position should be rejected
+        let err = Mmr::<Sha256>::load_peaks_from_reader(
+            &mut Cursor::new(2u64.to_be_bytes()),
+            Scheme::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let mut mmr = Mmr::<Sha256>::new();
+        mmr.add(&Digest::from(vec![0u8; 32]));
+        let mut buf = Vec::new();
+        mmr.dump_peaks_to_writer(&mut buf).unwrap();
+        buf[15] = 1;
+        let err = Mmr::<Sha256>::load_peaks_from_reader(&mut Cursor::new(&buf), Scheme::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_compact_serialisation() {
+        for count in [0u8, 1, 11, 100] {
+            let mut mmr = Mmr::<Sha256>::new();
+            let mut positions = Vec::new();
+            for i in 0..count {
+                positions.push(mmr.add(&Digest::from(vec![i; 32])));
+            }
+            let mut buf = Vec::new();
+            mmr.to_compact_serialised(&mut buf).unwrap();
+            assert_eq!(buf.len() as u64, 8 + 32 * mmr.size());
+            assert_eq!(buf[..8], mmr.size().to_be_bytes());
+
+            let mut restored =
+                Mmr::<Sha256>::from_compact_serialised(&mut Cursor::new(&buf), Scheme::default())
+                    .unwrap();
+            assert_eq!(restored.size(), mmr.size());
+            assert_eq!(restored.root_hash(), mmr.root_hash());
+            for pos in positions.iter() {
+                assert_eq!(restored.proof(*pos).unwrap(), mmr.proof(*pos).unwrap());
+            }
+
+            // the restored MMR should continue to track the original
+            let element = Digest::from(vec![255u8; 32]);
+            assert_eq!(restored.add(&element), mmr.add(&element));
+            assert_eq!(restored.root_hash(), mmr.root_hash());
+
+            // truncated input should fail
+            let truncated = &buf[..buf.len() - 1];
+            let err = Mmr::<Sha256>::from_compact_serialised(
+                &mut Cursor::new(truncated),
+                Scheme::default(),
+            )
+            .err()
+            .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        // an invalid size should be rejected before reading any nodes
+        let err = Mmr::<Sha256>::from_compact_serialised(
+            &mut Cursor::new(2u64.to_be_bytes()),
+            Scheme::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // pruned MMRs can't be serialised
+        let mut mmr = Mmr::<Sha256>::new();
+        mmr.add(&Digest::from(vec![0u8; 32]));
+        let err = mmr
+            .clone_pruned()
+            .to_compact_serialised(&mut Vec::new())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_proof_batch_optimal() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..200 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let root = mmr.root_hash();
+        let mut hasher = Sha256::default();
+        let index_of = |pos: u64| positions.iter().position(|p| *p == pos).unwrap();
+        for (i, pos) in positions.iter().enumerate() {
+            assert_eq!(leaf_count(*pos), i as u64);
+        }
+
+        // Every proof verifies and the requested elements are all covered
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let count = rng.gen_range(1..8);
+            let mut requested: Vec<u64> = (0..count)
+                .map(|_| positions[rng.gen_range(0..positions.len())])
+                .collect();
+            // include a cluster of neighbours
+            let cluster = rng.gen_range(0..positions.len() - 4);
+            requested.extend_from_slice(&positions[cluster..cluster + 4]);
+            let batch = mmr.proof_batch_optimal(&requested).unwrap();
+            let mut covered: Vec<u64> = Vec::new();
+            for (strategy, proof) in batch.iter() {
+                let (start, end) = match *strategy {
+                    ProofStrategy::Individual(pos) => (pos, pos),
+                    ProofStrategy::Range(start, end) => {
+                        assert!(start < end);
+                        (start, end)
+                    }
+                };
+                let span = &elements[index_of(start)..=index_of(end)];
+                assert!(proof.verify_range_inclusion(span, start, end, &root, &mut hasher));
+                covered.extend(requested.iter().filter(|pos| (start..=end).contains(*pos)));
+            }
+            covered.sort_unstable();
+            covered.dedup();
+            requested.sort_unstable();
+            requested.dedup();
+            assert_eq!(covered, requested);
+
+            // No partition of the sorted elements into runs requires fewer hashes
+            let total: usize = batch
+                .iter()
+                .map(|(strategy, _)| match *strategy {
+                    ProofStrategy::Individual(pos) => mmr.run_cost(pos, pos, 1),
+                    ProofStrategy::Range(start, end) => {
+                        let count = requested.iter().filter(|pos| (start..=end).contains(*pos));
+                        mmr.run_cost(start, end, count.count())
+                    }
+                })
+                .sum();
+            let n = requested.len();
+            for splits in 0u32..(1 << (n - 1)) {
+                let mut cost = 0;
+                let mut start = 0;
+                for end in 1..=n {
+                    if end == n || splits & (1 << (end - 1)) != 0 {
+                        cost += mmr.run_cost(requested[start], requested[end - 1], end - start);
+                        start = end;
+                    }
+                }
+                assert!(total <= cost);
+            }
+        }
+
+        // Isolated elements are proven individually, while neighbouring ones share a range proof
+        let batch = mmr
+            .proof_batch_optimal(&[positions[0], positions[199], positions[101], positions[100]])
+            .unwrap();
+        let strategies: Vec<_> = batch.iter().map(|(strategy, _)| *strategy).collect();
+        assert_eq!(
+            strategies,
+            vec![
+                ProofStrategy::Individual(positions[0]),
+                ProofStrategy::Range(positions[100], positions[101]),
+                ProofStrategy::Individual(positions[199]),
+            ]
+        );
+        assert!(mmr.proof_batch_optimal(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::new();
+        for i in 0u8..100 {
+            positions.push(mmr.add(&Digest::from(vec![i; 32])));
+        }
+        let mut pruned = mmr.clone_pruned();
+        let mut pruned_positions = Vec::new();
+        for i in 100u8..120 {
+            pruned_positions.push(pruned.add(&Digest::from(vec![i; 32])));
+        }
+
+        for (mut mmr, positions) in [(mmr, positions), (pruned, pruned_positions)] {
+            let bytes = mmr.serialize();
+            let mut restored = Mmr::<Sha256>::deserialize(&bytes, Scheme::default()).unwrap();
+            assert_eq!(restored.size(), mmr.size());
+            assert_eq!(restored.oldest_retained_pos(), mmr.oldest_retained_pos());
+            assert_eq!(restored.root_hash(), mmr.root_hash());
+            for pos in positions.iter() {
+                assert_eq!(restored.proof(*pos).unwrap(), mmr.proof(*pos).unwrap());
+            }
+            assert_eq!(restored.serialize(), bytes);
+
+            // the restored MMR should continue to track the original
+            let element = Digest::from(vec![255u8; 32]);
+            assert_eq!(restored.add(&element), mmr.add(&element));
+            assert_eq!(restored.root_hash(), mmr.root_hash());
+
+            // the node count must match the length of the input
+            for len in [0, 20, bytes.len() - 1] {
+                assert!(matches!(
+                    Mmr::<Sha256>::deserialize(&bytes[..len], Scheme::default()),
+                    Err(Error::InvalidEncoding(_))
+                ));
+            }
+            let mut extended = bytes.clone();
+            extended.extend_from_slice(&[0u8; 32]);
+            assert!(matches!(
+                Mmr::<Sha256>::deserialize(&extended, Scheme::default()),
+                Err(Error::InvalidEncoding(_))
+            ));
+        }
+
+        // an unknown version, an invalid size, or a missing pinned node is rejected
+        let mut mmr = Mmr::<Sha256>::new();
+        mmr.add(&Digest::from(vec![0u8; 32]));
+        mmr.add(&Digest::from(vec![1u8; 32]));
+        let mut bytes = mmr.serialize();
+        bytes[0] = super::SERIALIZATION_VERSION + 1;
+        assert_eq!(
+            Mmr::<Sha256>::deserialize(&bytes, Scheme::default()).err(),
+            Some(Error::UnsupportedVersion(super::SERIALIZATION_VERSION + 1))
+        );
+        let header = 1 + 1 + 32;
+        let mut bytes = mmr.serialize();
+        bytes[header..header + 8].copy_from_slice(&2u64.to_be_bytes());
+        assert_eq!(
+            Mmr::<Sha256>::deserialize(&bytes, Scheme::default()).err(),
+            Some(Error::InvalidSize(2))
+        );
+        let mut bytes = mmr.clone_pruned().serialize();
+        bytes[header + 16..header + 20].copy_from_slice(&0u32.to_be_bytes());
+        bytes.drain(header + 20..header + 20 + 8 + 32);
+        assert_eq!(
+            Mmr::<Sha256>::deserialize(&bytes, Scheme::default()).err(),
+            Some(Error::ElementPruned(2))
+        );
+    }
+
+    #[test]
+    fn test_leaf_hashes() {
+        for count in [0u8, 1, 11, 100] {
+            let mut mmr = Mmr::<Sha256>::new();
+            let mut hasher = Sha256::new();
+            let mut expected = Vec::new();
+            for i in 0..count {
+                let element = Digest::from(vec![i; 32]);
+                let pos = mmr.add(&element);
+                expected.push(Hasher::new(&mut hasher).leaf_hash(pos, &element));
+            }
+            let leaves = mmr.export_leaf_hashes().unwrap();
+            assert_eq!(leaves, expected);
+
+            // importing the leaf hashes should rebuild every node of the original
+            let mut imported = Mmr::<Sha256>::import_leaf_hashes(&leaves, Scheme::default());
+            assert_eq!(imported.nodes, mmr.nodes);
+            assert_eq!(imported.root_hash(), mmr.root_hash());
+
+            // the imported MMR should continue to track the original
+            let element = Digest::from(vec![255u8; 32]);
+            assert_eq!(imported.add(&element), mmr.add(&element));
+            assert_eq!(imported.root_hash(), mmr.root_hash());
+        }
+
+        // leaf hashes can't be exported once pruned
+        let mut mmr = Mmr::<Sha256>::new();
+        mmr.add(&Digest::from(vec![0u8; 32]));
+        assert_eq!(
+            mmr.clone_pruned().export_leaf_hashes(),
+            Err(Error::ElementPruned(0))
+        );
+    }
+    #[test]
+    fn test_witness_for_peak() {
+        let mut mmr = Mmr::<Sha256>::new();
+        assert_eq!(mmr.witness_for_peak(0), None);
+        for i in 0u8..50 {
+            mmr.add(&Digest::from(vec![i; 32]));
+            let size = mmr.size();
+            let root_hash = mmr.root_hash();
+            let peaks = mmr.peak_hashes();
+            assert_eq!(mmr.witness_for_peak(peaks.len()), None);
+
+            // reconstruct the root from each peak and its witness
+            for (peak_idx, (_, peak)) in peaks.iter().enumerate() {
+                let mut witness = mmr.witness_for_peak(peak_idx).unwrap();
+                assert_eq!(witness.len(), peaks.len() - 1);
+                witness.insert(peak_idx, peak.clone());
+                let mut hasher = Sha256::default();
+                hasher.update(&[ROOT_TAG]);
+                hasher.update(&size.to_be_bytes());
+                for hash in witness.iter() {
+                    hasher.update(hash);
+                }
+                assert_eq!(hasher.finalize(), root_hash);
+
+                // a different peak hash should not reconstruct the root
+                witness[peak_idx] = Digest::from(vec![0u8; 32]);
+                let mut hasher = Sha256::default();
+                hasher.update(&[ROOT_TAG]);
+                hasher.update(&size.to_be_bytes());
+                for hash in witness.iter() {
+                    hasher.update(hash);
+                }
+                assert_ne!(hasher.finalize(), root_hash);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clone_pruned() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut old_positions = Vec::new();
+        for i in 0u8..20 {
+            old_positions.push(mmr.add(&Digest::from(vec![i; 32])));
+        }
+        let mut pruned = mmr.clone_pruned();
+        assert_eq!(pruned.size(), mmr.size());
+        assert_eq!(pruned.oldest_retained_pos(), mmr.size());
+        assert_eq!(pruned.retained_nodes(), mmr.peak_positions().len());
+        assert_eq!(pruned.root_hash(), mmr.root_hash());
+        assert_eq!(pruned.checkpoint(), mmr.checkpoint());
+
+        // proofs for old elements require pruned nodes
+        for pos in old_positions.iter() {
+            assert!(matches!(pruned.proof(*pos), Err(Error::ElementPruned(_))));
+        }
+
+        // the pruned copy can keep adding elements and proving them
+        let mut new_positions = Vec::new();
+        for i in 20u8..50 {
+            let element = Digest::from(vec![i; 32]);
+            let pos = pruned.add(&element);
+            assert_eq!(pos, mmr.add(&element));
+            assert_eq!(pruned.root_hash(), mmr.root_hash());
+            new_positions.push(pos);
+        }
+        for pos in new_positions.iter() {
+            assert_eq!(pruned.proof(*pos).unwrap(), mmr.proof(*pos).unwrap());
+        }
+        assert_eq!(
+            pruned
+                .range_proof(new_positions[0], new_positions[29])
+                .unwrap(),
+            mmr.range_proof(new_positions[0], new_positions[29])
+                .unwrap()
+        );
+        assert!(matches!(
+            pruned.range_proof(old_positions[19], new_positions[0]),
+            Err(Error::ElementPruned(_))
+        ));
+    }
+
+    #[test]
+    fn test_write() {
+        let elements: Vec<Digest> = (0u8..100).map(|i| Digest::from(vec![i; 32])).collect();
+        let mut reference = Mmr::<Sha256>::from(elements.clone());
+
+        // copy all elements at once
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut reader = Cursor::new(elements.concat());
+        assert_eq!(io::copy(&mut reader, &mut mmr).unwrap(), 3200);
+        mmr.flush().unwrap();
+        assert_eq!(mmr.root_hash(), reference.root_hash());
+
+        // write in chunks that don't align with element boundaries
+        let mut mmr = Mmr::<Sha256>::new();
+        let bytes = elements.concat();
+        for chunk in bytes.chunks(7) {
+            assert_eq!(mmr.write(chunk).unwrap(), chunk.len());
+        }
+        mmr.flush().unwrap();
+        assert_eq!(mmr.root_hash(), reference.root_hash());
+    }
+
+    #[test]
+    fn test_write_partial_element() {
+        let mut mmr = Mmr::<Sha256>::new();
+        mmr.write_all(&[1u8; 40]).unwrap();
+        assert_eq!(mmr.size(), 1);
+
+        // flushing a partial element should fail without dropping it
+        let err = mmr.flush().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        mmr.write_all(&[2u8; 24]).unwrap();
+        mmr.flush().unwrap();
+        assert_eq!(mmr.size(), 3);
+
+        let mut reference = Mmr::<Sha256>::new();
+        reference.add(&Digest::from(vec![1u8; 32]));
+        let mut second = vec![1u8; 8];
+        second.extend_from_slice(&[2u8; 24]);
+        reference.add(&Digest::from(second));
+        assert_eq!(mmr.root_hash(), reference.root_hash());
+    }
+
+    #[test]
+    fn test_proof_generator() {
+        const ELEMENTS: u8 = 100;
+        const READERS: usize = 4;
+
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0..ELEMENTS {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let generator = mmr.proof_generator();
+        let root = mmr.root_hash();
+        assert_eq!(generator.size(), mmr.size());
+        assert_eq!(generator.root_hash(), root);
+        assert_eq!(generator.checkpoint(), &mmr.checkpoint());
+
+        // readers serve proofs from the snapshot while the writer keeps adding elements
+        std::thread::scope(|s| {
+            for reader in 0..READERS {
+                let generator = generator.clone();
+                let (elements, positions) = (&elements, &positions);
+                s.spawn(move || {
+                    let mut hasher = Sha256::default();
+                    for (element, pos) in elements.iter().zip(positions).skip(reader) {
+                        let proof = generator.proof(*pos).unwrap();
+                        assert_eq!(proof.size, generator.size());
+                        assert!(proof.verify_element_inclusion(
+                            element,
+                            *pos,
+                            &generator.root_hash(),
+                            &mut hasher
+                        ));
+                    }
+                    let proof = generator.range_proof(positions[10], positions[90]).unwrap();
+                    assert!(proof.verify_range_inclusion(
+                        &elements[10..=90],
+                        positions[10],
+                        positions[90],
+                        &generator.root_hash(),
+                        &mut hasher
+                    ));
+                });
+            }
+            for i in ELEMENTS..2 * ELEMENTS {
+                mmr.add(&Digest::from(vec![i; 32]));
+            }
+        });
+
+        // the old snapshot is unaffected, and a new one reflects the additions
+        assert_ne!(mmr.root_hash(), root);
+        assert_eq!(generator.root_hash(), root);
+        assert_eq!(
+            generator.proof(positions[7]).unwrap(),
+            mmr.historical_range_proof(generator.size(), positions[7], positions[7])
+                .unwrap()
+        );
+        let latest = mmr.proof_generator();
+        assert_eq!(latest.root_hash(), mmr.root_hash());
+        for pos in positions.iter() {
+            assert_eq!(latest.proof(*pos).unwrap(), mmr.proof(*pos).unwrap());
+        }
+
+        // nodes pruned before the snapshot was taken remain unavailable
+        let mut pruned = mmr.clone_pruned();
+        pruned.add(&elements[0]);
+        let generator = pruned.proof_generator();
+        assert_eq!(generator.root_hash(), pruned.root_hash());
+        assert!(matches!(
+            generator.proof(positions[0]),
+            Err(Error::ElementPruned(_))
+        ));
+        assert_eq!(
+            generator.proof(mmr.size()).unwrap(),
+            pruned.proof(mmr.size()).unwrap()
+        );
+
+        // proofs beyond the snapshot are rejected
+        assert_eq!(
+            generator.proof(generator.size()).err(),
+            Some(Error::InvalidPos(generator.size()))
+        );
+    }
+
+    #[test]
+    fn test_keyed_scheme() {
+        let elements: Vec<_> = (0u8..11).map(|i| Digest::from(vec![i; 32])).collect();
+        let mut mmr = Mmr::<Sha256>::new_keyed(b"commonware");
+        let mut other = Mmr::<Sha256>::new_keyed(b"other");
+        let mut unkeyed = Mmr::<Sha256>::new();
+        assert_eq!(mmr.scheme(), &Scheme::keyed(b"commonware"));
+        let positions: Vec<_> = elements.iter().map(|e| mmr.add(e)).collect();
+        for element in elements.iter() {
+            other.add(element);
+            unkeyed.add(element);
+        }
+
+        // the same elements produce unrelated roots under different keys
+        let root_hash = mmr.root_hash();
+        assert_eq!(
+            hex(&root_hash),
+            "aacfdb4f149090990019cfd08d57c4583b1390440fd2628599a3902aa2755e72"
+        );
+        assert_ne!(root_hash, other.root_hash());
+        assert_ne!(root_hash, unkeyed.root_hash());
+        assert_ne!(
+            Mmr::<Sha256>::new_keyed(b"a").root_hash(),
+            Mmr::<Sha256>::new().root_hash()
+        );
+
+        // proofs only verify under the key they were generated with
+        let mut hasher = Sha256::default();
+        let mut proof = mmr.proof(positions[8]).unwrap();
+        assert!(proof.verify_element_inclusion(
+            &elements[8],
+            positions[8],
+            &root_hash,
+            &mut hasher
+        ));
+        let mut range_proof = mmr.range_proof(positions[2], positions[9]).unwrap();
+        assert!(range_proof.verify_range_inclusion(
+            &elements[2..=9],
+            positions[2],
+            positions[9],
+            &root_hash,
+            &mut hasher
+        ));
+        for scheme in [Scheme::keyed(b"other"), Scheme::keyed(b""), Scheme::Tagged] {
+            proof.scheme = scheme.clone();
+            assert!(!proof.verify_element_inclusion(
+                &elements[8],
+                positions[8],
+                &root_hash,
+                &mut hasher
+            ));
+            range_proof.scheme = scheme;
+            assert!(!range_proof.verify_range_inclusion(
+                &elements[2..=9],
+                positions[2],
+                positions[9],
+                &root_hash,
+                &mut hasher
+            ));
+        }
+
+        // a deserialized proof verifies only under the key the verifier decodes it with
+        let bytes = proof.to_bytes();
+        for scheme in [Scheme::keyed(b"other"), Scheme::Tagged] {
+            let decoded = Proof::from_bytes::<Sha256>(&bytes, scheme).unwrap();
+            assert!(!decoded.verify_element_inclusion(
+                &elements[8],
+                positions[8],
+                &root_hash,
+                &mut hasher
+            ));
+        }
+        let decoded = Proof::from_bytes::<Sha256>(&bytes, Scheme::keyed(b"commonware")).unwrap();
+        assert!(decoded.verify_element_inclusion(
+            &elements[8],
+            positions[8],
+            &root_hash,
+            &mut hasher
+        ));
+    }
+
+    #[test]
+    fn test_keyed_round_trips() {
+        let scheme = Scheme::keyed(b"commonware");
+        let mut mmr = Mmr::<Sha256>::new_with_scheme(scheme.clone());
+        let elements: Vec<_> = (0u8..20).map(|i| Digest::from(vec![i; 32])).collect();
+        for element in &elements {
+            mmr.add(element);
+        }
+        let root_hash = mmr.root_hash();
+        let others = [Scheme::default(), Scheme::keyed(b"other")];
+
+        // formats recording the scheme restore it, and reject any other
+        let bytes = mmr.serialize();
+        let mut restored = Mmr::<Sha256>::deserialize(&bytes, scheme.clone()).unwrap();
+        assert_eq!(restored.scheme(), &scheme);
+        assert_eq!(restored.root_hash(), root_hash);
+        let mut export = Vec::new();
+        mmr.export(&mut export).unwrap();
+        let mut restored = Mmr::<Sha256>::import(&export[..], scheme.clone(), None).unwrap();
+        assert_eq!(restored.scheme(), &scheme);
+        assert_eq!(restored.root_hash(), root_hash);
+        for other in others.clone() {
+            assert_eq!(
+                Mmr::<Sha256>::deserialize(&bytes, other.clone()).err(),
+                Some(Error::SchemeMismatch)
+            );
+            assert_eq!(
+                Mmr::<Sha256>::import(&export[..], other, None).err(),
+                Some(Error::SchemeMismatch)
+            );
+        }
+
+        // formats that don't record the scheme restore the one supplied
+        let mut buf = Vec::new();
+        mmr.to_compact_serialised(&mut buf).unwrap();
+        let mut restored =
+            Mmr::<Sha256>::from_compact_serialised(&mut Cursor::new(&buf), scheme.clone()).unwrap();
+        assert_eq!(restored.root_hash(), root_hash);
+        let mut buf = Vec::new();
+        mmr.dump_peaks_to_writer(&mut buf).unwrap();
+        let mut accumulator =
+            Mmr::<Sha256>::load_peaks_from_reader(&mut Cursor::new(&buf), scheme.clone()).unwrap();
+        assert_eq!(accumulator.root_hash(), root_hash);
+        let leaves = mmr.export_leaf_hashes().unwrap();
+        let mut restored = Mmr::<Sha256>::import_leaf_hashes(&leaves, scheme.clone());
+        assert_eq!(restored.root_hash(), root_hash);
+        let mut hasher = Sha256::new();
+        let streamed = Mmr::<Sha256>::root_hash_streaming(
+            Cursor::new(elements.concat()),
+            elements.len(),
+            &mut hasher,
+            scheme,
+        );
+        assert_eq!(streamed.unwrap(), root_hash);
+    }
+
+    #[test]
+    fn test_untagged_scheme() {
+        let elements: Vec<_> = (0u8..11).map(|i| Digest::from(vec![i; 32])).collect();
+        let mut tagged = Mmr::<Sha256>::new();
+        let mut untagged = Mmr::<Sha256>::new_with_scheme(Scheme::Untagged);
+        assert_eq!(tagged.scheme(), &Scheme::Tagged);
+        assert_eq!(untagged.scheme(), &Scheme::Untagged);
+        let positions: Vec<_> = elements.iter().map(|e| untagged.add(e)).collect();
+        for element in elements.iter() {
+            tagged.add(element);
+        }
+
+        // the untagged MMR reproduces the legacy hashes, which omit the domain tags
+        let mut hasher = Sha256::default();
+        hasher.update(&0u64.to_be_bytes());
+        hasher.update(&elements[0]);
+        let leaf_0 = hasher.finalize();
+        hasher.update(&1u64.to_be_bytes());
+        hasher.update(&elements[1]);
+        let leaf_1 = hasher.finalize();
+        hasher.update(&2u64.to_be_bytes());
+        hasher.update(&leaf_0);
+        hasher.update(&leaf_1);
+        assert_eq!(untagged.node_at(2).unwrap(), &hasher.finalize());
+        hasher.update(&untagged.size().to_be_bytes());
+        for (_, hash) in untagged.peak_hashes() {
+            hasher.update(&hash);
+        }
+        assert_eq!(hasher.finalize(), untagged.root_hash());
+        assert_ne!(tagged.root_hash(), untagged.root_hash());
+
+        // proofs carry the scheme of the MMR, and only verify under it
+        let root_hash = untagged.root_hash();
+        let mut proof = untagged.proof(positions[8]).unwrap();
+        assert_eq!(proof.scheme, Scheme::Untagged);
+        assert!(proof.verify_element_inclusion(
+            &elements[8],
+            positions[8],
+            &root_hash,
+            &mut hasher
+        ));
+        proof.scheme = Scheme::Tagged;
+        assert!(!proof.verify_element_inclusion(
+            &elements[8],
+            positions[8],
+            &root_hash,
+            &mut hasher
+        ));
+
+        // derived MMRs keep the scheme
+        let mut pruned = untagged.clone_pruned();
+        assert_eq!(pruned.root_hash(), root_hash);
+        assert_eq!(untagged.clone_peaks_only().root_hash(), root_hash);
+        assert_eq!(untagged.checkpoint().root, root_hash);
+        let generator = untagged.proof_generator();
+        assert_eq!(generator.root_hash(), root_hash);
+        assert_eq!(
+            generator.proof(positions[8]).unwrap().scheme,
+            Scheme::Untagged
+        );
+        let merkle_proof = untagged.to_merkle_proof(positions[8]).unwrap();
+        assert!(merkle_proof.verify(&root_hash, &mut hasher));
+        assert!(untagged.verify_internal_node(2, &mut hasher).unwrap());
+        assert!(tagged.verify_internal_node(2, &mut hasher).unwrap());
+        assert_ne!(tagged.node_at(2), untagged.node_at(2));
+    }
+
+    /// Exercise the MMR, its proofs, and its encodings end to end with the hasher `H`, checking
+    /// that nothing assumes a particular digest length.
+    fn run_suite<H: CHasher>() {
+        let mut hasher = H::new();
+        let mut mmr = Mmr::<H>::new();
+        assert_eq!(mmr.root_hash(), Mmr::<H>::empty_root(&mut hasher));
+        let elements: Vec<_> = (0u8..50).map(|i| Digest::from(vec![i; H::len()])).collect();
+        let positions: Vec<_> = elements.iter().map(|e| mmr.add(e)).collect();
+        let root_hash = mmr.root_hash();
+        assert_eq!(root_hash.len(), H::len());
+        for pos in (0..mmr.size()).filter(|pos| pos_to_height(*pos) > 0) {
+            assert!(mmr.verify_internal_node(pos, &mut hasher).unwrap());
+        }
+
+        // proofs verify (only for their own elements) and survive encoding
+        for (i, pos) in positions.iter().enumerate() {
+            let proof = mmr.proof(*pos).unwrap();
+            proof.sanity_check(H::len()).unwrap();
+            assert!(proof.verify_element_inclusion(&elements[i], *pos, &root_hash, &mut hasher));
+            let other = &elements[(i + 1) % elements.len()];
+            assert!(!proof.verify_element_inclusion(other, *pos, &root_hash, &mut hasher));
+            assert_eq!(
+                Proof::from_bytes::<H>(&proof.to_bytes(), Scheme::default()).unwrap(),
+                proof
+            );
+        }
+        let (start, end) = (positions[3], positions[41]);
+        let proof = mmr.range_proof(start, end).unwrap();
+        assert!(proof.verify_range_inclusion(
+            &elements[3..=41],
+            start,
+            end,
+            &root_hash,
+            &mut hasher
+        ));
+
+        // hashes of any other length are rejected
+        let bytes = proof.to_bytes();
+        assert!(Proof::from_bytes::<H>(&bytes[..bytes.len() - 1], Scheme::default()).is_err());
+        assert_eq!(
+            proof.sanity_check(H::len() + 1),
+            Err(Error::InvalidDigestLength(H::len() + 1, H::len()))
+        );
+
+        // every encoding of the MMR restores its root
+        let mut restored = Mmr::<H>::deserialize(&mmr.serialize(), Scheme::default()).unwrap();
+        assert_eq!(restored.root_hash(), root_hash);
+        let mut buf = Vec::new();
+        mmr.to_compact_serialised(&mut buf).unwrap();
+        assert_eq!(buf.len() as u64, 8 + H::len() as u64 * mmr.size());
+        let mut restored =
+            Mmr::<H>::from_compact_serialised(&mut Cursor::new(&buf), Scheme::default()).unwrap();
+        assert_eq!(restored.root_hash(), root_hash);
+        let mut buf = Vec::new();
+        mmr.export(&mut buf).unwrap();
+        let mut restored =
+            Mmr::<H>::import(Cursor::new(&buf), Scheme::default(), Some(&root_hash)).unwrap();
+        assert_eq!(restored.root_hash(), root_hash);
+        let mut buf = Vec::new();
+        mmr.dump_peaks_to_writer(&mut buf).unwrap();
+        let mut accumulator =
+            Mmr::<H>::load_peaks_from_reader(&mut Cursor::new(&buf), Scheme::default()).unwrap();
+        assert_eq!(accumulator.root_hash(), root_hash);
+        let checkpoint = mmr.checkpoint();
+        assert_eq!(
+            Checkpoint::deserialize::<H>(&checkpoint.serialize()),
+            Some(checkpoint)
+        );
+        let leaves = elements.concat();
+        let streamed = Mmr::<H>::root_hash_streaming(
+            Cursor::new(&leaves),
+            elements.len(),
+            &mut hasher,
+            Scheme::default(),
+        );
+        assert_eq!(streamed.unwrap(), root_hash);
+        assert_eq!(Mmr::<H>::build_from(&elements).root_hash(), root_hash);
+
+        // pruned copies continue to track the MMR
+        let mut pruned = mmr.clone_pruned();
+        let mut peaks_only = mmr.clone_peaks_only();
+        let element = Digest::from(vec![255u8; H::len()]);
+        let pos = mmr.add(&element);
+        assert_eq!(pruned.add(&element), pos);
+        assert_eq!(peaks_only.add(&element), pos);
+        let root_hash = mmr.root_hash();
+        assert_eq!(pruned.root_hash(), root_hash);
+        assert_eq!(peaks_only.root_hash(), root_hash);
+        assert_eq!(pruned.proof(pos).unwrap(), mmr.proof(pos).unwrap());
+    }
+
+    #[test]
+    fn test_suite_sha256() {
+        run_suite::<Sha256>();
+    }
+
+    #[test]
+    fn test_suite_blake3() {
+        run_suite::<Blake3>();
+    }
+
+    #[test]
+    fn test_suite_wide_digest() {
+        run_suite::<Wide>();
+    }
+
+    #[test]
+    fn test_pruning_policy() {
+        let mut hasher = Sha256::default();
+        let mut mmr = Mmr::<Sha256>::with_pruning_policy(PruningPolicy::RetainLastN(100));
+        let mut reference = Mmr::<Sha256>::new();
+        let elements: Vec<_> = (0u64..10_000)
+            .map(|i| Digest::from(i.to_be_bytes().repeat(4)))
+            .collect();
+        let mut positions = Vec::new();
+        for element in elements.iter() {
+            let pos = mmr.add(element);
+            assert_eq!(pos, reference.add(element));
+            positions.push(pos);
+
+            // the nodes of the last 100 elements (about 200) are retained, plus at most as many
+            // again since the last prune, plus the pinned peaks
+            assert!(mmr.retained_nodes() <= 2 * 200 + 64);
+        }
+        assert!(mmr.oldest_retained_pos() > 0);
+        let root_hash = mmr.root_hash();
+        assert_eq!(root_hash, reference.root_hash());
+
+        // the last 100 elements can be proven, but not those outside the window
+        for (element, pos) in elements.iter().zip(positions.iter()).skip(9_900) {
+            let proof = mmr.proof(*pos).unwrap();
+            assert_eq!(proof, reference.proof(*pos).unwrap());
+            assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+        }
+        assert!(matches!(
+            mmr.proof(positions[0]),
+            Err(Error::ElementPruned(_))
+        ));
+        assert!(matches!(
+            mmr.range_proof(positions[0], positions[50]),
+            Err(Error::ElementPruned(_))
+        ));
+
+        // retaining no elements keeps only the pinned peaks
+        let mut mmr = Mmr::<Sha256>::with_pruning_policy(PruningPolicy::RetainLastN(0));
+        for element in elements.iter().take(1_000) {
+            mmr.add(element);
+            assert_eq!(mmr.retained_nodes(), mmr.peak_positions().len());
+        }
+        assert_eq!(
+            mmr.root_hash(),
+            Mmr::<Sha256>::build_from(&elements[..1_000]).root_hash()
+        );
+
+        // nodes before a fixed position are pruned once the MMR reaches it
+        let mut mmr = Mmr::<Sha256>::with_pruning_policy(PruningPolicy::RetainAfter(100));
+        for element in elements.iter().take(50) {
+            mmr.add(element);
+        }
+        assert_eq!(mmr.oldest_retained_pos(), 0);
+        for element in elements.iter().take(1_000).skip(50) {
+            mmr.add(element);
+        }
+        assert_eq!(mmr.oldest_retained_pos(), 98);
+        let root_hash = mmr.root_hash();
+        assert_eq!(
+            root_hash,
+            Mmr::<Sha256>::build_from(&elements[..1_000]).root_hash()
+        );
+        let pos = node_count(51);
+        let proof = mmr.proof(pos).unwrap();
+        assert!(proof.verify_element_inclusion(&elements[51], pos, &root_hash, &mut hasher));
+        assert!(matches!(mmr.proof(0), Err(Error::ElementPruned(_))));
     }
 }
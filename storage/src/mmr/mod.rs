@@ -23,6 +23,10 @@
 //! The "root hash" of an MMR is the result of hashing together the size of the MMR and the hashes
 //! of every peak in decreasing order of height.
 //!
+//! An empty MMR has no peaks, so its root hash is the hash of its size (0) alone (see
+//! `Mmr::empty_root`). No range of elements can be proven within it, but a proof of size 0 with no
+//! hashes proves that a root hash is that of an empty MMR (see `Proof::verify_empty`).
+//!
 //! # Examples
 //!
 //! (Borrowed from <https://docs.grin.mw/wiki/chain-state/merkle-mountain-range/>): After adding 11
@@ -62,8 +66,164 @@
 //!   Hash(18, element_18)                                      // third peak
 //! )
 //! ```
+//!
+//! Under the default `Scheme::Tagged`, the input of every leaf, internal node, and root hash is
+//! additionally prefixed with a distinct one-byte tag (0x00, 0x01, and 0x02 respectively), so that
+//! no hash of one kind can be passed off as another (e.g. an internal node as a leaf whose element
+//! is the concatenation of its children). `Scheme::Untagged` omits the tags, reproducing the
+//! hashes of MMRs built before they were introduced, and `Scheme::Keyed` follows each tag with a
+//! key (e.g. identifying a chain or application), so that MMRs built with different keys have
+//! unrelated roots even if they hold the same elements.
 
+use thiserror::Error;
+
+pub mod accumulator;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(feature = "rkyv")]
+pub mod archived;
+pub mod cache;
+pub mod compat;
+pub mod export;
 mod hasher;
-mod iterator;
+pub mod iterator;
+pub mod log;
 pub mod mem;
+pub mod recording;
+pub mod resolver;
+pub mod shared;
+pub mod stats;
+pub mod sync;
+pub mod util;
+pub mod value;
 pub mod verification;
+
+pub use hasher::{Hasher, Scheme};
+pub use util::{leaf_count, node_count};
+
+cfg_if::cfg_if! {
+    if #[cfg(not(target_arch = "wasm32"))] {
+        pub mod journaled;
+        pub mod readonly;
+    }
+}
+
+/// Errors that can occur when interacting with an MMR.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("invalid size: {0}")]
+    InvalidSize(u64),
+    #[error("invalid proof length: {0}")]
+    InvalidProofLength(usize),
+    #[error("invalid digest length: expected={0} actual={1}")]
+    InvalidDigestLength(usize, usize),
+    #[error("invalid proof encoding: {0} bytes")]
+    InvalidProofEncoding(usize),
+    #[error("element pruned: {0}")]
+    ElementPruned(u64),
+    #[error("elements not retained")]
+    ElementsNotRetained,
+    #[error("chunk gap: missing node {0}")]
+    ChunkGap(u64),
+    #[error("chunk overlap: node {0} already synced")]
+    ChunkOverlap(u64),
+    #[error("invalid chunk size: {0}")]
+    InvalidChunkSize(u64),
+    #[error("invalid node: {0}")]
+    InvalidNode(u64),
+    #[error("root mismatch")]
+    RootMismatch,
+    #[error("invalid position: {0}")]
+    InvalidPos(u64),
+    #[error("invalid range: {0}..={1}")]
+    InvalidRange(u64, u64),
+    #[error("index out of range: {0}")]
+    IndexOutOfRange(u64),
+    #[error("invalid bitmap length: {0}")]
+    InvalidBitmapLength(usize),
+    #[error("invalid archive")]
+    InvalidArchive,
+    #[error("invalid encoding: {0} bytes")]
+    InvalidEncoding(usize),
+    #[error("unsupported version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("invalid magic")]
+    InvalidMagic,
+    #[error("hasher mismatch")]
+    HasherMismatch,
+    #[error("scheme mismatch")]
+    SchemeMismatch,
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+    #[error("io error: {0}")]
+    Io(std::io::ErrorKind),
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::mmr::mem::Mmr;
+    use commonware_cryptography::{Blake3, Digest, Hasher as CHasher, Sha256};
+    use commonware_utils::hex;
+    use rand::{CryptoRng, Rng};
+
+    /// A hasher with 64-byte digests (the SHA-256 digest of the input followed by its BLAKE3
+    /// digest), for testing MMRs over digests that aren't 32 bytes long.
+    #[derive(Clone, Default)]
+    pub(crate) struct Wide {
+        sha256: Sha256,
+        blake3: Blake3,
+    }
+
+    impl CHasher for Wide {
+        fn new() -> Self {
+            Self::default()
+        }
+
+        fn update(&mut self, message: &[u8]) {
+            self.sha256.update(message);
+            self.blake3.update(message);
+        }
+
+        fn finalize(&mut self) -> Digest {
+            [self.sha256.finalize(), self.blake3.finalize()]
+                .concat()
+                .into()
+        }
+
+        fn reset(&mut self) {
+            self.sha256.reset();
+            self.blake3.reset();
+        }
+
+        fn validate(digest: &Digest) -> bool {
+            digest.len() == Self::len()
+        }
+
+        fn len() -> usize {
+            Sha256::len() + Blake3::len()
+        }
+
+        fn random<R: Rng + CryptoRng>(rng: &mut R) -> Digest {
+            let mut digest = vec![0u8; Self::len()];
+            rng.fill_bytes(&mut digest);
+            digest.into()
+        }
+    }
+
+    #[test]
+    fn test_blake3_root_vector() {
+        // The example MMR with 11 elements (19 nodes) from the module documentation
+        let mut mmr = Mmr::<Blake3>::new();
+        for i in 0u8..11 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        assert_eq!(
+            hex(&mmr.root_hash()),
+            "2bac88ecd9dd20cbbe9dc017ffb4ae25d850167cfa44fc5b454ec8ca73d65bf9"
+        );
+        assert_eq!(
+            hex(&Mmr::<Blake3>::empty_root(&mut Blake3::new())),
+            "c837765444815de6db1a10b88c56b546eb45d640b9323beb30f37038174a2c97"
+        );
+    }
+}
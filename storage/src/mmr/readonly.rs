@@ -0,0 +1,554 @@
+//! A read-only view of an MMR persisted by a journaled `Mmr`.
+//!
+//! `MmrReader` serves proofs directly from the persisted sections using positioned reads, without
+//! loading them into memory or ever writing to storage. Only the peaks (needed for the root hash)
+//! are read when the reader is created, and each proof reads only the O(log n) nodes it contains.
+//! If the `Mmr` has been pruned, proofs can only be generated for retained elements. The elements
+//! of a batch being appended by `Mmr::append_batch_atomic` (or interrupted before it was committed)
+//! are ignored.
+
+use crate::metadata::Metadata;
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{largest_valid_size, PeakIterator};
+use crate::mmr::journaled::{
+    check_scheme, load_compacted, load_intent, load_pruned, open_sections, read_node, stored_nodes,
+    Config, Error, Layout, Section,
+};
+use crate::mmr::verification::{nodes_required_for_range_proof, Proof};
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use commonware_runtime::{Blob, Clock, Storage};
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+
+/// Implementation of `MmrReader`.
+pub struct MmrReader<B: Blob, H: CHasher> {
+    sections: BTreeMap<u64, Section<B>>,
+    layout: Layout,
+    size: u64,
+    root: Digest,
+    scheme: Scheme,
+
+    pruned_to_pos: u64,
+    pinned_nodes: HashMap<u64, Digest>,
+
+    _phantom_h: PhantomData<H>,
+}
+
+impl<B: Blob, H: CHasher> MmrReader<B, H> {
+    /// Open a reader over the nodes persisted by a journaled `Mmr` with the given `cfg` (the
+    /// `write_buffer`, `sync_policy`, `cache_entries`, `hot_nodes`, `root_index_interval`,
+    /// `verify_on_init`, `max_concurrent_reads`, `registry`, and `metrics_prefix` are ignored).
+    pub async fn open<E: Clock + Storage<B>>(runtime: &E, cfg: &Config) -> Result<Self, Error> {
+        // The metadata is read without creating or repairing any blob, as the writer may be in the
+        // middle of syncing it
+        let metadata = Metadata::read(runtime, &cfg.metadata_partition).await?;
+        check_scheme::<H>(&metadata, &cfg.scheme)?;
+        let (pruned_to_pos, pinned_nodes) = load_pruned(&metadata, H::len())?;
+        let layout = Layout::new(cfg.section_nodes, cfg.page_nodes, H::len());
+
+        // Stale sections (left by an interrupted compaction) are ignored rather than removed
+        let compacted = load_compacted(&metadata)?;
+        let (sections, _) = open_sections(runtime, &cfg.partition, &layout, compacted).await?;
+        let max_size = load_intent(&metadata, H::len())?.map_or(u64::MAX, |intent| intent.pre_size);
+        Self::from_sections(
+            sections,
+            layout,
+            pruned_to_pos,
+            pinned_nodes,
+            max_size,
+            cfg.scheme.clone(),
+        )
+        .await
+    }
+
+    /// Return a reader over the nodes stored in `sections` (keyed by section index), each holding
+    /// `section_nodes` nodes split into checksummed pages of `page_nodes` nodes, of an MMR pruned
+    /// to `pruned_to_pos` with the given `pinned_nodes` (keyed by position). Any incomplete tail
+    /// (from an interrupted write) is ignored rather than truncated. The nodes must have been hashed
+    /// with `scheme`.
+    pub async fn new(
+        sections: BTreeMap<u64, B>,
+        section_nodes: u64,
+        page_nodes: u64,
+        pruned_to_pos: u64,
+        pinned_nodes: HashMap<u64, Digest>,
+        scheme: Scheme,
+    ) -> Result<Self, Error> {
+        let sections = sections
+            .into_iter()
+            .map(|(section, blob)| (section, Section::new(blob)))
+            .collect();
+        let layout = Layout::new(section_nodes, page_nodes, H::len());
+        Self::from_sections(
+            sections,
+            layout,
+            pruned_to_pos,
+            pinned_nodes,
+            u64::MAX,
+            scheme,
+        )
+        .await
+    }
+
+    /// Return a reader over the nodes stored in `sections` (hashed with `scheme`), ignoring any
+    /// beyond the (valid) size `max_size`.
+    async fn from_sections(
+        sections: BTreeMap<u64, Section<B>>,
+        layout: Layout,
+        pruned_to_pos: u64,
+        pinned_nodes: HashMap<u64, Digest>,
+        max_size: u64,
+        scheme: Scheme,
+    ) -> Result<Self, Error> {
+        let oldest_section = pruned_to_pos / layout.section_nodes();
+        let stored = stored_nodes(&sections, &layout, oldest_section, None).await?;
+        let mut reader = Self {
+            sections,
+            layout,
+            size: largest_valid_size(stored).min(max_size),
+            root: Digest::new(),
+            scheme: scheme.clone(),
+
+            pruned_to_pos,
+            pinned_nodes,
+
+            _phantom_h: PhantomData,
+        };
+
+        // Compute the root hash from the peaks
+        let mut peaks = Vec::new();
+        for (peak_pos, _) in PeakIterator::new(reader.size) {
+            peaks.push(reader.get_node(peak_pos).await?);
+        }
+        let mut hasher = H::new();
+        reader.root = Hasher::with_scheme(&mut hasher, scheme).root_hash(reader.size, peaks.iter());
+        Ok(reader)
+    }
+
+    /// Return the total number of nodes in the MMR.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Return the root hash of the MMR.
+    pub fn root_hash(&self) -> Digest {
+        self.root.clone()
+    }
+
+    /// Return the position of the oldest node that has not been pruned.
+    pub fn pruned_to_pos(&self) -> u64 {
+        self.pruned_to_pos
+    }
+
+    /// Return the hash of the node at `pos`, verifying the checksum of the page containing it.
+    pub async fn get_node(&self, pos: u64) -> Result<Digest, Error> {
+        if pos >= self.size {
+            return Err(Error::ElementOutOfRange(pos));
+        }
+        if pos < self.pruned_to_pos {
+            return self
+                .pinned_nodes
+                .get(&pos)
+                .cloned()
+                .ok_or(Error::ElementPruned(pos));
+        }
+        read_node(&self.sections, &self.layout, pos, None).await
+    }
+
+    /// Return an inclusion proof for the specified element.
+    pub async fn proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        self.range_proof(element_pos, element_pos).await
+    }
+
+    /// Return an inclusion proof for the specified range of elements. The range is inclusive of
+    /// both endpoints.
+    pub async fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        if end_element_pos >= self.size {
+            return Err(Error::ElementOutOfRange(end_element_pos));
+        }
+        if start_element_pos < self.pruned_to_pos {
+            return Err(Error::ElementPruned(start_element_pos));
+        }
+        let positions =
+            nodes_required_for_range_proof(self.size, start_element_pos, end_element_pos);
+        let mut hashes = Vec::with_capacity(positions.len());
+        for pos in positions {
+            hashes.push(self.get_node(pos).await?);
+        }
+        Ok(Proof {
+            size: self.size,
+            hashes,
+            scheme: self.scheme.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MmrReader, Scheme};
+    use crate::mmr::journaled::{Config, Error, Mmr, SyncPolicy};
+    use crate::mmr::mem::Mmr as MemMmr;
+    use commonware_cryptography::{Digest, Sha256};
+    use commonware_macros::test_traced;
+    use commonware_runtime::{
+        deterministic::Executor, Blob, Clock, Error as RError, Runner, Storage,
+    };
+    use prometheus_client::registry::Registry;
+    use std::collections::{BTreeMap, HashMap};
+    use std::future::Future;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use std::time::{Duration, SystemTime};
+
+    /// Wraps a `Blob` and counts the reads and writes issued to it.
+    #[derive(Clone)]
+    struct CountingBlob<B: Blob> {
+        blob: B,
+        reads: Arc<AtomicUsize>,
+        writes: Arc<AtomicUsize>,
+    }
+
+    impl<B: Blob> Blob for CountingBlob<B> {
+        async fn len(&self) -> Result<u64, RError> {
+            self.blob.len().await
+        }
+
+        async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<(), RError> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.blob.read_at(buf, offset).await
+        }
+
+        async fn write_at(&self, buf: &[u8], offset: u64) -> Result<(), RError> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.blob.write_at(buf, offset).await
+        }
+
+        async fn truncate(&self, len: u64) -> Result<(), RError> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.blob.truncate(len).await
+        }
+
+        async fn sync(&self) -> Result<(), RError> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.blob.sync().await
+        }
+
+        async fn close(self) -> Result<(), RError> {
+            self.blob.close().await
+        }
+    }
+
+    /// Wraps a `Storage`, counting the blobs it creates (opened without already existing) and
+    /// removes, and the reads and writes issued to the blobs it opens.
+    #[derive(Clone)]
+    struct CountingStorage<E> {
+        inner: E,
+        creates: Arc<AtomicUsize>,
+        reads: Arc<AtomicUsize>,
+        writes: Arc<AtomicUsize>,
+    }
+
+    impl<E: Clock> Clock for CountingStorage<E> {
+        fn current(&self) -> SystemTime {
+            self.inner.current()
+        }
+
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+            self.inner.sleep(duration)
+        }
+
+        fn sleep_until(&self, deadline: SystemTime) -> impl Future<Output = ()> + Send + 'static {
+            self.inner.sleep_until(deadline)
+        }
+    }
+
+    impl<B: Blob, E: Storage<B>> Storage<CountingBlob<B>> for CountingStorage<E> {
+        async fn open(&self, partition: &str, name: &[u8]) -> Result<CountingBlob<B>, RError> {
+            let exists = match self.inner.scan(partition).await {
+                Ok(names) => names.iter().any(|stored| stored == name),
+                Err(_) => false,
+            };
+            if !exists {
+                self.creates.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(CountingBlob {
+                blob: self.inner.open(partition, name).await?,
+                reads: self.reads.clone(),
+                writes: self.writes.clone(),
+            })
+        }
+
+        async fn remove(&self, partition: &str, name: Option<&[u8]>) -> Result<(), RError> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.inner.remove(partition, name).await
+        }
+
+        async fn scan(&self, partition: &str) -> Result<Vec<Vec<u8>>, RError> {
+            self.inner.scan(partition).await
+        }
+    }
+
+    #[test_traced]
+    fn test_reader() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // Build an MMR with the journaled writer
+            let cfg = Config {
+                registry: Arc::new(Mutex::new(Registry::default())),
+                metrics_prefix: "mmr".to_string(),
+                partition: "test".to_string(),
+                metadata_partition: "test_metadata".to_string(),
+                write_buffer: 64,
+                section_nodes: 128,
+                page_nodes: 16,
+                sync_policy: SyncPolicy::Never,
+                write_behind: false,
+                cache_entries: 0,
+                hot_nodes: 0,
+                root_index_interval: 0,
+                verify_on_init: false,
+                max_concurrent_reads: 1,
+                scheme: Scheme::default(),
+            };
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            let mut elements = Vec::new();
+            for i in 0u64..1_000 {
+                let element = Digest::from(i.to_be_bytes().repeat(4));
+                reference.add(&element);
+                elements.push((mmr.add(&element).await.unwrap(), element));
+            }
+            let root_hash = mmr.root_hash();
+            let size = mmr.size();
+            mmr.close().await.unwrap();
+
+            // Open a reader over instrumented sections
+            let reads = Arc::new(AtomicUsize::new(0));
+            let writes = Arc::new(AtomicUsize::new(0));
+            let mut sections = BTreeMap::new();
+            for section in 0..size.div_ceil(cfg.section_nodes) {
+                let blob = CountingBlob {
+                    blob: context.open("test", &section.to_be_bytes()).await.unwrap(),
+                    reads: reads.clone(),
+                    writes: writes.clone(),
+                };
+                sections.insert(section, blob);
+            }
+            let section_count = sections.len();
+            let reader = MmrReader::<_, Sha256>::new(
+                sections,
+                cfg.section_nodes,
+                cfg.page_nodes,
+                0,
+                HashMap::new(),
+                cfg.scheme.clone(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(reader.size(), size);
+            assert_eq!(reader.root_hash(), root_hash);
+            // one read to verify the last page of each section, and one per peak
+            assert_eq!(reads.load(Ordering::Relaxed), section_count + 6);
+
+            // Proofs should only read the nodes they contain and verify against the writer's root
+            let mut hasher = Sha256::default();
+            for (pos, element) in elements.iter().step_by(37) {
+                let before = reads.load(Ordering::Relaxed);
+                let proof = reader.proof(*pos).await.unwrap();
+                let proof_reads = reads.load(Ordering::Relaxed) - before;
+                assert_eq!(proof_reads, proof.hashes.len());
+                assert!(proof_reads < 2 * (64 - size.leading_zeros() as usize));
+                assert_eq!(proof, reference.proof(*pos).unwrap());
+                assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+            }
+            let (start, end) = (elements[10].0, elements[900].0);
+            let proof = reader.range_proof(start, end).await.unwrap();
+            let range: Vec<Digest> = elements[10..=900].iter().map(|(_, e)| e.clone()).collect();
+            assert!(proof.verify_range_inclusion(&range, start, end, &root_hash, &mut hasher));
+
+            // Range proofs spanning many sections should match the writer
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            assert_eq!(proof, mmr.range_proof(start, end).await.unwrap());
+            assert_eq!(mmr.root_hash(), reader.root_hash());
+
+            // Nodes can be read directly, and out of range reads fail
+            for (peak_pos, peak) in reference.peak_hashes() {
+                assert_eq!(reader.get_node(peak_pos).await.unwrap(), peak);
+            }
+            assert!(matches!(
+                reader.get_node(size).await,
+                Err(Error::ElementOutOfRange(pos)) if pos == size
+            ));
+
+            // The reader never writes
+            assert_eq!(writes.load(Ordering::Relaxed), 0);
+        });
+    }
+
+    #[test_traced]
+    fn test_reader_open() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            // An empty partition yields an empty MMR
+            let cfg = Config {
+                registry: Arc::new(Mutex::new(Registry::default())),
+                metrics_prefix: "mmr".to_string(),
+                partition: "test".to_string(),
+                metadata_partition: "test_metadata".to_string(),
+                write_buffer: 1,
+                section_nodes: 4,
+                page_nodes: 2,
+                sync_policy: SyncPolicy::Never,
+                write_behind: false,
+                cache_entries: 0,
+                hot_nodes: 0,
+                root_index_interval: 0,
+                verify_on_init: false,
+                max_concurrent_reads: 1,
+                scheme: Scheme::default(),
+            };
+            let reader = MmrReader::<_, Sha256>::open(&context, &cfg).await.unwrap();
+            assert_eq!(reader.size(), 0);
+            assert_eq!(reader.root_hash(), MemMmr::<Sha256>::new().root_hash());
+
+            // The reader should ignore an incomplete tail without removing it
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            for i in 0u8..10 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+            }
+            mmr.close().await.unwrap();
+            let blob = context.open("test", &4u64.to_be_bytes()).await.unwrap();
+            let len = blob.len().await.unwrap();
+            blob.write_at(&[1u8; 20], len).await.unwrap();
+            blob.close().await.unwrap();
+            let reader = MmrReader::<_, Sha256>::open(&context, &cfg).await.unwrap();
+            assert_eq!(reader.size(), reference.size());
+            assert_eq!(reader.root_hash(), reference.root_hash());
+            assert_eq!(context.scan("test").await.unwrap().len(), 5);
+
+            // The reader should respect pruning by the writer
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            mmr.prune_to_pos(10).await.unwrap();
+            mmr.close().await.unwrap();
+            let reader = MmrReader::<_, Sha256>::open(&context, &cfg).await.unwrap();
+            assert_eq!(reader.pruned_to_pos(), 10);
+            assert_eq!(reader.root_hash(), reference.root_hash());
+            assert_eq!(
+                reader.proof(11).await.unwrap(),
+                reference.proof(11).unwrap()
+            );
+            assert!(matches!(
+                reader.proof(8).await,
+                Err(Error::ElementPruned(8))
+            ));
+        });
+    }
+
+    #[test_traced]
+    fn test_reader_open_never_writes() {
+        // Initialize the deterministic runtime
+        let (executor, context, _) = Executor::default();
+        executor.start(async move {
+            let storage = CountingStorage {
+                inner: context.clone(),
+                creates: Arc::new(AtomicUsize::new(0)),
+                reads: Arc::new(AtomicUsize::new(0)),
+                writes: Arc::new(AtomicUsize::new(0)),
+            };
+            let cfg = Config {
+                registry: Arc::new(Mutex::new(Registry::default())),
+                metrics_prefix: "mmr".to_string(),
+                partition: "test".to_string(),
+                metadata_partition: "test_metadata".to_string(),
+                write_buffer: 1,
+                section_nodes: 4,
+                page_nodes: 2,
+                sync_policy: SyncPolicy::Never,
+                write_behind: false,
+                cache_entries: 0,
+                hot_nodes: 0,
+                root_index_interval: 0,
+                verify_on_init: false,
+                max_concurrent_reads: 1,
+                scheme: Scheme::default(),
+            };
+
+            // Opening a reader before anything was written creates nothing
+            let reader = MmrReader::<_, Sha256>::open(&storage, &cfg).await.unwrap();
+            assert_eq!(reader.size(), 0);
+            assert_eq!(storage.creates.load(Ordering::Relaxed), 0);
+            assert_eq!(storage.writes.load(Ordering::Relaxed), 0);
+            assert!(context.scan("test_metadata").await.is_err());
+
+            // Write and prune an MMR, syncing the pruning boundary to both metadata blobs
+            let mut mmr = Mmr::<_, _, Sha256>::init(context.clone(), cfg.clone())
+                .await
+                .unwrap();
+            let mut reference = MemMmr::<Sha256>::new();
+            for i in 0u8..10 {
+                let element = Digest::from(vec![i; 32]);
+                mmr.add(&element).await.unwrap();
+                reference.add(&element);
+            }
+            mmr.prune_to_pos(10).await.unwrap();
+            mmr.sync().await.unwrap();
+            mmr.close().await.unwrap();
+
+            // Corrupt the latest metadata blob, as if the writer were in the middle of syncing it
+            let mut latest = None;
+            for name in [&b"left"[..], &b"right"[..]] {
+                let blob = context.open("test_metadata", name).await.unwrap();
+                let mut timestamp = [0u8; 16];
+                blob.read_at(&mut timestamp, 0).await.unwrap();
+                let timestamp = u128::from_be_bytes(timestamp);
+                if latest
+                    .as_ref()
+                    .is_none_or(|(latest, _, _)| timestamp > *latest)
+                {
+                    latest = Some((timestamp, name, blob.len().await.unwrap()));
+                }
+                blob.close().await.unwrap();
+            }
+            let (_, name, len) = latest.unwrap();
+            let blob = context.open("test_metadata", name).await.unwrap();
+            blob.write_at(b"corrupted", 16).await.unwrap();
+            blob.close().await.unwrap();
+
+            // The reader falls back to the other metadata blob without creating, truncating, or
+            // otherwise writing to any blob
+            let reader = MmrReader::<_, Sha256>::open(&storage, &cfg).await.unwrap();
+            assert_eq!(reader.pruned_to_pos(), 10);
+            assert_eq!(reader.root_hash(), reference.root_hash());
+            assert_eq!(
+                reader.proof(11).await.unwrap(),
+                reference.proof(11).unwrap()
+            );
+            assert_eq!(storage.creates.load(Ordering::Relaxed), 0);
+            assert_eq!(storage.writes.load(Ordering::Relaxed), 0);
+            let blob = context.open("test_metadata", name).await.unwrap();
+            assert_eq!(blob.len().await.unwrap(), len);
+            let mut buf = [0u8; 9];
+            blob.read_at(&mut buf, 16).await.unwrap();
+            assert_eq!(&buf, b"corrupted");
+        });
+    }
+}
@@ -0,0 +1,199 @@
+//! A `Hasher` that records every input it hashes, for replaying the exact bytes hashed by an MMR
+//! into some external transcript (e.g. for Fiat-Shamir).
+//!
+//! Wrapping the hasher used to build or verify an MMR in a `RecordingHasher` does not change any
+//! hash output. Every input hashed by an MMR (leaf, node, and root hashes) is prefixed with the
+//! big-endian position (or, for a root hash, size) it is computed for, following the domain tag
+//! (and key, under `Scheme::Keyed`) of the scheme, which is decoded and recorded alongside the
+//! input. A `RecordingHasher` expects the default scheme unless created with
+//! `RecordingHasher::with_scheme` (or by `Mmr::new_recording`, which sets the scheme of both).
+
+use crate::mmr::hasher::Scheme;
+use crate::mmr::mem::Mmr;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use rand::{CryptoRng, Rng};
+
+/// Wraps a `Hasher` and records each `(position, input_bytes)` pair it finalizes.
+#[derive(Clone)]
+pub struct RecordingHasher<H: CHasher> {
+    hasher: H,
+    scheme: Scheme,
+    input: Vec<u8>,
+    records: Vec<(u64, Vec<u8>)>,
+}
+
+impl<H: CHasher> Default for RecordingHasher<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: CHasher> RecordingHasher<H> {
+    /// Return a new `RecordingHasher` that decodes positions from inputs encoded with `scheme`.
+    pub fn with_scheme(scheme: Scheme) -> Self {
+        Self {
+            scheme,
+            ..Self::new()
+        }
+    }
+
+    /// Return the recorded `(position, input_bytes)` pairs, in the order they were hashed.
+    pub fn records(&self) -> &[(u64, Vec<u8>)] {
+        &self.records
+    }
+
+    /// Return the recorded `(position, input_bytes)` pairs and clear them.
+    pub fn take_records(&mut self) -> Vec<(u64, Vec<u8>)> {
+        std::mem::take(&mut self.records)
+    }
+}
+
+impl<H: CHasher> Mmr<RecordingHasher<H>> {
+    /// Return a new (empty) `Mmr` that hashes with `scheme` through a `RecordingHasher` expecting
+    /// the same scheme.
+    pub fn new_recording(scheme: Scheme) -> Self {
+        let mut mmr = Self::new_with_scheme(scheme.clone());
+        *mmr.hasher() = RecordingHasher::with_scheme(scheme);
+        mmr
+    }
+}
+
+impl<H: CHasher> CHasher for RecordingHasher<H> {
+    fn new() -> Self {
+        Self {
+            hasher: H::new(),
+            scheme: Scheme::default(),
+            input: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, message: &[u8]) {
+        self.input.extend_from_slice(message);
+        self.hasher.update(message);
+    }
+
+    fn finalize(&mut self) -> Digest {
+        let input = std::mem::take(&mut self.input);
+        let offset = self.scheme.prefix_len();
+        let pos = match input.get(offset..offset + 8) {
+            Some(prefix) => u64::from_be_bytes(prefix.try_into().unwrap()),
+            None => 0, // never produced by an MMR
+        };
+        self.records.push((pos, input));
+        self.hasher.finalize()
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.hasher.reset();
+    }
+
+    fn validate(digest: &Digest) -> bool {
+        H::validate(digest)
+    }
+
+    fn len() -> usize {
+        H::len()
+    }
+
+    fn random<R: Rng + CryptoRng>(rng: &mut R) -> Digest {
+        H::random(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecordingHasher;
+    use crate::mmr::hasher::{Scheme, LEAF_TAG};
+    use crate::mmr::mem::Mmr;
+    use commonware_cryptography::{Digest, Hasher as CHasher, Sha256};
+
+    #[test]
+    fn test_records_add() {
+        let mut mmr = Mmr::<RecordingHasher<Sha256>>::new();
+        let mut reference = Mmr::<Sha256>::new();
+        let element = Digest::from(vec![1u8; 32]);
+        mmr.add(&element);
+        reference.add(&element);
+
+        // adding the second element hashes the leaf (position 1) and its parent (position 2)
+        let element = Digest::from(vec![2u8; 32]);
+        mmr.hasher().take_records();
+        mmr.add(&element);
+        reference.add(&element);
+        let records = mmr.hasher().take_records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].0, 1);
+        assert_eq!(
+            records[0].1,
+            [&[LEAF_TAG][..], &1u64.to_be_bytes(), &element].concat()
+        );
+        assert_eq!(records[1].0, 2);
+
+        // replaying the recorded inputs reproduces the node hashes
+        let mut hasher = Sha256::new();
+        hasher.update(&records[0].1);
+        assert_eq!(hasher.finalize(), reference.proof(0).unwrap().hashes[0]);
+        hasher.update(&records[1].1);
+        assert_eq!(hasher.finalize(), reference.peak_hashes()[0].1);
+
+        // recording doesn't change any hash output
+        assert_eq!(mmr.root_hash(), reference.root_hash());
+    }
+
+    #[test]
+    fn test_records_verification() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let elements: Vec<Digest> = (0u8..11).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<u64> = elements.iter().map(|e| mmr.add(e)).collect();
+        let root_hash = mmr.root_hash();
+
+        // verifying element 8 (position 15) hashes its leaf, its parent, then the root
+        let proof = mmr.proof(positions[8]).unwrap();
+        let mut hasher = RecordingHasher::<Sha256>::new();
+        assert!(proof.verify_element_inclusion(
+            &elements[8],
+            positions[8],
+            &root_hash,
+            &mut hasher
+        ));
+        let recorded: Vec<u64> = hasher.records().iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(recorded, vec![15, 17, 19]);
+    }
+
+    #[test]
+    fn test_records_untagged() {
+        let mut mmr = Mmr::<RecordingHasher<Sha256>>::new_recording(Scheme::Untagged);
+        let mut reference = Mmr::<Sha256>::new_with_scheme(Scheme::Untagged);
+        for i in 0u8..3 {
+            let element = Digest::from(vec![i; 32]);
+            mmr.add(&element);
+            reference.add(&element);
+        }
+
+        // the untagged inputs start with the position
+        let records = mmr.hasher().take_records();
+        let recorded: Vec<u64> = records.iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(recorded, vec![0, 1, 2, 3]);
+        let element = Digest::from(vec![0u8; 32]);
+        assert_eq!(records[0].1, [&0u64.to_be_bytes()[..], &element].concat());
+        assert_eq!(mmr.root_hash(), reference.root_hash());
+    }
+
+    #[test]
+    fn test_records_keyed() {
+        let scheme = Scheme::keyed(b"commonware");
+        let mut mmr = Mmr::<RecordingHasher<Sha256>>::new_recording(scheme);
+        for i in 0u8..3 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+
+        // the position follows the tag and the length-prefixed key
+        let records = mmr.hasher().take_records();
+        let recorded: Vec<u64> = records.iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(recorded, vec![0, 1, 2, 3]);
+        assert_eq!(&records[2].1[1..9], &10u64.to_be_bytes());
+        assert_eq!(&records[2].1[9..19], b"commonware");
+    }
+}
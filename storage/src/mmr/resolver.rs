@@ -0,0 +1,260 @@
+//! Completion of proofs missing some of their hashes by fetching them from an untrusted source.
+//!
+//! A light client may hold a proof missing a few of its hashes (for example, a witness it only
+//! partially retained). Rather than downloading the whole proof again, `verify_with_resolver`
+//! determines the positions of the missing hashes from the shape of the proof (see
+//! `Proof::positions`), fetches only those nodes from a `NodeResolver` (such as a peer), and returns
+//! the completed proof once it verifies. Fetched hashes are untrusted until the completed proof
+//! reproduces the expected root hash.
+
+use crate::mmr::hasher::Scheme;
+use crate::mmr::iterator::PeakIterator;
+use crate::mmr::mem::Mmr;
+use crate::mmr::verification::{nodes_required_for_range_proof, Proof};
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::future::Future;
+
+/// A source of node hashes, such as a peer holding the MMR.
+pub trait NodeResolver {
+    /// Return the hashes of the nodes at `positions`, in the same order.
+    fn get_nodes(
+        &self,
+        positions: &[u64],
+    ) -> impl Future<Output = Result<Vec<Digest>, Error>> + Send;
+}
+
+impl<H: CHasher> NodeResolver for Mmr<H> {
+    /// Returns `Error::ElementPruned` if any of the nodes has been pruned (or doesn't exist).
+    async fn get_nodes(&self, positions: &[u64]) -> Result<Vec<Digest>, Error> {
+        positions
+            .iter()
+            .map(|pos| {
+                self.get_node(*pos)
+                    .cloned()
+                    .ok_or(Error::ElementPruned(*pos))
+            })
+            .collect()
+    }
+}
+
+/// A proof missing some of its hashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialProof {
+    /// The total number of nodes in the MMR.
+    pub size: u64,
+    /// The hashes of the proof (in the same order as those of `Proof`), with `None` in place of
+    /// each missing hash.
+    pub hashes: Vec<Option<Digest>>,
+    /// The scheme the MMR hashes with.
+    pub scheme: Scheme,
+}
+
+impl From<Proof> for PartialProof {
+    fn from(proof: Proof) -> Self {
+        Self {
+            size: proof.size,
+            hashes: proof.hashes.into_iter().map(Some).collect(),
+            scheme: proof.scheme,
+        }
+    }
+}
+
+/// Fetch the hashes missing from `proof` from `resolver`, and return the completed proof if it
+/// proves that the `elements` appear consecutively between positions `start_element_pos` through
+/// `end_element_pos` (inclusive) within the MMR with root hash `root_hash`.
+///
+/// Returns `Error::InvalidSize` or `Error::InvalidPos` if the range isn't contained within an MMR
+/// of the proof's size, `Error::InvalidProofLength` if the proof (or the resolver's response) has
+/// the wrong number of hashes, any error returned by `resolver`, and `Error::RootMismatch` if the
+/// completed proof doesn't verify.
+pub async fn verify_with_resolver<H: CHasher, R: NodeResolver>(
+    proof: &PartialProof,
+    elements: &[Digest],
+    start_element_pos: u64,
+    end_element_pos: u64,
+    root_hash: &Digest,
+    hasher: &mut H,
+    resolver: &R,
+) -> Result<Proof, Error> {
+    if !PeakIterator::check_validity(proof.size) {
+        return Err(Error::InvalidSize(proof.size));
+    }
+    if end_element_pos >= proof.size {
+        return Err(Error::InvalidPos(end_element_pos));
+    }
+    if start_element_pos > end_element_pos {
+        return Err(Error::InvalidPos(start_element_pos));
+    }
+    let positions = nodes_required_for_range_proof(proof.size, start_element_pos, end_element_pos);
+    if positions.len() != proof.hashes.len() {
+        return Err(Error::InvalidProofLength(proof.hashes.len()));
+    }
+
+    // Fetch the missing hashes and fill them in
+    let missing: Vec<u64> = positions
+        .iter()
+        .zip(proof.hashes.iter())
+        .filter(|(_, hash)| hash.is_none())
+        .map(|(pos, _)| *pos)
+        .collect();
+    let mut fetched = Vec::new();
+    if !missing.is_empty() {
+        fetched = resolver.get_nodes(&missing).await?;
+        if fetched.len() != missing.len() {
+            return Err(Error::InvalidProofLength(fetched.len()));
+        }
+    }
+    let mut fetched = fetched.into_iter();
+    let hashes = proof
+        .hashes
+        .iter()
+        .map(|hash| match hash {
+            Some(hash) => hash.clone(),
+            None => fetched.next().unwrap(),
+        })
+        .collect();
+    let proof = Proof {
+        size: proof.size,
+        hashes,
+        scheme: proof.scheme.clone(),
+    };
+    if !proof.verify_range_inclusion(
+        elements,
+        start_element_pos,
+        end_element_pos,
+        root_hash,
+        hasher,
+    ) {
+        return Err(Error::RootMismatch);
+    }
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_cryptography::Sha256;
+    use commonware_runtime::{deterministic::Executor, Runner};
+
+    /// A resolver returning corrupted hashes for the nodes of an `Mmr`.
+    struct MaliciousResolver<'a>(&'a Mmr<Sha256>);
+
+    impl NodeResolver for MaliciousResolver<'_> {
+        async fn get_nodes(&self, positions: &[u64]) -> Result<Vec<Digest>, Error> {
+            let mut nodes = self.0.get_nodes(positions).await?;
+            nodes[0] = Digest::from(vec![0u8; 32]);
+            Ok(nodes)
+        }
+    }
+
+    /// A resolver returning one fewer hash than requested.
+    struct TruncatingResolver<'a>(&'a Mmr<Sha256>);
+
+    impl NodeResolver for TruncatingResolver<'_> {
+        async fn get_nodes(&self, positions: &[u64]) -> Result<Vec<Digest>, Error> {
+            let mut nodes = self.0.get_nodes(positions).await?;
+            nodes.pop();
+            Ok(nodes)
+        }
+    }
+
+    #[test]
+    fn test_verify_with_resolver() {
+        let (executor, _, _) = Executor::default();
+        executor.start(async move {
+            let mut mmr = Mmr::<Sha256>::new();
+            let mut hasher = Sha256::default();
+            let elements: Vec<_> = (0u8..49).map(|i| Digest::from(vec![i; 32])).collect();
+            let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+            let root_hash = mmr.root_hash();
+            let (start, end) = (positions[10], positions[20]);
+            let range = &elements[10..=20];
+            let proof = mmr.range_proof(start, end).unwrap();
+
+            // Missing hashes are fetched from the resolver, restoring the original proof
+            let mut partial = PartialProof::from(proof.clone());
+            partial.hashes[0] = None;
+            partial.hashes[2] = None;
+            let completed =
+                verify_with_resolver(&partial, range, start, end, &root_hash, &mut hasher, &mmr)
+                    .await
+                    .unwrap();
+            assert_eq!(completed, proof);
+
+            // A complete proof needs nothing from the resolver
+            let complete = PartialProof::from(proof.clone());
+            let malicious = MaliciousResolver(&mmr);
+            let completed = verify_with_resolver(
+                &complete,
+                range,
+                start,
+                end,
+                &root_hash,
+                &mut hasher,
+                &malicious,
+            )
+            .await
+            .unwrap();
+            assert_eq!(completed, proof);
+
+            // Corrupted or missing fetched hashes fail cleanly
+            let result = verify_with_resolver(
+                &partial,
+                range,
+                start,
+                end,
+                &root_hash,
+                &mut hasher,
+                &malicious,
+            )
+            .await;
+            assert_eq!(result, Err(Error::RootMismatch));
+            let truncating = TruncatingResolver(&mmr);
+            let result = verify_with_resolver(
+                &partial,
+                range,
+                start,
+                end,
+                &root_hash,
+                &mut hasher,
+                &truncating,
+            )
+            .await;
+            assert_eq!(result, Err(Error::InvalidProofLength(1)));
+
+            // So do wrong elements and malformed proofs
+            let result = verify_with_resolver(
+                &partial,
+                &elements[11..=21],
+                start,
+                end,
+                &root_hash,
+                &mut hasher,
+                &mmr,
+            )
+            .await;
+            assert_eq!(result, Err(Error::RootMismatch));
+            let mut short = partial.clone();
+            short.hashes.pop();
+            let result =
+                verify_with_resolver(&short, range, start, end, &root_hash, &mut hasher, &mmr)
+                    .await;
+            assert_eq!(
+                result,
+                Err(Error::InvalidProofLength(proof.hashes.len() - 1))
+            );
+            let result = verify_with_resolver(
+                &partial,
+                range,
+                start,
+                mmr.size(),
+                &root_hash,
+                &mut hasher,
+                &mmr,
+            )
+            .await;
+            assert_eq!(result, Err(Error::InvalidPos(mmr.size())));
+        });
+    }
+}
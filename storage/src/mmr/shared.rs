@@ -0,0 +1,248 @@
+//! A thread-safe MMR that serves proofs from immutable snapshots while elements are appended.
+//!
+//! Appends are serialized behind a short exclusive section over an in-memory `Mmr`, after which a
+//! `ProofGenerator` of its new state is published as the latest `Snapshot`. Readers only hold a
+//! lock long enough to clone the latest snapshot's `Arc`, so long-running proof generation never
+//! blocks the append path (and vice versa).
+//!
+//! Snapshots share the nodes of the MMR (see `Mmr::proof_generator`), so each one only holds a
+//! reference to them. Publishing a snapshot merges the nodes added since the previous one into
+//! that shared storage, which clones a reference to every retained node.
+
+use crate::mmr::hasher::Scheme;
+use crate::mmr::mem::{Mmr, ProofGenerator};
+use crate::mmr::verification::Proof;
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// An immutable view of a `SharedMmr` as of some committed size.
+pub type Snapshot = ProofGenerator;
+
+struct Inner<H: CHasher> {
+    writer: Mutex<Mmr<H>>,
+    latest: RwLock<Arc<Snapshot>>,
+}
+
+/// A cloneable handle to an MMR that can be appended to and read from concurrently.
+pub struct SharedMmr<H: CHasher> {
+    inner: Arc<Inner<H>>,
+}
+
+impl<H: CHasher> Clone for SharedMmr<H> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<H: CHasher> Default for SharedMmr<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: CHasher> SharedMmr<H> {
+    /// Return a new (empty) `SharedMmr`.
+    pub fn new() -> Self {
+        Self::new_with_scheme(Scheme::default())
+    }
+
+    /// Return a new (empty) `SharedMmr` that hashes with the given `scheme`.
+    pub fn new_with_scheme(scheme: Scheme) -> Self {
+        let mut mmr = Mmr::new_with_scheme(scheme);
+        let snapshot = mmr.proof_generator();
+        Self {
+            inner: Arc::new(Inner {
+                writer: Mutex::new(mmr),
+                latest: RwLock::new(Arc::new(snapshot)),
+            }),
+        }
+    }
+
+    /// Add an element to the MMR, publish a snapshot including it, and return its position in the
+    /// MMR.
+    pub fn add(&self, element: &Digest) -> u64 {
+        let mut mmr = self.inner.writer.lock().unwrap();
+        let element_pos = mmr.add(element);
+
+        // Publish the new committed state.
+        let snapshot = Arc::new(mmr.proof_generator());
+        *self.inner.latest.write().unwrap() = snapshot;
+        element_pos
+    }
+
+    /// Return the latest committed snapshot of the MMR.
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.inner.latest.read().unwrap().clone()
+    }
+
+    /// Return the size of the latest committed snapshot of the MMR.
+    pub fn size(&self) -> u64 {
+        self.snapshot().size()
+    }
+
+    /// Return the root hash of the latest committed snapshot of the MMR.
+    pub fn root_hash(&self) -> Digest {
+        self.snapshot().root_hash()
+    }
+
+    /// Return an inclusion proof for the specified element against the latest committed snapshot.
+    /// Use `snapshot` to obtain a proof and root hash that are guaranteed to be consistent.
+    ///
+    /// Returns `Error::InvalidPos` if the element is beyond the size of the snapshot.
+    pub fn proof(&self, element_pos: u64) -> Result<Proof, Error> {
+        self.snapshot().proof(element_pos)
+    }
+
+    /// Return an inclusion proof for the specified range of elements against the latest committed
+    /// snapshot. The range is inclusive of both endpoints.
+    ///
+    /// Returns `Error::InvalidPos` if the range extends beyond the size of the snapshot.
+    pub fn range_proof(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<Proof, Error> {
+        self.snapshot()
+            .range_proof(start_element_pos, end_element_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scheme, SharedMmr};
+    use crate::mmr::iterator::pos_to_height;
+    use crate::mmr::mem::Mmr;
+    use crate::mmr::util::node_count;
+    use crate::mmr::Error;
+    use commonware_cryptography::{Digest, Sha256};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn element_for(pos: u64) -> Digest {
+        Digest::from(pos.to_be_bytes().repeat(4))
+    }
+
+    #[test]
+    fn test_shared_mmr_matches_mmr() {
+        let shared = SharedMmr::<Sha256>::new();
+        let mut mmr = Mmr::<Sha256>::new();
+        assert_eq!(shared.root_hash(), mmr.root_hash());
+
+        let mut positions = Vec::new();
+        for i in 0..3500 {
+            let element = element_for(i);
+            let pos = shared.add(&element);
+            assert_eq!(pos, mmr.add(&element));
+            positions.push(pos);
+        }
+        assert_eq!(shared.size(), mmr.size());
+        assert_eq!(shared.root_hash(), mmr.root_hash());
+        for pos in positions.iter().step_by(97) {
+            assert_eq!(shared.proof(*pos).unwrap(), mmr.proof(*pos).unwrap());
+        }
+        assert_eq!(
+            shared.range_proof(positions[10], positions[3000]).unwrap(),
+            mmr.range_proof(positions[10], positions[3000]).unwrap()
+        );
+
+        // proofs beyond the size of the snapshot are rejected
+        let size = shared.size();
+        assert_eq!(shared.proof(size), Err(Error::InvalidPos(size)));
+        assert_eq!(
+            shared.range_proof(positions[10], size),
+            Err(Error::InvalidPos(size))
+        );
+
+        // an old snapshot should be unaffected by subsequent additions
+        let snapshot = shared.snapshot();
+        let root = snapshot.root_hash();
+        let proof = snapshot.proof(positions[5]).unwrap();
+        shared.add(&element_for(0));
+        assert_eq!(snapshot.root_hash(), root);
+        assert_eq!(snapshot.proof(positions[5]).unwrap(), proof);
+        assert_eq!(snapshot.proof(size), Err(Error::InvalidPos(size)));
+        assert_ne!(shared.root_hash(), root);
+    }
+
+    #[test]
+    fn test_shared_mmr_scheme() {
+        let scheme = Scheme::keyed(b"commonware");
+        let shared = SharedMmr::<Sha256>::new_with_scheme(scheme.clone());
+        let mut mmr = Mmr::<Sha256>::new_with_scheme(scheme.clone());
+        assert_eq!(shared.root_hash(), mmr.root_hash());
+        let mut hasher = Sha256::default();
+        for i in 0..100 {
+            let element = element_for(i);
+            let pos = shared.add(&element);
+            assert_eq!(pos, mmr.add(&element));
+            let proof = shared.proof(pos).unwrap();
+            assert_eq!(proof.scheme, scheme);
+            assert!(proof.verify_element_inclusion(
+                &element,
+                pos,
+                &shared.root_hash(),
+                &mut hasher
+            ));
+        }
+        assert_eq!(shared.root_hash(), mmr.root_hash());
+        assert_ne!(shared.root_hash(), SharedMmr::<Sha256>::new().root_hash());
+    }
+
+    #[test]
+    fn test_shared_mmr_concurrent_proofs() {
+        const ELEMENTS: u64 = 2_000;
+        const READERS: usize = 4;
+
+        let shared = SharedMmr::<Sha256>::new();
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            // appender: the element added at each position is derived from that position
+            let appender = shared.clone();
+            let done = &done;
+            s.spawn(move || {
+                for _ in 0..ELEMENTS {
+                    let pos = appender.size();
+                    assert_eq!(appender.add(&element_for(pos)), pos);
+                }
+                done.store(true, Ordering::SeqCst);
+            });
+
+            // proof servers: verify a sample of elements against the root of the same snapshot
+            for reader in 0..READERS {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    let mut hasher = Sha256::default();
+                    let mut verified = 0;
+                    loop {
+                        let finished = done.load(Ordering::SeqCst);
+                        let snapshot = shared.snapshot();
+                        let root = snapshot.root_hash();
+                        let leaves = (0..snapshot.size())
+                            .skip(reader)
+                            .step_by(13)
+                            .filter(|pos| pos_to_height(*pos) == 0);
+                        for pos in leaves {
+                            let (proof, context) =
+                                snapshot.range_proof_with_context(pos, pos).unwrap();
+                            assert_eq!(context.root, root);
+                            assert!(proof.verify_element_inclusion(
+                                &element_for(pos),
+                                pos,
+                                &root,
+                                &mut hasher
+                            ));
+                            verified += 1;
+                        }
+                        if finished {
+                            break;
+                        }
+                    }
+                    assert!(verified > 0);
+                });
+            }
+        });
+        assert_eq!(shared.size(), node_count(ELEMENTS));
+    }
+}
@@ -0,0 +1,153 @@
+//! A wrapper around an in-memory `Mmr` that tracks statistics about its lifetime.
+
+use crate::mmr::iterator::PeakIterator;
+use crate::mmr::mem::Mmr;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+
+/// Statistics collected by a `StatsTracker`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MmrStats {
+    /// Total number of elements added through the tracker.
+    pub elements_added: u64,
+    /// Total number of leaf and node hashes computed while adding elements.
+    pub hash_operations: u64,
+    /// Total number of times two peaks were merged under a new parent.
+    pub peak_merges: u64,
+    /// Current number of peaks in the MMR.
+    pub peak_count: usize,
+}
+
+/// Wraps an `Mmr` and records `MmrStats` for every element added through it.
+pub struct StatsTracker<H: CHasher> {
+    mmr: Mmr<H>,
+    stats: MmrStats,
+}
+
+impl<H: CHasher> Default for StatsTracker<H> {
+    fn default() -> Self {
+        Self::new(Mmr::new())
+    }
+}
+
+impl<H: CHasher> StatsTracker<H> {
+    /// Return a new `StatsTracker` wrapping `mmr`. Counters start from zero regardless of how many
+    /// elements `mmr` already contains.
+    pub fn new(mmr: Mmr<H>) -> Self {
+        let stats = MmrStats {
+            peak_count: PeakIterator::new(mmr.size()).count(),
+            ..MmrStats::default()
+        };
+        Self { mmr, stats }
+    }
+
+    /// Add an element to the wrapped MMR and return its position in the MMR.
+    pub fn add(&mut self, element: &Digest) -> u64 {
+        let element_pos = self.mmr.add(element);
+
+        // Every node added beyond the leaf is a parent created by merging two peaks.
+        let merges = self.mmr.size() - element_pos - 1;
+        self.stats.elements_added += 1;
+        self.stats.hash_operations += 1 + merges;
+        self.stats.peak_merges += merges;
+        self.stats.peak_count = PeakIterator::new(self.mmr.size()).count();
+        element_pos
+    }
+
+    /// Return the statistics collected so far.
+    pub fn stats(&self) -> MmrStats {
+        self.stats.clone()
+    }
+
+    /// Return a reference to the wrapped MMR.
+    pub fn mmr(&self) -> &Mmr<H> {
+        &self.mmr
+    }
+
+    /// Return a mutable reference to the wrapped MMR. Elements added directly to the MMR are not
+    /// reflected in the collected statistics (other than in `peak_count`, once another element is
+    /// added through the tracker).
+    pub fn mmr_mut(&mut self) -> &mut Mmr<H> {
+        &mut self.mmr
+    }
+
+    /// Consume the tracker and return the wrapped MMR.
+    pub fn into_inner(self) -> Mmr<H> {
+        self.mmr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_cryptography::Sha256;
+
+    #[test]
+    fn test_stats_tracker() {
+        let mut tracker = StatsTracker::<Sha256>::default();
+        assert_eq!(tracker.stats(), MmrStats::default());
+
+        // Add the 11 elements from the example in mod.rs (19 nodes, 3 peaks).
+        let element = Digest::from(vec![1u8; 32]);
+        for _ in 0..11 {
+            tracker.add(&element);
+        }
+        let stats = tracker.stats();
+        assert_eq!(stats.elements_added, 11);
+        assert_eq!(stats.hash_operations, 19);
+        assert_eq!(stats.peak_merges, 8);
+        assert_eq!(stats.peak_count, 3);
+
+        // Adding a 12th element merges the two height-0 peaks.
+        tracker.add(&element);
+        let stats = tracker.stats();
+        assert_eq!(stats.elements_added, 12);
+        assert_eq!(stats.hash_operations, 22);
+        assert_eq!(stats.peak_merges, 10);
+        assert_eq!(stats.peak_count, 2);
+
+        // The wrapped MMR should be identical to one built without the tracker.
+        let mut mmr = Mmr::<Sha256>::new();
+        for _ in 0..12 {
+            mmr.add(&element);
+        }
+        assert_eq!(tracker.into_inner().root_hash(), mmr.root_hash());
+    }
+
+    #[test]
+    fn test_stats_tracker_existing_mmr() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let element = Digest::from(vec![1u8; 32]);
+        for _ in 0..7 {
+            mmr.add(&element);
+        }
+        let mut tracker = StatsTracker::new(mmr);
+        let stats = tracker.stats();
+        assert_eq!(stats.elements_added, 0);
+        assert_eq!(stats.peak_count, 3);
+
+        tracker.add(&element);
+        let stats = tracker.stats();
+        assert_eq!(stats.elements_added, 1);
+        assert_eq!(stats.hash_operations, 4);
+        assert_eq!(stats.peak_merges, 3);
+        assert_eq!(stats.peak_count, 1);
+    }
+
+    #[test]
+    fn test_stats_tracker_mmr_mut() {
+        let mut tracker = StatsTracker::<Sha256>::default();
+        let element = Digest::from(vec![1u8; 32]);
+        tracker.add(&element);
+
+        // Elements added directly merge peaks the tracker didn't count, which must not underflow
+        // the peak count once another element is added through it.
+        for _ in 0..6 {
+            tracker.mmr_mut().add(&element);
+        }
+        tracker.add(&element);
+        let stats = tracker.stats();
+        assert_eq!(stats.elements_added, 2);
+        assert_eq!(stats.peak_merges, 3);
+        assert_eq!(stats.peak_count, 1);
+    }
+}
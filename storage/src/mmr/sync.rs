@@ -0,0 +1,362 @@
+//! Bootstrap an MMR from another by transferring its nodes in verified chunks.
+//!
+//! A serving MMR produces `SyncChunk`s with `Mmr::sync_chunk`, each containing a contiguous range
+//! of nodes that ends at a valid MMR size along with a proof authenticating the peaks of that
+//! (prefix) MMR against the root of the full MMR. An `MmrSyncer` pinned to that root ingests chunks
+//! in order, checking that every parent node matches the hash of its children and that the prefix
+//! peaks reconstruct the pinned root, before appending the nodes to its local store.
+
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{pos_to_height, PeakIterator};
+use crate::mmr::mem::Mmr;
+use crate::mmr::verification::Proof;
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::collections::HashMap;
+
+/// A contiguous range of nodes of an MMR, along with a proof authenticating them against the root
+/// of the MMR they were taken from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncChunk {
+    /// The position and hash of each node in the chunk, in increasing order of position.
+    pub nodes: Vec<(u64, Digest)>,
+
+    /// The hashes of the nodes (all beyond the end of the chunk) required to reconstruct the root
+    /// of the full MMR from the peaks of the MMR ending with the chunk. These are the hashes of a
+    /// range proof from the first leaf through the last leaf before the end of the chunk.
+    pub proof: Proof,
+}
+
+/// The progress of an `MmrSyncer` after ingesting a chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of nodes synced so far (the position of the next node to sync).
+    pub synced: u64,
+
+    /// The number of nodes that remain to be synced.
+    pub remaining: u64,
+}
+
+impl Progress {
+    /// Return true if every node has been synced.
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// Return the position of the last leaf of an MMR of the given non-zero `size`.
+pub(crate) fn last_leaf_pos(size: u64) -> u64 {
+    let (peak_pos, height) = PeakIterator::new(size).last().unwrap();
+    peak_pos - height as u64
+}
+
+/// Implementation of `MmrSyncer`.
+pub struct MmrSyncer<H: CHasher> {
+    hasher: H,
+    // The scheme the MMR being synced hashes with.
+    scheme: Scheme,
+    // The size and root hash of the MMR being synced.
+    target_size: u64,
+    target_root: Digest,
+    // The nodes synced so far.
+    nodes: Vec<Digest>,
+}
+
+impl<H: CHasher> MmrSyncer<H> {
+    /// Return a new `MmrSyncer` that syncs the MMR with the given size and root hash, which hashes
+    /// with `scheme`.
+    ///
+    /// Returns `Error::InvalidSize` if `target_size` is not a valid MMR size.
+    pub fn new(target_size: u64, target_root: Digest, scheme: Scheme) -> Result<Self, Error> {
+        if !PeakIterator::check_validity(target_size) {
+            return Err(Error::InvalidSize(target_size));
+        }
+        Ok(Self {
+            hasher: H::new(),
+            scheme,
+            target_size,
+            target_root,
+            nodes: Vec::new(),
+        })
+    }
+
+    /// Return the progress of the sync.
+    pub fn progress(&self) -> Progress {
+        let synced = self.nodes.len() as u64;
+        Progress {
+            synced,
+            remaining: self.target_size - synced,
+        }
+    }
+
+    /// Verify `chunk` against the pinned root and append its nodes to the local store.
+    ///
+    /// The chunk must start at the next position to sync and its nodes must be contiguous:
+    /// `Error::ChunkOverlap` or `Error::ChunkGap` is returned (with the offending position)
+    /// otherwise. Returns `Error::InvalidChunkSize` if the chunk doesn't end at a valid MMR size
+    /// within the target, `Error::InvalidNode` if a parent node doesn't match the hash of its
+    /// children, and `Error::RootMismatch` if the chunk doesn't reconstruct the pinned root. A
+    /// rejected chunk leaves the syncer unmodified.
+    pub fn ingest(&mut self, chunk: SyncChunk) -> Result<Progress, Error> {
+        // Check the chunk continues exactly where the last one ended
+        let start_pos = self.nodes.len() as u64;
+        for (expected, (pos, _)) in (start_pos..).zip(chunk.nodes.iter()) {
+            if *pos < expected {
+                return Err(Error::ChunkOverlap(*pos));
+            }
+            if *pos > expected {
+                return Err(Error::ChunkGap(expected));
+            }
+        }
+        let end_pos = start_pos + chunk.nodes.len() as u64;
+        if end_pos == start_pos
+            || end_pos > self.target_size
+            || !PeakIterator::check_validity(end_pos)
+        {
+            return Err(Error::InvalidChunkSize(end_pos));
+        }
+
+        // Append the nodes, verifying each parent against its children, and discard them again if
+        // the chunk is rejected.
+        self.nodes
+            .extend(chunk.nodes.into_iter().map(|(_, node)| node));
+        let result = self.verify(start_pos, end_pos, &chunk.proof);
+        if let Err(err) = result {
+            self.nodes.truncate(start_pos as usize);
+            return Err(err);
+        }
+        Ok(self.progress())
+    }
+
+    fn verify(&mut self, start_pos: u64, end_pos: u64, proof: &Proof) -> Result<(), Error> {
+        let hasher = &mut Hasher::with_scheme(&mut self.hasher, self.scheme.clone());
+        for pos in start_pos..end_pos {
+            let height = pos_to_height(pos);
+            if height == 0 {
+                continue;
+            }
+            let left = &self.nodes[(pos - (1 << height)) as usize];
+            let right = &self.nodes[(pos - 1) as usize];
+            if hasher.node_hash(pos, left, right) != self.nodes[pos as usize] {
+                return Err(Error::InvalidNode(pos));
+            }
+        }
+
+        // Reconstruct the target root from the synced nodes and the proof
+        if proof.size != self.target_size {
+            return Err(Error::RootMismatch);
+        }
+        let positions = proof.positions(0, last_leaf_pos(end_pos));
+        if positions.len() != proof.hashes.len() {
+            return Err(Error::RootMismatch);
+        }
+        let proof_nodes: HashMap<u64, &Digest> =
+            positions.into_iter().zip(proof.hashes.iter()).collect();
+        let mut peaks = Vec::new();
+        for (peak_pos, height) in PeakIterator::new(self.target_size) {
+            peaks.push(node_hash(
+                hasher,
+                &self.nodes,
+                &proof_nodes,
+                peak_pos,
+                height,
+            )?);
+        }
+        if hasher.root_hash(self.target_size, peaks.iter()) != self.target_root {
+            return Err(Error::RootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Return an `Mmr` containing every node synced so far (which always form a valid MMR).
+    pub fn into_mmr(self) -> Mmr<H> {
+        Mmr::from_nodes(self.nodes, self.scheme)
+    }
+}
+
+/// Compute the hash of the node at `pos` (with the given `height`) in the target MMR, taking it
+/// from the synced `nodes` or `proof_nodes` if present and from the hashes of its children
+/// otherwise.
+fn node_hash<H: CHasher>(
+    hasher: &mut Hasher<H>,
+    nodes: &[Digest],
+    proof_nodes: &HashMap<u64, &Digest>,
+    pos: u64,
+    height: u32,
+) -> Result<Digest, Error> {
+    if let Some(node) = nodes.get(pos as usize) {
+        return Ok(node.clone());
+    }
+    if let Some(node) = proof_nodes.get(&pos) {
+        return Ok((*node).clone());
+    }
+    if height == 0 {
+        return Err(Error::RootMismatch);
+    }
+    let left = node_hash(hasher, nodes, proof_nodes, pos - (1 << height), height - 1)?;
+    let right = node_hash(hasher, nodes, proof_nodes, pos - 1, height - 1)?;
+    Ok(hasher.node_hash(pos, &left, &right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MmrSyncer, Progress, Scheme};
+    use crate::mmr::{mem::Mmr, Error};
+    use commonware_cryptography::{Digest, Sha256};
+
+    fn build_mmr(leaves: u64) -> Mmr<Sha256> {
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0..leaves {
+            mmr.add(&Digest::from(i.to_be_bytes().repeat(4)));
+        }
+        mmr
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut source = build_mmr(100_000);
+        let size = source.size();
+        let root = source.root_hash();
+
+        let mut syncer = MmrSyncer::<Sha256>::new(size, root.clone(), Scheme::default()).unwrap();
+        assert_eq!(
+            syncer.progress(),
+            Progress {
+                synced: 0,
+                remaining: size
+            }
+        );
+        let mut chunks = 0;
+        while !syncer.progress().is_complete() {
+            let chunk = source.sync_chunk(syncer.progress().synced, 4096).unwrap();
+            assert!(chunk.nodes.len() <= 4096);
+            let progress = syncer.ingest(chunk).unwrap();
+            assert_eq!(progress.synced + progress.remaining, size);
+            chunks += 1;
+        }
+        assert!(chunks >= size / 4096);
+
+        // The synced MMR should match the source
+        let mut synced = syncer.into_mmr();
+        assert_eq!(synced.size(), source.size());
+        assert_eq!(synced.root_hash(), root);
+        assert_eq!(synced.proof(0).unwrap(), source.proof(0).unwrap());
+        let element = Digest::from(vec![1u8; 32]);
+        assert_eq!(synced.add(&element), source.add(&element));
+        assert_eq!(synced.root_hash(), source.root_hash());
+    }
+
+    #[test]
+    fn test_sync_small_chunks() {
+        // Chunks too small for an append should still make progress
+        let mut source = build_mmr(100);
+        let mut syncer =
+            MmrSyncer::<Sha256>::new(source.size(), source.root_hash(), Scheme::default()).unwrap();
+        while !syncer.progress().is_complete() {
+            let chunk = source.sync_chunk(syncer.progress().synced, 1).unwrap();
+            syncer.ingest(chunk).unwrap();
+        }
+        assert_eq!(syncer.into_mmr().root_hash(), source.root_hash());
+    }
+
+    #[test]
+    fn test_sync_keyed() {
+        let scheme = Scheme::keyed(b"commonware");
+        let mut source = Mmr::<Sha256>::new_with_scheme(scheme.clone());
+        for i in 0u64..1_000 {
+            source.add(&Digest::from(i.to_be_bytes().repeat(4)));
+        }
+        let (size, root) = (source.size(), source.root_hash());
+
+        // A syncer expecting another scheme (or key) rejects the chunks of a keyed MMR
+        for other in [Scheme::default(), Scheme::keyed(b"other")] {
+            let mut syncer = MmrSyncer::<Sha256>::new(size, root.clone(), other).unwrap();
+            let chunk = source.sync_chunk(0, 100).unwrap();
+            assert!(syncer.ingest(chunk).is_err());
+        }
+
+        // A syncer expecting its scheme syncs it, and the synced MMR keeps hashing with it
+        let mut syncer = MmrSyncer::<Sha256>::new(size, root.clone(), scheme.clone()).unwrap();
+        while !syncer.progress().is_complete() {
+            let chunk = source.sync_chunk(syncer.progress().synced, 100).unwrap();
+            syncer.ingest(chunk).unwrap();
+        }
+        let mut synced = syncer.into_mmr();
+        assert_eq!(synced.scheme(), &scheme);
+        assert_eq!(synced.root_hash(), root);
+        let element = Digest::from(vec![1u8; 32]);
+        assert_eq!(synced.add(&element), source.add(&element));
+        assert_eq!(synced.root_hash(), source.root_hash());
+    }
+
+    #[test]
+    fn test_sync_invalid_chunks() {
+        let mut source = build_mmr(1_000);
+        let size = source.size();
+        let mut syncer =
+            MmrSyncer::<Sha256>::new(size, source.root_hash(), Scheme::default()).unwrap();
+        let first = source.sync_chunk(0, 100).unwrap();
+        let end = first.nodes.len() as u64;
+
+        // Chunks must start at the next position to sync
+        let later = source.sync_chunk(end, 100).unwrap();
+        assert_eq!(syncer.ingest(later.clone()), Err(Error::ChunkGap(0)));
+
+        // A tampered leaf is detected by its parent
+        let mut chunk = first.clone();
+        chunk.nodes[0].1 = Digest::from(vec![0u8; 32]);
+        assert_eq!(syncer.ingest(chunk), Err(Error::InvalidNode(2)));
+
+        // A tampered peak is detected by the root
+        let mut chunk = first.clone();
+        chunk.nodes.last_mut().unwrap().1 = Digest::from(vec![0u8; 32]);
+        assert_eq!(syncer.ingest(chunk), Err(Error::RootMismatch));
+
+        // A tampered proof is detected by the root
+        let mut chunk = first.clone();
+        chunk.proof.hashes[0] = Digest::from(vec![0u8; 32]);
+        assert_eq!(syncer.ingest(chunk), Err(Error::RootMismatch));
+        let mut chunk = first.clone();
+        chunk.proof.hashes.pop();
+        assert_eq!(syncer.ingest(chunk), Err(Error::RootMismatch));
+
+        // Chunks must end at a valid size
+        let mut chunk = first.clone();
+        chunk.nodes.truncate(2);
+        assert_eq!(syncer.ingest(chunk), Err(Error::InvalidChunkSize(2)));
+        let mut chunk = first.clone();
+        chunk.nodes.clear();
+        assert_eq!(syncer.ingest(chunk), Err(Error::InvalidChunkSize(0)));
+
+        // Nodes within a chunk must be contiguous
+        let mut chunk = first.clone();
+        chunk.nodes.remove(5);
+        assert_eq!(syncer.ingest(chunk), Err(Error::ChunkGap(5)));
+
+        // None of the rejected chunks should have been ingested
+        assert_eq!(syncer.progress().synced, 0);
+        assert_eq!(syncer.ingest(first.clone()).unwrap().synced, end);
+
+        // Chunks must not overlap those already ingested
+        assert_eq!(syncer.ingest(first), Err(Error::ChunkOverlap(0)));
+        let mut chunk = later.clone();
+        chunk
+            .nodes
+            .insert(0, (end - 1, source.proof(0).unwrap().hashes[0].clone()));
+        assert_eq!(syncer.ingest(chunk), Err(Error::ChunkOverlap(end - 1)));
+        syncer.ingest(later).unwrap();
+
+        // Chunks from an MMR with a different root are rejected
+        let other = build_mmr(1_001);
+        let chunk = other.sync_chunk(syncer.progress().synced, 100).unwrap();
+        assert_eq!(syncer.ingest(chunk), Err(Error::RootMismatch));
+
+        // Invalid chunk requests
+        assert_eq!(source.sync_chunk(2, 100), Err(Error::InvalidSize(2)));
+        assert_eq!(source.sync_chunk(size, 100), Err(Error::InvalidSize(size)));
+        assert!(matches!(
+            MmrSyncer::<Sha256>::new(2, source.root_hash(), Scheme::default()),
+            Err(Error::InvalidSize(2))
+        ));
+    }
+}
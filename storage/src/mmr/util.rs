@@ -0,0 +1,272 @@
+//! Utilities for reasoning about the structure of an MMR.
+
+use crate::mmr::iterator::PeakIterator;
+
+/// Return the position of the lowest common ancestor of the nodes at `pos_a` and `pos_b` in an
+/// MMR with the given (valid) `size`, or `None` if either node doesn't exist or they belong to the
+/// trees of different peaks. A node is considered an ancestor of itself.
+///
+/// For the example MMR depicted in the module documentation of `mmr`, the lowest common ancestor
+/// of nodes 3 and 8 is node 14, and nodes 8 and 16 have no common ancestor.
+pub fn lca(size: u64, pos_a: u64, pos_b: u64) -> Option<u64> {
+    if pos_a >= size || pos_b >= size {
+        return None;
+    }
+
+    // Find the peak whose tree contains both nodes (if any)
+    let mut tree_start = 0;
+    for (peak_pos, height) in PeakIterator::new(size) {
+        if pos_a > peak_pos || pos_b > peak_pos {
+            tree_start = peak_pos + 1;
+            continue;
+        }
+        if pos_a < tree_start || pos_b < tree_start {
+            return None;
+        }
+
+        // Descend from the peak until the nodes are in different subtrees (or one of them is
+        // reached). The left child of the node at `pos` with height `height` is at
+        // `pos - 2^height`, and its subtree contains every position in the tree up to it.
+        let (mut pos, mut height) = (peak_pos, height);
+        loop {
+            if pos == pos_a || pos == pos_b {
+                return Some(pos);
+            }
+            let left_pos = pos - (1 << height);
+            let (a_left, b_left) = (pos_a <= left_pos, pos_b <= left_pos);
+            if a_left != b_left {
+                return Some(pos);
+            }
+            pos = if a_left { left_pos } else { pos - 1 };
+            height -= 1;
+        }
+    }
+    None
+}
+
+/// Return the number of nodes (leaves and parents) in an MMR with `leaf_count` leaves, i.e. its
+/// size. The leaves form one perfect tree per set bit of `leaf_count`, and a tree of `n` leaves has
+/// `n - 1` parents, so this is `2 * leaf_count - popcount(leaf_count)`.
+///
+/// For the example MMR depicted in the module documentation of `mmr`, `node_count(11)` is 19.
+pub fn node_count(leaf_count: u64) -> u64 {
+    // Equivalent to 2 * leaf_count - popcount(leaf_count), without overflowing for 2^63 leaves
+    leaf_count + (leaf_count - leaf_count.count_ones() as u64)
+}
+
+/// Return the number of leaves in an MMR with the given (valid) `size`, the inverse of
+/// `node_count`: the sum of the leaves of the tree of each peak. The leaves preceding a leaf form
+/// an MMR whose size is the leaf's position, so `leaf_count(pos)` is also the index of the leaf at
+/// `pos` (and `node_count(index)` the position of the leaf with that index).
+///
+/// For the example MMR depicted in the module documentation of `mmr`, `leaf_count(19)` is 11.
+pub fn leaf_count(size: u64) -> u64 {
+    PeakIterator::new(size)
+        .map(|(_, height)| subtree_leaf_count(height))
+        .sum()
+}
+
+/// Return the number of nodes (leaves and parents) in a perfect binary subtree of the given
+/// `height`, i.e. `2^(height+1) - 1`. A subtree rooted at position `pos` therefore spans positions
+/// `pos + 1 - subtree_size(height)` through `pos`.
+///
+/// Panics if `height` is greater than 63.
+///
+/// For the example MMR depicted in the module documentation of `mmr`, the tree of the first peak
+/// (node 14, height 3) has `subtree_size(3) = 15` nodes.
+pub fn subtree_size(height: u32) -> u64 {
+    // Equivalent to 2^(height+1) - 1, without overflowing for height 63
+    u64::MAX >> (63 - height)
+}
+
+/// Return the number of leaves in a perfect binary subtree of the given `height`, i.e.
+/// `2^height`.
+///
+/// Panics if `height` is greater than 63.
+///
+/// For the example MMR depicted in the module documentation of `mmr`, the tree of the first peak
+/// (node 14, height 3) has `subtree_leaf_count(3) = 8` leaves.
+pub fn subtree_leaf_count(height: u32) -> u64 {
+    1 << height
+}
+
+/// Return the smallest valid MMR size that is at least `n`, e.g. to pad an MMR to the next
+/// boundary. Since every append adds at most 64 nodes, this is at most 63 greater than `n`.
+///
+/// For the example MMR depicted in the module documentation of `mmr`, `next_valid_size(12)` is 15.
+pub fn next_valid_size(mut n: u64) -> u64 {
+    while !PeakIterator::check_validity(n) {
+        n += 1;
+    }
+    n
+}
+
+/// Return the largest valid MMR size that is less than `n`, or `None` if `n` is 0.
+///
+/// For the example MMR depicted in the module documentation of `mmr`, `prev_valid_size(15)` is 11.
+pub fn prev_valid_size(n: u64) -> Option<u64> {
+    let mut size = n.checked_sub(1)?;
+    while !PeakIterator::check_validity(size) {
+        size -= 1;
+    }
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmr::iterator::pos_to_height;
+    use crate::mmr::mem::Mmr;
+    use commonware_cryptography::{Digest, Sha256};
+
+    /// Return the positions of the ancestors of the node at `pos` (including itself) in an MMR
+    /// with the given size, by walking up from the node.
+    fn ancestors(size: u64, mut pos: u64) -> Vec<u64> {
+        let mut ancestors = vec![pos];
+        loop {
+            // A node is a right child if the node following it is its parent (one level higher)
+            let height = pos_to_height(pos);
+            let parent = if pos + 1 < size && pos_to_height(pos + 1) == height + 1 {
+                pos + 1
+            } else {
+                pos + (2 << height)
+            };
+            if parent >= size || pos_to_height(parent) != height + 1 {
+                return ancestors;
+            }
+            ancestors.push(parent);
+            pos = parent;
+        }
+    }
+
+    #[test]
+    fn test_lca() {
+        // The example MMR with 11 elements (19 nodes)
+        assert_eq!(lca(19, 0, 1), Some(2));
+        assert_eq!(lca(19, 0, 3), Some(6));
+        assert_eq!(lca(19, 3, 8), Some(14));
+        assert_eq!(lca(19, 7, 11), Some(13));
+        assert_eq!(lca(19, 15, 16), Some(17));
+        assert_eq!(lca(19, 4, 6), Some(6));
+        assert_eq!(lca(19, 14, 10), Some(14));
+        assert_eq!(lca(19, 18, 18), Some(18));
+
+        // Nodes under different peaks have no common ancestor
+        assert_eq!(lca(19, 8, 16), None);
+        assert_eq!(lca(19, 14, 17), None);
+        assert_eq!(lca(19, 16, 18), None);
+
+        // Nodes outside the MMR have no common ancestor
+        assert_eq!(lca(19, 0, 19), None);
+        assert_eq!(lca(0, 0, 0), None);
+
+        // Compare against the lowest common ancestor found by walking up from each node
+        let size = node_count(100);
+        for pos_a in 0..size {
+            let ancestors_a = ancestors(size, pos_a);
+            for pos_b in 0..size {
+                let ancestors_b = ancestors(size, pos_b);
+                let expected = ancestors_a.iter().find(|pos| ancestors_b.contains(pos));
+                assert_eq!(lca(size, pos_a, pos_b), expected.copied());
+            }
+        }
+    }
+
+    #[test]
+    fn test_node_count() {
+        let expected = [
+            (0, 0),
+            (1, 1),
+            (2, 3),
+            (3, 4),
+            (4, 7),
+            (7, 11),
+            (8, 15),
+            (11, 19),
+        ];
+        for (leaf_count, size) in expected {
+            assert_eq!(node_count(leaf_count), size);
+        }
+        assert_eq!(node_count(1 << 63), u64::MAX);
+
+        // Matches the size of an MMR after each add
+        let mut mmr = Mmr::<Sha256>::new();
+        for leaf_count in 0u64..=100 {
+            assert_eq!(node_count(leaf_count), mmr.size());
+            mmr.add(&Digest::from(vec![leaf_count as u8; 32]));
+        }
+    }
+
+    #[test]
+    fn test_leaf_count() {
+        let expected = [
+            (0, 0),
+            (1, 1),
+            (3, 2),
+            (4, 3),
+            (7, 4),
+            (11, 7),
+            (15, 8),
+            (19, 11),
+        ];
+        for (size, count) in expected {
+            assert_eq!(leaf_count(size), count);
+        }
+
+        // Inverts `node_count`, and gives the index of each leaf from its position
+        let mut mmr = Mmr::<Sha256>::new();
+        for index in 0u64..=100 {
+            assert_eq!(leaf_count(node_count(index)), index);
+            let pos = mmr.add(&Digest::from(vec![index as u8; 32]));
+            assert_eq!(leaf_count(pos), index);
+        }
+    }
+
+    #[test]
+    fn test_subtree_size() {
+        for height in 0u32..=62 {
+            assert_eq!(subtree_size(height), 2u64.pow(height + 1) - 1);
+            assert_eq!(subtree_leaf_count(height), 2u64.pow(height));
+            assert_eq!(subtree_size(height), 2 * subtree_leaf_count(height) - 1);
+        }
+        assert_eq!(subtree_size(63), u64::MAX);
+        assert_eq!(subtree_leaf_count(63), 1 << 63);
+
+        // Each peak of an MMR spans the subtree ending at its position
+        let size = node_count(100);
+        let mut start = 0;
+        let mut leaves = 0;
+        for (peak_pos, height) in PeakIterator::new(size) {
+            assert_eq!(peak_pos + 1 - subtree_size(height), start);
+            start = peak_pos + 1;
+            leaves += subtree_leaf_count(height);
+        }
+        assert_eq!(start, size);
+        assert_eq!(leaves, 100);
+    }
+
+    #[test]
+    fn test_valid_size() {
+        assert_eq!(next_valid_size(0), 0);
+        assert_eq!(next_valid_size(12), 15);
+        assert_eq!(next_valid_size(u64::MAX - 1), u64::MAX);
+        assert_eq!(prev_valid_size(0), None);
+        assert_eq!(prev_valid_size(1), Some(0));
+        assert_eq!(prev_valid_size(15), Some(11));
+
+        for n in 0..10_000 {
+            let next = next_valid_size(n);
+            assert!(next >= n);
+            assert!(PeakIterator::check_validity(next));
+            assert!((n..next).all(|size| !PeakIterator::check_validity(size)));
+
+            let Some(prev) = prev_valid_size(n) else {
+                assert_eq!(n, 0);
+                continue;
+            };
+            assert!(prev < n);
+            assert!(PeakIterator::check_validity(prev));
+            assert!((prev + 1..n).all(|size| !PeakIterator::check_validity(size)));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! An MMR whose leaves authenticate arbitrary values along with their positions.
+//!
+//! A `ValueMmr` stores the value added at each leaf alongside an in-memory `Mmr` in which the value
+//! itself is the element, so (by the MMR's position-bound leaf hashing) each leaf is `Hash(pos,
+//! value)`. A `ValueProof` for a leaf therefore authenticates both the value and the position it
+//! was added at, as in a key-value log keyed by position.
+
+use crate::mmr::mem::Mmr;
+use crate::mmr::verification::Proof;
+use crate::mmr::Error;
+use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::collections::HashMap;
+
+/// An inclusion proof for the value stored at a leaf of a `ValueMmr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValueProof {
+    pub proof: Proof,
+}
+
+impl ValueProof {
+    /// Return true if the proof proves that `value` is stored at the leaf at position `pos` within
+    /// the `ValueMmr` with root hash `root_hash`.
+    pub fn verify_value_inclusion<H: CHasher>(
+        &self,
+        pos: u64,
+        value: &[u8],
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        self.proof
+            .verify_element_inclusion(&Digest::copy_from_slice(value), pos, root_hash, hasher)
+    }
+}
+
+/// Implementation of `ValueMmr`.
+pub struct ValueMmr<H: CHasher> {
+    mmr: Mmr<H>,
+    // The value stored at each leaf, keyed by position.
+    values: HashMap<u64, Digest>,
+}
+
+impl<H: CHasher> Default for ValueMmr<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: CHasher> ValueMmr<H> {
+    /// Return a new (empty) `ValueMmr`.
+    pub fn new() -> Self {
+        Self {
+            mmr: Mmr::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Return the underlying MMR.
+    pub fn mmr(&self) -> &Mmr<H> {
+        &self.mmr
+    }
+
+    /// Return the total number of nodes in the MMR.
+    pub fn size(&self) -> u64 {
+        self.mmr.size()
+    }
+
+    /// Add `value` as a new leaf and return its position in the MMR.
+    pub fn add(&mut self, value: &[u8]) -> u64 {
+        let value = Digest::copy_from_slice(value);
+        let pos = self.mmr.add(&value);
+        self.values.insert(pos, value);
+        pos
+    }
+
+    /// Return the value stored at the leaf at position `pos`, if any.
+    pub fn get(&self, pos: u64) -> Option<&[u8]> {
+        self.values.get(&pos).map(|value| value.as_ref())
+    }
+
+    /// Computes the root hash of the MMR.
+    pub fn root_hash(&mut self) -> Digest {
+        self.mmr.root_hash()
+    }
+
+    /// Return the value stored at the leaf at position `pos` along with a proof of its inclusion.
+    ///
+    /// Returns `Error::InvalidPos` if there is no leaf at `pos`.
+    pub fn proof(&self, pos: u64) -> Result<(&[u8], ValueProof), Error> {
+        let value = self.get(pos).ok_or(Error::InvalidPos(pos))?;
+        let proof = self.mmr.proof(pos)?;
+        Ok((value, ValueProof { proof }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commonware_cryptography::Sha256;
+
+    #[test]
+    fn test_value_mmr() {
+        let mut mmr = ValueMmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let values: Vec<Vec<u8>> = (0u8..49).map(|i| vec![i; i as usize]).collect();
+        let positions: Vec<u64> = values.iter().map(|value| mmr.add(value)).collect();
+        let root_hash = mmr.root_hash();
+
+        // Leaves are the position-bound hashes of the values, as if they were added as elements
+        let mut reference = Mmr::<Sha256>::new();
+        for value in values.iter() {
+            reference.add(&Digest::copy_from_slice(value));
+        }
+        assert_eq!(root_hash, reference.root_hash());
+
+        // Every value is proven at its position
+        for (value, pos) in values.iter().zip(positions.iter()) {
+            assert_eq!(mmr.get(*pos), Some(value.as_slice()));
+            let (stored, proof) = mmr.proof(*pos).unwrap();
+            assert_eq!(stored, value.as_slice());
+            assert!(proof.verify_value_inclusion(*pos, value, &root_hash, &mut hasher));
+        }
+
+        // Proofs don't verify for other values or positions
+        let (_, proof) = mmr.proof(positions[10]).unwrap();
+        assert!(!proof.verify_value_inclusion(positions[10], &values[11], &root_hash, &mut hasher));
+        assert!(!proof.verify_value_inclusion(positions[11], &values[10], &root_hash, &mut hasher));
+
+        // Non-leaf positions hold no value
+        assert_eq!(mmr.get(2), None);
+        assert_eq!(mmr.proof(2).err(), Some(Error::InvalidPos(2)));
+    }
+}
@@ -1,6 +1,9 @@
-use crate::mmr::hasher::Hasher;
-use crate::mmr::iterator::PeakIterator;
+use crate::mmr::hasher::{Hasher, Scheme};
+use crate::mmr::iterator::{next_leaf_pos, pos_to_height, PathIterator, PeakIterator};
+use crate::mmr::util::{self, leaf_count, subtree_size};
+use crate::mmr::Error;
 use commonware_cryptography::{Digest, Hasher as CHasher};
+use std::collections::HashMap;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// A Proof contains the information necessary for proving the inclusion of an element, or some
@@ -8,11 +11,335 @@ use commonware_cryptography::{Digest, Hasher as CHasher};
 pub struct Proof {
     pub size: u64, // total # of nodes in the MMR
     pub hashes: Vec<Digest>,
+    pub scheme: Scheme, // the scheme the MMR hashes with (supplied by the verifier, not serialized)
+}
+
+/// A ProofContext records the state of the MMR a proof was generated against, allowing the proof
+/// to later be verified against the root it was actually generated for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofContext {
+    pub root: Digest, // root hash of the MMR
+    pub size: u64,    // total # of nodes in the MMR
+}
+
+/// A ProofCost estimates the work required to generate a proof, derived purely from the positions
+/// of the nodes involved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofCost {
+    pub node_reads: usize,   // # of stored nodes read to generate the proof
+    pub proof_hashes: usize, // # of hashes in the generated proof
+}
+
+impl ProofCost {
+    /// Return the cost of generating a proof for the specified range of elements within an MMR of
+    /// the given `size`. Every hash in a proof is read directly from a stored node, so the number
+    /// of node reads equals the number of proof hashes.
+    pub(crate) fn for_range(size: u64, start_element_pos: u64, end_element_pos: u64) -> Self {
+        let nodes = nodes_required_for_range_proof(size, start_element_pos, end_element_pos).len();
+        Self {
+            node_reads: nodes,
+            proof_hashes: nodes,
+        }
+    }
+}
+
+/// A ProofStrategy describes how an element (or cluster of elements) in a batch is proven by
+/// `Mmr::proof_batch_optimal`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofStrategy {
+    /// A proof for the single element at the given position.
+    Individual(u64),
+    /// A range proof spanning the elements between the given positions (inclusive), which can only
+    /// be verified given every element in the span (not only those requested).
+    Range(u64, u64),
+}
+
+/// An ElementWitness carries the pre-image of an element, for applications in which the element
+/// added to the MMR is the hash of some raw bytes that the prover must also supply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElementWitness {
+    pub element_bytes: Vec<u8>, // bytes whose hash is the element
+}
+
+/// An MmrDelta holds the nodes appended to an MMR since it had some size, from which proofs
+/// generated at that size can be brought up to date with `Proof::apply_delta`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MmrDelta {
+    pub size: u64,          // total # of nodes in the MMR before the nodes were appended
+    pub nodes: Vec<Digest>, // hashes of the appended nodes, in position order
+}
+
+/// A Checkpoint is a consistent snapshot of the size, leaf count, and root hash of an MMR, suitable
+/// for signing and gossiping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub size: u64,       // total # of nodes in the MMR
+    pub leaf_count: u64, // total # of elements in the MMR
+    pub root: Digest,    // root hash of the MMR
+}
+
+impl Checkpoint {
+    /// Serialize the checkpoint as the big-endian size and leaf count followed by the root hash.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + 8 + self.root.len());
+        bytes.extend_from_slice(&self.size.to_be_bytes());
+        bytes.extend_from_slice(&self.leaf_count.to_be_bytes());
+        bytes.extend_from_slice(&self.root);
+        bytes
+    }
+
+    /// Deserialize a checkpoint produced by `serialize`, returning `None` if the bytes are not a
+    /// well-formed checkpoint for the given hasher.
+    pub fn deserialize<H: CHasher>(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 8 + 8 + H::len() {
+            return None;
+        }
+        let size = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let leaf_count = u64::from_be_bytes(bytes[8..16].try_into().unwrap());
+        if !PeakIterator::check_validity(size) {
+            return None;
+        }
+        if leaf_count != util::leaf_count(size) {
+            return None;
+        }
+        Some(Self {
+            size,
+            leaf_count,
+            root: Digest::copy_from_slice(&bytes[16..]),
+        })
+    }
 }
 
 impl Proof {
+    /// Serialize the proof as the big-endian size and hash count followed by the hashes. The
+    /// `scheme` is a property of the MMR rather than of any one proof, so it isn't serialized.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let hashes_len: usize = self.hashes.iter().map(|hash| hash.len()).sum();
+        let mut bytes = Vec::with_capacity(8 + 4 + hashes_len);
+        bytes.extend_from_slice(&self.size.to_be_bytes());
+        bytes.extend_from_slice(&(self.hashes.len() as u32).to_be_bytes());
+        for hash in self.hashes.iter() {
+            bytes.extend_from_slice(hash);
+        }
+        bytes
+    }
+
+    /// Deserialize a proof produced by `to_bytes` from (possibly untrusted) bytes, to be verified
+    /// under `scheme`: the scheme (and key) of the MMR the verifier expects, which the prover can't
+    /// choose.
+    ///
+    /// The hash count in the header is checked against the length of the buffer before anything is
+    /// allocated, so a crafted header can't trigger an allocation larger than the input itself.
+    /// The returned proof is not otherwise validated (see `sanity_check`).
+    pub fn from_bytes<H: CHasher>(bytes: &[u8], scheme: Scheme) -> Result<Self, Error> {
+        if bytes.len() < 8 + 4 {
+            return Err(Error::InvalidProofEncoding(bytes.len()));
+        }
+        let size = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let count = u32::from_be_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let body = &bytes[12..];
+        let digest_len = H::len();
+        if count.checked_mul(digest_len) != Some(body.len()) {
+            return Err(Error::InvalidProofEncoding(bytes.len()));
+        }
+        let hashes = body
+            .chunks_exact(digest_len)
+            .map(Digest::copy_from_slice)
+            .collect();
+        Ok(Self {
+            size,
+            hashes,
+            scheme,
+        })
+    }
+
+    /// Cheaply check that the proof is structurally plausible without performing any hashing:
+    /// `size` must be the size of a non-empty MMR, the number of hashes must not exceed the
+    /// maximum any range proof over an MMR of that size can contain, and every hash must be
+    /// `expected_digest_len` bytes long.
+    ///
+    /// A successful check does not imply the proof will verify.
+    pub fn sanity_check(&self, expected_digest_len: usize) -> Result<(), Error> {
+        if self.size == 0 || !PeakIterator::check_validity(self.size) {
+            return Err(Error::InvalidSize(self.size));
+        }
+
+        // A range proof contains the hashes of the peaks that don't contain the range, plus at
+        // most one sibling per level along both the leftmost and rightmost paths of the range.
+        let mut peak_iterator = PeakIterator::new(self.size);
+        let (_, max_height) = peak_iterator.next().unwrap();
+        let peak_count = 1 + peak_iterator.count();
+        let max_hashes = peak_count - 1 + 2 * max_height as usize;
+        if self.hashes.len() > max_hashes {
+            return Err(Error::InvalidProofLength(self.hashes.len()));
+        }
+
+        for hash in self.hashes.iter() {
+            if hash.len() != expected_digest_len {
+                return Err(Error::InvalidDigestLength(expected_digest_len, hash.len()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the position of the node corresponding to each hash in the proof (in the same order
+    /// as `hashes`), assuming the proof is for the specified range of elements. The range is
+    /// inclusive of both endpoints.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is not contained within an MMR of size `self.size`.
+    pub fn positions(&self, start_element_pos: u64, end_element_pos: u64) -> Vec<u64> {
+        nodes_required_for_range_proof(self.size, start_element_pos, end_element_pos)
+    }
+
+    /// Return the number of elements that must be supplied to verify the proof as a proof for the
+    /// specified range of elements (one per leaf in the range), computed from position math alone.
+    /// The range is inclusive of both endpoints.
+    ///
+    /// Returns `Error::InvalidRange` if either endpoint isn't the position of a leaf within an MMR
+    /// of size `self.size` (or they're out of order).
+    pub fn required_elements_count(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+    ) -> Result<usize, Error> {
+        if !PeakIterator::check_validity(self.size)
+            || start_element_pos > end_element_pos
+            || end_element_pos >= self.size
+            || pos_to_height(start_element_pos) != 0
+            || pos_to_height(end_element_pos) != 0
+        {
+            return Err(Error::InvalidRange(start_element_pos, end_element_pos));
+        }
+        Ok(self.leaf_span(start_element_pos, end_element_pos) as usize)
+    }
+
+    /// Return the number of leaves covered by the specified range of elements, skipping the
+    /// positions of any parent nodes between them. The range is inclusive of both endpoints.
+    ///
+    /// This lets a caller check that it holds one element per leaf before verifying the proof (see
+    /// also `required_elements_count`, which returns an error rather than panicking).
+    ///
+    /// # Panics
+    ///
+    /// Panics if either endpoint isn't the position of a leaf within an MMR of size `self.size`, or
+    /// if they're out of order.
+    pub fn leaf_span(&self, start_element_pos: u64, end_element_pos: u64) -> u64 {
+        assert!(
+            pos_to_height(start_element_pos) == 0 && pos_to_height(end_element_pos) == 0,
+            "position is not an element"
+        );
+        assert!(start_element_pos <= end_element_pos, "range out of order");
+        assert!(end_element_pos < self.size, "range exceeds size");
+
+        leaf_count(end_element_pos) - leaf_count(start_element_pos) + 1
+    }
+
+    /// Return the proof for the specified range of elements within the MMR after the nodes of
+    /// `delta` were appended, given that `self` is a proof for that range before they were (and
+    /// that `delta` starts at its size). The range is inclusive of both endpoints.
+    ///
+    /// Nodes are never modified once added, so the siblings within the tree of the range's old peak
+    /// are unchanged. Every other node the updated proof requires is either an old peak (which is
+    /// in `self`) or an appended node (which is in `delta`), so no other nodes need be fetched. The
+    /// updated proof is only as trustworthy as `delta`, and must be verified against the new root.
+    ///
+    /// Returns `Error::InvalidSize` if `delta` doesn't start at the proof's size or doesn't end at
+    /// a valid MMR size, `Error::InvalidRange` if the range isn't contained within an MMR of the
+    /// proof's size, and `Error::InvalidProofLength` if the proof has the wrong number of hashes
+    /// for the range.
+    pub fn apply_delta(
+        &self,
+        start_element_pos: u64,
+        end_element_pos: u64,
+        delta: &MmrDelta,
+    ) -> Result<Proof, Error> {
+        if delta.size != self.size {
+            return Err(Error::InvalidSize(delta.size));
+        }
+        let size = self.size + delta.nodes.len() as u64;
+        if !PeakIterator::check_validity(size) {
+            return Err(Error::InvalidSize(size));
+        }
+        self.required_elements_count(start_element_pos, end_element_pos)?;
+        let positions = self.positions(start_element_pos, end_element_pos);
+        if positions.len() != self.hashes.len() {
+            return Err(Error::InvalidProofLength(self.hashes.len()));
+        }
+
+        let mut nodes: HashMap<u64, &Digest> = positions.into_iter().zip(&self.hashes).collect();
+        nodes.extend((self.size..).zip(&delta.nodes));
+        let hashes = nodes_required_for_range_proof(size, start_element_pos, end_element_pos)
+            .into_iter()
+            .map(|pos| nodes[&pos].clone())
+            .collect();
+        Ok(Proof {
+            size,
+            hashes,
+            scheme: self.scheme.clone(),
+        })
+    }
+
+    /// Return true if the proof is of an empty MMR (it has size 0 and no hashes) and `root_hash` is
+    /// the root hash of an empty MMR (see `Mmr::empty_root`).
+    pub fn verify_empty<H: CHasher>(&self, root_hash: &Digest, hasher: &mut H) -> bool {
+        self.size == 0
+            && self.hashes.is_empty()
+            && Hasher::with_scheme(hasher, self.scheme.clone()).root_hash(0, std::iter::empty())
+                == *root_hash
+    }
+
+    /// Return true if `self`, a proof for the range `self_start..=self_end`, holds everything needed
+    /// to verify `other`, a proof for the range `other_start..=other_end` within an MMR of the same
+    /// size, given the elements of `self`'s range. Each hash in `other` must either be in `self` (at
+    /// the same position, with the same value) or be the hash of a node whose subtree contains some
+    /// element of `self`'s range (which verifying `self` computes). In particular, a proof covers
+    /// every proof of a sub-range of its own. A caching layer holding `self` (and its elements)
+    /// needn't request `other`.
+    ///
+    /// Returns false if the proofs are for different sizes, or if either range isn't contained
+    /// within an MMR of that size or doesn't match the number of hashes in its proof.
+    pub fn is_superset_of(
+        &self,
+        other: &Proof,
+        self_start: u64,
+        self_end: u64,
+        other_start: u64,
+        other_end: u64,
+    ) -> bool {
+        if self.size != other.size || !PeakIterator::check_validity(self.size) {
+            return false;
+        }
+        for (start, end) in [(self_start, self_end), (other_start, other_end)] {
+            if start > end || end >= self.size {
+                return false;
+            }
+        }
+        let self_positions = self.positions(self_start, self_end);
+        let other_positions = other.positions(other_start, other_end);
+        if self_positions.len() != self.hashes.len() || other_positions.len() != other.hashes.len()
+        {
+            return false;
+        }
+        other_positions
+            .iter()
+            .zip(other.hashes.iter())
+            .all(|(pos, hash)| {
+                if let Some(index) = self_positions.iter().position(|p| p == pos) {
+                    return self.hashes[index] == *hash;
+                }
+                let height = pos_to_height(*pos);
+                let leftmost_leaf = pos + 1 - subtree_size(height);
+                leftmost_leaf <= self_end && pos - height as u64 >= self_start
+            })
+    }
+
     /// Return true if `proof` proves that `element` appears at position `element_pos` within the MMR
     /// with root hash `root_hash`.
+    ///
+    /// With the `tracing` feature enabled, emits a debug event recording the size of the MMR, the
+    /// element's position, the number of hashes in the proof, the outcome, and the time taken.
     pub fn verify_element_inclusion<H: CHasher>(
         &self,
         element: &Digest,
@@ -20,12 +347,163 @@ impl Proof {
         root_hash: &Digest,
         hasher: &mut H,
     ) -> bool {
-        self.verify_range_inclusion(
+        #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+        let start = std::time::Instant::now();
+        let verified = self.verify_range_inclusion(
             &[element.clone()],
             element_pos,
             element_pos,
             root_hash,
             hasher,
+        );
+        #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+        tracing::debug!(
+            mmr_size = self.size,
+            element_pos,
+            num_hashes = self.hashes.len(),
+            verified,
+            duration_ns = start.elapsed().as_nanos() as u64,
+            "verified proof"
+        );
+        verified
+    }
+
+    /// Return true if `proof` proves that the hash of the `witness` bytes appears at position
+    /// `element_pos` within the MMR with root hash `root_hash` (see `Mmr::proof_with_witness`).
+    pub fn verify_element_with_witness<H: CHasher>(
+        &self,
+        witness: &ElementWitness,
+        element_pos: u64,
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        hasher.update(&witness.element_bytes);
+        let element = hasher.finalize();
+        self.verify_element_inclusion(&element, element_pos, root_hash, hasher)
+    }
+
+    /// Return true if `peaks` are the peaks of an MMR of the proof's size whose root hash is
+    /// `bagged_root`, and `proof` proves that `element` appears at position `element_pos` within it.
+    ///
+    /// This allows clients that only store the peaks of an MMR (rather than its nodes) to verify
+    /// against them directly. `peaks` must hold the position and hash of every peak, ordered
+    /// left-to-right by position (the order of `Mmr::peak_hashes`, which is decreasing order of
+    /// height). Any other ordering, or a missing or extra peak, fails verification.
+    pub fn verify_element_inclusion_with_precomputed_peaks<H: CHasher>(
+        &self,
+        element: &Digest,
+        element_pos: u64,
+        peaks: &[(u64, Digest)],
+        bagged_root: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        let positions = peaks.iter().map(|(peak_pos, _)| *peak_pos);
+        if !PeakIterator::new(self.size)
+            .map(|(peak_pos, _)| peak_pos)
+            .eq(positions)
+        {
+            return false;
+        }
+        let root = Hasher::with_scheme(hasher, self.scheme.clone())
+            .root_hash(self.size, peaks.iter().map(|(_, hash)| hash));
+        if root != *bagged_root {
+            return false;
+        }
+        self.verify_element_inclusion(element, element_pos, bagged_root, hasher)
+    }
+
+    /// Return true if `proof` proves that the `elements` appear consecutively between positions
+    /// `start_element_pos` through `end_element_pos` (inclusive) within the MMR as it existed when
+    /// it had `trusted_size` nodes and root hash `trusted_root_hash`. Peaks added after
+    /// `trusted_size` play no part in the verification, so the proof must have been generated at
+    /// that size (see `Mmr::historical_range_proof`). Returns false if the range extends beyond
+    /// `trusted_size`.
+    pub fn verify_historical_range_inclusion<H: CHasher>(
+        &self,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        trusted_size: u64,
+        trusted_root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        if end_element_pos >= trusted_size || self.size != trusted_size {
+            return false;
+        }
+        self.verify_range_inclusion(
+            elements,
+            start_element_pos,
+            end_element_pos,
+            trusted_root_hash,
+            hasher,
+        )
+    }
+
+    /// Return true if `proof` proves that `element` appears at position `element_pos` and
+    /// `next_element` appears at the position of the element immediately following it within the
+    /// MMR with root hash `root_hash`.
+    pub fn verify_adjacency<H: CHasher>(
+        &self,
+        element: &Digest,
+        element_pos: u64,
+        next_element: &Digest,
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        if pos_to_height(element_pos) != 0 {
+            return false;
+        }
+        let next_element_pos = next_leaf_pos(element_pos);
+        if next_element_pos >= self.size {
+            return false;
+        }
+        self.verify_range_inclusion(
+            &[element.clone(), next_element.clone()],
+            element_pos,
+            next_element_pos,
+            root_hash,
+            hasher,
+        )
+    }
+
+    /// Return true if `proof` proves that `element` appears at position `element_pos` within the MMR
+    /// described by `checkpoint`.
+    pub fn verify_element_inclusion_at_checkpoint<H: CHasher>(
+        &self,
+        element: &Digest,
+        element_pos: u64,
+        checkpoint: &Checkpoint,
+        hasher: &mut H,
+    ) -> bool {
+        self.verify_range_inclusion_at_checkpoint(
+            &[element.clone()],
+            element_pos,
+            element_pos,
+            checkpoint,
+            hasher,
+        )
+    }
+
+    /// Return true if `proof` proves that the `elements` appear consecutively between positions
+    /// `start_element_pos` through `end_element_pos` (inclusive) within the MMR described by
+    /// `checkpoint`.
+    pub fn verify_range_inclusion_at_checkpoint<H: CHasher>(
+        &self,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        checkpoint: &Checkpoint,
+        hasher: &mut H,
+    ) -> bool {
+        if self.size != checkpoint.size {
+            return false;
+        }
+        self.verify_range_inclusion(
+            elements,
+            start_element_pos,
+            end_element_pos,
+            &checkpoint.root,
+            hasher,
         )
     }
 
@@ -40,21 +518,172 @@ impl Proof {
         root_hash: &Digest,
         hasher: &mut H,
     ) -> bool {
-        let mut proof_hashes_iter = self.hashes.iter();
+        self.verify_range_inclusion_from(
+            elements.iter(),
+            start_element_pos,
+            end_element_pos,
+            root_hash,
+            hasher,
+        )
+    }
+
+    /// Return true if `proof` proves that the `elements`, provided in reverse order (the element
+    /// at `end_element_pos` first), appear consecutively between positions `start_element_pos`
+    /// through `end_element_pos` (inclusive) within the MMR with root hash `root_hash`.
+    pub fn verify_range_inclusion_reversed<H: CHasher>(
+        &self,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        self.verify_range_inclusion_from(
+            elements.iter().rev(),
+            start_element_pos,
+            end_element_pos,
+            root_hash,
+            hasher,
+        )
+    }
+
+    /// Return the index of the first of the candidate `roots` against which `proof` proves that the
+    /// `elements` appear consecutively between positions `start_element_pos` through
+    /// `end_element_pos` (inclusive), or `None` if it matches none of them.
+    ///
+    /// The root implied by the proof is reconstructed only once, regardless of the number of
+    /// candidates (e.g. the competing roots of a reorg).
+    pub fn verify_against_any<H: CHasher>(
+        &self,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        roots: &[Digest],
+        hasher: &mut H,
+    ) -> Option<usize> {
+        let root = self.reconstruct_root_from(
+            elements.iter(),
+            start_element_pos,
+            end_element_pos,
+            hasher,
+        )?;
+        roots.iter().position(|candidate| *candidate == root)
+    }
+
+    /// Return true if `proof` (see `Mmr::sparse_proof`) proves that the `elements` appear, in
+    /// increasing order of position, at the positions between `start_element_pos` and
+    /// `end_element_pos` (inclusive) whose entries in `present` are set, within the MMR with root
+    /// hash `root_hash`. `present` holds one entry per leaf in the range, in order, so leaves of
+    /// the range that are absent may be skipped without supplying their elements.
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_sparse_inclusion<H: CHasher>(
+        &self,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        present: &[bool],
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        // Reject anomalous sizes as `reconstruct_root_from` does
+        if self.size == 0 || !PeakIterator::check_validity(self.size) || self.size == u64::MAX {
+            return false;
+        }
+        if end_element_pos >= self.size {
+            return false;
+        }
+        let Some(leaves) = present_positions(start_element_pos, end_element_pos, present) else {
+            return false;
+        };
+        if leaves.len() != elements.len() {
+            return false;
+        }
+
         let mut elements_iter = elements.iter();
+        let mut hashes_iter = self.hashes.iter();
+        let mut mmr_hasher = Hasher::<H>::with_scheme(hasher, self.scheme.clone());
+        let mut peak_hashes = Vec::new();
+        let mut leaves = leaves.as_slice();
+        for (peak_pos, height) in PeakIterator::new(self.size) {
+            let split = leaves.partition_point(|pos| *pos <= peak_pos);
+            let Some(peak_hash) = sparse_subtree_hash(
+                peak_pos,
+                height,
+                &leaves[..split],
+                &mut elements_iter,
+                &mut hashes_iter,
+                &mut mmr_hasher,
+            ) else {
+                return false;
+            };
+            peak_hashes.push(peak_hash);
+            leaves = &leaves[split..];
+        }
+
+        // Every hash must have been consumed to prevent proof malleability
+        if hashes_iter.next().is_some() {
+            return false;
+        }
+        mmr_hasher.root_hash(self.size, peak_hashes.iter()) == *root_hash
+    }
+
+    /// Verify the range proof consuming `elements_iter` in increasing order of position.
+    fn verify_range_inclusion_from<'a, H: CHasher>(
+        &'a self,
+        elements_iter: impl ExactSizeIterator<Item = &'a Digest>,
+        start_element_pos: u64,
+        end_element_pos: u64,
+        root_hash: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        self.reconstruct_root_from(elements_iter, start_element_pos, end_element_pos, hasher)
+            .is_some_and(|root| root == *root_hash)
+    }
+
+    /// Return the root hash of the MMR implied by the range proof and the elements it proves
+    /// (consumed from `elements_iter` in increasing order of position), or `None` if there isn't
+    /// exactly one element per leaf in the range (see `required_elements_count`) or the proof
+    /// doesn't have exactly the hashes required.
+    ///
+    /// The proof may be untrusted, so all position arithmetic is checked and any anomaly (such as a
+    /// `size` that isn't a valid MMR size) results in `None` rather than a panic or wrapped value.
+    fn reconstruct_root_from<'a, H: CHasher>(
+        &'a self,
+        mut elements_iter: impl ExactSizeIterator<Item = &'a Digest>,
+        start_element_pos: u64,
+        end_element_pos: u64,
+        hasher: &mut H,
+    ) -> Option<Digest> {
+        // No range is contained within an empty MMR, and the tallest tree of an MMR of size
+        // `u64::MAX` has 2^64 leaves, which can't be represented
+        if self.size == 0 || !PeakIterator::check_validity(self.size) || self.size == u64::MAX {
+            return None;
+        }
+
+        // Reject a mismatched number of elements before hashing any of them
+        let required = self
+            .required_elements_count(start_element_pos, end_element_pos)
+            .ok()?;
+        if elements_iter.len() != required {
+            return None;
+        }
+        let mut proof_hashes_iter = self.hashes.iter();
         let mut siblings_iter = self.hashes.iter().rev();
-        let mut mmr_hasher = Hasher::<H>::new(hasher);
+        let mut mmr_hasher = Hasher::<H>::with_scheme(hasher, self.scheme.clone());
 
         // Include peak hashes only for trees that have no elements from the range, and keep track of
         // the starting and ending trees of those that do contain some.
         let mut peak_hashes: Vec<Digest> = Vec::new();
         let mut proof_hashes_used = 0;
         for (peak_pos, height) in PeakIterator::new(self.size) {
-            let leftmost_pos = peak_pos + 2 - (1 << (height + 1));
+            let two_h = 1u64.checked_shl(height)?;
+            let leftmost_pos = peak_pos
+                .checked_add(2)?
+                .checked_sub(two_h.checked_mul(2)?)?;
             if peak_pos >= start_element_pos && leftmost_pos <= end_element_pos {
                 match peak_hash_from_range(
                     peak_pos,
-                    1 << height,
+                    two_h,
                     start_element_pos,
                     end_element_pos,
                     &mut elements_iter,
@@ -62,19 +691,21 @@ impl Proof {
                     &mut mmr_hasher,
                 ) {
                     Ok(peak_hash) => peak_hashes.push(peak_hash),
-                    Err(_) => return false, // missing hashes
+                    Err(_) => return None, // missing hashes
                 }
             } else if let Some(hash) = proof_hashes_iter.next() {
                 proof_hashes_used += 1;
                 peak_hashes.push(hash.clone());
             } else {
-                return false;
+                return None;
             }
         }
 
         if elements_iter.next().is_some() {
-            return false; // some elements were not used in the proof
+            return None; // some elements were not used in the proof
         }
+        // If the range is within the only peak, no peak hashes are used and every hash must have
+        // been consumed as a sibling.
         let next_sibling = siblings_iter.next();
         if (proof_hashes_used == 0 && next_sibling.is_some())
             || (next_sibling.is_some()
@@ -82,43 +713,426 @@ impl Proof {
         {
             // some proof data was not used during verification, so we must return false to prevent
             // proof malleability attacks.
-            return false;
+            return None;
         }
-        *root_hash == mmr_hasher.root_hash(self.size, peak_hashes.iter())
+        Some(mmr_hasher.root_hash(self.size, peak_hashes.iter()))
     }
 }
 
-fn peak_hash_from_range<'a, H: CHasher>(
-    node_pos: u64,      // current node position in the tree
-    two_h: u64,         // 2^height of the current node
-    leftmost_pos: u64,  // leftmost leaf in the tree to be traversed
-    rightmost_pos: u64, // rightmost leaf in the tree to be traversed
-    elements: &mut impl Iterator<Item = &'a Digest>,
-    sibling_hashes: &mut impl Iterator<Item = &'a Digest>,
-    hasher: &mut Hasher<H>,
-) -> Result<Digest, ()> {
-    assert_ne!(two_h, 0);
-    if two_h == 1 {
-        // we are at a leaf
-        match elements.next() {
-            Some(element) => return Ok(hasher.leaf_hash(node_pos, element)),
-            None => return Err(()),
-        }
+/// A ProofBuilder assembles a `Proof` from a size and a sequence of hashes, allowing proofs to be
+/// constructed by hand (e.g. to exercise other verifiers in tests).
+#[derive(Clone, Debug, Default)]
+pub struct ProofBuilder {
+    size: u64,
+    hashes: Vec<Digest>,
+    scheme: Scheme,
+}
+
+impl ProofBuilder {
+    /// Return a builder for a proof with no hashes over an MMR of size 0.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    let left_pos = node_pos - two_h;
-    let mut left_hash: Option<Digest> = None;
-    let right_pos = left_pos + two_h - 1;
-    let mut right_hash: Option<Digest> = None;
+    /// Set the size of the MMR the proof is over.
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = size;
+        self
+    }
 
-    if left_pos >= leftmost_pos {
-        // Descend left
-        match peak_hash_from_range(
-            left_pos,
-            two_h >> 1,
-            leftmost_pos,
-            rightmost_pos,
-            elements,
+    /// Set the scheme of the MMR the proof is over (`Scheme::Tagged` by default).
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Append `hash` to the proof's hashes.
+    pub fn push_hash(mut self, hash: Digest) -> Self {
+        self.hashes.push(hash);
+        self
+    }
+
+    /// Return the assembled proof, or an error if it fails `Proof::sanity_check` with every hash
+    /// expected to be as long as the first.
+    pub fn build(self) -> Result<Proof, Error> {
+        let proof = Proof {
+            size: self.size,
+            hashes: self.hashes,
+            scheme: self.scheme,
+        };
+        let digest_len = proof.hashes.first().map_or(0, |hash| hash.len());
+        proof.sanity_check(digest_len)?;
+        Ok(proof)
+    }
+}
+
+/// The version of the format produced by `FrozenProof::to_bytes`.
+const FROZEN_PROOF_VERSION: u8 = 1;
+
+/// A FrozenProof is a `Proof` that may only be interpreted against the MMR size it was generated
+/// at. Rather than the root hash of the MMR, it is verified against a "frozen root" that
+/// additionally commits to the format version and the size (see `FrozenProof::frozen_root`), so
+/// verification fails whenever the proof is presented with any other size (even one whose peak
+/// structure is identical).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrozenProof {
+    pub proof: Proof,
+}
+
+impl FrozenProof {
+    /// Return a frozen proof wrapping `proof`, which is bound to `proof.size`.
+    pub fn new(proof: Proof) -> Self {
+        Self { proof }
+    }
+
+    /// Return the size of the MMR the proof is bound to.
+    pub fn size(&self) -> u64 {
+        self.proof.size
+    }
+
+    /// Computes the frozen root that proofs bound to `size` are verified against, given the root
+    /// hash of the MMR at that size.
+    pub fn frozen_root<H: CHasher>(size: u64, root_hash: &Digest, hasher: &mut H) -> Digest {
+        hasher.update(&[FROZEN_PROOF_VERSION]);
+        hasher.update(&size.to_be_bytes());
+        hasher.update(root_hash);
+        hasher.finalize()
+    }
+
+    /// Return true if the proof proves that `element` appears at position `element_pos` within the
+    /// MMR whose frozen root (at the size the proof is bound to) is `frozen_root`.
+    pub fn verify_element_inclusion<H: CHasher>(
+        &self,
+        element: &Digest,
+        element_pos: u64,
+        frozen_root: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        self.verify_range_inclusion(
+            &[element.clone()],
+            element_pos,
+            element_pos,
+            frozen_root,
+            hasher,
+        )
+    }
+
+    /// Return true if the proof proves that the `elements` appear consecutively between positions
+    /// `start_element_pos` through `end_element_pos` (inclusive) within the MMR whose frozen root
+    /// (at the size the proof is bound to) is `frozen_root`.
+    pub fn verify_range_inclusion<H: CHasher>(
+        &self,
+        elements: &[Digest],
+        start_element_pos: u64,
+        end_element_pos: u64,
+        frozen_root: &Digest,
+        hasher: &mut H,
+    ) -> bool {
+        let Some(root) = self.proof.reconstruct_root_from(
+            elements.iter(),
+            start_element_pos,
+            end_element_pos,
+            hasher,
+        ) else {
+            return false;
+        };
+        Self::frozen_root(self.proof.size, &root, hasher) == *frozen_root
+    }
+
+    /// Serialize the proof as the format version followed by the serialized `Proof` (see
+    /// `Proof::to_bytes`).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![FROZEN_PROOF_VERSION];
+        bytes.extend_from_slice(&self.proof.to_bytes());
+        bytes
+    }
+
+    /// Deserialize a frozen proof produced by `to_bytes` from (possibly untrusted) bytes, to be
+    /// verified under `scheme` (see `Proof::from_bytes`), returning `Error::UnsupportedVersion` if
+    /// it was produced by an incompatible version.
+    pub fn from_bytes<H: CHasher>(bytes: &[u8], scheme: Scheme) -> Result<Self, Error> {
+        let Some((&version, proof)) = bytes.split_first() else {
+            return Err(Error::InvalidProofEncoding(0));
+        };
+        if version != FROZEN_PROOF_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        Ok(Self::new(Proof::from_bytes::<H>(proof, scheme)?))
+    }
+}
+
+/// Verify a batch of inclusion proofs in parallel, returning whether each verified. Each item of
+/// `proofs` is a tuple of `(proof, element, element_pos, root_hash)`. Every worker thread verifies
+/// with its own clone of `hasher`.
+#[cfg(feature = "rayon")]
+pub fn verify_batch_parallel<H: CHasher>(
+    proofs: &[(Proof, Digest, u64, Digest)],
+    hasher: &H,
+) -> Vec<bool> {
+    use rayon::prelude::*;
+
+    proofs
+        .par_iter()
+        .map_init(
+            || hasher.clone(),
+            |hasher, (proof, element, element_pos, root_hash)| {
+                hasher.reset();
+                proof.verify_element_inclusion(element, *element_pos, root_hash, hasher)
+            },
+        )
+        .collect()
+}
+
+/// Return true if `proof1` proves that `elements1` appear consecutively between positions `start1`
+/// through `end1` (inclusive), `proof2` proves the same of `elements2` between `start2` and `end2`,
+/// both within the MMR with root hash `root_hash`, and the two ranges agree on the element at every
+/// position they share.
+///
+/// Proofs verifying against the same root can only disagree on a shared element by exhibiting a
+/// hash collision, so the final check is a defense in depth for callers comparing proofs served to
+/// different clients.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_range_overlap<H: CHasher>(
+    proof1: &Proof,
+    elements1: &[Digest],
+    start1: u64,
+    end1: u64,
+    proof2: &Proof,
+    elements2: &[Digest],
+    start2: u64,
+    end2: u64,
+    root_hash: &Digest,
+    hasher: &mut H,
+) -> bool {
+    if !proof1.verify_range_inclusion(elements1, start1, end1, root_hash, hasher)
+        || !proof2.verify_range_inclusion(elements2, start2, end2, root_hash, hasher)
+    {
+        return false;
+    }
+
+    // Pair each element with the position of its leaf, then compare those at shared positions
+    let leaves = |elements: &[Digest], start: u64| {
+        let mut positions = Vec::with_capacity(elements.len());
+        let mut pos = start;
+        for _ in elements {
+            positions.push(pos);
+            pos = next_leaf_pos(pos);
+        }
+        positions
+    };
+    let positions2 = leaves(elements2, start2);
+    leaves(elements1, start1)
+        .iter()
+        .zip(elements1)
+        .all(|(pos, element)| match positions2.binary_search(pos) {
+            Ok(index) => elements2[index] == *element,
+            Err(_) => true,
+        })
+}
+
+/// Return the positions of the nodes whose hashes make up a proof for the specified range of
+/// elements within an MMR of the given `size`, in the order they appear in the proof. The range is
+/// inclusive of both endpoints.
+pub(crate) fn nodes_required_for_range_proof(
+    size: u64,
+    start_element_pos: u64,
+    end_element_pos: u64,
+) -> Vec<u64> {
+    let mut positions = Vec::new();
+    let mut start_tree_with_element = (u64::MAX, 0);
+    let mut end_tree_with_element = (u64::MAX, 0);
+
+    // Include peaks only for trees that have no elements from the range, and keep track of the
+    // starting and ending trees of those that do contain some.
+    let mut peak_iterator = PeakIterator::new(size);
+    while let Some(item) = peak_iterator.next() {
+        if start_tree_with_element.0 == u64::MAX && item.0 >= start_element_pos {
+            // found the first tree to contain an element in the range
+            start_tree_with_element = item;
+            if item.0 >= end_element_pos {
+                // start and end tree are the same
+                end_tree_with_element = item;
+                continue;
+            }
+            for item in peak_iterator.by_ref() {
+                if item.0 >= end_element_pos {
+                    // found the last tree to contain an element in the range
+                    end_tree_with_element = item;
+                    break;
+                }
+            }
+        } else {
+            positions.push(item.0);
+        }
+    }
+    assert!(start_tree_with_element.0 != u64::MAX);
+    assert!(end_tree_with_element.0 != u64::MAX);
+
+    // For the trees containing elements in the range, add left-sibling positions of nodes along
+    // the leftmost path, and right-sibling positions of nodes along the rightmost path, in
+    // decreasing order of the position of the parent node.
+    let left_path_iter = PathIterator::new(
+        start_element_pos,
+        start_tree_with_element.0,
+        start_tree_with_element.1,
+    );
+
+    let mut siblings = Vec::<(u64, u64)>::new();
+    if start_element_pos == end_element_pos {
+        // For the (common) case of a single element range, the right and left path are the
+        // same so no need to process each independently.
+        siblings.extend(left_path_iter);
+    } else {
+        let right_path_iter = PathIterator::new(
+            end_element_pos,
+            end_tree_with_element.0,
+            end_tree_with_element.1,
+        );
+        // filter the right path for right siblings only
+        siblings.extend(right_path_iter.filter(|(parent_pos, pos)| *parent_pos == *pos + 1));
+        // filter the left path for left siblings only
+        siblings.extend(left_path_iter.filter(|(parent_pos, pos)| *parent_pos != *pos + 1));
+
+        // If the range spans more than one tree, then the positions must already be in the
+        // correct order. Otherwise, we enforce the desired order through sorting.
+        if start_tree_with_element.0 == end_tree_with_element.0 {
+            siblings.sort_by(|a, b| b.0.cmp(&a.0));
+        }
+    }
+    positions.extend(siblings.into_iter().map(|(_, pos)| pos));
+    positions
+}
+
+/// Return the positions of the leaves between `start_element_pos` and `end_element_pos`
+/// (inclusive) whose entries in `present` (one per leaf in the range, in order) are set, or `None`
+/// if either endpoint isn't a leaf or `present` doesn't have exactly one entry per leaf.
+pub(crate) fn present_positions(
+    start_element_pos: u64,
+    end_element_pos: u64,
+    present: &[bool],
+) -> Option<Vec<u64>> {
+    if start_element_pos > end_element_pos
+        || pos_to_height(start_element_pos) != 0
+        || pos_to_height(end_element_pos) != 0
+        || present.is_empty()
+    {
+        return None;
+    }
+    let mut positions = Vec::new();
+    let mut pos = start_element_pos;
+    for (index, is_present) in present.iter().enumerate() {
+        if index > 0 {
+            if pos >= end_element_pos {
+                return None;
+            }
+            pos = next_leaf_pos(pos);
+        }
+        if *is_present {
+            positions.push(pos);
+        }
+    }
+    (pos == end_element_pos).then_some(positions)
+}
+
+/// Return the positions of the nodes whose hashes make up a sparse proof for the leaves at
+/// `leaves` (in increasing order) within an MMR of the given `size`: the roots of the largest
+/// subtrees containing none of them, in increasing order of position.
+pub(crate) fn nodes_required_for_sparse_proof(size: u64, leaves: &[u64]) -> Vec<u64> {
+    fn visit(pos: u64, height: u32, leaves: &[u64], positions: &mut Vec<u64>) {
+        if leaves.is_empty() {
+            positions.push(pos);
+            return;
+        }
+        if height == 0 {
+            return;
+        }
+        let left_pos = pos - 1 - subtree_size(height - 1);
+        let split = leaves.partition_point(|leaf| *leaf <= left_pos);
+        visit(left_pos, height - 1, &leaves[..split], positions);
+        visit(pos - 1, height - 1, &leaves[split..], positions);
+    }
+
+    let mut positions = Vec::new();
+    let mut leaves = leaves;
+    for (peak_pos, height) in PeakIterator::new(size) {
+        let split = leaves.partition_point(|leaf| *leaf <= peak_pos);
+        visit(peak_pos, height, &leaves[..split], &mut positions);
+        leaves = &leaves[split..];
+    }
+    positions
+}
+
+/// Return the hash of the node at `pos` with the given `height`, computed from the `leaves` of its
+/// subtree (consuming their elements from `elements`) and the hashes of its largest subtrees
+/// containing none of them (consumed from `hashes`), or `None` if either runs out.
+fn sparse_subtree_hash<'a, H: CHasher>(
+    pos: u64,
+    height: u32,
+    leaves: &[u64],
+    elements: &mut impl Iterator<Item = &'a Digest>,
+    hashes: &mut impl Iterator<Item = &'a Digest>,
+    hasher: &mut Hasher<H>,
+) -> Option<Digest> {
+    if leaves.is_empty() {
+        return hashes.next().cloned();
+    }
+    if height == 0 {
+        return Some(hasher.leaf_hash(pos, elements.next()?));
+    }
+    let left_pos = pos - 1 - subtree_size(height - 1);
+    let split = leaves.partition_point(|leaf| *leaf <= left_pos);
+    let left = sparse_subtree_hash(
+        left_pos,
+        height - 1,
+        &leaves[..split],
+        elements,
+        hashes,
+        hasher,
+    )?;
+    let right = sparse_subtree_hash(
+        pos - 1,
+        height - 1,
+        &leaves[split..],
+        elements,
+        hashes,
+        hasher,
+    )?;
+    Some(hasher.node_hash(pos, &left, &right))
+}
+
+fn peak_hash_from_range<'a, H: CHasher>(
+    node_pos: u64,      // current node position in the tree
+    two_h: u64,         // 2^height of the current node
+    leftmost_pos: u64,  // leftmost leaf in the tree to be traversed
+    rightmost_pos: u64, // rightmost leaf in the tree to be traversed
+    elements: &mut impl Iterator<Item = &'a Digest>,
+    sibling_hashes: &mut impl Iterator<Item = &'a Digest>,
+    hasher: &mut Hasher<H>,
+) -> Result<Digest, ()> {
+    if two_h == 0 {
+        return Err(());
+    }
+    if two_h == 1 {
+        // we are at a leaf
+        match elements.next() {
+            Some(element) => return Ok(hasher.leaf_hash(node_pos, element)),
+            None => return Err(()),
+        }
+    }
+
+    let left_pos = node_pos.checked_sub(two_h).ok_or(())?;
+    let mut left_hash: Option<Digest> = None;
+    let right_pos = node_pos.checked_sub(1).ok_or(())?;
+    let mut right_hash: Option<Digest> = None;
+
+    if left_pos >= leftmost_pos {
+        // Descend left
+        match peak_hash_from_range(
+            left_pos,
+            two_h >> 1,
+            leftmost_pos,
+            rightmost_pos,
+            elements,
             sibling_hashes,
             hasher,
         ) {
@@ -159,7 +1173,12 @@ fn peak_hash_from_range<'a, H: CHasher>(
 
 #[cfg(test)]
 mod tests {
-    use crate::mmr::mem::Mmr;
+    use super::{
+        verify_range_overlap, Checkpoint, FrozenProof, Proof, ProofBuilder, FROZEN_PROOF_VERSION,
+    };
+    use crate::mmr::hasher::{Scheme, ROOT_TAG};
+    use crate::mmr::iterator::PeakIterator;
+    use crate::mmr::{mem::Mmr, Error};
     use commonware_cryptography::{Digest, Hasher as CHasher, Sha256};
 
     #[test]
@@ -180,7 +1199,7 @@ mod tests {
 
         // confirm the proof of inclusion for each leaf successfully verifies
         for leaf in leaves.iter().by_ref() {
-            let proof = mmr.proof(*leaf);
+            let proof = mmr.proof(*leaf).unwrap();
             assert!(
                 proof.verify_element_inclusion::<Sha256>(&element, *leaf, &root_hash, &mut hasher),
                 "valid proof should verify successfully"
@@ -189,7 +1208,7 @@ mod tests {
 
         // confirm mangling the proof or proof args results in failed validation
         const POS: u64 = 18;
-        let proof = mmr.proof(POS);
+        let proof = mmr.proof(POS).unwrap();
         assert!(
             proof.verify_element_inclusion::<Sha256>(&element, POS, &root_hash, &mut hasher),
             "proof verification should be successful"
@@ -276,7 +1295,7 @@ mod tests {
             for j in i + 1..elements.len() {
                 let start_pos = element_positions[i];
                 let end_pos = element_positions[j];
-                let range_proof = mmr.range_proof(start_pos, end_pos);
+                let range_proof = mmr.range_proof(start_pos, end_pos).unwrap();
                 assert!(
                     range_proof.verify_range_inclusion::<Sha256>(
                         &elements[i..j + 1],
@@ -297,7 +1316,7 @@ mod tests {
         let end_index = 39;
         let start_pos = element_positions[start_index];
         let end_pos = element_positions[end_index];
-        let range_proof = mmr.range_proof(start_pos, end_pos);
+        let range_proof = mmr.range_proof(start_pos, end_pos).unwrap();
         let valid_elements = &elements[start_index..end_index + 1];
         assert!(
             range_proof.verify_range_inclusion::<Sha256>(
@@ -423,4 +1442,1025 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_sanity_check() {
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        let mut element_positions = Vec::<u64>::new();
+        for i in 0..49 {
+            element_positions.push(mmr.add(&Digest::from(vec![i as u8; Sha256::len()])));
+        }
+
+        // every proof generated by the MMR should pass the check
+        for i in 0..element_positions.len() {
+            for j in i..element_positions.len() {
+                let proof = mmr
+                    .range_proof(element_positions[i], element_positions[j])
+                    .unwrap();
+                assert_eq!(proof.sanity_check(Sha256::len()), Ok(()));
+            }
+        }
+
+        // invalid sizes should be rejected
+        let proof = mmr.proof(element_positions[10]).unwrap();
+        let mut invalid_proof = proof.clone();
+        invalid_proof.size = 0;
+        assert_eq!(
+            invalid_proof.sanity_check(Sha256::len()),
+            Err(Error::InvalidSize(0))
+        );
+        invalid_proof.size = 2;
+        assert_eq!(
+            invalid_proof.sanity_check(Sha256::len()),
+            Err(Error::InvalidSize(2))
+        );
+
+        // too many hashes should be rejected
+        let mut invalid_proof = proof.clone();
+        while invalid_proof.hashes.len() <= 2 * 5 + 2 {
+            invalid_proof
+                .hashes
+                .push(Digest::from(vec![0u8; Sha256::len()]));
+        }
+        assert_eq!(
+            invalid_proof.sanity_check(Sha256::len()),
+            Err(Error::InvalidProofLength(invalid_proof.hashes.len()))
+        );
+
+        // hashes of the wrong length should be rejected
+        let mut invalid_proof = proof.clone();
+        invalid_proof.hashes[0] = Digest::from(vec![0u8; Sha256::len() - 1]);
+        assert_eq!(
+            invalid_proof.sanity_check(Sha256::len()),
+            Err(Error::InvalidDigestLength(Sha256::len(), Sha256::len() - 1))
+        );
+        assert_eq!(
+            proof.sanity_check(Sha256::len() + 1),
+            Err(Error::InvalidDigestLength(Sha256::len() + 1, Sha256::len()))
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_serialization() {
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        for i in 0..11 {
+            mmr.add(&Digest::from(vec![i as u8; Sha256::len()]));
+        }
+        let checkpoint = mmr.checkpoint();
+        let bytes = checkpoint.serialize();
+        assert_eq!(bytes.len(), 8 + 8 + Sha256::len());
+        assert_eq!(
+            Checkpoint::deserialize::<Sha256>(&bytes),
+            Some(checkpoint.clone())
+        );
+
+        // truncated or extended bytes should be rejected
+        assert_eq!(
+            Checkpoint::deserialize::<Sha256>(&bytes[..bytes.len() - 1]),
+            None
+        );
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(Checkpoint::deserialize::<Sha256>(&extended), None);
+
+        // an invalid size should be rejected
+        let mut invalid = checkpoint.clone();
+        invalid.size = 20;
+        assert_eq!(
+            Checkpoint::deserialize::<Sha256>(&invalid.serialize()),
+            None
+        );
+
+        // a leaf count inconsistent with the size should be rejected
+        let mut invalid = checkpoint.clone();
+        invalid.leaf_count = 12;
+        assert_eq!(
+            Checkpoint::deserialize::<Sha256>(&invalid.serialize()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_verify_at_checkpoint() {
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0..11 {
+            elements.push(Digest::from(vec![i as u8; Sha256::len()]));
+            positions.push(mmr.add(elements.last().unwrap()));
+        }
+        let checkpoint = mmr.checkpoint();
+        let mut hasher = Sha256::default();
+
+        let proof = mmr.proof(positions[5]).unwrap();
+        assert!(proof.verify_element_inclusion_at_checkpoint(
+            &elements[5],
+            positions[5],
+            &checkpoint,
+            &mut hasher
+        ));
+        let range_proof = mmr.range_proof(positions[1], positions[9]).unwrap();
+        assert!(range_proof.verify_range_inclusion_at_checkpoint(
+            &elements[1..10],
+            positions[1],
+            positions[9],
+            &checkpoint,
+            &mut hasher
+        ));
+
+        // a checkpoint of a different size should fail even with the same root
+        let mut invalid = checkpoint.clone();
+        invalid.size = 18;
+        assert!(!proof.verify_element_inclusion_at_checkpoint(
+            &elements[5],
+            positions[5],
+            &invalid,
+            &mut hasher
+        ));
+
+        // a checkpoint taken after further additions should fail
+        mmr.add(&elements[0]);
+        assert!(!proof.verify_element_inclusion_at_checkpoint(
+            &elements[5],
+            positions[5],
+            &mmr.checkpoint(),
+            &mut hasher
+        ));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_verify_batch_parallel() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..100 {
+            elements.push(Digest::from(vec![i; 32]));
+            positions.push(mmr.add(elements.last().unwrap()));
+        }
+        let root_hash = mmr.root_hash();
+        let mut batch: Vec<_> = elements
+            .iter()
+            .zip(positions.iter())
+            .map(|(element, pos)| {
+                (
+                    mmr.proof(*pos).unwrap(),
+                    element.clone(),
+                    *pos,
+                    root_hash.clone(),
+                )
+            })
+            .collect();
+
+        let hasher = Sha256::default();
+        let results = super::verify_batch_parallel(&batch, &hasher);
+        assert_eq!(results.len(), batch.len());
+        assert!(results.iter().all(|verified| *verified));
+
+        // corrupt a few items and make sure only those fail
+        batch[3].1 = Digest::from(vec![255u8; 32]);
+        batch[50].3 = elements[0].clone();
+        batch[99].2 = positions[98];
+        let results = super::verify_batch_parallel(&batch, &hasher);
+        for (i, verified) in results.iter().enumerate() {
+            assert_eq!(*verified, ![3, 50, 99].contains(&i));
+        }
+    }
+
+    #[test]
+    fn test_verify_range_reversed() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..49 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let root_hash = mmr.root_hash();
+        let mut hasher = Sha256::default();
+
+        for (start, end) in [(0, 0), (3, 17), (0, 48), (30, 31)] {
+            let (start_pos, end_pos) = (positions[start], positions[end]);
+            let proof = mmr.range_proof(start_pos, end_pos).unwrap();
+            let mut reversed = elements[start..=end].to_vec();
+            reversed.reverse();
+            assert!(proof.verify_range_inclusion_reversed(
+                &reversed,
+                start_pos,
+                end_pos,
+                &root_hash,
+                &mut hasher
+            ));
+
+            // elements in the original order should fail (unless there is only one)
+            assert_eq!(
+                proof.verify_range_inclusion_reversed(
+                    &elements[start..=end],
+                    start_pos,
+                    end_pos,
+                    &root_hash,
+                    &mut hasher
+                ),
+                start == end
+            );
+
+            // extra elements (at either end) must cause verification to fail
+            let mut extended = reversed.clone();
+            extended.push(elements[0].clone());
+            assert!(!proof.verify_range_inclusion_reversed(
+                &extended,
+                start_pos,
+                end_pos,
+                &root_hash,
+                &mut hasher
+            ));
+            let mut extended = vec![elements[0].clone()];
+            extended.extend(reversed.iter().cloned());
+            assert!(!proof.verify_range_inclusion_reversed(
+                &extended,
+                start_pos,
+                end_pos,
+                &root_hash,
+                &mut hasher
+            ));
+
+            // missing elements must cause verification to fail
+            assert!(!proof.verify_range_inclusion_reversed(
+                &reversed[1..],
+                start_pos,
+                end_pos,
+                &root_hash,
+                &mut hasher
+            ));
+        }
+    }
+
+    #[test]
+    fn test_proof_positions() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::new();
+        for i in 0u8..50 {
+            positions.push(mmr.add(&Digest::from(vec![i; 32])));
+        }
+        for (start, end) in [(0, 0), (3, 17), (0, 49), (49, 49), (10, 10)] {
+            let (start_pos, end_pos) = (positions[start], positions[end]);
+            let proof = mmr.range_proof(start_pos, end_pos).unwrap();
+            let node_positions = proof.positions(start_pos, end_pos);
+            assert_eq!(node_positions.len(), proof.hashes.len());
+            for (pos, hash) in node_positions.iter().zip(proof.hashes.iter()) {
+                assert!(*pos < proof.size);
+                assert_eq!(mmr.get_node(*pos), Some(hash));
+            }
+        }
+    }
+
+    #[test]
+    fn test_required_elements_count() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..50).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+        let root_hash = mmr.root_hash();
+        for start in 0..elements.len() {
+            for end in start..elements.len() {
+                let (start_pos, end_pos) = (positions[start], positions[end]);
+                let proof = mmr.range_proof(start_pos, end_pos).unwrap();
+                let count = proof.required_elements_count(start_pos, end_pos).unwrap();
+                assert_eq!(count, end - start + 1);
+                assert!(proof.verify_range_inclusion(
+                    &elements[start..start + count],
+                    start_pos,
+                    end_pos,
+                    &root_hash,
+                    &mut hasher
+                ));
+            }
+        }
+
+        // Supplying any other number of elements fails verification
+        let proof = mmr.range_proof(positions[3], positions[17]).unwrap();
+        for elements in [&elements[3..17], &elements[3..19], &elements[..0]] {
+            assert!(!proof.verify_range_inclusion(
+                elements,
+                positions[3],
+                positions[17],
+                &root_hash,
+                &mut hasher
+            ));
+            assert_eq!(
+                proof.verify_against_any(
+                    elements,
+                    positions[3],
+                    positions[17],
+                    &[root_hash.clone()],
+                    &mut hasher
+                ),
+                None
+            );
+        }
+
+        // Ranges that aren't between leaves of the MMR are invalid
+        for (start_pos, end_pos) in [
+            (positions[17], positions[3]),
+            (2, positions[17]),
+            (positions[3], 2),
+            (positions[3], mmr.size()),
+        ] {
+            assert_eq!(
+                proof.required_elements_count(start_pos, end_pos),
+                Err(Error::InvalidRange(start_pos, end_pos))
+            );
+        }
+    }
+
+    #[test]
+    fn test_leaf_span() {
+        // The example MMR with 11 elements (19 nodes)
+        let mut mmr = Mmr::<Sha256>::new();
+        let elements: Vec<_> = (0u8..11).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+        let proof = mmr.range_proof(0, 18).unwrap();
+        assert_eq!(proof.leaf_span(0, 0), 1);
+        assert_eq!(proof.leaf_span(0, 1), 2);
+        assert_eq!(proof.leaf_span(1, 3), 2);
+        assert_eq!(proof.leaf_span(4, 7), 2);
+        assert_eq!(proof.leaf_span(0, 18), 11);
+        assert_eq!(proof.leaf_span(8, 15), 4);
+
+        // Matches the number of elements between any two leaves
+        for start in 0..positions.len() {
+            for end in start..positions.len() {
+                let (start_pos, end_pos) = (positions[start], positions[end]);
+                assert_eq!(
+                    proof.leaf_span(start_pos, end_pos),
+                    (end - start + 1) as u64
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "position is not an element")]
+    fn test_leaf_span_not_element() {
+        let mut mmr = Mmr::<Sha256>::new();
+        for i in 0u8..11 {
+            mmr.add(&Digest::from(vec![i; 32]));
+        }
+        mmr.range_proof(0, 18).unwrap().leaf_span(0, 2);
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..70).map(|i| Digest::from(vec![i; 32])).collect();
+        let mut positions = Vec::new();
+        let mut sizes = vec![0];
+        for element in elements.iter() {
+            positions.push(mmr.add(element));
+            sizes.push(mmr.size());
+        }
+        let root_hash = mmr.root_hash();
+
+        // Proofs generated at any earlier size are updated to the proofs at the current size
+        for old_count in [1, 2, 3, 7, 8, 11, 32, 49, 69] {
+            let old_size = sizes[old_count];
+            let delta = mmr.delta_since(old_size).unwrap();
+            assert_eq!(delta.size, old_size);
+            assert_eq!(delta.nodes.len() as u64, mmr.size() - old_size);
+            for (start, end) in [(0, 0), (0, old_count - 1), (old_count / 2, old_count - 1)] {
+                let (start_pos, end_pos) = (positions[start], positions[end]);
+                let proof = mmr
+                    .historical_range_proof(old_size, start_pos, end_pos)
+                    .unwrap();
+                let updated = proof.apply_delta(start_pos, end_pos, &delta).unwrap();
+                assert_eq!(updated, mmr.range_proof(start_pos, end_pos).unwrap());
+                assert!(updated.verify_range_inclusion(
+                    &elements[start..=end],
+                    start_pos,
+                    end_pos,
+                    &root_hash,
+                    &mut hasher
+                ));
+            }
+        }
+
+        // An empty delta leaves the proof unchanged
+        let proof = mmr.proof(positions[5]).unwrap();
+        let delta = mmr.delta_since(mmr.size()).unwrap();
+        assert_eq!(
+            proof.apply_delta(positions[5], positions[5], &delta),
+            Ok(proof.clone())
+        );
+
+        // Deltas must start at the proof's size and end at a valid size
+        let old_proof = mmr.historical_range_proof(sizes[8], 0, 0).unwrap();
+        let delta = mmr.delta_since(sizes[9]).unwrap();
+        assert_eq!(
+            old_proof.apply_delta(0, 0, &delta),
+            Err(Error::InvalidSize(sizes[9]))
+        );
+        let mut delta = mmr.delta_since(sizes[8]).unwrap();
+        delta.nodes.pop();
+        assert_eq!(
+            old_proof.apply_delta(0, 0, &delta),
+            Err(Error::InvalidSize(mmr.size() - 1))
+        );
+        let delta = mmr.delta_since(sizes[8]).unwrap();
+        assert_eq!(
+            old_proof.apply_delta(0, 2, &delta),
+            Err(Error::InvalidRange(0, 2))
+        );
+        let mut truncated = old_proof.clone();
+        truncated.hashes.pop();
+        assert_eq!(
+            truncated.apply_delta(0, 0, &delta),
+            Err(Error::InvalidProofLength(truncated.hashes.len()))
+        );
+
+        // The delta is only available from valid sizes whose appended nodes are retained
+        assert_eq!(mmr.delta_since(2), Err(Error::InvalidSize(2)));
+        assert_eq!(
+            mmr.delta_since(mmr.size() + 1),
+            Err(Error::InvalidSize(mmr.size() + 1))
+        );
+        let mut pruned = mmr.clone_pruned();
+        assert_eq!(
+            pruned.delta_since(sizes[8]),
+            Err(Error::ElementPruned(sizes[8]))
+        );
+        pruned.add(&elements[0]);
+        assert_eq!(pruned.delta_since(mmr.size()).unwrap().nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_proof_is_superset_of() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::new();
+        for i in 0u8..20 {
+            positions.push(mmr.add(&Digest::from(vec![i; 32])));
+        }
+        let proof =
+            |start: usize, end: usize| mmr.range_proof(positions[start], positions[end]).unwrap();
+
+        // a proof covers the proof of every sub-range of its own (including both halves of itself)
+        for start in 0..20 {
+            for end in start..20 {
+                let outer = proof(start, end);
+                for inner_start in start..=end {
+                    for inner_end in inner_start..=end {
+                        assert!(outer.is_superset_of(
+                            &proof(inner_start, inner_end),
+                            positions[start],
+                            positions[end],
+                            positions[inner_start],
+                            positions[inner_end],
+                        ));
+                    }
+                }
+            }
+        }
+
+        // but not of a proof reaching beyond it, unless the extra nodes are computed from its range
+        let (a, b) = (positions[3], positions[9]);
+        assert!(!proof(3, 9).is_superset_of(&proof(3, 17), a, b, a, positions[17]));
+        assert!(!proof(3, 3).is_superset_of(&proof(16, 16), a, a, positions[16], positions[16]));
+        assert!(proof(3, 3).is_superset_of(&proof(2, 2), a, a, positions[2], positions[2]));
+
+        // a shared hash with a different value, a different size, or a malformed proof isn't covered
+        let mut tampered = proof(3, 5);
+        tampered.hashes[0] = Digest::from(vec![0u8; 32]);
+        assert!(!proof(3, 9).is_superset_of(&tampered, a, b, a, positions[5]));
+        let mut other = Mmr::<Sha256>::new();
+        for i in 0u8..21 {
+            other.add(&Digest::from(vec![i; 32]));
+        }
+        let larger = other.range_proof(a, positions[5]).unwrap();
+        assert!(!proof(3, 9).is_superset_of(&larger, a, b, a, positions[5]));
+        let mut short = proof(3, 5);
+        short.hashes.pop();
+        assert!(!proof(3, 9).is_superset_of(&short, a, b, a, positions[5]));
+        assert!(!proof(3, 9).is_superset_of(&proof(3, 5), a, b, a, mmr.size()));
+    }
+
+    #[test]
+    fn test_proof_serialization() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut positions = Vec::new();
+        for i in 0u8..50 {
+            positions.push(mmr.add(&Digest::from(vec![i; 32])));
+        }
+        for (start, end) in [(0, 0), (3, 17), (0, 49), (49, 49)] {
+            let proof = mmr.range_proof(positions[start], positions[end]).unwrap();
+            let bytes = proof.to_bytes();
+            assert_eq!(bytes.len(), 8 + 4 + 32 * proof.hashes.len());
+            assert_eq!(
+                Proof::from_bytes::<Sha256>(&bytes, Scheme::default()),
+                Ok(proof)
+            );
+        }
+    }
+
+    #[test]
+    fn test_proof_deserialization_invalid() {
+        let proof = Proof {
+            size: 19,
+            hashes: vec![Digest::from(vec![1u8; 32]); 3],
+            scheme: Scheme::default(),
+        };
+        let bytes = proof.to_bytes();
+
+        // truncated header
+        assert_eq!(
+            Proof::from_bytes::<Sha256>(&bytes[..11], Scheme::default()),
+            Err(Error::InvalidProofEncoding(11))
+        );
+
+        // truncated or extended body
+        assert_eq!(
+            Proof::from_bytes::<Sha256>(&bytes[..bytes.len() - 1], Scheme::default()),
+            Err(Error::InvalidProofEncoding(bytes.len() - 1))
+        );
+        assert_eq!(
+            Proof::from_bytes::<Sha256>(&bytes[..bytes.len() - 32], Scheme::default()),
+            Err(Error::InvalidProofEncoding(bytes.len() - 32))
+        );
+        let mut extended = bytes.clone();
+        extended.extend_from_slice(&[0u8; 32]);
+        assert_eq!(
+            Proof::from_bytes::<Sha256>(&extended, Scheme::default()),
+            Err(Error::InvalidProofEncoding(extended.len()))
+        );
+    }
+
+    #[test]
+    fn test_proof_deserialization_huge_count() {
+        // a header claiming a billion hashes followed by a tiny body should fail without
+        // attempting to allocate space for the claimed hashes
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&19u64.to_be_bytes());
+        bytes.extend_from_slice(&1_000_000_000u32.to_be_bytes());
+        bytes.extend_from_slice(&[7u8; 64]);
+        assert_eq!(
+            Proof::from_bytes::<Sha256>(&bytes, Scheme::default()),
+            Err(Error::InvalidProofEncoding(bytes.len()))
+        );
+    }
+
+    #[test]
+    fn test_frozen_proof() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut elements = Vec::new();
+        let mut positions = Vec::new();
+        for i in 0u8..50 {
+            let element = Digest::from(vec![i; 32]);
+            positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let mut hasher = Sha256::default();
+        let size = mmr.size();
+        let root = mmr.root_hash();
+        let frozen_root = FrozenProof::frozen_root(size, &root, &mut hasher);
+
+        // Frozen proofs verify against the frozen root, but not the plain root
+        let proof = FrozenProof::new(mmr.range_proof(positions[3], positions[17]).unwrap());
+        assert_eq!(proof.size(), size);
+        assert!(proof.verify_range_inclusion(
+            &elements[3..=17],
+            positions[3],
+            positions[17],
+            &frozen_root,
+            &mut hasher
+        ));
+        assert!(!proof.verify_range_inclusion(
+            &elements[3..=17],
+            positions[3],
+            positions[17],
+            &root,
+            &mut hasher
+        ));
+        let proof = FrozenProof::new(mmr.proof(positions[20]).unwrap());
+        assert!(proof.verify_element_inclusion(
+            &elements[20],
+            positions[20],
+            &frozen_root,
+            &mut hasher
+        ));
+
+        // Substituting any other size fails, as does verifying against a frozen root for another
+        // size (even with the correct root hash)
+        for other_size in 1..size + 10 {
+            if other_size == size || !PeakIterator::check_validity(other_size) {
+                continue;
+            }
+            let mut substituted = proof.clone();
+            substituted.proof.size = other_size;
+            if other_size > positions[20] {
+                assert!(!substituted.verify_element_inclusion(
+                    &elements[20],
+                    positions[20],
+                    &frozen_root,
+                    &mut hasher
+                ));
+            }
+            let other_frozen_root = FrozenProof::frozen_root(other_size, &root, &mut hasher);
+            assert!(!proof.verify_element_inclusion(
+                &elements[20],
+                positions[20],
+                &other_frozen_root,
+                &mut hasher
+            ));
+        }
+
+        // The serialized proof is versioned
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes[0], FROZEN_PROOF_VERSION);
+        assert_eq!(
+            FrozenProof::from_bytes::<Sha256>(&bytes, Scheme::default()),
+            Ok(proof.clone())
+        );
+        let mut unsupported = bytes.clone();
+        unsupported[0] = FROZEN_PROOF_VERSION + 1;
+        assert_eq!(
+            FrozenProof::from_bytes::<Sha256>(&unsupported, Scheme::default()),
+            Err(Error::UnsupportedVersion(FROZEN_PROOF_VERSION + 1))
+        );
+        assert_eq!(
+            FrozenProof::from_bytes::<Sha256>(&[], Scheme::default()),
+            Err(Error::InvalidProofEncoding(0))
+        );
+        assert_eq!(
+            FrozenProof::from_bytes::<Sha256>(&bytes[..bytes.len() - 1], Scheme::default()),
+            Err(Error::InvalidProofEncoding(bytes.len() - 2))
+        );
+    }
+
+    #[test]
+    fn test_proof_builder() {
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        let mut hasher = Sha256::default();
+        let mut element_positions = Vec::<u64>::new();
+        for i in 0..49 {
+            element_positions.push(mmr.add(&Digest::from(vec![i as u8; Sha256::len()])));
+        }
+        let root_hash = mmr.root_hash();
+
+        // a proof assembled from the hashes of a generated proof is identical to it and verifies
+        let proof = mmr.proof(element_positions[10]).unwrap();
+        let built = proof
+            .hashes
+            .iter()
+            .fold(ProofBuilder::new().size(proof.size), |builder, hash| {
+                builder.push_hash(hash.clone())
+            })
+            .build()
+            .unwrap();
+        assert_eq!(built, proof);
+        let element = Digest::from(vec![10u8; Sha256::len()]);
+        assert!(built.verify_element_inclusion(
+            &element,
+            element_positions[10],
+            &root_hash,
+            &mut hasher
+        ));
+
+        // implausible proofs are rejected
+        assert_eq!(ProofBuilder::new().build(), Err(Error::InvalidSize(0)));
+        assert_eq!(
+            ProofBuilder::new().size(2).build(),
+            Err(Error::InvalidSize(2))
+        );
+        assert_eq!(
+            ProofBuilder::new()
+                .size(1)
+                .push_hash(Digest::from(vec![0u8; Sha256::len()]))
+                .build(),
+            Err(Error::InvalidProofLength(1))
+        );
+        assert_eq!(
+            ProofBuilder::new()
+                .size(mmr.size())
+                .push_hash(Digest::from(vec![0u8; Sha256::len()]))
+                .push_hash(Digest::from(vec![0u8; Sha256::len() - 1]))
+                .build(),
+            Err(Error::InvalidDigestLength(Sha256::len(), Sha256::len() - 1))
+        );
+    }
+
+    #[test]
+    fn test_verify_with_precomputed_peaks() {
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        let mut hasher = Sha256::default();
+        let mut elements = Vec::new();
+        let mut element_positions = Vec::new();
+        for i in 0..49 {
+            let element = Digest::from(vec![i as u8; Sha256::len()]);
+            element_positions.push(mmr.add(&element));
+            elements.push(element);
+        }
+        let peaks = mmr.peak_hashes();
+        let root_hash = mmr.root_hash();
+
+        // every element verifies against the peaks of the MMR
+        for (element, pos) in elements.iter().zip(element_positions.iter()) {
+            let proof = mmr.proof(*pos).unwrap();
+            assert!(proof.verify_element_inclusion_with_precomputed_peaks(
+                element,
+                *pos,
+                &peaks,
+                &root_hash,
+                &mut hasher
+            ));
+        }
+
+        // the wrong element, or a root that doesn't match the peaks, fails verification
+        let proof = mmr.proof(element_positions[10]).unwrap();
+        assert!(!proof.verify_element_inclusion_with_precomputed_peaks(
+            &elements[11],
+            element_positions[10],
+            &peaks,
+            &root_hash,
+            &mut hasher
+        ));
+        let other_root = Digest::from(vec![0u8; Sha256::len()]);
+        assert!(!proof.verify_element_inclusion_with_precomputed_peaks(
+            &elements[10],
+            element_positions[10],
+            &peaks,
+            &other_root,
+            &mut hasher
+        ));
+
+        // peaks that are out of order, missing, or tampered with fail verification
+        let mut reversed = peaks.clone();
+        reversed.reverse();
+        let mut tampered = peaks.clone();
+        tampered[1].1 = other_root.clone();
+        for invalid in [reversed, peaks[1..].to_vec(), tampered] {
+            assert!(!proof.verify_element_inclusion_with_precomputed_peaks(
+                &elements[10],
+                element_positions[10],
+                &invalid,
+                &root_hash,
+                &mut hasher
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_against_any() {
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        let mut hasher = Sha256::default();
+        let mut elements = Vec::new();
+        let mut element_positions = Vec::new();
+        let mut roots = Vec::new();
+        let mut sizes = Vec::new();
+        for i in 0..49 {
+            let element = Digest::from(vec![i as u8; Sha256::len()]);
+            element_positions.push(mmr.add(&element));
+            elements.push(element);
+            roots.push(mmr.root_hash());
+            sizes.push(mmr.size());
+        }
+        let (start, end) = (element_positions[10], element_positions[20]);
+        let proof = mmr.range_proof(start, end).unwrap();
+
+        // the first matching candidate is returned
+        let other_root = Digest::from(vec![0u8; Sha256::len()]);
+        let root_hash = mmr.root_hash();
+        let candidates = [
+            other_root.clone(),
+            roots[30].clone(),
+            root_hash.clone(),
+            root_hash.clone(),
+        ];
+        assert_eq!(
+            proof.verify_against_any(&elements[10..=20], start, end, &candidates, &mut hasher),
+            Some(2)
+        );
+
+        // no candidate matches the wrong elements, and nothing matches an empty candidate list
+        assert_eq!(
+            proof.verify_against_any(&elements[11..=21], start, end, &candidates, &mut hasher),
+            None
+        );
+        assert_eq!(
+            proof.verify_against_any(&elements[10..=20], start, end, &[], &mut hasher),
+            None
+        );
+
+        // a proof from an earlier state matches the root of that state
+        let old_proof = mmr.historical_range_proof(sizes[30], start, end).unwrap();
+        assert_eq!(
+            old_proof.verify_against_any(&elements[10..=20], start, end, &roots, &mut hasher),
+            Some(30)
+        );
+    }
+
+    #[test]
+    fn test_verify_empty() {
+        let mut hasher = Sha256::default();
+        let mut mmr: Mmr<Sha256> = Mmr::default();
+        let empty_root = Mmr::<Sha256>::empty_root(&mut hasher);
+        assert_eq!(mmr.root_hash(), empty_root);
+        hasher.update(&[ROOT_TAG]);
+        hasher.update(&0u64.to_be_bytes());
+        assert_eq!(hasher.finalize(), empty_root);
+
+        // the empty proof only verifies against the empty root
+        let proof = Proof {
+            size: 0,
+            hashes: vec![],
+            scheme: Scheme::default(),
+        };
+        assert!(proof.verify_empty(&empty_root, &mut hasher));
+        mmr.add(&Digest::from(vec![0u8; Sha256::len()]));
+        let root_hash = mmr.root_hash();
+        assert!(!proof.verify_empty(&root_hash, &mut hasher));
+        let proof_with_hash = Proof {
+            size: 0,
+            hashes: vec![root_hash.clone()],
+            scheme: Scheme::default(),
+        };
+        assert!(!proof_with_hash.verify_empty(&empty_root, &mut hasher));
+        assert!(!mmr.proof(0).unwrap().verify_empty(&root_hash, &mut hasher));
+
+        // no range can be proven within an empty MMR
+        for (start, end) in [(0, 0), (5, 7)] {
+            assert!(!proof.verify_range_inclusion(&[], start, end, &empty_root, &mut hasher));
+        }
+        assert_eq!(
+            proof.verify_against_any(&[], 0, 0, &[empty_root], &mut hasher),
+            None
+        );
+    }
+
+    #[test]
+    fn test_verify_single_peak() {
+        let mut hasher = Sha256::default();
+        for leaves in [1u64, 2, 4, 8, 64] {
+            // an MMR that is a single perfect tree
+            let mut mmr: Mmr<Sha256> = Mmr::default();
+            let mut elements = Vec::new();
+            let mut element_positions = Vec::new();
+            for i in 0..leaves {
+                let element = Digest::from(vec![i as u8; Sha256::len()]);
+                element_positions.push(mmr.add(&element));
+                elements.push(element);
+            }
+            let peaks = mmr.peak_hashes();
+            assert_eq!(peaks.len(), 1);
+            let root_hash = mmr.root_hash();
+            assert_ne!(root_hash, peaks[0].1); // the root still commits to the size
+
+            for (element, pos) in elements.iter().zip(element_positions.iter()) {
+                // the proof holds only the siblings along the path to the peak
+                let proof = mmr.proof(*pos).unwrap();
+                assert_eq!(proof.hashes.len(), leaves.trailing_zeros() as usize);
+                assert!(proof.verify_element_inclusion(element, *pos, &root_hash, &mut hasher));
+
+                // an extra hash at either end, or a missing sibling, fails verification
+                let extra = Digest::from(vec![0xffu8; Sha256::len()]);
+                let mut prefixed = proof.clone();
+                prefixed.hashes.insert(0, extra.clone());
+                let mut suffixed = proof.clone();
+                suffixed.hashes.push(extra);
+                for invalid in [prefixed, suffixed] {
+                    assert!(!invalid.verify_element_inclusion(
+                        element,
+                        *pos,
+                        &root_hash,
+                        &mut hasher
+                    ));
+                }
+                if !proof.hashes.is_empty() {
+                    let mut truncated = proof.clone();
+                    truncated.hashes.pop();
+                    assert!(!truncated.verify_element_inclusion(
+                        element,
+                        *pos,
+                        &root_hash,
+                        &mut hasher
+                    ));
+                }
+            }
+
+            // a range spanning the whole tree needs no hashes at all
+            let proof = mmr
+                .range_proof(element_positions[0], *element_positions.last().unwrap())
+                .unwrap();
+            assert!(proof.hashes.is_empty());
+            assert!(proof.verify_range_inclusion(
+                &elements,
+                element_positions[0],
+                *element_positions.last().unwrap(),
+                &root_hash,
+                &mut hasher
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_extreme_sizes() {
+        let mut hasher = Sha256::default();
+        let element = Digest::from(vec![0u8; Sha256::len()]);
+        let root_hash = Digest::from(vec![1u8; Sha256::len()]);
+        let hashes: Vec<Digest> = (0..64u8)
+            .map(|i| Digest::from(vec![i; Sha256::len()]))
+            .collect();
+
+        // Sizes whose tallest peak is as high as possible (or that aren't valid sizes at all) fail
+        // verification without panicking, for elements anywhere in (or beyond) the MMR
+        let sizes = [
+            u64::MAX,
+            u64::MAX - 1,
+            u64::MAX >> 1,
+            (u64::MAX >> 1) + (u64::MAX >> 2),
+            (u64::MAX >> 1) + 1,
+        ];
+        let positions = [
+            0,
+            1,
+            u64::MAX >> 2,
+            u64::MAX >> 1,
+            u64::MAX - 2,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+        for size in sizes {
+            for pos in positions {
+                for hash_count in [0, 1, 62, 64] {
+                    let proof = Proof {
+                        size,
+                        hashes: hashes[..hash_count].to_vec(),
+                        scheme: Scheme::default(),
+                    };
+                    assert!(!proof.verify_element_inclusion(
+                        &element,
+                        pos,
+                        &root_hash,
+                        &mut hasher
+                    ));
+                    assert!(!proof.verify_range_inclusion(
+                        &[element.clone(), element.clone()],
+                        pos,
+                        pos.saturating_add(1),
+                        &root_hash,
+                        &mut hasher
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_range_overlap() {
+        let mut mmr = Mmr::<Sha256>::new();
+        let mut hasher = Sha256::default();
+        let elements: Vec<_> = (0u8..49).map(|i| Digest::from(vec![i; 32])).collect();
+        let positions: Vec<_> = elements.iter().map(|element| mmr.add(element)).collect();
+        let root_hash = mmr.root_hash();
+        let range = |start: usize, end: usize| {
+            let proof = mmr.range_proof(positions[start], positions[end]).unwrap();
+            (
+                proof,
+                elements[start..=end].to_vec(),
+                positions[start],
+                positions[end],
+            )
+        };
+        let check = |first: &(Proof, Vec<Digest>, u64, u64),
+                     second: &(Proof, Vec<Digest>, u64, u64),
+                     hasher: &mut Sha256| {
+            verify_range_overlap(
+                &first.0, &first.1, first.2, first.3, &second.0, &second.1, second.2, second.3,
+                &root_hash, hasher,
+            )
+        };
+
+        // Overlapping, nested, identical, and disjoint ranges are consistent
+        let first = range(5, 20);
+        for second in [range(10, 30), range(7, 9), range(5, 20), range(21, 48)] {
+            assert!(check(&first, &second, &mut hasher));
+            assert!(check(&second, &first, &mut hasher));
+        }
+
+        // Either range failing to verify fails the check
+        let mut tampered = range(10, 30);
+        tampered.1[0] = elements[0].clone();
+        assert!(!check(&first, &tampered, &mut hasher));
+        assert!(!check(&tampered, &first, &mut hasher));
+        let mut other = Mmr::<Sha256>::new();
+        for element in elements.iter().rev() {
+            other.add(element);
+        }
+        let foreign = (
+            other.range_proof(positions[10], positions[30]).unwrap(),
+            elements[10..=30].to_vec(),
+            positions[10],
+            positions[30],
+        );
+        assert!(!check(&first, &foreign, &mut hasher));
+    }
 }